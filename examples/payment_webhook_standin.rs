@@ -0,0 +1,59 @@
+//! Posts a signed `payment.captured` webhook to a running server, standing in for the gateway.
+//!
+//! ```sh
+//! PAYMENT_WEBHOOK_SECRET=secret cargo run --example payment_webhook_standin -- \
+//!     127.0.0.1:3000 <user_id> <payment_id> <amount in rupees> [event id]
+//! ```
+//! Passing the same event id twice exercises the replay handling.
+use std::env;
+
+use sliet_techfest_backend::payment::webhook_signature;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.len() < 4 {
+        anyhow::bail!("usage: <host:port> <user_id> <payment_id> <amount> [event id]");
+    }
+    let (address, user_id, payment_id) = (&args[0], &args[1], &args[2]);
+    let amount: i64 = args[3].parse()?;
+    let event_id = args
+        .get(4)
+        .cloned()
+        .unwrap_or_else(|| format!("evt_{}", rand::random::<u32>()));
+    let secret = env::var("PAYMENT_WEBHOOK_SECRET")?;
+
+    let body = serde_json::json!({
+        "entity": "event",
+        "event": "payment.captured",
+        "payload": {
+            "payment": {
+                "entity": {
+                    "id": payment_id,
+                    "order_id": format!("order_{payment_id}"),
+                    "amount": amount * 100,
+                    "currency": "INR",
+                    "status": "captured",
+                    "notes": { "user_id": user_id },
+                }
+            }
+        }
+    })
+    .to_string();
+    let signature = webhook_signature(secret.as_bytes(), body.as_bytes());
+
+    let mut stream = TcpStream::connect(address).await?;
+    let request = format!(
+        "POST /payment/webhook HTTP/1.1\r\nHost: {address}\r\nContent-Type: application/json\r\n\
+         X-Razorpay-Signature: {signature}\r\nX-Razorpay-Event-Id: {event_id}\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(request.as_bytes()).await?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await?;
+    println!("{}", response.lines().next().unwrap_or_default());
+    Ok(())
+}
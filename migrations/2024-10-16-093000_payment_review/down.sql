@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE payments
+  DROP COLUMN rejection_reason,
+  DROP COLUMN submitted_at,
+  DROP COLUMN reviewed_by;
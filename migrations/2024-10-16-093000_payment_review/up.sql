@@ -0,0 +1,5 @@
+-- Your SQL goes here
+ALTER TABLE payments
+  ADD COLUMN rejection_reason TEXT,
+  ADD COLUMN submitted_at TIMESTAMP NOT NULL DEFAULT now(),
+  ADD COLUMN reviewed_by INTEGER REFERENCES users(id) ON DELETE SET NULL;
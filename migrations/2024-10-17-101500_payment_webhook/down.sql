@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE payment_webhook_events;
+ALTER TABLE payments DROP COLUMN order_id;
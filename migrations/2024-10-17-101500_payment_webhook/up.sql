@@ -0,0 +1,8 @@
+-- Your SQL goes here
+ALTER TABLE payments ADD COLUMN order_id TEXT;
+
+CREATE TABLE payment_webhook_events (
+	event_id TEXT PRIMARY KEY,
+	payment_id TEXT,
+	received_at TIMESTAMP NOT NULL DEFAULT now()
+);
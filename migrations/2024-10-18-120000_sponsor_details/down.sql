@@ -0,0 +1,8 @@
+-- This file should undo anything in `up.sql`
+DELETE FROM sponsors WHERE photo_hash IS NULL;
+ALTER TABLE sponsors
+  DROP COLUMN tier,
+  DROP COLUMN display_order,
+  DROP COLUMN website,
+  ALTER COLUMN photo_hash SET NOT NULL;
+DROP TYPE SPONSOR_TIER;
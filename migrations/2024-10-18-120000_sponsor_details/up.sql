@@ -0,0 +1,15 @@
+-- Your SQL goes here
+CREATE TYPE SPONSOR_TIER AS ENUM (
+	'TITLE',
+	'PLATINUM',
+	'GOLD',
+	'SILVER',
+	'BRONZE',
+	'PARTNER'
+);
+
+ALTER TABLE sponsors
+  ADD COLUMN tier SPONSOR_TIER NOT NULL DEFAULT 'PARTNER',
+  ADD COLUMN display_order INTEGER NOT NULL DEFAULT 0,
+  ADD COLUMN website TEXT,
+  ALTER COLUMN photo_hash DROP NOT NULL;
@@ -0,0 +1,11 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE workshop_waitlist;
+DROP TABLE team_event_waitlist;
+DROP TABLE individual_event_waitlist;
+
+ALTER TABLE workshops
+  DROP COLUMN max_participants;
+
+ALTER TABLE events
+  DROP COLUMN max_participants,
+  DROP COLUMN max_teams;
@@ -0,0 +1,28 @@
+-- Your SQL goes here
+ALTER TABLE events
+  ADD COLUMN max_participants INTEGER CHECK (max_participants >= 0),
+  ADD COLUMN max_teams INTEGER CHECK (max_teams >= 0);
+
+ALTER TABLE workshops
+  ADD COLUMN max_participants INTEGER CHECK (max_participants >= 0);
+
+CREATE TABLE individual_event_waitlist (
+	event_id INTEGER REFERENCES events(id) ON DELETE CASCADE NOT NULL,
+	user_id INTEGER REFERENCES users(id) ON DELETE CASCADE NOT NULL,
+	joined_at TIMESTAMP NOT NULL DEFAULT now(),
+	PRIMARY KEY(event_id, user_id)
+);
+
+CREATE TABLE team_event_waitlist (
+	event_id INTEGER REFERENCES events(id) ON DELETE CASCADE NOT NULL,
+	team_id INTEGER REFERENCES teams(id) ON DELETE CASCADE NOT NULL,
+	joined_at TIMESTAMP NOT NULL DEFAULT now(),
+	PRIMARY KEY(event_id, team_id)
+);
+
+CREATE TABLE workshop_waitlist (
+	workshop_id INTEGER REFERENCES workshops(id) ON DELETE CASCADE NOT NULL,
+	user_id INTEGER REFERENCES users(id) ON DELETE CASCADE NOT NULL,
+	joined_at TIMESTAMP NOT NULL DEFAULT now(),
+	PRIMARY KEY(workshop_id, user_id)
+);
@@ -0,0 +1,9 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE team_event_participations
+  DROP COLUMN below_min_size;
+
+ALTER TABLE events
+  DROP CONSTRAINT events_team_size_check,
+  DROP COLUMN min_team_size,
+  DROP COLUMN max_team_size,
+  DROP COLUMN count_pending_requests;
@@ -0,0 +1,9 @@
+-- Your SQL goes here
+ALTER TABLE events
+  ADD COLUMN min_team_size INTEGER NOT NULL DEFAULT 1 CHECK (min_team_size >= 1),
+  ADD COLUMN max_team_size INTEGER NOT NULL DEFAULT 4,
+  ADD COLUMN count_pending_requests BOOLEAN NOT NULL DEFAULT false,
+  ADD CONSTRAINT events_team_size_check CHECK (min_team_size <= max_team_size);
+
+ALTER TABLE team_event_participations
+  ADD COLUMN below_min_size BOOLEAN NOT NULL DEFAULT false;
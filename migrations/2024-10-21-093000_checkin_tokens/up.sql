@@ -0,0 +1,5 @@
+-- Your SQL goes here
+CREATE TABLE used_checkin_tokens (
+	jti TEXT PRIMARY KEY,
+	used_at TIMESTAMP NOT NULL DEFAULT now()
+);
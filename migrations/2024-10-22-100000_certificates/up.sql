@@ -0,0 +1,18 @@
+-- Your SQL goes here
+-- Name, title, domain and date are copied in when the certificate is issued, so verifying it
+-- shows exactly what was printed even if the event is renamed later.
+CREATE TABLE certificates (
+	id TEXT PRIMARY KEY,
+	user_id INT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+	event_id INT REFERENCES events(id) ON DELETE CASCADE,
+	workshop_id INT REFERENCES workshops(id) ON DELETE CASCADE,
+	recipient_name TEXT NOT NULL,
+	title TEXT NOT NULL,
+	domain_name TEXT NOT NULL,
+	held_on DATE NOT NULL,
+	issued_at TIMESTAMP NOT NULL DEFAULT now(),
+	CHECK ((event_id IS NULL) <> (workshop_id IS NULL))
+);
+
+CREATE UNIQUE INDEX certificates_user_event ON certificates (user_id, event_id) WHERE event_id IS NOT NULL;
+CREATE UNIQUE INDEX certificates_user_workshop ON certificates (user_id, workshop_id) WHERE workshop_id IS NOT NULL;
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE points_ledger;
+DROP TYPE POINT_REASON;
@@ -0,0 +1,28 @@
+-- Your SQL goes here
+CREATE TYPE POINT_REASON AS ENUM (
+	'EVENT_ATTENDANCE',
+	'WORKSHOP_ATTENDANCE',
+	'ATTENDANCE_REVOKED',
+	'TEAM_SHARE',
+	'SPENT'
+);
+
+-- Append only. Balances are the sum of `amount` for a user or team, credits are positive and
+-- debits negative. Team event points land on the team and reach members through TEAM_SHARE
+-- pairs: a debit on the team and a credit on the member, both carrying the event.
+CREATE TABLE points_ledger (
+	id SERIAL PRIMARY KEY,
+	user_id INT REFERENCES users(id) ON DELETE CASCADE,
+	team_id INT REFERENCES teams(id) ON DELETE CASCADE,
+	amount INT NOT NULL,
+	reason POINT_REASON NOT NULL,
+	event_id INT REFERENCES events(id) ON DELETE SET NULL,
+	workshop_id INT REFERENCES workshops(id) ON DELETE SET NULL,
+	domain_id INT REFERENCES domains(id) ON DELETE SET NULL,
+	note TEXT,
+	created_at TIMESTAMP NOT NULL DEFAULT now(),
+	CHECK ((user_id IS NULL) <> (team_id IS NULL))
+);
+
+CREATE INDEX points_ledger_user ON points_ledger (user_id) WHERE user_id IS NOT NULL;
+CREATE INDEX points_ledger_team ON points_ledger (team_id, event_id) WHERE team_id IS NOT NULL;
@@ -0,0 +1,21 @@
+-- Your SQL goes here
+CREATE TYPE PLACEMENT AS ENUM (
+	'FIRST',
+	'SECOND',
+	'THIRD',
+	'SPECIAL_MENTION'
+);
+
+CREATE TABLE event_results (
+	id SERIAL PRIMARY KEY,
+	event_id INT NOT NULL REFERENCES events(id) ON DELETE CASCADE,
+	placement PLACEMENT NOT NULL,
+	user_id INT REFERENCES users(id) ON DELETE CASCADE,
+	team_id INT REFERENCES teams(id) ON DELETE CASCADE,
+	note TEXT,
+	published_by INT REFERENCES users(id) ON DELETE SET NULL,
+	published_at TIMESTAMP NOT NULL DEFAULT now(),
+	CHECK ((user_id IS NULL) <> (team_id IS NULL)),
+	UNIQUE (event_id, user_id),
+	UNIQUE (event_id, team_id)
+);
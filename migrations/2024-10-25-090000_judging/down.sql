@@ -0,0 +1,7 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE judge_scores;
+DROP TABLE judging_criteria;
+DROP TABLE event_judges;
+
+ALTER TABLE events
+  DROP COLUMN judging_finalized_at;
@@ -0,0 +1,35 @@
+-- Your SQL goes here
+-- Set once the scores of an event are final, after which nothing about its judging may change
+ALTER TABLE events
+  ADD COLUMN judging_finalized_at TIMESTAMP;
+
+CREATE TABLE event_judges (
+	event_id INT NOT NULL REFERENCES events(id) ON DELETE CASCADE,
+	user_id INT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+	PRIMARY KEY (event_id, user_id)
+);
+
+CREATE TABLE judging_criteria (
+	id SERIAL PRIMARY KEY,
+	event_id INT NOT NULL REFERENCES events(id) ON DELETE CASCADE,
+	name TEXT NOT NULL,
+	weight DOUBLE PRECISION NOT NULL CHECK (weight > 0),
+	max_marks INT NOT NULL CHECK (max_marks > 0),
+	UNIQUE (event_id, name)
+);
+
+-- One mark per judge, criterion and entrant, where the entrant is a user for individual events
+-- and a team for team events
+CREATE TABLE judge_scores (
+	id SERIAL PRIMARY KEY,
+	criterion_id INT NOT NULL REFERENCES judging_criteria(id) ON DELETE CASCADE,
+	judge_id INT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+	user_id INT REFERENCES users(id) ON DELETE CASCADE,
+	team_id INT REFERENCES teams(id) ON DELETE CASCADE,
+	marks DOUBLE PRECISION NOT NULL CHECK (marks >= 0),
+	submitted_at TIMESTAMP NOT NULL DEFAULT now(),
+	CHECK ((user_id IS NULL) <> (team_id IS NULL))
+);
+
+CREATE UNIQUE INDEX judge_scores_user ON judge_scores (criterion_id, judge_id, user_id) WHERE user_id IS NOT NULL;
+CREATE UNIQUE INDEX judge_scores_team ON judge_scores (criterion_id, judge_id, team_id) WHERE team_id IS NOT NULL;
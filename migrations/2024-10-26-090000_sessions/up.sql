@@ -0,0 +1,18 @@
+-- Your SQL goes here
+-- One row per signed in device. Only a hash of the refresh token is kept, and the previous one
+-- too, so a refresh token that was already rotated away can be recognised when it is replayed.
+CREATE TABLE sessions (
+	id SERIAL PRIMARY KEY,
+	user_id INT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+	token_hash TEXT NOT NULL UNIQUE,
+	previous_token_hash TEXT,
+	user_agent TEXT,
+	ip TEXT,
+	created_at TIMESTAMP NOT NULL DEFAULT now(),
+	last_used_at TIMESTAMP NOT NULL DEFAULT now(),
+	expires_at TIMESTAMP NOT NULL,
+	revoked_at TIMESTAMP
+);
+
+CREATE INDEX sessions_user ON sessions (user_id);
+CREATE INDEX sessions_previous_token ON sessions (previous_token_hash);
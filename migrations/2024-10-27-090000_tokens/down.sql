@@ -0,0 +1,9 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE sessions
+  DROP COLUMN epoch;
+
+ALTER TABLE users
+  DROP COLUMN session_epoch;
+
+DROP TABLE tokens;
+DROP TYPE TOKEN_PURPOSE;
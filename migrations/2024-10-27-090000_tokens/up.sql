@@ -0,0 +1,23 @@
+-- Your SQL goes here
+CREATE TYPE TOKEN_PURPOSE AS ENUM ('EMAIL_VERIFICATION', 'PASSWORD_RESET');
+
+-- Tokens sent out in links. Only their hash is kept, and each one can be redeemed once.
+CREATE TABLE tokens (
+	id SERIAL PRIMARY KEY,
+	user_id INT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+	purpose TOKEN_PURPOSE NOT NULL,
+	token_hash TEXT NOT NULL UNIQUE,
+	created_at TIMESTAMP NOT NULL DEFAULT now(),
+	expires_at TIMESTAMP NOT NULL,
+	used_at TIMESTAMP
+);
+
+CREATE INDEX tokens_user_purpose ON tokens (user_id, purpose);
+
+-- Bumped whenever the password changes. Sessions remember the epoch they were opened in and end
+-- once it moves on.
+ALTER TABLE users
+  ADD COLUMN session_epoch INT NOT NULL DEFAULT 0;
+
+ALTER TABLE sessions
+  ADD COLUMN epoch INT NOT NULL DEFAULT 0;
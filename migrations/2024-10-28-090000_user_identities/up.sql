@@ -0,0 +1,14 @@
+-- Your SQL goes here
+-- Accounts at outside identity providers that can sign in as a user. `subject` is the provider's
+-- stable id for the account, the email there may change.
+CREATE TABLE user_identities (
+	provider TEXT NOT NULL,
+	subject TEXT NOT NULL,
+	user_id INT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+	email TEXT NOT NULL,
+	created_at TIMESTAMP NOT NULL DEFAULT now(),
+	last_sign_in_at TIMESTAMP NOT NULL DEFAULT now(),
+	PRIMARY KEY (provider, subject)
+);
+
+CREATE INDEX user_identities_user ON user_identities (user_id);
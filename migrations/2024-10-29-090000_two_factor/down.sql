@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE two_factor_required_roles;
+DROP TABLE recovery_codes;
+DROP TABLE two_factor;
@@ -0,0 +1,27 @@
+-- Your SQL goes here
+-- TOTP secret of a user. It only counts once `enabled_at` is set, after the first code was
+-- confirmed. `last_used_step` keeps a code from being used twice.
+CREATE TABLE two_factor (
+	user_id INT PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+	secret BYTEA NOT NULL,
+	enabled_at TIMESTAMP,
+	last_used_step BIGINT,
+	created_at TIMESTAMP NOT NULL DEFAULT now()
+);
+
+-- One-time codes for when the authenticator is lost. Only their hash is kept.
+CREATE TABLE recovery_codes (
+	id SERIAL PRIMARY KEY,
+	user_id INT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+	code_hash TEXT NOT NULL UNIQUE,
+	used_at TIMESTAMP
+);
+
+CREATE INDEX recovery_codes_user ON recovery_codes (user_id);
+
+-- Roles whose members may not act on their privileges until they enable two-factor
+CREATE TABLE two_factor_required_roles (
+	role ROLE PRIMARY KEY,
+	required_by INT REFERENCES users(id) ON DELETE SET NULL,
+	created_at TIMESTAMP NOT NULL DEFAULT now()
+);
@@ -0,0 +1,20 @@
+-- Your SQL goes here
+CREATE TYPE MAIL_STATUS AS ENUM ('PENDING', 'SENT', 'FAILED');
+
+-- Mail waiting to go out, or that went out. Handlers only add rows here; a background worker sends
+-- them and retries failures with backoff until it gives up and marks them FAILED.
+CREATE TABLE outbox (
+	id SERIAL PRIMARY KEY,
+	recipient_name TEXT NOT NULL,
+	recipient_email TEXT NOT NULL,
+	subject TEXT NOT NULL,
+	html_body TEXT NOT NULL,
+	status MAIL_STATUS NOT NULL DEFAULT 'PENDING',
+	attempts INT NOT NULL DEFAULT 0,
+	last_error TEXT,
+	next_attempt_at TIMESTAMP NOT NULL DEFAULT now(),
+	created_at TIMESTAMP NOT NULL DEFAULT now(),
+	sent_at TIMESTAMP
+);
+
+CREATE INDEX outbox_due ON outbox (status, next_attempt_at);
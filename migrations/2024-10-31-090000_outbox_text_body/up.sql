@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE outbox ADD COLUMN text_body TEXT;
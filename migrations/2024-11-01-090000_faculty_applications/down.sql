@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE faculty_applications;
+DROP TYPE APPLICATION_STATUS;
@@ -0,0 +1,16 @@
+-- Your SQL goes here
+CREATE TYPE APPLICATION_STATUS AS ENUM ('PENDING', 'APPROVED', 'REJECTED');
+
+-- Faculty sign up as participants, a super admin grants the coordinator role on approval
+CREATE TABLE faculty_applications (
+	user_id INT PRIMARY KEY REFERENCES faculty(user_id) ON DELETE CASCADE,
+	status APPLICATION_STATUS NOT NULL DEFAULT 'PENDING',
+	reason TEXT,
+	reviewed_by INT REFERENCES users(id) ON DELETE SET NULL,
+	reviewed_at TIMESTAMP,
+	created_at TIMESTAMP NOT NULL DEFAULT now()
+);
+
+-- Faculty who signed up before there was a review keep the role they have
+INSERT INTO faculty_applications (user_id, status, reviewed_at)
+SELECT user_id, 'APPROVED', now() FROM faculty;
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+-- Users created through Google sign in have no date of birth or phone until they give them
+ALTER TABLE users ADD COLUMN profile_complete BOOLEAN NOT NULL DEFAULT TRUE;
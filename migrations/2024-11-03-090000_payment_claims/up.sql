@@ -0,0 +1,3 @@
+-- Your SQL goes here
+-- The user the gateway says made a payment that someone else submitted, until a super admin decides
+ALTER TABLE payments ADD COLUMN claimed_by INT REFERENCES users(id) ON DELETE SET NULL;
@@ -1,179 +1,201 @@
-use std::time::{Duration, SystemTime, SystemTimeError};
+use std::{
+    convert::Infallible,
+    net::{IpAddr, SocketAddr},
+    time::{Duration, SystemTime, SystemTimeError},
+};
 
 use argon2::{
     password_hash::{PasswordHash, PasswordVerifier},
     Argon2,
 };
-use axum::{extract::State, Form};
+use axum::{
+    async_trait,
+    extract::{ConnectInfo, FromRequestParts, State},
+    Form, Json,
+};
 use axum_extra::extract::{
     cookie::{Cookie, SameSite},
     CookieJar,
 };
+use diesel::dsl::IntervalDsl;
 use diesel::prelude::*;
-use http::StatusCode;
-use jsonwebtoken::{DecodingKey, EncodingKey, Header};
-use once_cell::sync::Lazy;
+use diesel::result::Error;
+use http::{request::Parts, HeaderMap, StatusCode};
+use jsonwebtoken::{DecodingKey, EncodingKey, Header, Validation};
+use once_cell::sync::OnceCell;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    forms::{faculty::FacultySignUp, student::StudentSignUp, users::SignInForm},
-    models::users::User,
-    schema::{faculty, students, users},
+    error::AppError,
+    faculty::file_application,
+    forms::{
+        faculty::FacultySignUp, sessions::SessionId, student::StudentSignUp, users::SignInForm,
+    },
+    models::{
+        sessions::{NewSession, Session, SessionInfo, ACCESS_TOKEN_TTL, REFRESH_TOKEN_TTL},
+        tokens::{hash_token, new_token, TokenPurpose},
+        users::User,
+    },
+    outbox::{enqueue, queue_mail},
+    schema::{faculty, sessions, students, users},
     state::SiteState,
+    throttle::{too_many_attempts, Key, Scope, SIGN_IN_LOCKOUT},
+    tokens::issue_token,
+    two_factor::{challenge, enabled_factor},
 };
 
 pub async fn sign_in(
     State(state): State<SiteState>,
+    client: Client,
     cookie_jar: CookieJar,
     Form(data): Form<SignInForm>,
-) -> Result<CookieJar, StatusCode> {
+) -> Result<(StatusCode, CookieJar), AppError> {
+    let ip = client.ip.clone();
+    let mut keys = vec![Key::email(&data.email)];
+    keys.extend(ip.clone().map(Key::Ip));
+    state
+        .throttle
+        .check(Scope::SignIn, &keys)
+        .map_err(too_many_attempts)?;
     let argon2 = Argon2::default();
+    let connection = &mut state.connection.get()?;
     let user = users::table
         .select(User::as_select())
         .filter(users::email.eq(data.email))
-        .get_result(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::UNAUTHORIZED
-        })?;
-    let pass = PasswordHash::new(&user.password_hash).map_err(|e| {
-        log::error!("{e:?}");
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?;
-    match argon2.verify_password(data.password.as_bytes(), &pass) {
-        Ok(_) => {
-            let claims: UserClaims = (&user).try_into().map_err(|e| {
-                log::error!("{e:?}");
-                StatusCode::INTERNAL_SERVER_ERROR
-            })?;
-            let cookie: Cookie = (&claims).try_into().map_err(|e| {
-                log::error!("{e:?}");
-                StatusCode::INTERNAL_SERVER_ERROR
-            })?;
-            Ok(cookie_jar.add(cookie))
+        .get_result(connection)
+        .optional()?;
+    let verified = match &user {
+        Some(user) => {
+            let pass = PasswordHash::new(&user.password_hash).map_err(AppError::internal)?;
+            argon2
+                .verify_password(data.password.as_bytes(), &pass)
+                .map_err(|e| log::error!("{e:?}"))
+                .is_ok()
         }
-        Err(e) => {
-            log::error!("{e:?}");
-            Err(StatusCode::UNAUTHORIZED)
+        None => false,
+    };
+    match user {
+        Some(user) if verified => {
+            if enabled_factor(connection, user.id)?.is_some() {
+                // Failures are only forgotten once the code checks out too, so they keep
+                // limiting guesses at it
+                return Ok((StatusCode::ACCEPTED, challenge(cookie_jar, &user)?));
+            }
+            state.throttle.clear(Scope::SignIn, &keys[0]);
+            Ok((
+                StatusCode::OK,
+                start_session(&state, cookie_jar, &client, &user)?,
+            ))
+        }
+        user => {
+            let locked = state.throttle.hit(Scope::SignIn, &keys);
+            if let Some(user) = user.filter(|_| locked.contains(&keys[0])) {
+                log::warn!("Sign in for {} locked after repeated failures", user.email);
+                let ip = ip.unwrap_or_else(|| "an unknown address".to_owned());
+                let mail = user.account_locked_email(
+                    &state.templates,
+                    SIGN_IN_LOCKOUT.as_secs() / 60,
+                    &ip,
+                );
+                if let Err(e) = queue_mail(&state, mail) {
+                    log::error!("{e:?}");
+                }
+            }
+            Err(AppError::unauthorized("Wrong email or password"))
         }
     }
 }
 
 pub async fn student_sign_up(
     State(state): State<SiteState>,
+    client: Client,
     cookie_jar: CookieJar,
     Form(data): Form<StudentSignUp>,
-) -> Result<CookieJar, StatusCode> {
+) -> Result<CookieJar, AppError> {
     if let Some((_, _)) = data.email.trim_ascii().rsplit_once('@') {
         log::info!("{:?} Not from sliet is being registered", data.email);
     } else {
-        return Err(StatusCode::BAD_REQUEST);
+        return Err(AppError::invalid("email", "Not an email address"));
     }
-    let user: User = data.clone().try_into().map_err(|v| {
-        log::error!("{v:?}");
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?;
+    let user: User = data.clone().try_into().map_err(AppError::internal)?;
     let user: User = user
         .insert_into(users::table)
         .returning(User::as_returning())
-        .get_result(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::CONFLICT
-        })?;
+        .get_result(&mut state.connection.get()?)?;
     data.to_student(&user)
         .insert_into(students::table)
-        .execute(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::CONFLICT
-        })?;
-    let claims: UserClaims = (&user).try_into().map_err(|e| {
-        log::error!("{e:?}");
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?;
-    let cookie: Cookie = (&claims).try_into().map_err(|e| {
-        log::error!("{e:?}");
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?;
-    user.send_verification_email(state.mailer, &state.mail_builder)
-        .await
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
+        .execute(&mut state.connection.get()?)?;
+    let cookie_jar = start_session(&state, cookie_jar, &client, &user)?;
+    send_verification(&state, &user)?;
+    Ok(cookie_jar)
+}
+
+/// Queues a mail with a fresh verification link for the user.
+fn send_verification(state: &SiteState, user: &User) -> Result<(), AppError> {
+    state
+        .connection
+        .get()?
+        .transaction::<_, Error, _>(|connection| {
+            let token = issue_token(connection, user.id, TokenPurpose::EMAIL_VERIFICATION)?;
+            enqueue(
+                connection,
+                &[user.verification_email(&state.templates, &token)],
+            )
         })?;
-    Ok(cookie_jar.add(cookie))
+    state.wake_outbox();
+    Ok(())
 }
 
-pub async fn resend_email(State(state): State<SiteState>, user: User) -> Result<(), StatusCode> {
-    user.send_verification_email(state.mailer, &state.mail_builder)
-        .await
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })
+pub async fn resend_email(
+    State(state): State<SiteState>,
+    client: Client,
+    user: User,
+) -> Result<(), AppError> {
+    let mut keys = vec![Key::email(&user.email)];
+    keys.extend(client.ip.clone().map(Key::Ip));
+    state
+        .throttle
+        .check(Scope::Mail, &keys)
+        .map_err(too_many_attempts)?;
+    state.throttle.hit(Scope::Mail, &keys);
+    send_verification(&state, &user)
 }
 
 pub async fn faculty_sign_up(
     State(state): State<SiteState>,
+    client: Client,
     cookie_jar: CookieJar,
     Form(data): Form<FacultySignUp>,
-) -> Result<CookieJar, StatusCode> {
-    let user: User = data.clone().try_into().map_err(|v| {
-        log::error!("{v:?}");
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?;
-    let user: User = user
-        .insert_into(users::table)
-        .returning(User::as_returning())
-        .get_result(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::CONFLICT
-        })?;
-    data.to_faculty(&user)
-        .insert_into(faculty::table)
-        .execute(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::CONFLICT
+) -> Result<CookieJar, AppError> {
+    let user: User = data.clone().try_into().map_err(AppError::internal)?;
+    // A participant until a super admin approves the application, see `crate::faculty`
+    let user: User = state
+        .connection
+        .get()?
+        .transaction::<_, Error, _>(|connection| {
+            let user: User = user
+                .insert_into(users::table)
+                .returning(User::as_returning())
+                .get_result(connection)?;
+            data.to_faculty(&user)
+                .insert_into(faculty::table)
+                .execute(connection)?;
+            file_application(connection, user.id)?;
+            Ok(user)
         })?;
-    let claims: UserClaims = (&user).try_into().map_err(|e| {
-        log::error!("{e:?}");
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?;
-    let cookie: Cookie = (&claims).try_into().map_err(|e| {
-        log::error!("{e:?}");
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?;
-    user.send_verification_email(state.mailer, &state.mail_builder)
-        .await
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?;
-    Ok(cookie_jar.add(cookie))
+    let cookie_jar = start_session(&state, cookie_jar, &client, &user)?;
+    send_verification(&state, &user)?;
+    Ok(cookie_jar)
 }
 
+pub const ACCESS_COOKIE: &str = "jwt-token";
+pub const REFRESH_COOKIE: &str = "refresh-token";
+
 #[derive(Serialize, Deserialize)]
 pub struct UserClaims {
     pub id: i32,
-    pub hash: String,
+    /// Session the token belongs to, checked on every request so revoking it takes effect at once
+    pub sid: i32,
     pub exp: u64,
 }
 
@@ -192,22 +214,28 @@ impl Keys {
     }
 }
 
-pub static KEYS: Lazy<Keys> = Lazy::new(|| {
-    let secret = std::env::var("JWT_SECRET")
-        .expect("JWT_SECRET Environment Vaiable not set, it must be set.");
-    Keys::new(secret.as_bytes())
-});
+static KEYS: OnceCell<Keys> = OnceCell::new();
 
-impl TryFrom<&User> for UserClaims {
-    type Error = SystemTimeError;
+/// Sets the secret [`keys`] come from, once at startup from `auth.jwt_secret`. Cookies are signed
+/// where there is no [`SiteState`] at hand, so the keys live here instead.
+pub fn init_keys(secret: &str) {
+    if KEYS.set(Keys::new(secret.as_bytes())).is_err() {
+        log::warn!("JWT keys were already set, keeping the first ones");
+    }
+}
 
-    fn try_from(value: &User) -> Result<Self, Self::Error> {
+pub fn keys() -> &'static Keys {
+    KEYS.get()
+        .expect("JWT keys are set from the config when the server starts")
+}
+
+impl UserClaims {
+    pub fn new(user: &User, sid: i32) -> Result<Self, SystemTimeError> {
         Ok(UserClaims {
-            id: value.id,
-            hash: value.password_hash.clone(),
-            exp: (SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?
-                + Duration::from_secs(60 * 60 * 24))
-            .as_secs(),
+            id: user.id,
+            sid,
+            exp: (SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)? + ACCESS_TOKEN_TTL)
+                .as_secs(),
         })
     }
 }
@@ -216,12 +244,12 @@ impl<'a: 'static> TryInto<Cookie<'a>> for &UserClaims {
     type Error = jsonwebtoken::errors::Error;
 
     fn try_into(self) -> Result<Cookie<'a>, Self::Error> {
-        jsonwebtoken::encode(&Header::default(), self, &KEYS.encoding).map(|s| {
-            Cookie::build(("jwt-token", s))
+        jsonwebtoken::encode(&Header::default(), self, &keys().encoding).map(|s| {
+            Cookie::build((ACCESS_COOKIE, s))
                 .http_only(true)
                 .secure(true)
                 .same_site(SameSite::None)
-                .max_age(Duration::from_secs(60 * 60 * 12).try_into().unwrap())
+                .max_age(ACCESS_TOKEN_TTL.try_into().unwrap())
                 .partitioned(true)
                 .path("/")
                 .build()
@@ -229,15 +257,330 @@ impl<'a: 'static> TryInto<Cookie<'a>> for &UserClaims {
     }
 }
 
-pub async fn logout(cookie_jar: CookieJar) -> CookieJar {
-    cookie_jar.add(
-        Cookie::build(("jwt-token", "a"))
-            .http_only(true)
-            .secure(true)
-            .same_site(SameSite::None)
-            .max_age(Duration::from_secs(60 * 60 * 12).try_into().unwrap())
-            .partitioned(true)
-            .path("/")
-            .build(),
+/// The refresh token is only ever needed by `/auth/refresh` and `/auth/logout`, so it is not
+/// sent along with every other request.
+fn refresh_cookie(token: String) -> Cookie<'static> {
+    Cookie::build((REFRESH_COOKIE, token))
+        .http_only(true)
+        .secure(true)
+        .same_site(SameSite::None)
+        .max_age(REFRESH_TOKEN_TTL.try_into().unwrap())
+        .partitioned(true)
+        .path("/auth")
+        .build()
+}
+
+fn clear_cookies(cookie_jar: CookieJar) -> CookieJar {
+    [(ACCESS_COOKIE, "/"), (REFRESH_COOKIE, "/auth")]
+        .into_iter()
+        .fold(cookie_jar, |cookie_jar, (name, path)| {
+            cookie_jar.add(
+                Cookie::build((name, ""))
+                    .http_only(true)
+                    .secure(true)
+                    .same_site(SameSite::None)
+                    .max_age(Duration::ZERO.try_into().unwrap())
+                    .partitioned(true)
+                    .path(path)
+                    .build(),
+            )
+        })
+}
+
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+}
+
+/// Who sent a request, as recorded with their sessions and counted by the throttle.
+pub struct Client {
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+/// Address of the client. Anyone can send the forwarding headers, so they are only believed when
+/// the peer is one of the trusted reverse proxies, and then only the last address it appended to
+/// `X-Forwarded-For`; earlier ones come from the client. Otherwise the client is the peer itself.
+pub fn client_ip(
+    headers: &HeaderMap,
+    peer: Option<IpAddr>,
+    trusted_proxies: &[IpAddr],
+) -> Option<String> {
+    let peer = peer.map(|peer| peer.to_canonical());
+    match peer {
+        Some(proxy) if trusted_proxies.contains(&proxy) => header(headers, "x-forwarded-for")
+            .and_then(|v| v.rsplit(',').next())
+            .or_else(|| header(headers, "x-real-ip"))
+            .map(|v| v.trim().to_owned())
+            .filter(|v| !v.is_empty())
+            .or_else(|| Some(proxy.to_string())),
+        peer => peer.map(|peer| peer.to_string()),
+    }
+}
+
+#[async_trait]
+impl FromRequestParts<SiteState> for Client {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &SiteState,
+    ) -> Result<Self, Self::Rejection> {
+        let peer = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(address)| address.ip());
+        Ok(Client {
+            ip: client_ip(&parts.headers, peer, &state.config.server.trusted_proxies),
+            user_agent: header(&parts.headers, "user-agent").map(|v| v.chars().take(512).collect()),
+        })
+    }
+}
+
+/// Opens a new session for the user and adds its access and refresh cookies.
+pub fn start_session(
+    state: &SiteState,
+    cookie_jar: CookieJar,
+    client: &Client,
+    user: &User,
+) -> Result<CookieJar, AppError> {
+    let token = new_token();
+    let sid: i32 = diesel::insert_into(sessions::table)
+        .values((
+            NewSession {
+                user_id: user.id,
+                token_hash: hash_token(&token),
+                user_agent: client.user_agent.clone(),
+                ip: client.ip.clone(),
+            },
+            sessions::expires_at
+                .eq(diesel::dsl::now + (REFRESH_TOKEN_TTL.as_secs() as i64).seconds()),
+            sessions::epoch.eq(users::table
+                .select(users::session_epoch)
+                .filter(users::id.eq(user.id))
+                .single_value()
+                .assume_not_null()),
+        ))
+        .returning(sessions::id)
+        .get_result(&mut state.connection.get()?)
+        .map_err(AppError::internal)?;
+    let claims = UserClaims::new(user, sid).map_err(AppError::internal)?;
+    let cookie: Cookie = (&claims).try_into().map_err(AppError::internal)?;
+    Ok(cookie_jar.add(cookie).add(refresh_cookie(token)))
+}
+
+/// Session of the request's access token. The token may have expired, its signature is all that
+/// matters to know which session it came from.
+pub fn current_session_id(cookie_jar: &CookieJar) -> Option<i32> {
+    let mut validation = Validation::new(jsonwebtoken::Algorithm::HS256);
+    validation.validate_exp = false;
+    jsonwebtoken::decode::<UserClaims>(
+        cookie_jar.get(ACCESS_COOKIE)?.value(),
+        &keys().decoding,
+        &validation,
     )
+    .ok()
+    .map(|token| token.claims.sid)
+}
+
+/// Swaps the refresh token for a new one and issues a new access token.
+pub async fn refresh(
+    State(state): State<SiteState>,
+    client: Client,
+    cookie_jar: CookieJar,
+) -> Result<CookieJar, AppError> {
+    let token_hash = hash_token(
+        cookie_jar
+            .get(REFRESH_COOKIE)
+            .ok_or_else(|| AppError::unauthorized("Not signed in"))?
+            .value(),
+    );
+    let token = new_token();
+    let ip = client.ip;
+    let refreshed = state
+        .connection
+        .get()?
+        .transaction::<_, Error, _>(|connection| {
+            let session: Option<(i32, i32)> = sessions::table
+                .inner_join(users::table)
+                .select((sessions::id, sessions::user_id))
+                .filter(sessions::token_hash.eq(&token_hash))
+                .filter(sessions::revoked_at.is_null())
+                .filter(sessions::epoch.eq(users::session_epoch))
+                .filter(sessions::expires_at.gt(diesel::dsl::now))
+                .for_update()
+                .get_result(connection)
+                .optional()?;
+            let Some((sid, user_id)) = session else {
+                // A refresh token that was already swapped is used again, so someone else holds a
+                // copy of it. Ending the session signs out both them and the owner.
+                diesel::update(sessions::table)
+                    .filter(sessions::previous_token_hash.eq(&token_hash))
+                    .filter(sessions::revoked_at.is_null())
+                    .set(sessions::revoked_at.eq(diesel::dsl::now.nullable()))
+                    .execute(connection)?;
+                return Ok(None);
+            };
+            diesel::update(sessions::table)
+                .filter(sessions::id.eq(sid))
+                .set((
+                    sessions::token_hash.eq(hash_token(&token)),
+                    sessions::previous_token_hash.eq(&token_hash),
+                    sessions::ip.eq(ip),
+                    sessions::last_used_at.eq(diesel::dsl::now),
+                ))
+                .execute(connection)?;
+            let user: User = users::table
+                .select(User::as_select())
+                .filter(users::id.eq(user_id))
+                .get_result(connection)?;
+            Ok(Some((sid, user)))
+        })?;
+    let Some((sid, user)) = refreshed else {
+        return Err(AppError::unauthorized(
+            "Session expired or signed out, sign in again",
+        ));
+    };
+    let claims = UserClaims::new(&user, sid).map_err(AppError::internal)?;
+    let cookie: Cookie = (&claims).try_into().map_err(AppError::internal)?;
+    Ok(cookie_jar.add(cookie).add(refresh_cookie(token)))
+}
+
+/// Ends the session of this device and clears its cookies.
+pub async fn logout(State(state): State<SiteState>, cookie_jar: CookieJar) -> CookieJar {
+    let token_hash = cookie_jar
+        .get(REFRESH_COOKIE)
+        .map(|c| hash_token(c.value()));
+    let sid = current_session_id(&cookie_jar);
+    if token_hash.is_some() || sid.is_some() {
+        let revoked =
+            state
+                .connection
+                .get()
+                .map_err(|e| e.to_string())
+                .and_then(|mut connection| {
+                    diesel::update(sessions::table)
+                        .filter(
+                            sessions::token_hash
+                                .nullable()
+                                .eq(token_hash)
+                                .or(sessions::id.nullable().eq(sid)),
+                        )
+                        .filter(sessions::revoked_at.is_null())
+                        .set(sessions::revoked_at.eq(diesel::dsl::now.nullable()))
+                        .execute(&mut connection)
+                        .map_err(|e| e.to_string())
+                });
+        if let Err(e) = revoked {
+            log::error!("{e:?}");
+        }
+    }
+    clear_cookies(cookie_jar)
+}
+
+/// Ends every session of the user, on all devices.
+pub async fn logout_all(
+    State(state): State<SiteState>,
+    user: User,
+    cookie_jar: CookieJar,
+) -> Result<CookieJar, AppError> {
+    diesel::update(sessions::table)
+        .filter(sessions::user_id.eq(user.id))
+        .filter(sessions::revoked_at.is_null())
+        .set(sessions::revoked_at.eq(diesel::dsl::now.nullable()))
+        .execute(&mut state.connection.get()?)?;
+    Ok(clear_cookies(cookie_jar))
+}
+
+/// Devices the user is signed in on, most recently used first.
+pub async fn get_sessions(
+    State(state): State<SiteState>,
+    user: User,
+    cookie_jar: CookieJar,
+) -> Result<Json<Vec<SessionInfo>>, AppError> {
+    let current = current_session_id(&cookie_jar);
+    let sessions: Vec<Session> = sessions::table
+        .select(Session::as_select())
+        .filter(sessions::user_id.eq(user.id))
+        .filter(sessions::revoked_at.is_null())
+        .filter(sessions::expires_at.gt(diesel::dsl::now))
+        .order(sessions::last_used_at.desc())
+        .load(&mut state.connection.get()?)?;
+    Ok(Json(
+        sessions
+            .into_iter()
+            .map(|session| SessionInfo::new(session, current))
+            .collect(),
+    ))
+}
+
+/// Signs one of the user's devices out.
+pub async fn revoke_session(
+    State(state): State<SiteState>,
+    user: User,
+    Form(data): Form<SessionId>,
+) -> Result<(), AppError> {
+    let revoked = diesel::update(sessions::table)
+        .filter(sessions::id.eq(data.id))
+        .filter(sessions::user_id.eq(user.id))
+        .filter(sessions::revoked_at.is_null())
+        .set(sessions::revoked_at.eq(diesel::dsl::now.nullable()))
+        .execute(&mut state.connection.get()?)?;
+    if revoked == 0 {
+        return Err(AppError::not_found("No such session"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn forwarded(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", value.parse().unwrap());
+        headers.insert("x-real-ip", "198.51.100.9".parse().unwrap());
+        headers
+    }
+
+    fn ip(address: &str) -> IpAddr {
+        address.parse().unwrap()
+    }
+
+    #[test]
+    fn ignores_forwarding_headers_without_a_trusted_proxy() {
+        let headers = forwarded("203.0.113.7");
+        assert_eq!(
+            client_ip(&headers, Some(ip("192.0.2.1")), &[]).as_deref(),
+            Some("192.0.2.1")
+        );
+        assert_eq!(
+            client_ip(&headers, Some(ip("192.0.2.1")), &[ip("127.0.0.1")]).as_deref(),
+            Some("192.0.2.1")
+        );
+        assert_eq!(client_ip(&headers, None, &[ip("127.0.0.1")]), None);
+    }
+
+    #[test]
+    fn believes_the_last_address_a_trusted_proxy_added() {
+        let proxies = [ip("127.0.0.1")];
+        let proxy = Some(ip("::ffff:127.0.0.1"));
+        assert_eq!(
+            client_ip(&forwarded("10.1.1.1, 203.0.113.7"), proxy, &proxies).as_deref(),
+            Some("203.0.113.7")
+        );
+        let mut headers = forwarded("203.0.113.7");
+        headers.remove("x-forwarded-for");
+        assert_eq!(
+            client_ip(&headers, proxy, &proxies).as_deref(),
+            Some("198.51.100.9")
+        );
+        assert_eq!(
+            client_ip(&HeaderMap::new(), proxy, &proxies).as_deref(),
+            Some("127.0.0.1")
+        );
+    }
 }
@@ -0,0 +1,190 @@
+//! Manages the server from a shell, without prompting, so it also works in a container.
+//! Reads the same `.env` and configuration as the server, and every argument can also be given
+//! through the environment variable shown in `--help`.
+//!
+//! ```sh
+//! ADMIN_PASSWORD=... admin create-admin --name "Jane Doe" --email jane@sliet.ac.in \
+//!     --phone 9876543210 --dob 1990-01-31
+//! admin promote --email john@sliet.ac.in --role FACULTY_COORDINATOR
+//! ```
+use anyhow::{anyhow, Context};
+use clap::{Parser, Subcommand};
+use diesel::prelude::*;
+use dotenvy::dotenv;
+use sliet_techfest_backend::{
+    config::Config,
+    models::{
+        tokens::TokenPurpose,
+        users::{hash_password, Role, User},
+    },
+    outbox::enqueue,
+    roles::change_role,
+    schema::users,
+    state::{connection_pool, run_migrations},
+    templates::MailTemplates,
+    tokens::issue_token,
+};
+
+#[derive(Parser)]
+#[command(about = "Manage the techFEST backend from the command line")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Create a verified super admin
+    CreateAdmin {
+        #[arg(long, env = "ADMIN_NAME")]
+        name: String,
+        #[arg(long, env = "ADMIN_EMAIL")]
+        email: String,
+        #[arg(long, env = "ADMIN_PHONE")]
+        phone: String,
+        /// Date of birth, like 1990-01-31
+        #[arg(long, env = "ADMIN_DOB")]
+        dob: chrono::NaiveDate,
+        /// At least 10 characters. Prefer the environment variable, so it stays out of the history
+        #[arg(long, env = "ADMIN_PASSWORD", hide_env_values = true)]
+        password: String,
+    },
+    /// Set the role of a user, dropping the coordinator posts the new role can not use
+    Promote {
+        #[arg(long, env = "USER_EMAIL")]
+        email: String,
+        /// SUPER_ADMIN, FACULTY_COORDINATOR, STUDENT_COORDINATOR or PARTICIPANT
+        #[arg(long, value_parser = parse_role)]
+        role: Role,
+    },
+    /// Set a new password and sign the user out everywhere
+    ResetPassword {
+        #[arg(long, env = "USER_EMAIL")]
+        email: String,
+        /// At least 10 characters. Prefer the environment variable, so it stays out of the history
+        #[arg(long, env = "USER_PASSWORD", hide_env_values = true)]
+        password: String,
+    },
+    /// Mark the email of a user as verified
+    VerifyUser {
+        #[arg(long, env = "USER_EMAIL")]
+        email: String,
+    },
+    /// Queue a new verification mail, the running server sends it
+    ResendVerification {
+        #[arg(long, env = "USER_EMAIL")]
+        email: String,
+    },
+    /// Apply the pending database migrations
+    RunMigrations,
+}
+
+fn parse_role(role: &str) -> Result<Role, String> {
+    Role::VARIANTS
+        .into_iter()
+        .find(|variant| format!("{variant:?}").eq_ignore_ascii_case(role))
+        .ok_or_else(|| format!("{role:?} is not one of {:?}", Role::VARIANTS))
+}
+
+fn check_password(password: &str) -> anyhow::Result<()> {
+    if password.chars().count() < 10 {
+        return Err(anyhow!("The password needs at least 10 characters"));
+    }
+    Ok(())
+}
+
+fn find_user(connection: &mut PgConnection, email: &str) -> anyhow::Result<User> {
+    users::table
+        .select(User::as_select())
+        .filter(users::email.eq(email))
+        .get_result(connection)
+        .optional()?
+        .ok_or_else(|| anyhow!("No user with the email {email}"))
+}
+
+fn main() -> anyhow::Result<()> {
+    let _ = dotenv();
+    pretty_env_logger::init();
+    let cli = Cli::parse();
+    let config = Config::load()?;
+    let pool = connection_pool(&config.database).context("Connecting to the database")?;
+    let connection = &mut pool.get()?;
+    match cli.command {
+        Command::CreateAdmin {
+            name,
+            email,
+            phone,
+            dob,
+            password,
+        } => {
+            check_password(&password)?;
+            let admin = User {
+                id: 0,
+                name,
+                dob,
+                email,
+                phone,
+                role: Role::SUPER_ADMIN,
+                photo_hash: None,
+                verified: true,
+                password_hash: hash_password(&password)
+                    .map_err(|e| anyhow!("Hashing the password: {e}"))?,
+            };
+            let admin: User = admin
+                .insert_into(users::table)
+                .returning(User::as_returning())
+                .get_result(connection)
+                .context("Creating the user, is the email already taken?")?;
+            println!("Created super admin {} with id {}", admin.email, admin.id);
+        }
+        Command::Promote { email, role } => {
+            let user = find_user(connection, &email)?;
+            change_role(connection, user.id, &role).map_err(|e| anyhow!(e.message().to_owned()))?;
+            println!("{} is now a {role}", user.email);
+        }
+        Command::ResetPassword { email, password } => {
+            check_password(&password)?;
+            let user = find_user(connection, &email)?;
+            let password_hash =
+                hash_password(&password).map_err(|e| anyhow!("Hashing the password: {e}"))?;
+            diesel::update(users::table)
+                .filter(users::id.eq(user.id))
+                .set((
+                    users::password_hash.eq(password_hash),
+                    users::session_epoch.eq(users::session_epoch + 1),
+                ))
+                .execute(connection)?;
+            println!("Password of {email} reset, every session is signed out");
+        }
+        Command::VerifyUser { email } => {
+            let user = find_user(connection, &email)?;
+            diesel::update(users::table)
+                .filter(users::id.eq(user.id))
+                .set(users::verified.eq(true))
+                .execute(connection)?;
+            println!("{} is verified", user.email);
+        }
+        Command::ResendVerification { email } => {
+            let user = find_user(connection, &email)?;
+            if user.verified {
+                return Err(anyhow!("{} is already verified", user.email));
+            }
+            let templates = MailTemplates::from_config(&config)?;
+            connection.transaction::<_, diesel::result::Error, _>(|connection| {
+                let token = issue_token(connection, user.id, TokenPurpose::EMAIL_VERIFICATION)?;
+                enqueue(connection, &[user.verification_email(&templates, &token)])
+            })?;
+            println!("Verification mail for {} queued", user.email);
+        }
+        Command::RunMigrations => {
+            let applied = run_migrations(connection)?;
+            if applied.is_empty() {
+                println!("Already up to date");
+            }
+            for version in applied {
+                println!("Applied {version}");
+            }
+        }
+    }
+    Ok(())
+}
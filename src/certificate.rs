@@ -0,0 +1,392 @@
+//! Participation certificates for everything a user actually attended.
+//!
+//! Certificates are issued the first time a user lists them, one per attended event or workshop,
+//! and rendered to PDF on download from the template in `certificate.template` (a JSON
+//! [`CertificateTemplate`], the bundled `certificate_template.json` when unset). Text is set in the
+//! standard Helvetica fonts every PDF reader ships, so no font files have to be deployed.
+use anyhow::Context;
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use http::{header, HeaderMap};
+use pdf_writer::{Content, Finish, Name, Pdf, Rect, Ref, Str, TextStr};
+
+use crate::{
+    config::CertificateConfig,
+    error::AppError,
+    forms::certificates::CertificateId,
+    models::{
+        certificates::{
+            new_certificate_id, Certificate, CertificateTemplate, CertificateVerification,
+            NewCertificate,
+        },
+        points::Attendance,
+        users::{Role, User},
+    },
+    schema::{
+        certificates, domains, events, individual_event_participation, team_event_participations,
+        team_members, workshop_participation, workshops,
+    },
+    state::SiteState,
+};
+
+static DEFAULT_TEMPLATE: &str = include_str!("models/certificate_template.json");
+
+/// The layout in `certificate.template`, or the bundled one. Read once at startup, so a broken
+/// file stops the server instead of the first download.
+pub fn load_template(certificate: &CertificateConfig) -> anyhow::Result<CertificateTemplate> {
+    Ok(match &certificate.template {
+        Some(path) => {
+            let source = std::fs::read_to_string(path)
+                .with_context(|| format!("Reading {}", path.display()))?;
+            serde_json::from_str(&source)
+                .with_context(|| format!("In the certificate template {}", path.display()))?
+        }
+        None => {
+            serde_json::from_str(DEFAULT_TEMPLATE).context("In the bundled certificate template")?
+        }
+    })
+}
+
+/// Advance widths of the printable ASCII range in Helvetica, from the standard AFM metrics.
+const HELVETICA_WIDTHS: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556, 556,
+    556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556, 1015, 667, 667, 722, 722, 667,
+    611, 778, 722, 278, 500, 667, 556, 833, 722, 778, 667, 778, 722, 667, 611, 722, 667, 944, 667,
+    667, 611, 278, 278, 278, 469, 556, 333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500,
+    222, 833, 556, 556, 556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584,
+];
+
+/// Same as [`HELVETICA_WIDTHS`] for Helvetica-Bold.
+const HELVETICA_BOLD_WIDTHS: [u16; 95] = [
+    278, 333, 474, 556, 556, 889, 722, 238, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556, 556,
+    556, 556, 556, 556, 556, 556, 556, 333, 333, 584, 584, 584, 611, 975, 722, 722, 722, 722, 667,
+    611, 778, 722, 278, 556, 722, 611, 833, 722, 778, 667, 778, 722, 667, 611, 722, 667, 944, 667,
+    667, 611, 333, 278, 333, 584, 556, 333, 556, 611, 556, 611, 556, 333, 611, 611, 278, 278, 556,
+    278, 889, 611, 611, 611, 611, 389, 556, 333, 611, 556, 778, 556, 556, 500, 389, 280, 389, 584,
+];
+
+const REGULAR_FONT: Name = Name(b"F1");
+const BOLD_FONT: Name = Name(b"F2");
+
+/// WinAnsi bytes of `text`. The standard fonts have no glyphs outside Latin-1.
+fn win_ansi(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|c| match u32::from(c) {
+            32..=126 | 160..=255 => c as u8,
+            _ => b'?',
+        })
+        .collect()
+}
+
+fn text_width(text: &[u8], bold: bool, size: f32) -> f32 {
+    let widths = if bold {
+        &HELVETICA_BOLD_WIDTHS
+    } else {
+        &HELVETICA_WIDTHS
+    };
+    let units: u32 = text
+        .iter()
+        .map(|&b| match b {
+            32..=126 => u32::from(widths[usize::from(b - 32)]),
+            _ => 556,
+        })
+        .sum();
+    units as f32 * size / 1000.0
+}
+
+fn render_certificate(
+    certificate: &Certificate,
+    template: &CertificateTemplate,
+    verification_url: &str,
+) -> Vec<u8> {
+    let catalog_id = Ref::new(1);
+    let page_tree_id = Ref::new(2);
+    let page_id = Ref::new(3);
+    let regular_font_id = Ref::new(4);
+    let bold_font_id = Ref::new(5);
+    let content_id = Ref::new(6);
+    let info_id = Ref::new(7);
+
+    let mut pdf = Pdf::new();
+    pdf.catalog(catalog_id).pages(page_tree_id);
+    pdf.pages(page_tree_id).kids([page_id]).count(1);
+    let mut page = pdf.page(page_id);
+    page.media_box(Rect::new(0.0, 0.0, template.width, template.height));
+    page.parent(page_tree_id);
+    page.contents(content_id);
+    page.resources()
+        .fonts()
+        .pair(REGULAR_FONT, regular_font_id)
+        .pair(BOLD_FONT, bold_font_id);
+    page.finish();
+    pdf.type1_font(regular_font_id)
+        .base_font(Name(b"Helvetica"))
+        .encoding_predefined(Name(b"WinAnsiEncoding"));
+    pdf.type1_font(bold_font_id)
+        .base_font(Name(b"Helvetica-Bold"))
+        .encoding_predefined(Name(b"WinAnsiEncoding"));
+    pdf.document_info(info_id)
+        .title(TextStr(&format!("Certificate {}", certificate.id)));
+
+    let date = certificate.held_on.format("%-d %B %Y").to_string();
+    let verification_url = format!("{verification_url}?id={}", certificate.id);
+    let mut content = Content::new();
+    if let Some(inset) = template.border {
+        content
+            .set_line_width(2.0)
+            .rect(
+                inset,
+                inset,
+                template.width - 2.0 * inset,
+                template.height - 2.0 * inset,
+            )
+            .stroke();
+    }
+    for text in &template.texts {
+        let filled = text
+            .text
+            .replace("{name}", &certificate.recipient_name)
+            .replace("{title}", &certificate.title)
+            .replace("{domain}", &certificate.domain_name)
+            .replace("{date}", &date)
+            .replace("{certificate_id}", &certificate.id)
+            .replace("{verification_url}", &verification_url);
+        let bytes = win_ansi(&filled);
+        let x = text
+            .x
+            .unwrap_or_else(|| (template.width - text_width(&bytes, text.bold, text.size)) / 2.0);
+        let [r, g, b] = text.color;
+        content
+            .begin_text()
+            .set_fill_rgb(r, g, b)
+            .set_font(if text.bold { BOLD_FONT } else { REGULAR_FONT }, text.size)
+            .next_line(x, text.y)
+            .show(Str(&bytes))
+            .end_text();
+    }
+    pdf.stream(content_id, &content.finish());
+    pdf.finish()
+}
+
+/// Issues the certificates `user` is missing for attended events, team events and workshops.
+fn issue_certificates(connection: &mut PgConnection, user: &User) -> QueryResult<usize> {
+    let individual: Vec<(i32, String, String, NaiveDateTime)> =
+        individual_event_participation::table
+            .inner_join(events::table.inner_join(domains::table))
+            .select((events::id, events::name, domains::name, events::start_time))
+            .filter(individual_event_participation::user_id.eq(user.id))
+            .filter(individual_event_participation::attended.eq(true))
+            .load(connection)?;
+    let team: Vec<(i32, String, String, NaiveDateTime)> = team_members::table
+        .inner_join(
+            team_event_participations::table
+                .on(team_event_participations::team_id.eq(team_members::team_id)),
+        )
+        .inner_join(events::table.on(events::id.eq(team_event_participations::event_id)))
+        .inner_join(domains::table.on(domains::id.eq(events::domain_id)))
+        .select((events::id, events::name, domains::name, events::start_time))
+        .filter(team_members::student_id.eq(user.id))
+        .filter(team_event_participations::attended.eq(true))
+        .load(connection)?;
+    let workshop: Vec<(i32, String, String, NaiveDateTime)> = workshop_participation::table
+        .inner_join(workshops::table.inner_join(domains::table))
+        .select((
+            workshops::id,
+            workshops::name,
+            domains::name,
+            workshops::start_time,
+        ))
+        .filter(workshop_participation::user_id.eq(user.id))
+        .filter(workshop_participation::attended.eq(true))
+        .load(connection)?;
+
+    let new_certificate =
+        |event_id, workshop_id, title, domain_name, start_time: NaiveDateTime| NewCertificate {
+            id: new_certificate_id(),
+            user_id: user.id,
+            event_id,
+            workshop_id,
+            recipient_name: user.name.clone(),
+            title,
+            domain_name,
+            held_on: start_time.date(),
+        };
+    let new_certificates: Vec<NewCertificate> = individual
+        .into_iter()
+        .chain(team)
+        .map(|(id, title, domain_name, start_time)| {
+            new_certificate(Some(id), None, title, domain_name, start_time)
+        })
+        .chain(
+            workshop
+                .into_iter()
+                .map(|(id, title, domain_name, start_time)| {
+                    new_certificate(None, Some(id), title, domain_name, start_time)
+                }),
+        )
+        .collect();
+    // Already issued ones hit the unique indexes and keep their original id
+    diesel::insert_into(certificates::table)
+        .values(&new_certificates)
+        .on_conflict_do_nothing()
+        .execute(connection)
+}
+
+/// Withdraws the certificates of a registration whose attendance was taken back, so they can no
+/// longer be downloaded or verified. Runs in the transaction that clears `attended`.
+pub fn revoke_certificates(
+    connection: &mut PgConnection,
+    attendance: Attendance,
+) -> QueryResult<usize> {
+    match attendance {
+        Attendance::Event { event_id, user_id } => diesel::delete(certificates::table)
+            .filter(certificates::event_id.eq(event_id))
+            .filter(certificates::user_id.eq(user_id))
+            .execute(connection),
+        Attendance::TeamEvent { event_id, team_id } => diesel::delete(certificates::table)
+            .filter(certificates::event_id.eq(event_id))
+            .filter(
+                certificates::user_id.eq_any(
+                    team_members::table
+                        .select(team_members::student_id)
+                        .filter(team_members::team_id.eq(team_id)),
+                ),
+            )
+            .execute(connection),
+        Attendance::Workshop {
+            workshop_id,
+            user_id,
+        } => diesel::delete(certificates::table)
+            .filter(certificates::workshop_id.eq(workshop_id))
+            .filter(certificates::user_id.eq(user_id))
+            .execute(connection),
+    }
+}
+
+/// The caller's certificates, issuing any that are due first.
+pub async fn get_certificates(
+    State(state): State<SiteState>,
+    user: User,
+) -> Result<Json<Vec<Certificate>>, AppError> {
+    if !user.verified {
+        return Err(AppError::forbidden("Verify your email first"));
+    }
+    let certificates = state.connection.get()?.transaction(|connection| {
+        issue_certificates(connection, &user)?;
+        certificates::table
+            .select(Certificate::as_select())
+            .filter(certificates::user_id.eq(user.id))
+            .order((certificates::held_on, certificates::title))
+            .load(connection)
+    })?;
+    Ok(Json(certificates))
+}
+
+/// PDF of one of the caller's certificates. Super admins may download anyone's.
+pub async fn download_certificate(
+    State(state): State<SiteState>,
+    user: User,
+    Query(data): Query<CertificateId>,
+) -> Result<(HeaderMap, Vec<u8>), AppError> {
+    if !user.verified {
+        return Err(AppError::forbidden("Verify your email first"));
+    }
+    let certificate: Certificate = certificates::table
+        .select(Certificate::as_select())
+        .filter(certificates::id.eq(data.id.trim().to_ascii_lowercase()))
+        .get_result(&mut state.connection.get()?)
+        .optional()?
+        // Someone else's certificate is answered the same as one that does not exist
+        .filter(|certificate: &Certificate| {
+            certificate.user_id == user.id || matches!(user.role, Role::SUPER_ADMIN)
+        })
+        .ok_or_else(|| AppError::not_found("No such certificate"))?;
+
+    let mut header_map = HeaderMap::new();
+    header_map.insert(
+        header::CONTENT_TYPE,
+        "application/pdf"
+            .parse()
+            .expect("Parsing \"application/pdf\" should have been fine."),
+    );
+    header_map.insert(
+        header::CONTENT_DISPOSITION,
+        format!(
+            "attachment; filename=\"certificate-{}.pdf\"",
+            certificate.id
+        )
+        .parse()
+        .map_err(AppError::internal)?,
+    );
+    let verification_url = state
+        .config
+        .certificate
+        .verification_url(&state.config.site);
+    Ok((
+        header_map,
+        render_certificate(&certificate, &state.certificate_template, &verification_url),
+    ))
+}
+
+/// Public check that a certificate id was really issued, and to whom for what.
+pub async fn verify_certificate(
+    State(state): State<SiteState>,
+    Query(data): Query<CertificateId>,
+) -> Result<Json<CertificateVerification>, AppError> {
+    certificates::table
+        .select(CertificateVerification::as_select())
+        .filter(certificates::id.eq(data.id.trim().to_ascii_lowercase()))
+        .get_result(&mut state.connection.get()?)
+        .optional()?
+        .map(Json)
+        .ok_or_else(|| AppError::not_found("No such certificate"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Config, SiteConfig};
+
+    fn certificate() -> Certificate {
+        Certificate {
+            id: "3fa9c0de".to_owned(),
+            user_id: 1,
+            event_id: Some(1),
+            workshop_id: None,
+            recipient_name: "Jane Doe".to_owned(),
+            title: "Robowars".to_owned(),
+            domain_name: "Robotics".to_owned(),
+            held_on: chrono::NaiveDate::from_ymd_opt(2024, 11, 6).unwrap(),
+            issued_at: NaiveDateTime::default(),
+        }
+    }
+
+    fn contains(haystack: &[u8], needle: &str) -> bool {
+        haystack
+            .windows(needle.len())
+            .any(|window| window == needle.as_bytes())
+    }
+
+    #[test]
+    fn prints_the_verification_link_of_the_deployment() {
+        let config = Config {
+            site: SiteConfig {
+                url: "https://staging.techfestsliet.org".to_owned(),
+                ..SiteConfig::default()
+            },
+            ..Config::default()
+        };
+        let template = load_template(&config.certificate).unwrap();
+        let verification_url = config.certificate.verification_url(&config.site);
+        let pdf = render_certificate(&certificate(), &template, &verification_url);
+        assert!(contains(
+            &pdf,
+            "https://staging.techfestsliet.org/api/certificate/verify?id=3fa9c0de"
+        ));
+        assert!(!contains(&pdf, "https://techfestsliet.org"));
+    }
+}
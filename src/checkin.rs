@@ -0,0 +1,203 @@
+use std::io::Cursor;
+
+use axum::{
+    extract::{Query, State},
+    Form, Json,
+};
+use diesel::dsl::IntervalDsl;
+use diesel::prelude::*;
+use diesel::result::Error;
+use http::{header, HeaderMap, StatusCode};
+use jsonwebtoken::{Header, Validation};
+use qrcode::{render::svg, QrCode};
+
+use crate::{
+    auth::keys,
+    error::AppError,
+    forms::{
+        checkin::{CheckInQuery, QrFormat, ScanCheckIn},
+        users::Profile,
+    },
+    models::{
+        checkin::{CheckInClaims, CheckInResponse, CheckInTarget, CHECKIN_AUDIENCE},
+        points::Attendance,
+        users::User,
+    },
+    points::set_attendance,
+    policy::{authorize, Action},
+    schema::{
+        individual_event_participation, team_event_participations, team_members,
+        used_checkin_tokens, users, workshop_participation,
+    },
+    state::SiteState,
+};
+
+/// QR code for the caller's own registration, holding a signed token that expires after
+/// `CHECKIN_TOKEN_TTL`. Only participants that are actually registered get one.
+pub async fn get_checkin_qr(
+    State(state): State<SiteState>,
+    user: User,
+    Query(data): Query<CheckInQuery>,
+) -> Result<(HeaderMap, Vec<u8>), AppError> {
+    if !user.verified {
+        return Err(AppError::forbidden("Verify your email first"));
+    }
+    let target = data.target().ok_or_else(|| {
+        AppError::new(
+            StatusCode::BAD_REQUEST,
+            "Give an event, a team and event, or a workshop",
+        )
+    })?;
+    let connection = &mut state.connection.get()?;
+    let registered: bool = match target {
+        CheckInTarget::Event { event_id } => diesel::select(diesel::dsl::exists(
+            individual_event_participation::table
+                .filter(individual_event_participation::event_id.eq(event_id))
+                .filter(individual_event_participation::user_id.eq(user.id)),
+        ))
+        .get_result(connection),
+        CheckInTarget::Team { event_id, team_id } => diesel::select(
+            diesel::dsl::exists(
+                team_members::table
+                    .filter(team_members::team_id.eq(team_id))
+                    .filter(team_members::student_id.eq(user.id)),
+            )
+            .and(diesel::dsl::exists(
+                team_event_participations::table
+                    .filter(team_event_participations::team_id.eq(team_id))
+                    .filter(team_event_participations::event_id.eq(event_id)),
+            )),
+        )
+        .get_result(connection),
+        CheckInTarget::Workshop { workshop_id } => diesel::select(diesel::dsl::exists(
+            workshop_participation::table
+                .filter(workshop_participation::workshop_id.eq(workshop_id))
+                .filter(workshop_participation::user_id.eq(user.id)),
+        ))
+        .get_result(connection),
+    }?;
+    if !registered {
+        return Err(AppError::not_found("You are not registered for this"));
+    }
+
+    let claims = CheckInClaims::new(user.id, target).map_err(AppError::internal)?;
+    let token = jsonwebtoken::encode(&Header::default(), &claims, &keys().encoding)
+        .map_err(AppError::internal)?;
+    let code = QrCode::new(token.as_bytes()).map_err(AppError::internal)?;
+
+    let mut header_map = HeaderMap::new();
+    header_map.insert(
+        header::CACHE_CONTROL,
+        "no-store"
+            .parse()
+            .expect("Parsing \"no-store\" should have been fine."),
+    );
+    let body = match data.format {
+        QrFormat::Svg => {
+            header_map.insert(
+                header::CONTENT_TYPE,
+                "image/svg+xml"
+                    .parse()
+                    .expect("Parsing \"image/svg+xml\" should have been fine."),
+            );
+            code.render::<svg::Color>()
+                .min_dimensions(256, 256)
+                .build()
+                .into_bytes()
+        }
+        QrFormat::Png => {
+            header_map.insert(
+                header::CONTENT_TYPE,
+                "image/png"
+                    .parse()
+                    .expect("Parsing \"image/png\" should have been fine."),
+            );
+            let mut png = Vec::new();
+            code.render::<image::Luma<u8>>()
+                .min_dimensions(256, 256)
+                .build()
+                .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+                .map_err(AppError::internal)?;
+            png
+        }
+    };
+    Ok((header_map, body))
+}
+
+/// Marks the registration in a scanned check-in code as attended. Forged or expired codes are
+/// `UNAUTHORIZED`, codes that were already scanned once are `CONFLICT`.
+pub async fn scan_checkin(
+    State(state): State<SiteState>,
+    user: User,
+    Form(data): Form<ScanCheckIn>,
+) -> Result<Json<CheckInResponse>, AppError> {
+    let mut validation = Validation::new(jsonwebtoken::Algorithm::HS256);
+    validation.set_audience(&[CHECKIN_AUDIENCE]);
+    let claims =
+        jsonwebtoken::decode::<CheckInClaims>(data.token.trim(), &keys().decoding, &validation)
+            .map_err(|e| {
+                log::warn!("Rejected check-in token: {e}");
+                AppError::unauthorized("The check-in code is forged or expired")
+            })?
+            .claims;
+    let action = match claims.target {
+        CheckInTarget::Event { event_id } | CheckInTarget::Team { event_id, .. } => {
+            Action::MarkEventAttendance { event_id }
+        }
+        CheckInTarget::Workshop { workshop_id } => Action::MarkWorkshopAttendance { workshop_id },
+    };
+    authorize(&state, &user, action)?;
+
+    let profile = state
+        .connection
+        .get()?
+        .transaction::<_, Error, _>(|connection| {
+            // Tokens expire long before this, the rows only have to outlive them
+            diesel::delete(used_checkin_tokens::table)
+                .filter(used_checkin_tokens::used_at.lt(diesel::dsl::now - 1.day()))
+                .execute(connection)?;
+            let inserted = diesel::insert_into(used_checkin_tokens::table)
+                .values(used_checkin_tokens::jti.eq(&claims.jti))
+                .on_conflict_do_nothing()
+                .execute(connection)?;
+            if inserted == 0 {
+                return Ok(None);
+            }
+            let attendance = match claims.target {
+                CheckInTarget::Event { event_id } => Attendance::Event {
+                    event_id,
+                    user_id: claims.user_id,
+                },
+                CheckInTarget::Team { event_id, team_id } => {
+                    Attendance::TeamEvent { event_id, team_id }
+                }
+                CheckInTarget::Workshop { workshop_id } => Attendance::Workshop {
+                    workshop_id,
+                    user_id: claims.user_id,
+                },
+            };
+            if set_attendance(connection, attendance, true)?.is_none() {
+                // Left the event after the code was issued, keep the token unused
+                return Err(Error::NotFound);
+            }
+            users::table
+                .select(Profile::as_select())
+                .filter(users::id.eq(claims.user_id))
+                .get_result(connection)
+                .map(Some)
+        })
+        .map_err(|e| match e {
+            Error::NotFound => AppError::not_found("The registration no longer exists"),
+            e => AppError::from(e),
+        })?;
+    match profile {
+        Some(profile) => Ok(Json(CheckInResponse {
+            target: claims.target,
+            profile,
+        })),
+        None => {
+            log::warn!("Check-in token {} was replayed", claims.jti);
+            Err(AppError::conflict("This check-in code was already used"))
+        }
+    }
+}
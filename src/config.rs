@@ -0,0 +1,694 @@
+//! Server configuration. Read from the TOML file in `CONFIG_FILE`, or `config.toml` when that
+//! exists, then overridden by the environment, so a deployment can keep secrets out of the file.
+//! Everything is checked once at startup, and the server refuses to start with a bad value
+//! instead of failing on the first request that needs it. See `config.example.toml` for every
+//! setting and the environment variable that overrides it.
+use std::{
+    env,
+    fmt::Display,
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{anyhow, Context};
+use http::HeaderValue;
+use reqwest::Url;
+use serde::{de::DeserializeOwned, de::IntoDeserializer, Deserialize};
+
+use crate::mail::SmtpTls;
+
+/// Read when `CONFIG_FILE` is not set, if it exists.
+pub const DEFAULT_PATH: &str = "config.toml";
+
+#[derive(Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub database: DatabaseConfig,
+    pub auth: AuthConfig,
+    pub site: SiteConfig,
+    pub fest: FestConfig,
+    pub mail: MailConfig,
+    pub oidc: OidcConfig,
+    pub payment: PaymentConfig,
+    pub certificate: CertificateConfig,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// `BIND_ADDRESS`
+    pub bind: SocketAddr,
+    /// Origins allowed to call with credentials, `CORS_ORIGINS` separated by commas. Only the
+    /// site itself when empty.
+    pub cors_origins: Vec<String>,
+    /// Where uploaded images are kept, `IMAGE_URL`
+    pub image_dir: PathBuf,
+    /// Reverse proxies whose `X-Forwarded-For` and `X-Real-IP` are believed, `TRUSTED_PROXIES`
+    /// separated by commas. When empty the peer address is the client's.
+    pub trusted_proxies: Vec<IpAddr>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind: SocketAddr::from(([0, 0, 0, 0], 3000)),
+            cors_origins: Vec::new(),
+            image_dir: "images/".into(),
+            trusted_proxies: Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    /// `DATABASE_URL`, required
+    pub url: String,
+    /// `DATABASE_MAX_CONNECTIONS`
+    pub max_connections: u32,
+    /// Connections kept open while idle, `DATABASE_MIN_IDLE`. As many as `max_connections` when
+    /// not set.
+    pub min_idle: Option<u32>,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            max_connections: 10,
+            min_idle: None,
+        }
+    }
+}
+
+#[derive(Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    /// Signs every session and sign in cookie, `JWT_SECRET`, required
+    pub jwt_secret: String,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SiteConfig {
+    /// The frontend, `SITE_URL`, or `FRONTEND_URL` as before
+    pub url: String,
+    /// Where this server is reachable, `API_URL`. The site with `/api` when not set.
+    pub api_url: Option<String>,
+    /// Where a verified email link lands, `VERIFIED_REDIRECT`. The site when not set.
+    pub verified_redirect: Option<String>,
+}
+
+impl Default for SiteConfig {
+    fn default() -> Self {
+        Self {
+            url: "https://techfestsliet.org".to_owned(),
+            api_url: None,
+            verified_redirect: None,
+        }
+    }
+}
+
+impl SiteConfig {
+    /// The site without a trailing `/`.
+    pub fn url(&self) -> &str {
+        self.url.trim_end_matches('/')
+    }
+
+    pub fn api_url(&self) -> String {
+        match &self.api_url {
+            Some(url) => url.trim_end_matches('/').to_owned(),
+            None => format!("{}/api", self.url()),
+        }
+    }
+
+    pub fn verified_redirect(&self) -> String {
+        self.verified_redirect
+            .clone()
+            .unwrap_or_else(|| format!("{}/", self.url()))
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FestConfig {
+    /// `FEST_NAME`
+    pub name: String,
+    /// `FEST_YEAR`
+    pub year: u16,
+    /// `FEST_TAGLINE`
+    pub tagline: String,
+}
+
+impl Default for FestConfig {
+    fn default() -> Self {
+        Self {
+            name: "techFEST".to_owned(),
+            year: 2024,
+            tagline: "Crafting Tomorrow's Reality".to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MailTransport {
+    /// Send through the SMTP server
+    #[default]
+    Smtp,
+    /// Write `.eml` files into `mail.dir`
+    File,
+    /// Keep mail in memory
+    Memory,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MailConfig {
+    /// `MAIL_TRANSPORT`
+    pub transport: MailTransport,
+    /// Where the `file` transport writes, `MAIL_DIR`
+    pub dir: PathBuf,
+    /// Templates used instead of the built in ones, `MAIL_TEMPLATE_DIR`
+    pub template_dir: Option<PathBuf>,
+    /// `MAIL_FROM_NAME`
+    pub from_name: String,
+    /// `MAIL_FROM`
+    pub from_email: String,
+    pub smtp: SmtpConfig,
+}
+
+impl Default for MailConfig {
+    fn default() -> Self {
+        Self {
+            transport: MailTransport::Smtp,
+            dir: "mail/".into(),
+            template_dir: None,
+            from_name: "Techfest".to_owned(),
+            from_email: "techfest@sliet.ac.in".to_owned(),
+            smtp: SmtpConfig::default(),
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SmtpConfig {
+    /// `SMTP_HOST`
+    pub host: String,
+    /// `SMTP_PORT`. 465 for implicit TLS and 587 otherwise when not set.
+    pub port: Option<u16>,
+    /// `SMTP_TLS`
+    pub tls: SmtpTls,
+    /// `SMTP_USERNAME`, or `GOOGLE_CLIENT_ID` as before
+    pub username: Option<String>,
+    /// `SMTP_PASSWORD`, or `GOOGLE_SECRET` as before
+    pub password: Option<String>,
+}
+
+impl Default for SmtpConfig {
+    fn default() -> Self {
+        Self {
+            host: "smtp.gmail.com".to_owned(),
+            port: None,
+            tls: SmtpTls::StartTls,
+            username: None,
+            password: None,
+        }
+    }
+}
+
+impl SmtpConfig {
+    pub fn port(&self) -> u16 {
+        match (self.port, self.tls) {
+            (Some(port), _) => port,
+            (None, SmtpTls::Implicit) => 465,
+            (None, _) => 587,
+        }
+    }
+}
+
+/// Google sign in, see [`crate::oidc`]. Off unless the client id, secret and redirect url are all
+/// set.
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OidcConfig {
+    /// `OIDC_CLIENT_ID`
+    pub client_id: Option<String>,
+    /// `OIDC_CLIENT_SECRET`
+    pub client_secret: Option<String>,
+    /// The public url of `/auth/google/callback`, `OIDC_REDIRECT_URL`
+    pub redirect_url: Option<String>,
+    /// `OIDC_ISSUER`
+    pub issuer: String,
+    /// Email domains whose accounts are verified as soon as they sign in, `OIDC_TRUSTED_DOMAINS`
+    /// separated by commas
+    pub trusted_domains: Vec<String>,
+}
+
+impl Default for OidcConfig {
+    fn default() -> Self {
+        Self {
+            client_id: None,
+            client_secret: None,
+            redirect_url: None,
+            issuer: "https://accounts.google.com".to_owned(),
+            trusted_domains: vec!["sliet.ac.in".to_owned()],
+        }
+    }
+}
+
+impl OidcConfig {
+    pub fn enabled(&self) -> bool {
+        self.client_id.is_some() && self.client_secret.is_some() && self.redirect_url.is_some()
+    }
+}
+
+#[derive(Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PaymentConfig {
+    /// The secret the gateway signs its webhooks with, `PAYMENT_WEBHOOK_SECRET`. The webhook is
+    /// refused when not set.
+    pub webhook_secret: Option<String>,
+}
+
+#[derive(Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CertificateConfig {
+    /// The JSON layout, `CERTIFICATE_TEMPLATE`. The bundled one when not set.
+    pub template: Option<PathBuf>,
+    /// The link printed on certificates, `CERTIFICATE_VERIFICATION_URL`. The verify route under
+    /// `site.api_url` when not set.
+    pub verification_url: Option<String>,
+}
+
+impl CertificateConfig {
+    pub fn verification_url(&self, site: &SiteConfig) -> String {
+        match &self.verification_url {
+            Some(url) => url.clone(),
+            None => format!("{}/certificate/verify", site.api_url()),
+        }
+    }
+}
+
+/// Replaces `value` with the variable `name` when it is set.
+fn override_parsed<T>(value: &mut T, name: &str) -> anyhow::Result<()>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    if let Ok(set) = env::var(name) {
+        *value = set.trim().parse().map_err(|e| anyhow!("{name}: {e}"))?;
+    }
+    Ok(())
+}
+
+/// [`override_parsed`] for the lowercase names of an enum, as written in the file.
+fn override_named<T: DeserializeOwned>(value: &mut T, name: &str) -> anyhow::Result<()> {
+    if let Ok(set) = env::var(name) {
+        *value = T::deserialize(set.trim().into_deserializer())
+            .map_err(|e: serde::de::value::Error| anyhow!("{name}: {e}"))?;
+    }
+    Ok(())
+}
+
+fn override_optional(value: &mut Option<String>, names: &[&str]) {
+    if let Some(set) = names.iter().find_map(|name| env::var(name).ok()) {
+        *value = Some(set);
+    }
+}
+
+impl Config {
+    /// Reads the file, applies the environment and checks the result.
+    pub fn load() -> anyhow::Result<Self> {
+        let mut config = match env::var_os("CONFIG_FILE") {
+            Some(path) => Self::from_file(Path::new(&path))?,
+            None if Path::new(DEFAULT_PATH).is_file() => Self::from_file(Path::new(DEFAULT_PATH))?,
+            None => Self::default(),
+        };
+        config.apply_env()?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+        log::info!("Reading the configuration from {}", path.display());
+        let source =
+            std::fs::read_to_string(path).with_context(|| format!("Reading {}", path.display()))?;
+        toml::from_str(&source).with_context(|| format!("In {}", path.display()))
+    }
+
+    fn apply_env(&mut self) -> anyhow::Result<()> {
+        override_parsed(&mut self.server.bind, "BIND_ADDRESS")?;
+        if let Ok(origins) = env::var("CORS_ORIGINS") {
+            self.server.cors_origins = origins
+                .split(',')
+                .map(str::trim)
+                .filter(|origin| !origin.is_empty())
+                .map(str::to_owned)
+                .collect();
+        }
+        override_parsed(&mut self.server.image_dir, "IMAGE_URL")?;
+        if let Ok(proxies) = env::var("TRUSTED_PROXIES") {
+            self.server.trusted_proxies = proxies
+                .split(',')
+                .map(str::trim)
+                .filter(|proxy| !proxy.is_empty())
+                .map(|proxy| {
+                    proxy
+                        .parse()
+                        .with_context(|| format!("TRUSTED_PROXIES: {proxy}"))
+                })
+                .collect::<anyhow::Result<_>>()?;
+        }
+
+        override_parsed(&mut self.database.url, "DATABASE_URL")?;
+        override_parsed(
+            &mut self.database.max_connections,
+            "DATABASE_MAX_CONNECTIONS",
+        )?;
+        if let Ok(min_idle) = env::var("DATABASE_MIN_IDLE") {
+            self.database.min_idle = Some(min_idle.trim().parse().context("DATABASE_MIN_IDLE")?);
+        }
+
+        override_parsed(&mut self.auth.jwt_secret, "JWT_SECRET")?;
+
+        // `FRONTEND_URL` named the site before there was a config file
+        override_parsed(&mut self.site.url, "FRONTEND_URL")?;
+        override_parsed(&mut self.site.url, "SITE_URL")?;
+        override_optional(&mut self.site.api_url, &["API_URL"]);
+        override_optional(&mut self.site.verified_redirect, &["VERIFIED_REDIRECT"]);
+
+        override_parsed(&mut self.fest.name, "FEST_NAME")?;
+        override_parsed(&mut self.fest.year, "FEST_YEAR")?;
+        override_parsed(&mut self.fest.tagline, "FEST_TAGLINE")?;
+
+        override_named(&mut self.mail.transport, "MAIL_TRANSPORT")?;
+        override_parsed(&mut self.mail.dir, "MAIL_DIR")?;
+        if let Some(dir) = env::var_os("MAIL_TEMPLATE_DIR") {
+            self.mail.template_dir = Some(dir.into());
+        }
+        override_parsed(&mut self.mail.from_name, "MAIL_FROM_NAME")?;
+        override_parsed(&mut self.mail.from_email, "MAIL_FROM")?;
+        override_parsed(&mut self.mail.smtp.host, "SMTP_HOST")?;
+        if let Ok(port) = env::var("SMTP_PORT") {
+            self.mail.smtp.port = Some(port.trim().parse().context("SMTP_PORT")?);
+        }
+        override_named(&mut self.mail.smtp.tls, "SMTP_TLS")?;
+        // The Gmail credentials used before these were configurable still work
+        override_optional(
+            &mut self.mail.smtp.username,
+            &["SMTP_USERNAME", "GOOGLE_CLIENT_ID"],
+        );
+        override_optional(
+            &mut self.mail.smtp.password,
+            &["SMTP_PASSWORD", "GOOGLE_SECRET"],
+        );
+
+        override_optional(&mut self.oidc.client_id, &["OIDC_CLIENT_ID"]);
+        override_optional(&mut self.oidc.client_secret, &["OIDC_CLIENT_SECRET"]);
+        override_optional(&mut self.oidc.redirect_url, &["OIDC_REDIRECT_URL"]);
+        override_parsed(&mut self.oidc.issuer, "OIDC_ISSUER")?;
+        if let Ok(domains) = env::var("OIDC_TRUSTED_DOMAINS") {
+            self.oidc.trusted_domains = domains.split(',').map(str::to_owned).collect();
+        }
+
+        override_optional(
+            &mut self.payment.webhook_secret,
+            &["PAYMENT_WEBHOOK_SECRET"],
+        );
+
+        if let Some(template) = env::var_os("CERTIFICATE_TEMPLATE") {
+            self.certificate.template = Some(template.into());
+        }
+        override_optional(
+            &mut self.certificate.verification_url,
+            &["CERTIFICATE_VERIFICATION_URL"],
+        );
+        Ok(())
+    }
+
+    fn validate(&mut self) -> anyhow::Result<()> {
+        if self.database.url.trim().is_empty() {
+            return Err(anyhow!("Set database.url or DATABASE_URL"));
+        }
+        if self.database.max_connections == 0 {
+            return Err(anyhow!("database.max_connections must be at least 1"));
+        }
+        if self
+            .database
+            .min_idle
+            .is_some_and(|min_idle| min_idle > self.database.max_connections)
+        {
+            return Err(anyhow!(
+                "database.min_idle can not be more than database.max_connections"
+            ));
+        }
+
+        if self.auth.jwt_secret.is_empty() {
+            return Err(anyhow!("Set auth.jwt_secret or JWT_SECRET"));
+        }
+        if self.auth.jwt_secret.len() < 32 {
+            log::warn!("auth.jwt_secret is shorter than 32 bytes, use a longer random secret");
+        }
+
+        for (name, url) in [
+            ("site.url", Some(self.site.url.clone())),
+            ("site.api_url", self.site.api_url.clone()),
+            (
+                "site.verified_redirect",
+                self.site.verified_redirect.clone(),
+            ),
+        ] {
+            if let Some(url) = url {
+                Url::parse(&url).with_context(|| format!("{name} {url:?} is not a URL"))?;
+            }
+        }
+
+        // Browsers send the origin without a trailing `/`, and it has to match exactly
+        if self.server.cors_origins.is_empty() {
+            self.server.cors_origins.push(self.site.url().to_owned());
+        }
+        for origin in &mut self.server.cors_origins {
+            *origin = origin.trim_end_matches('/').to_owned();
+            Url::parse(origin)
+                .ok()
+                .filter(|url| url.path() == "/" && url.query().is_none())
+                .filter(|_| HeaderValue::from_str(origin).is_ok())
+                .ok_or_else(|| anyhow!("server.cors_origins has {origin:?}, not an origin"))?;
+        }
+
+        // A proxy on IPv4 reaches a dual stack listener as an IPv4-mapped IPv6 address
+        for proxy in &mut self.server.trusted_proxies {
+            *proxy = proxy.to_canonical();
+        }
+
+        if self.fest.name.trim().is_empty() {
+            return Err(anyhow!("fest.name can not be empty"));
+        }
+        if self.mail.from_email.trim().is_empty() {
+            return Err(anyhow!("mail.from_email can not be empty"));
+        }
+        if self.mail.transport == MailTransport::Smtp && self.mail.smtp.host.trim().is_empty() {
+            return Err(anyhow!("mail.smtp.host can not be empty"));
+        }
+
+        let oidc = &mut self.oidc;
+        for value in [
+            &mut oidc.client_id,
+            &mut oidc.client_secret,
+            &mut oidc.redirect_url,
+        ] {
+            // An empty variable reads as not set, as it did before there was a config file
+            if value.as_deref().is_some_and(|v| v.trim().is_empty()) {
+                *value = None;
+            }
+        }
+        match [&oidc.client_id, &oidc.client_secret, &oidc.redirect_url]
+            .iter()
+            .filter(|value| value.is_some())
+            .count()
+        {
+            0 => log::info!("OIDC client settings are not set, Google sign in is off"),
+            3 => {}
+            _ => {
+                return Err(anyhow!(
+                    "Set all of oidc.client_id, oidc.client_secret and oidc.redirect_url, or none"
+                ))
+            }
+        }
+        for (name, url) in [
+            ("oidc.issuer", Some(&oidc.issuer)),
+            ("oidc.redirect_url", oidc.redirect_url.as_ref()),
+            (
+                "certificate.verification_url",
+                self.certificate.verification_url.as_ref(),
+            ),
+        ] {
+            if let Some(url) = url {
+                Url::parse(url).with_context(|| format!("{name} {url:?} is not a URL"))?;
+            }
+        }
+        oidc.trusted_domains = oidc
+            .trusted_domains
+            .iter()
+            .map(|domain| domain.trim().trim_start_matches('@').to_lowercase())
+            .filter(|domain| !domain.is_empty())
+            .collect();
+
+        let webhook_secret = &mut self.payment.webhook_secret;
+        if webhook_secret
+            .as_deref()
+            .is_some_and(|v| v.trim().is_empty())
+        {
+            *webhook_secret = None;
+        }
+        if webhook_secret.is_none() {
+            log::info!("payment.webhook_secret is not set, the payment webhook is off");
+        }
+
+        if let Some(template) = &self.certificate.template {
+            if !template.is_file() {
+                return Err(anyhow!(
+                    "certificate.template {} is not a file",
+                    template.display()
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// The allowed origins as header values, checked in [`Config::load`].
+    pub fn cors_origins(&self) -> Vec<HeaderValue> {
+        self.server
+            .cors_origins
+            .iter()
+            .map(|origin| {
+                origin
+                    .parse()
+                    .expect("Origins are checked when the config is loaded")
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A config with only what is required, as read from `source` without the environment.
+    fn parse(source: &str) -> anyhow::Result<Config> {
+        let mut config: Config = toml::from_str(&format!(
+            "[database]\nurl = \"postgres://localhost/techfest\"\n\
+             [auth]\njwt_secret = \"0123456789abcdef0123456789abcdef\"\n{source}"
+        ))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn error_of(source: &str) -> String {
+        format!("{:#}", parse(source).err().expect("An invalid config"))
+    }
+
+    #[test]
+    fn accepts_the_defaults() {
+        let config = parse("").unwrap();
+        assert_eq!(config.server.cors_origins, ["https://techfestsliet.org"]);
+        assert!(!config.oidc.enabled());
+        assert_eq!(config.payment.webhook_secret, None);
+        assert_eq!(
+            config.certificate.verification_url(&config.site),
+            "https://techfestsliet.org/api/certificate/verify"
+        );
+    }
+
+    #[test]
+    fn requires_the_database_and_secret() {
+        let mut config = Config::default();
+        assert!(config.validate().is_err());
+        config.database.url = "postgres://localhost/techfest".to_owned();
+        assert!(config
+            .validate()
+            .unwrap_err()
+            .to_string()
+            .contains("auth.jwt_secret"));
+    }
+
+    #[test]
+    fn refuses_unknown_settings() {
+        assert!(error_of("[payment]\nsecret = \"x\"").contains("unknown field"));
+    }
+
+    #[test]
+    fn refuses_bad_urls_and_origins() {
+        assert!(error_of("[site]\nurl = \"techfestsliet\"").contains("site.url"));
+        assert!(
+            error_of("[server]\ncors_origins = [\"https://a.org/path\"]").contains("not an origin")
+        );
+        assert!(error_of("[oidc]\nissuer = \"accounts\"").contains("oidc.issuer"));
+        assert!(error_of("[certificate]\nverification_url = \"/verify\"")
+            .contains("certificate.verification_url"));
+    }
+
+    #[test]
+    fn needs_every_oidc_client_setting_or_none() {
+        assert!(
+            error_of("[oidc]\nclient_id = \"id\"\nclient_secret = \"secret\"")
+                .contains("oidc.redirect_url")
+        );
+        let config = parse(
+            "[oidc]\nclient_id = \"id\"\nclient_secret = \"secret\"\n\
+             redirect_url = \"https://techfestsliet.org/api/auth/google/callback\"\n\
+             trusted_domains = [\" SLIET.ac.in \", \"@nitj.ac.in\", \"\"]",
+        )
+        .unwrap();
+        assert!(config.oidc.enabled());
+        assert_eq!(config.oidc.trusted_domains, ["sliet.ac.in", "nitj.ac.in"]);
+        // Empty reads as not set
+        assert!(!parse("[oidc]\nclient_id = \"\"").unwrap().oidc.enabled());
+    }
+
+    #[test]
+    fn an_empty_webhook_secret_turns_the_webhook_off() {
+        let config = parse("[payment]\nwebhook_secret = \" \"").unwrap();
+        assert_eq!(config.payment.webhook_secret, None);
+        let config = parse("[payment]\nwebhook_secret = \"secret\"").unwrap();
+        assert_eq!(config.payment.webhook_secret.as_deref(), Some("secret"));
+    }
+
+    #[test]
+    fn builds_the_verification_url_from_the_api_url() {
+        let config = parse("[site]\napi_url = \"https://api.techfestsliet.org/\"").unwrap();
+        assert_eq!(
+            config.certificate.verification_url(&config.site),
+            "https://api.techfestsliet.org/certificate/verify"
+        );
+        let config =
+            parse("[certificate]\nverification_url = \"https://verify.example.org\"").unwrap();
+        assert_eq!(
+            config.certificate.verification_url(&config.site),
+            "https://verify.example.org"
+        );
+    }
+
+    #[test]
+    fn needs_the_certificate_template_to_exist() {
+        assert!(
+            error_of("[certificate]\ntemplate = \"no/such/template.json\"").contains("not a file")
+        );
+        let config =
+            parse("[certificate]\ntemplate = \"src/models/certificate_template.json\"").unwrap();
+        assert!(crate::certificate::load_template(&config.certificate).is_ok());
+    }
+
+    #[test]
+    fn compares_proxies_as_canonical_addresses() {
+        let config = parse("[server]\ntrusted_proxies = [\"::ffff:10.0.0.2\"]").unwrap();
+        assert_eq!(
+            config.server.trusted_proxies,
+            ["10.0.0.2".parse::<IpAddr>().unwrap()]
+        );
+    }
+}
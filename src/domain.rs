@@ -10,10 +10,11 @@ use axum_macros::debug_handler;
 use base64::{prelude::BASE64_URL_SAFE_NO_PAD, Engine};
 use diesel::prelude::*;
 use highway::HighwayHash;
-use http::{header, HeaderMap, StatusCode};
+use http::{header, HeaderMap};
 use tokio_util::io::ReaderStream;
 
 use crate::{
+    error::AppError,
     forms::{
         domains::{
             AddDomainFacultyCoordinator, AddDomainStudentCoordinator, ChangeDomain, CreateDomain,
@@ -25,48 +26,37 @@ use crate::{
         domains::Domain,
         faculty::{Faculty, FacultyResponse},
         students::{Student, StudentResponse},
-        users::{Role, User},
+        users::User,
     },
+    policy::{authorize, Action},
+    roles::{appoint, dismiss, Appointment},
     schema::{
         domains, faculty, faculty_coordinators, student_domain_coordinators, students, users,
     },
     state::SiteState,
 };
 
-pub async fn get_domain(State(state): State<SiteState>) -> Result<Json<Vec<Domain>>, StatusCode> {
-    domains::table
-        .select(Domain::as_select())
-        .get_results(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map(|v| Json(v))
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })
+pub async fn get_domain(State(state): State<SiteState>) -> Result<Json<Vec<Domain>>, AppError> {
+    let connection = &mut state.connection.get()?;
+    Ok(Json(
+        domains::table
+            .select(Domain::as_select())
+            .get_results(connection)?,
+    ))
 }
 
 pub async fn create_domain(
     State(state): State<SiteState>,
     user: User,
     Form(data): Form<CreateDomain>,
-) -> Result<Json<Domain>, StatusCode> {
-    match user.role {
-        Role::SUPER_ADMIN => {}
-        _ => return Err(StatusCode::UNAUTHORIZED),
-    }
-    data.insert_into(domains::table)
-        .returning(Domain::as_returning())
-        .get_result(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map(|v| Json(v))
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })
+) -> Result<Json<Domain>, AppError> {
+    authorize(&state, &user, Action::ManageDomain)?;
+    let connection = &mut state.connection.get()?;
+    Ok(Json(
+        data.insert_into(domains::table)
+            .returning(Domain::as_returning())
+            .get_result(connection)?,
+    ))
 }
 
 #[debug_handler]
@@ -74,47 +64,31 @@ pub async fn delete_domain(
     State(state): State<SiteState>,
     user: User,
     Form(data): Form<DeleteDomain>,
-) -> Result<Json<Domain>, StatusCode> {
-    match user.role {
-        Role::SUPER_ADMIN => {}
-        _ => return Err(StatusCode::UNAUTHORIZED),
-    }
-    diesel::delete(domains::table)
-        .filter(domains::id.eq(data.id))
-        .returning(Domain::as_returning())
-        .get_result(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map(|v| Json(v))
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })
+) -> Result<Json<Domain>, AppError> {
+    authorize(&state, &user, Action::ManageDomain)?;
+    let connection = &mut state.connection.get()?;
+    Ok(Json(
+        diesel::delete(domains::table)
+            .filter(domains::id.eq(data.id))
+            .returning(Domain::as_returning())
+            .get_result(connection)?,
+    ))
 }
 
 pub async fn change_domain(
     State(state): State<SiteState>,
     user: User,
     Form(data): Form<ChangeDomain>,
-) -> Result<Json<Domain>, StatusCode> {
-    match user.role {
-        Role::SUPER_ADMIN => {}
-        _ => return Err(StatusCode::UNAUTHORIZED),
-    }
-    diesel::update(domains::table)
-        .filter(domains::id.eq(data.id))
-        .set(data)
-        .returning(Domain::as_returning())
-        .get_result(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map(|v| Json(v))
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })
+) -> Result<Json<Domain>, AppError> {
+    authorize(&state, &user, Action::ManageDomain)?;
+    let connection = &mut state.connection.get()?;
+    Ok(Json(
+        diesel::update(domains::table)
+            .filter(domains::id.eq(data.id))
+            .set(data)
+            .returning(Domain::as_returning())
+            .get_result(connection)?,
+    ))
 }
 
 // Not Deleting the image in case some other user also happens to have the same exact image
@@ -123,26 +97,14 @@ pub async fn set_domain_photo(
     data: Query<DomainId>,
     user: User,
     photo: Bytes,
-) -> Result<(), StatusCode> {
-    match user.role {
-        Role::SUPER_ADMIN => {}
-        _ => return Err(StatusCode::UNAUTHORIZED),
-    }
-    if !user.verified || !user.is_payment_done(&state.connection) {
-        return Err(StatusCode::UNAUTHORIZED);
-    }
+) -> Result<(), AppError> {
+    authorize(&state, &user, Action::ManageDomain)?;
     let hash = state.bulk_hasher.hash256(photo.to_vec().as_slice());
     let photo = image::ImageReader::new(Cursor::new(photo))
         .with_guessed_format()
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::BAD_REQUEST
-        })?
+        .map_err(AppError::internal)?
         .decode()
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::BAD_REQUEST
-        })?;
+        .map_err(|e| AppError::invalid("photo", format!("Not a readable image: {e}")))?;
     let photo = photo.thumbnail(512, 512);
     let dir = format!(
         "{}/{}",
@@ -157,11 +119,8 @@ pub async fn set_domain_photo(
     log::info!("Saving image to {}", dir);
     photo
         .save_with_format(dir, image::ImageFormat::Avif)
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?;
-    diesel::update(domains::table)
+        .map_err(AppError::internal)?;
+    let updated = diesel::update(domains::table)
         .filter(domains::id.eq(data.id))
         .set(
             domains::photo_hash.eq(hash
@@ -170,14 +129,10 @@ pub async fn set_domain_photo(
                 .flatten()
                 .collect::<Vec<u8>>()),
         )
-        .execute(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::BAD_REQUEST
-        })?;
+        .execute(&mut state.connection.get()?)?;
+    if updated == 0 {
+        return Err(AppError::not_found("No such domain"));
+    }
     Ok(())
 }
 
@@ -185,36 +140,23 @@ pub async fn set_domain_photo(
 pub async fn get_domain_photo(
     State(state): State<SiteState>,
     Query(data): Query<DomainId>,
-) -> impl IntoResponse {
+) -> Result<impl IntoResponse, AppError> {
     // `File` implements `AsyncRead`
-    let photo_hash = domains::table
+    let photo_hash: Vec<u8> = domains::table
         .select(domains::photo_hash)
         .filter(domains::id.eq(data.id))
-        .get_result(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::NOT_FOUND
-        })?;
-    let photo_hash: Vec<u8> = match photo_hash {
-        None => return Err(StatusCode::NOT_FOUND),
-        Some(v) => v,
-    };
-    let file = match tokio::fs::File::open(format!(
+        .get_result::<Option<Vec<u8>>>(&mut state.connection.get()?)?
+        .ok_or_else(|| AppError::not_found("The domain has no photo"))?;
+    let file = tokio::fs::File::open(format!(
         "{}/{}",
         state.image_dir.to_string_lossy(),
         BASE64_URL_SAFE_NO_PAD.encode(photo_hash)
     ))
     .await
-    {
-        Ok(file) => file,
-        Err(e) => {
-            log::error!("{e:?}");
-            return Err(StatusCode::NOT_FOUND);
-        }
-    };
+    .map_err(|e| {
+        log::error!("{e:?}");
+        AppError::not_found("The photo is missing")
+    })?;
     let stream = ReaderStream::new(file);
     let body = Body::from_stream(stream);
 
@@ -232,109 +174,119 @@ pub async fn get_domain_photo(
 pub async fn get_domain_faculty_coordinator(
     State(state): State<SiteState>,
     Query(data): Query<GetDomainFacultyCoordinator>,
-) -> Result<Json<Vec<FacultyResponse>>, StatusCode> {
-    faculty_coordinators::table
+) -> Result<Json<Vec<FacultyResponse>>, AppError> {
+    let coordinators: Vec<(Faculty, Profile)> = faculty_coordinators::table
         .inner_join(domains::table)
         .inner_join(faculty::table.inner_join(users::table))
         .filter(domains::id.eq(data.id))
         .select((Faculty::as_select(), Profile::as_select()))
-        .get_results(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::NOT_FOUND
-        })?)
-        .map(|v: Vec<(Faculty, Profile)>| {
-            Json(
-                v.into_iter()
-                    .map(|(faculty, profile)| FacultyResponse { faculty, profile })
-                    .collect::<Vec<FacultyResponse>>(),
-            )
-        })
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::NOT_FOUND
-        })
+        .get_results(&mut state.connection.get()?)?;
+    Ok(Json(
+        coordinators
+            .into_iter()
+            .map(|(faculty, profile)| FacultyResponse { faculty, profile })
+            .collect(),
+    ))
 }
 
+/// Also makes them a faculty coordinator, see [`crate::roles`].
 pub async fn add_domain_faculty_coordinator(
     State(state): State<SiteState>,
     user: User,
     Form(data): Form<AddDomainFacultyCoordinator>,
-) -> StatusCode {
-    match user.role {
-        Role::SUPER_ADMIN => {}
-        _ => return StatusCode::UNAUTHORIZED,
-    }
-    if !user.verified || !user.is_payment_done(&state.connection) {
-        return StatusCode::UNAUTHORIZED;
-    }
-    match data
-        .insert_into(faculty_coordinators::table)
-        .execute(&mut match state.connection.get() {
-            Ok(v) => v,
-            Err(e) => {
-                log::error!("{e:?}");
-                return StatusCode::INTERNAL_SERVER_ERROR;
-            }
-        }) {
-        Ok(_) => StatusCode::OK,
-        Err(e) => {
-            log::error!("{e:?}");
-            StatusCode::BAD_REQUEST
-        }
-    }
+) -> Result<(), AppError> {
+    authorize(&state, &user, Action::ManageDomain)?;
+    let connection = &mut state.connection.get()?;
+    appoint(
+        connection,
+        data.faculty_id,
+        Appointment::Faculty,
+        |connection| {
+            data.clone()
+                .insert_into(faculty_coordinators::table)
+                .execute(connection)
+        },
+    )
+}
+
+/// Lowers them to a participant when it was the last domain they coordinated.
+pub async fn remove_domain_faculty_coordinator(
+    State(state): State<SiteState>,
+    user: User,
+    Form(data): Form<AddDomainFacultyCoordinator>,
+) -> Result<(), AppError> {
+    authorize(&state, &user, Action::ManageDomain)?;
+    let connection = &mut state.connection.get()?;
+    dismiss(connection, data.faculty_id, |connection| {
+        diesel::delete(faculty_coordinators::table)
+            .filter(faculty_coordinators::faculty_id.eq(data.faculty_id))
+            .filter(faculty_coordinators::domain_id.eq(data.domain_id))
+            .execute(connection)
+    })
 }
 
 pub async fn get_domain_student_coordinator(
     State(state): State<SiteState>,
     Query(data): Query<GetDomainFacultyCoordinator>,
-) -> Result<Json<Vec<StudentResponse>>, StatusCode> {
-    student_domain_coordinators::table
+) -> Result<Json<Vec<StudentResponse>>, AppError> {
+    let coordinators: Vec<(Student, Profile)> = student_domain_coordinators::table
         .inner_join(domains::table)
         .inner_join(students::table.inner_join(users::table))
         .filter(domains::id.eq(data.id))
         .select((Student::as_select(), Profile::as_select()))
-        .get_results(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::NOT_FOUND
-        })?)
-        .map(|v: Vec<(Student, Profile)>| {
-            Json(
-                v.into_iter()
-                    .map(|(student, profile)| StudentResponse { student, profile })
-                    .collect::<Vec<StudentResponse>>(),
-            )
-        })
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::NOT_FOUND
-        })
+        .get_results(&mut state.connection.get()?)?;
+    Ok(Json(
+        coordinators
+            .into_iter()
+            .map(|(student, profile)| StudentResponse { student, profile })
+            .collect(),
+    ))
 }
 
+/// Also makes them a student coordinator, see [`crate::roles`].
 pub async fn add_domain_student_coordinator(
     State(state): State<SiteState>,
     user: User,
     Form(data): Form<AddDomainStudentCoordinator>,
-) -> StatusCode {
-    match user.role {
-        Role::SUPER_ADMIN => {}
-        _ => return StatusCode::UNAUTHORIZED,
-    }
-    if !user.verified || !user.is_payment_done(&state.connection) {
-        return StatusCode::UNAUTHORIZED;
-    }
-    match data
-        .insert_into(student_domain_coordinators::table)
-        .execute(&mut match state.connection.get() {
-            Ok(v) => v,
-            Err(e) => {
-                log::error!("{e:?}");
-                return StatusCode::INTERNAL_SERVER_ERROR;
-            }
-        }) {
-        Err(e) => {
-            log::error!("{e:?}");
-            return StatusCode::BAD_REQUEST;
-        }
-        Ok(_) => StatusCode::OK,
-    }
+) -> Result<(), AppError> {
+    authorize(
+        &state,
+        &user,
+        Action::AppointDomainStudentCoordinator {
+            domain_id: data.domain_id,
+        },
+    )?;
+    let connection = &mut state.connection.get()?;
+    appoint(
+        connection,
+        data.student_id,
+        Appointment::Student,
+        |connection| {
+            data.clone()
+                .insert_into(student_domain_coordinators::table)
+                .execute(connection)
+        },
+    )
+}
+
+/// Lowers them to a participant when it was the last thing they coordinated.
+pub async fn remove_domain_student_coordinator(
+    State(state): State<SiteState>,
+    user: User,
+    Form(data): Form<AddDomainStudentCoordinator>,
+) -> Result<(), AppError> {
+    authorize(
+        &state,
+        &user,
+        Action::AppointDomainStudentCoordinator {
+            domain_id: data.domain_id,
+        },
+    )?;
+    let connection = &mut state.connection.get()?;
+    dismiss(connection, data.student_id, |connection| {
+        diesel::delete(student_domain_coordinators::table)
+            .filter(student_domain_coordinators::student_id.eq(data.student_id))
+            .filter(student_domain_coordinators::domain_id.eq(data.domain_id))
+            .execute(connection)
+    })
 }
@@ -0,0 +1,287 @@
+//! The error handlers answer with. Every failure has its status and a JSON body the frontend can
+//! tell apart without reading the message:
+//!
+//! ```json
+//! {"code": "conflict", "message": "Already taken", "fields": {"email": "Already taken"}}
+//! ```
+//!
+//! `code` is one of a fixed set of names, `message` is meant for people and `fields` has the form
+//! fields at fault, if any. Bare status codes convert with `?`, and so do database errors: a unique
+//! violation answers 409, a foreign key pointing nowhere 404, and anything unexpected is logged and
+//! answers 500 without details.
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+
+use axum::{
+    response::{IntoResponse, Response},
+    Json,
+};
+use diesel::result::{DatabaseErrorInformation, DatabaseErrorKind, Error};
+use http::StatusCode;
+use serde::Serialize;
+
+#[derive(Debug)]
+pub struct AppError {
+    status: StatusCode,
+    code: &'static str,
+    message: String,
+    fields: BTreeMap<String, String>,
+}
+
+#[derive(Serialize)]
+struct ErrorBody<'a> {
+    code: &'a str,
+    message: &'a str,
+    fields: &'a BTreeMap<String, String>,
+}
+
+/// The `code` a bare status answers with.
+fn code_of(status: StatusCode) -> &'static str {
+    match status {
+        StatusCode::BAD_REQUEST => "bad_request",
+        StatusCode::UNAUTHORIZED => "unauthorized",
+        StatusCode::FORBIDDEN => "forbidden",
+        StatusCode::NOT_FOUND => "not_found",
+        StatusCode::CONFLICT => "conflict",
+        StatusCode::PAYLOAD_TOO_LARGE => "too_large",
+        StatusCode::UNPROCESSABLE_ENTITY => "validation",
+        StatusCode::TOO_MANY_REQUESTS => "too_many_requests",
+        StatusCode::BAD_GATEWAY => "bad_gateway",
+        StatusCode::SERVICE_UNAVAILABLE => "unavailable",
+        status if status.is_client_error() => "bad_request",
+        _ => "internal",
+    }
+}
+
+impl AppError {
+    pub fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            code: code_of(status),
+            message: message.into(),
+            fields: BTreeMap::new(),
+        }
+    }
+
+    pub fn unauthorized(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::UNAUTHORIZED, message)
+    }
+
+    pub fn forbidden(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::FORBIDDEN, message)
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::NOT_FOUND, message)
+    }
+
+    pub fn conflict(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::CONFLICT, message)
+    }
+
+    /// A form field with a value that can not be used, answered with 400.
+    pub fn invalid(field: &str, message: impl Into<String>) -> Self {
+        let message = message.into();
+        Self::new(StatusCode::BAD_REQUEST, message.clone())
+            .with_code("validation")
+            .field(field, message)
+    }
+
+    /// Logs what went wrong and answers 500 without the details.
+    pub fn internal(error: impl Debug) -> Self {
+        log::error!("{error:?}");
+        Self::from(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+
+    /// Replaces the `code` derived from the status.
+    pub fn with_code(mut self, code: &'static str) -> Self {
+        self.code = code;
+        self
+    }
+
+    /// Blames `field` for the error too.
+    pub fn field(mut self, field: &str, message: impl Into<String>) -> Self {
+        self.fields.insert(field.to_owned(), message.into());
+        self
+    }
+
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl From<StatusCode> for AppError {
+    fn from(status: StatusCode) -> Self {
+        Self::new(status, status.canonical_reason().unwrap_or_default())
+    }
+}
+
+impl From<diesel::r2d2::PoolError> for AppError {
+    fn from(error: diesel::r2d2::PoolError) -> Self {
+        Self::internal(error)
+    }
+}
+
+/// The column a constraint like `users_email_key` or `teams_leader_id_fkey` covers, `email` and
+/// `leader_id` there. `None` for primary keys and constraints named some other way.
+fn constrained_column(info: &dyn DatabaseErrorInformation) -> Option<String> {
+    if let Some(column) = info.column_name() {
+        return Some(column.to_owned());
+    }
+    let column = info
+        .constraint_name()?
+        .strip_prefix(info.table_name()?)?
+        .strip_prefix('_')?;
+    ["_key", "_fkey"]
+        .into_iter()
+        .find_map(|suffix| column.strip_suffix(suffix))
+        .map(str::to_owned)
+}
+
+impl From<Error> for AppError {
+    fn from(error: Error) -> Self {
+        let Error::DatabaseError(kind, info) = &error else {
+            return match error {
+                Error::NotFound => Self::not_found("Not found"),
+                error => Self::internal(error),
+            };
+        };
+        let column = constrained_column(info.as_ref());
+        let (error, message) = match kind {
+            DatabaseErrorKind::UniqueViolation => {
+                (Self::conflict("Already exists"), "Already taken")
+            }
+            // Deleting a row others still point at
+            DatabaseErrorKind::ForeignKeyViolation
+                if info.message().starts_with("update or delete") =>
+            {
+                (Self::conflict("Still in use"), "Still in use")
+            }
+            DatabaseErrorKind::ForeignKeyViolation => (
+                Self::not_found("Refers to something that does not exist"),
+                "Does not exist",
+            ),
+            DatabaseErrorKind::NotNullViolation => (
+                Self::new(StatusCode::BAD_REQUEST, "A required value is missing")
+                    .with_code("validation"),
+                "Required",
+            ),
+            DatabaseErrorKind::CheckViolation => (
+                Self::new(StatusCode::BAD_REQUEST, "Invalid value").with_code("validation"),
+                "Invalid",
+            ),
+            _ => return Self::internal(error),
+        };
+        log::warn!("{:?}: {}", kind, info.message());
+        match column {
+            Some(column) => error.field(&column, message),
+            None => error,
+        }
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let body = ErrorBody {
+            code: self.code,
+            message: &self.message,
+            fields: &self.fields,
+        };
+        (self.status, Json(body)).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Info {
+        table: Option<&'static str>,
+        column: Option<&'static str>,
+        constraint: Option<&'static str>,
+    }
+
+    impl DatabaseErrorInformation for Info {
+        fn message(&self) -> &str {
+            "duplicate key value violates unique constraint"
+        }
+        fn details(&self) -> Option<&str> {
+            None
+        }
+        fn hint(&self) -> Option<&str> {
+            None
+        }
+        fn table_name(&self) -> Option<&str> {
+            self.table
+        }
+        fn column_name(&self) -> Option<&str> {
+            self.column
+        }
+        fn constraint_name(&self) -> Option<&str> {
+            self.constraint
+        }
+        fn statement_position(&self) -> Option<i32> {
+            None
+        }
+    }
+
+    fn column_of(table: &'static str, constraint: &'static str) -> Option<String> {
+        constrained_column(&Info {
+            table: Some(table),
+            column: None,
+            constraint: Some(constraint),
+        })
+    }
+
+    #[test]
+    fn column_from_unique_and_foreign_keys() {
+        assert_eq!(
+            column_of("users", "users_email_key").as_deref(),
+            Some("email")
+        );
+        assert_eq!(
+            column_of("teams", "teams_leader_id_fkey").as_deref(),
+            Some("leader_id")
+        );
+    }
+
+    #[test]
+    fn no_column_for_other_constraints() {
+        assert_eq!(column_of("users", "users_pkey"), None);
+        assert_eq!(column_of("users", "teams_leader_id_fkey"), None);
+        assert_eq!(column_of("users", "users_email_check"), None);
+        assert_eq!(
+            constrained_column(&Info {
+                table: None,
+                column: None,
+                constraint: Some("users_email_key"),
+            }),
+            None
+        );
+    }
+
+    #[test]
+    fn column_reported_by_the_database_wins() {
+        let info = Info {
+            table: Some("users"),
+            column: Some("phone"),
+            constraint: Some("users_email_key"),
+        };
+        assert_eq!(constrained_column(&info).as_deref(), Some("phone"));
+    }
+
+    #[test]
+    fn bare_status_takes_its_code() {
+        let error = AppError::from(StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(error.code, "too_many_requests");
+        assert_eq!(error.message(), "Too Many Requests");
+        let error = AppError::invalid("email", "Already taken");
+        assert_eq!(error.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(error.code, "validation");
+        assert_eq!(error.fields["email"], "Already taken");
+    }
+}
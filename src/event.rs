@@ -9,11 +9,13 @@ use axum::{
 use axum_macros::debug_handler;
 use base64::{prelude::BASE64_URL_SAFE_NO_PAD, Engine};
 use diesel::prelude::*;
+use diesel::result::Error;
 use highway::HighwayHash;
-use http::{header, HeaderMap, StatusCode};
+use http::{header, HeaderMap};
 use tokio_util::io::ReaderStream;
 
 use crate::{
+    error::AppError,
     forms::{
         domains::GetDomainEvent,
         events::{
@@ -24,104 +26,74 @@ use crate::{
         users::Profile,
     },
     models::{
-        domains::Domain, events::Event, students::{Student, StudentResponse}, users::{Role, User}
+        domains::Domain,
+        events::{Event, JoinStatus},
+        outbox::NewMail,
+        points::Attendance,
+        students::{Student, StudentResponse},
+        users::User,
     },
+    outbox::{enqueue, mail_teams, mail_users},
+    points::set_attendance,
+    policy::{authorize, Action},
+    registration::{check_team_size, Schedule},
+    roles::{appoint, dismiss, Appointment},
     schema::{
-        domains, events, faculty_coordinators, individual_event_participation,
-        student_domain_coordinators, student_event_coordinators, students,
-        team_event_participations, team_members, teams, users,
+        domains, events, individual_event_participation, individual_event_waitlist,
+        student_event_coordinators, students, team_event_participations, team_event_waitlist,
+        team_members, teams, users,
     },
     state::SiteState,
+    team::{lock_team, require_leader, team_size},
+    waitlist::{
+        fill_seats, notify_promoted_teams, notify_promoted_users, release_seat, take_seat,
+        EventSeats, TeamEventSeats,
+    },
 };
 
 pub async fn get_event(
     State(state): State<SiteState>,
     Query(data): Query<EventId>,
-) -> Result<Json<Event>, StatusCode> {
+) -> Result<Json<Event>, AppError> {
     events::table
         .select(Event::as_select())
         .filter(events::id.eq(data.id))
-        .get_result(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map(|v| Json(v))
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })
+        .get_result(&mut state.connection.get()?)
+        .optional()?
+        .map(Json)
+        .ok_or_else(|| AppError::not_found("No such event"))
 }
 
 pub async fn get_events_by_domain(
     State(state): State<SiteState>,
     Query(data): Query<GetDomainEvent>,
-) -> Result<Json<Vec<Event>>, StatusCode> {
-    events::table
-        .select(Event::as_select())
-        .filter(events::domain_id.eq(data.id))
-        .get_results(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map(|v| Json(v))
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })
+) -> Result<Json<Vec<Event>>, AppError> {
+    Ok(Json(
+        events::table
+            .select(Event::as_select())
+            .filter(events::domain_id.eq(data.id))
+            .get_results(&mut state.connection.get()?)?,
+    ))
 }
 
 pub async fn create_event(
     State(state): State<SiteState>,
     user: User,
     Form(data): Form<CreateEvent>,
-) -> Result<Json<Event>, StatusCode> {
-    if !user.verified || !user.is_payment_done(&state.connection) {
-        return Err(StatusCode::UNAUTHORIZED);
-    }
-    match user.role {
-        Role::SUPER_ADMIN => {}
-        Role::FACULTY_COORDINATOR => {
-            let _: i32 = faculty_coordinators::table
-                .select(faculty_coordinators::domain_id)
-                .filter(faculty_coordinators::domain_id.eq(data.domain_id))
-                .filter(faculty_coordinators::faculty_id.eq(user.id))
-                .get_result(&mut state.connection.get().map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::INTERNAL_SERVER_ERROR
-                })?)
-                .map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::UNAUTHORIZED
-                })?;
-        }
-        Role::STUDENT_COORDINATOR => {
-            let _: i32 = events::table
-                .inner_join(domains::table.inner_join(student_domain_coordinators::table))
-                .select(student_domain_coordinators::domain_id)
-                .filter(student_domain_coordinators::domain_id.eq(events::domain_id))
-                .filter(student_domain_coordinators::student_id.eq(user.id))
-                .get_result(&mut state.connection.get().map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::INTERNAL_SERVER_ERROR
-                })?)
-                .map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::UNAUTHORIZED
-                })?;
-        }
-        _ => return Err(StatusCode::UNAUTHORIZED),
-    }
-    data.insert_into(events::table)
-        .returning(Event::as_returning())
-        .get_result(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map(|v| Json(v))
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })
+) -> Result<Json<Event>, AppError> {
+    authorize(
+        &state,
+        &user,
+        Action::CreateEvent {
+            domain_id: data.domain_id,
+        },
+    )?;
+    data.validate()?;
+    Ok(Json(
+        data.insert_into(events::table)
+            .returning(Event::as_returning())
+            .get_result(&mut state.connection.get()?)?,
+    ))
 }
 
 #[debug_handler]
@@ -129,135 +101,118 @@ pub async fn delete_event(
     State(state): State<SiteState>,
     user: User,
     Form(data): Form<DeleteEvent>,
-) -> Result<Json<Event>, StatusCode> {
-    if !user.verified || !user.is_payment_done(&state.connection) {
-        return Err(StatusCode::UNAUTHORIZED);
-    }
-    match user.role {
-        Role::SUPER_ADMIN => {}
-        Role::FACULTY_COORDINATOR => {
-            let _: i32 = events::table
-                .inner_join(domains::table.inner_join(faculty_coordinators::table))
-                .select(faculty_coordinators::domain_id)
-                .filter(faculty_coordinators::domain_id.eq(events::domain_id))
-                .filter(faculty_coordinators::faculty_id.eq(user.id))
-                .get_result(&mut state.connection.get().map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::INTERNAL_SERVER_ERROR
-                })?)
-                .map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::UNAUTHORIZED
-                })?;
-        }
-        Role::STUDENT_COORDINATOR => {
-            let _: i32 = events::table
-                .inner_join(domains::table.inner_join(student_domain_coordinators::table))
-                .select(student_domain_coordinators::domain_id)
-                .filter(student_domain_coordinators::domain_id.eq(events::domain_id))
-                .filter(student_domain_coordinators::student_id.eq(user.id))
-                .get_result(&mut state.connection.get().map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::INTERNAL_SERVER_ERROR
-                })?)
-                .map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::UNAUTHORIZED
-                })?;
-        }
-        _ => return Err(StatusCode::UNAUTHORIZED),
-    }
+) -> Result<Json<Event>, AppError> {
+    authorize(&state, &user, Action::DeleteEvent { event_id: data.id })?;
     diesel::delete(events::table)
         .filter(events::id.eq(data.id))
         .returning(Event::as_returning())
-        .get_result(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map(|v| Json(v))
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })
+        .get_result(&mut state.connection.get()?)
+        .optional()?
+        .map(Json)
+        .ok_or_else(|| AppError::not_found("No such event"))
 }
 
 pub async fn change_event(
     State(state): State<SiteState>,
     user: User,
     Form(data): Form<ChangeEvent>,
-) -> Result<Json<Event>, StatusCode> {
-    if !user.verified || !user.is_payment_done(&state.connection) {
-        return Err(StatusCode::UNAUTHORIZED);
-    }
-    match user.role {
-        Role::SUPER_ADMIN => {}
-        Role::FACULTY_COORDINATOR => {
-            let _: i32 = events::table
-                .inner_join(domains::table.inner_join(faculty_coordinators::table))
-                .select(faculty_coordinators::domain_id)
-                .filter(faculty_coordinators::domain_id.eq(events::domain_id))
-                .filter(faculty_coordinators::faculty_id.eq(user.id))
-                .get_result(&mut state.connection.get().map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::INTERNAL_SERVER_ERROR
-                })?)
-                .map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::UNAUTHORIZED
-                })?;
-        }
-        Role::STUDENT_COORDINATOR => {
-            let student_domain_id: Result<i32, StatusCode> = events::table
-                .inner_join(domains::table.inner_join(student_domain_coordinators::table))
-                .select(student_domain_coordinators::domain_id)
-                .filter(student_domain_coordinators::domain_id.eq(events::domain_id))
-                .filter(student_domain_coordinators::student_id.eq(user.id))
-                .get_result(&mut state.connection.get().map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::INTERNAL_SERVER_ERROR
-                })?)
-                .map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::UNAUTHORIZED
-                });
-            let student_event_id: Result<i32, StatusCode> = events::table
-                .inner_join(student_event_coordinators::table)
-                .select(student_event_coordinators::event_id)
-                .filter(student_event_coordinators::event_id.eq(data.id))
-                .filter(student_event_coordinators::student_id.eq(user.id))
-                .get_result(&mut state.connection.get().map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::INTERNAL_SERVER_ERROR
-                })?)
-                .map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::UNAUTHORIZED
-                });
-            match student_domain_id {
-                Ok(_) => {}
-                Err(_) => match student_event_id {
-                    Ok(_) => {}
-                    Err(e) => {
-                        return Err(e);
-                    }
-                },
-            }
+) -> Result<Json<Event>, AppError> {
+    authorize(&state, &user, Action::UpdateEvent { event_id: data.id })?;
+    let capacity_changed = data.max_participants.is_some() || data.max_teams.is_some();
+    let connection = &mut state.connection.get()?;
+    let current = events::table
+        .select(Event::as_select())
+        .filter(events::id.eq(data.id))
+        .get_result(connection)
+        .optional()?
+        .ok_or_else(|| AppError::not_found("No such event"))?;
+    data.validate(&current)?;
+    // Filled with the update, so nobody is promoted into seats that were not given after all
+    let (event, users, teams) = connection.transaction(|connection| {
+        let event = diesel::update(events::table)
+            .filter(events::id.eq(data.id))
+            .set(data)
+            .returning(Event::as_returning())
+            .get_result(connection)?;
+        if !capacity_changed {
+            return Ok::<_, Error>((event, Vec::new(), Vec::new()));
         }
-        _ => return Err(StatusCode::UNAUTHORIZED),
+        let users = fill_seats(connection, &EventSeats(event.id))?;
+        let teams = fill_seats(connection, &TeamEventSeats(event.id))?;
+        Ok((event, users, teams))
+    })?;
+    notify_promoted_users(&state, &users, &event.name);
+    notify_promoted_teams(&state, &teams, &event.name);
+    if current.mode != event.mode
+        || current.venue != event.venue
+        || current.start_time != event.start_time
+        || current.end_time != event.end_time
+    {
+        notify_event_changed(&state, connection, &event);
     }
-    diesel::update(events::table)
-        .filter(events::id.eq(data.id))
-        .set(data)
-        .returning(Event::as_returning())
-        .get_result(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map(|v| Json(v))
-        .map_err(|e| {
+    Ok(Json(event))
+}
+
+/// Mails everyone registered for `event`, alone or in a team, when and where it now takes place.
+fn notify_event_changed(state: &SiteState, connection: &mut PgConnection, event: &Event) {
+    let registered = individual_event_participation::table
+        .select(individual_event_participation::user_id)
+        .filter(individual_event_participation::event_id.eq(event.id))
+        .load::<i32>(connection)
+        .and_then(|users| {
+            Ok((
+                users,
+                team_event_participations::table
+                    .select(team_event_participations::team_id)
+                    .filter(team_event_participations::event_id.eq(event.id))
+                    .load::<i32>(connection)?,
+            ))
+        });
+    let (users, teams) = match registered {
+        Ok(v) => v,
+        Err(e) => {
             log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
+            return;
+        }
+    };
+    let mail = |user: &User| {
+        user.event_changed_email(
+            &state.templates,
+            &event.name,
+            &event.mode,
+            event.start_time,
+            event.end_time,
+            &event.venue,
+        )
+    };
+    mail_users(state, &users, mail);
+    mail_teams(state, &teams, mail);
+}
+
+/// Queues a registration confirmation for the event to each of `recipients`, in the caller's
+/// transaction so it is only sent if the seat was really taken.
+fn queue_confirmations(
+    state: &SiteState,
+    connection: &mut PgConnection,
+    event_id: i32,
+    recipients: &[User],
+) -> QueryResult<usize> {
+    let event = events::table
+        .select(Event::as_select())
+        .filter(events::id.eq(event_id))
+        .get_result(connection)?;
+    let mails: Vec<NewMail> = recipients
+        .iter()
+        .map(|user| {
+            user.registration_confirmation_email(
+                &state.templates,
+                &event.name,
+                event.start_time,
+                &event.venue,
+            )
         })
+        .collect();
+    enqueue(connection, &mails)
 }
 
 // Not Deleting the image in case some other user also happens to have the same exact image
@@ -266,78 +221,14 @@ pub async fn set_event_photo(
     user: User,
     Query(data): Query<EventId>,
     photo: Bytes,
-) -> Result<(), StatusCode> {
-    if !user.verified || !user.is_payment_done(&state.connection) {
-        return Err(StatusCode::UNAUTHORIZED);
-    }
-    match user.role {
-        Role::SUPER_ADMIN => {}
-        Role::FACULTY_COORDINATOR => {
-            let _: i32 = events::table
-                .inner_join(domains::table.inner_join(faculty_coordinators::table))
-                .select(faculty_coordinators::domain_id)
-                .filter(faculty_coordinators::domain_id.eq(events::domain_id))
-                .filter(faculty_coordinators::faculty_id.eq(user.id))
-                .get_result(&mut state.connection.get().map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::INTERNAL_SERVER_ERROR
-                })?)
-                .map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::UNAUTHORIZED
-                })?;
-        }
-        Role::STUDENT_COORDINATOR => {
-            let student_domain_id: Result<i32, StatusCode> = events::table
-                .inner_join(domains::table.inner_join(student_domain_coordinators::table))
-                .select(student_domain_coordinators::domain_id)
-                .filter(student_domain_coordinators::domain_id.eq(events::domain_id))
-                .filter(student_domain_coordinators::student_id.eq(user.id))
-                .get_result(&mut state.connection.get().map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::INTERNAL_SERVER_ERROR
-                })?)
-                .map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::UNAUTHORIZED
-                });
-            let student_event_id: Result<i32, StatusCode> = events::table
-                .inner_join(student_event_coordinators::table)
-                .select(student_event_coordinators::event_id)
-                .filter(student_event_coordinators::event_id.eq(data.id))
-                .filter(student_event_coordinators::student_id.eq(user.id))
-                .get_result(&mut state.connection.get().map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::INTERNAL_SERVER_ERROR
-                })?)
-                .map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::UNAUTHORIZED
-                });
-            match student_domain_id {
-                Ok(_) => {}
-                Err(_) => match student_event_id {
-                    Ok(_) => {}
-                    Err(e) => {
-                        return Err(e);
-                    }
-                },
-            }
-        }
-        _ => return Err(StatusCode::UNAUTHORIZED),
-    }
+) -> Result<(), AppError> {
+    authorize(&state, &user, Action::UpdateEvent { event_id: data.id })?;
     let hash = state.bulk_hasher.hash256(photo.to_vec().as_slice());
     let photo = image::ImageReader::new(Cursor::new(photo))
         .with_guessed_format()
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::BAD_REQUEST
-        })?
+        .map_err(AppError::internal)?
         .decode()
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::BAD_REQUEST
-        })?;
+        .map_err(|e| AppError::invalid("photo", format!("Not a readable image: {e}")))?;
     let photo = photo.thumbnail(512, 512);
     photo
         .save_with_format(
@@ -353,12 +244,9 @@ pub async fn set_event_photo(
             ),
             image::ImageFormat::Avif,
         )
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::BAD_REQUEST
-        })?;
-    diesel::update(events::table)
-        .filter(events::id.eq(user.id))
+        .map_err(AppError::internal)?;
+    let updated = diesel::update(events::table)
+        .filter(events::id.eq(data.id))
         .set(
             events::photo_hash.eq(hash
                 .map(|v| v.to_le_bytes())
@@ -366,14 +254,10 @@ pub async fn set_event_photo(
                 .flatten()
                 .collect::<Vec<u8>>()),
         )
-        .execute(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::BAD_REQUEST
-        })?;
+        .execute(&mut state.connection.get()?)?;
+    if updated == 0 {
+        return Err(AppError::not_found("No such event"));
+    }
     Ok(())
 }
 
@@ -381,36 +265,23 @@ pub async fn set_event_photo(
 pub async fn get_event_photo(
     State(state): State<SiteState>,
     Query(data): Query<EventId>,
-) -> impl IntoResponse {
+) -> Result<impl IntoResponse, AppError> {
     // `File` implements `AsyncRead`
-    let photo_hash = events::table
+    let photo_hash: Vec<u8> = events::table
         .select(events::photo_hash)
         .filter(events::id.eq(data.id))
-        .get_result(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::NOT_FOUND
-        })?;
-    let photo_hash: Vec<u8> = match photo_hash {
-        None => return Err(StatusCode::NOT_FOUND),
-        Some(v) => v,
-    };
-    let file = match tokio::fs::File::open(format!(
+        .get_result::<Option<Vec<u8>>>(&mut state.connection.get()?)?
+        .ok_or_else(|| AppError::not_found("The event has no photo"))?;
+    let file = tokio::fs::File::open(format!(
         "{}/{}",
         state.image_dir.to_string_lossy(),
         BASE64_URL_SAFE_NO_PAD.encode(photo_hash)
     ))
     .await
-    {
-        Ok(file) => file,
-        Err(e) => {
-            log::error!("{e:?}");
-            return Err(StatusCode::NOT_FOUND);
-        }
-    };
+    .map_err(|e| {
+        log::error!("{e:?}");
+        AppError::not_found("The photo is missing")
+    })?;
     let stream = ReaderStream::new(file);
     let body = Body::from_stream(stream);
 
@@ -428,204 +299,110 @@ pub async fn get_event_photo(
 pub async fn get_event_coordinator(
     State(state): State<SiteState>,
     Query(data): Query<GetEventStudentCoordinator>,
-) -> Result<Json<Vec<StudentResponse>>, StatusCode> {
-    student_event_coordinators::table
+) -> Result<Json<Vec<StudentResponse>>, AppError> {
+    let coordinators: Vec<(Student, Profile)> = student_event_coordinators::table
         .inner_join(events::table)
         .inner_join(students::table.inner_join(users::table))
         .filter(events::id.eq(data.id))
         .select((Student::as_select(), Profile::as_select()))
-        .get_results(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::NOT_FOUND
-        })?)
-        .map(|v: Vec<(Student, Profile)>| {
-            Json(
-                v.into_iter()
-                    .map(|(student, profile)| StudentResponse { student, profile })
-                    .collect::<Vec<StudentResponse>>(),
-            )
-        })
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::NOT_FOUND
-        })
+        .get_results(&mut state.connection.get()?)?;
+    Ok(Json(
+        coordinators
+            .into_iter()
+            .map(|(student, profile)| StudentResponse { student, profile })
+            .collect(),
+    ))
 }
 
+/// Also makes them a student coordinator, see [`crate::roles`].
 pub async fn add_event_coordinator(
     State(state): State<SiteState>,
     user: User,
     Form(data): Form<AddEventStudentCoordinator>,
-) -> Result<(), StatusCode> {
-    if !user.verified || !user.is_payment_done(&state.connection) {
-        return Err(StatusCode::UNAUTHORIZED);
-    }
-    match user.role {
-        Role::SUPER_ADMIN => {}
-        _ => return Err(StatusCode::UNAUTHORIZED),
-    }
-    data.insert_into(student_event_coordinators::table)
-        .execute(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::BAD_REQUEST
-        })?;
-    Ok(())
+) -> Result<(), AppError> {
+    authorize(
+        &state,
+        &user,
+        Action::AppointEventCoordinator {
+            event_id: data.event_id,
+        },
+    )?;
+    let connection = &mut state.connection.get()?;
+    appoint(
+        connection,
+        data.student_id,
+        Appointment::Student,
+        |connection| {
+            data.clone()
+                .insert_into(student_event_coordinators::table)
+                .execute(connection)
+        },
+    )
+}
+
+/// Lowers them to a participant when it was the last thing they coordinated.
+pub async fn remove_event_coordinator(
+    State(state): State<SiteState>,
+    user: User,
+    Form(data): Form<AddEventStudentCoordinator>,
+) -> Result<(), AppError> {
+    authorize(
+        &state,
+        &user,
+        Action::AppointEventCoordinator {
+            event_id: data.event_id,
+        },
+    )?;
+    let connection = &mut state.connection.get()?;
+    dismiss(connection, data.student_id, |connection| {
+        diesel::delete(student_event_coordinators::table)
+            .filter(student_event_coordinators::student_id.eq(data.student_id))
+            .filter(student_event_coordinators::event_id.eq(data.event_id))
+            .execute(connection)
+    })
 }
 
 pub async fn get_event_individual_attendance(
     State(state): State<SiteState>,
     user: User,
     Query(data): Query<EventId>,
-) -> Result<Json<Vec<i32>>, StatusCode> {
-    match user.role {
-        Role::SUPER_ADMIN => {}
-        Role::FACULTY_COORDINATOR => {
-            let _: i32 = events::table
-                .inner_join(domains::table.inner_join(faculty_coordinators::table))
-                .select(faculty_coordinators::domain_id)
-                .filter(faculty_coordinators::domain_id.eq(events::domain_id))
-                .filter(faculty_coordinators::faculty_id.eq(user.id))
-                .get_result(&mut state.connection.get().map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::INTERNAL_SERVER_ERROR
-                })?)
-                .map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::UNAUTHORIZED
-                })?;
-        }
-        Role::STUDENT_COORDINATOR => {
-            let student_domain_id: Result<i32, StatusCode> = events::table
-                .inner_join(domains::table.inner_join(student_domain_coordinators::table))
-                .select(student_domain_coordinators::domain_id)
-                .filter(student_domain_coordinators::domain_id.eq(events::domain_id))
-                .filter(student_domain_coordinators::student_id.eq(user.id))
-                .get_result(&mut state.connection.get().map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::INTERNAL_SERVER_ERROR
-                })?)
-                .map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::UNAUTHORIZED
-                });
-            let student_event_id: Result<i32, StatusCode> = events::table
-                .inner_join(student_event_coordinators::table)
-                .select(student_event_coordinators::event_id)
-                .filter(student_event_coordinators::event_id.eq(data.id))
-                .filter(student_event_coordinators::student_id.eq(user.id))
-                .get_result(&mut state.connection.get().map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::INTERNAL_SERVER_ERROR
-                })?)
-                .map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::UNAUTHORIZED
-                });
-            match student_domain_id {
-                Ok(_) => {}
-                Err(_) => match student_event_id {
-                    Ok(_) => {}
-                    Err(e) => {
-                        return Err(e);
-                    }
-                },
-            }
-        }
-        _ => return Err(StatusCode::UNAUTHORIZED),
-    };
-    individual_event_participation::table
-        .select(individual_event_participation::user_id)
-        .filter(individual_event_participation::event_id.eq(data.id))
-        .load::<i32>(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map(|v| Json(v))
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })
+) -> Result<Json<Vec<i32>>, AppError> {
+    authorize(
+        &state,
+        &user,
+        Action::MarkEventAttendance { event_id: data.id },
+    )?;
+    Ok(Json(
+        individual_event_participation::table
+            .select(individual_event_participation::user_id)
+            .filter(individual_event_participation::event_id.eq(data.id))
+            .load::<i32>(&mut state.connection.get()?)?,
+    ))
 }
 
 pub async fn mark_event_individual_attendance(
     State(state): State<SiteState>,
     user: User,
     Form(data): Form<EventIndividualAttendance>,
-) -> Result<(), StatusCode> {
-    if !user.verified || !user.is_payment_done(&state.connection) {
-        return Err(StatusCode::UNAUTHORIZED);
-    }
-    match user.role {
-        Role::SUPER_ADMIN => {}
-        Role::FACULTY_COORDINATOR => {
-            let _: i32 = events::table
-                .inner_join(domains::table.inner_join(faculty_coordinators::table))
-                .select(faculty_coordinators::domain_id)
-                .filter(faculty_coordinators::domain_id.eq(events::domain_id))
-                .filter(faculty_coordinators::faculty_id.eq(user.id))
-                .get_result(&mut state.connection.get().map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::INTERNAL_SERVER_ERROR
-                })?)
-                .map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::UNAUTHORIZED
-                })?;
-        }
-        Role::STUDENT_COORDINATOR => {
-            let student_domain_id: Result<i32, StatusCode> = events::table
-                .inner_join(domains::table.inner_join(student_domain_coordinators::table))
-                .select(student_domain_coordinators::domain_id)
-                .filter(student_domain_coordinators::domain_id.eq(events::domain_id))
-                .filter(student_domain_coordinators::student_id.eq(user.id))
-                .get_result(&mut state.connection.get().map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::INTERNAL_SERVER_ERROR
-                })?)
-                .map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::UNAUTHORIZED
-                });
-            let student_event_id: Result<i32, StatusCode> = events::table
-                .inner_join(student_event_coordinators::table)
-                .select(student_event_coordinators::event_id)
-                .filter(student_event_coordinators::event_id.eq(data.event_id))
-                .filter(student_event_coordinators::student_id.eq(user.id))
-                .get_result(&mut state.connection.get().map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::INTERNAL_SERVER_ERROR
-                })?)
-                .map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::UNAUTHORIZED
-                });
-            match student_domain_id {
-                Ok(_) => {}
-                Err(_) => match student_event_id {
-                    Ok(_) => {}
-                    Err(e) => {
-                        return Err(e);
-                    }
-                },
-            }
-        }
-        _ => return Err(StatusCode::UNAUTHORIZED),
-    };
-    diesel::update(individual_event_participation::table)
-        .set(individual_event_participation::attended.eq(true))
-        .filter(individual_event_participation::user_id.eq(data.user_id))
-        .filter(individual_event_participation::event_id.eq(data.event_id))
-        .execute(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?;
+) -> Result<(), AppError> {
+    authorize(
+        &state,
+        &user,
+        Action::MarkEventAttendance {
+            event_id: data.event_id,
+        },
+    )?;
+    let changed = state.connection.get()?.transaction(|connection| {
+        set_attendance(
+            connection,
+            Attendance::Event {
+                event_id: data.event_id,
+                user_id: data.user_id,
+            },
+            true,
+        )
+    })?;
+    changed.ok_or_else(|| AppError::not_found("Not registered"))?;
     Ok(())
 }
 
@@ -633,233 +410,89 @@ pub async fn remove_event_individual_attendance(
     State(state): State<SiteState>,
     user: User,
     Form(data): Form<EventIndividualAttendance>,
-) -> Result<(), StatusCode> {
-    if !user.verified || !user.is_payment_done(&state.connection) {
-        return Err(StatusCode::UNAUTHORIZED);
-    }
-    match user.role {
-        Role::SUPER_ADMIN => {}
-        Role::FACULTY_COORDINATOR => {
-            let _: i32 = events::table
-                .inner_join(domains::table.inner_join(faculty_coordinators::table))
-                .select(faculty_coordinators::domain_id)
-                .filter(faculty_coordinators::domain_id.eq(events::domain_id))
-                .filter(faculty_coordinators::faculty_id.eq(user.id))
-                .get_result(&mut state.connection.get().map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::INTERNAL_SERVER_ERROR
-                })?)
-                .map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::UNAUTHORIZED
-                })?;
-        }
-        Role::STUDENT_COORDINATOR => {
-            let student_domain_id: Result<i32, StatusCode> = events::table
-                .inner_join(domains::table.inner_join(student_domain_coordinators::table))
-                .select(student_domain_coordinators::domain_id)
-                .filter(student_domain_coordinators::domain_id.eq(events::domain_id))
-                .filter(student_domain_coordinators::student_id.eq(user.id))
-                .get_result(&mut state.connection.get().map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::INTERNAL_SERVER_ERROR
-                })?)
-                .map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::UNAUTHORIZED
-                });
-            let student_event_id: Result<i32, StatusCode> = events::table
-                .inner_join(student_event_coordinators::table)
-                .select(student_event_coordinators::event_id)
-                .filter(student_event_coordinators::event_id.eq(data.event_id))
-                .filter(student_event_coordinators::student_id.eq(user.id))
-                .get_result(&mut state.connection.get().map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::INTERNAL_SERVER_ERROR
-                })?)
-                .map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::UNAUTHORIZED
-                });
-            match student_domain_id {
-                Ok(_) => {}
-                Err(_) => match student_event_id {
-                    Ok(_) => {}
-                    Err(e) => {
-                        return Err(e);
-                    }
-                },
-            }
-        }
-        _ => return Err(StatusCode::UNAUTHORIZED),
-    };
-    diesel::update(individual_event_participation::table)
-        .set(individual_event_participation::attended.eq(false))
-        .filter(individual_event_participation::user_id.eq(data.user_id))
-        .filter(individual_event_participation::event_id.eq(data.event_id))
-        .returning(individual_event_participation::user_id)
-        .execute(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map(|_| ())
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })
+) -> Result<(), AppError> {
+    authorize(
+        &state,
+        &user,
+        Action::MarkEventAttendance {
+            event_id: data.event_id,
+        },
+    )?;
+    let changed = state.connection.get()?.transaction(|connection| {
+        set_attendance(
+            connection,
+            Attendance::Event {
+                event_id: data.event_id,
+                user_id: data.user_id,
+            },
+            false,
+        )
+    })?;
+    changed.ok_or_else(|| AppError::not_found("Not registered"))?;
+    Ok(())
 }
 
 pub async fn get_event_team_attendance(
     State(state): State<SiteState>,
     user: User,
     Query(data): Query<EventId>,
-) -> Result<Json<Vec<i32>>, StatusCode> {
-    match user.role {
-        Role::SUPER_ADMIN => {}
-        Role::FACULTY_COORDINATOR => {
-            let _: i32 = events::table
-                .inner_join(domains::table.inner_join(faculty_coordinators::table))
-                .select(faculty_coordinators::domain_id)
-                .filter(faculty_coordinators::domain_id.eq(events::domain_id))
-                .filter(faculty_coordinators::faculty_id.eq(user.id))
-                .get_result(&mut state.connection.get().map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::INTERNAL_SERVER_ERROR
-                })?)
-                .map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::UNAUTHORIZED
-                })?;
-        }
-        Role::STUDENT_COORDINATOR => {
-            let student_domain_id: Result<i32, StatusCode> = events::table
-                .inner_join(domains::table.inner_join(student_domain_coordinators::table))
-                .select(student_domain_coordinators::domain_id)
-                .filter(student_domain_coordinators::domain_id.eq(events::domain_id))
-                .filter(student_domain_coordinators::student_id.eq(user.id))
-                .get_result(&mut state.connection.get().map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::INTERNAL_SERVER_ERROR
-                })?)
-                .map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::UNAUTHORIZED
-                });
-            let student_event_id: Result<i32, StatusCode> = events::table
-                .inner_join(student_event_coordinators::table)
-                .select(student_event_coordinators::event_id)
-                .filter(student_event_coordinators::event_id.eq(data.id))
-                .filter(student_event_coordinators::student_id.eq(user.id))
-                .get_result(&mut state.connection.get().map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::INTERNAL_SERVER_ERROR
-                })?)
-                .map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::UNAUTHORIZED
-                });
-            match student_domain_id {
-                Ok(_) => {}
-                Err(_) => match student_event_id {
-                    Ok(_) => {}
-                    Err(e) => {
-                        return Err(e);
-                    }
-                },
-            }
-        }
-        _ => return Err(StatusCode::UNAUTHORIZED),
-    };
-    team_event_participations::table
-        .select(team_event_participations::team_id)
-        .filter(team_event_participations::event_id.eq(data.id))
-        .load::<i32>(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map(|v| Json(v))
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })
+) -> Result<Json<Vec<i32>>, AppError> {
+    authorize(
+        &state,
+        &user,
+        Action::MarkEventAttendance { event_id: data.id },
+    )?;
+    Ok(Json(
+        team_event_participations::table
+            .select(team_event_participations::team_id)
+            .filter(team_event_participations::event_id.eq(data.id))
+            .load::<i32>(&mut state.connection.get()?)?,
+    ))
+}
+
+/// Teams that joined the event but lost members since and are now below `min_team_size`.
+pub async fn get_event_undersized_teams(
+    State(state): State<SiteState>,
+    user: User,
+    Query(data): Query<EventId>,
+) -> Result<Json<Vec<i32>>, AppError> {
+    authorize(
+        &state,
+        &user,
+        Action::MarkEventAttendance { event_id: data.id },
+    )?;
+    Ok(Json(
+        team_event_participations::table
+            .select(team_event_participations::team_id)
+            .filter(team_event_participations::event_id.eq(data.id))
+            .filter(team_event_participations::below_min_size.eq(true))
+            .load::<i32>(&mut state.connection.get()?)?,
+    ))
 }
 
 pub async fn mark_event_team_attendance(
     State(state): State<SiteState>,
     user: User,
     Form(data): Form<EventIndividualAttendance>,
-) -> Result<(), StatusCode> {
-    if !user.verified || !user.is_payment_done(&state.connection) {
-        return Err(StatusCode::UNAUTHORIZED);
-    }
-    match user.role {
-        Role::SUPER_ADMIN => {}
-        Role::FACULTY_COORDINATOR => {
-            let _: i32 = events::table
-                .inner_join(domains::table.inner_join(faculty_coordinators::table))
-                .select(faculty_coordinators::domain_id)
-                .filter(faculty_coordinators::domain_id.eq(events::domain_id))
-                .filter(faculty_coordinators::faculty_id.eq(user.id))
-                .get_result(&mut state.connection.get().map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::INTERNAL_SERVER_ERROR
-                })?)
-                .map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::UNAUTHORIZED
-                })?;
-        }
-        Role::STUDENT_COORDINATOR => {
-            let student_domain_id: Result<i32, StatusCode> = events::table
-                .inner_join(domains::table.inner_join(student_domain_coordinators::table))
-                .select(student_domain_coordinators::domain_id)
-                .filter(student_domain_coordinators::domain_id.eq(events::domain_id))
-                .filter(student_domain_coordinators::student_id.eq(user.id))
-                .get_result(&mut state.connection.get().map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::INTERNAL_SERVER_ERROR
-                })?)
-                .map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::UNAUTHORIZED
-                });
-            let student_event_id: Result<i32, StatusCode> = events::table
-                .inner_join(student_event_coordinators::table)
-                .select(student_event_coordinators::event_id)
-                .filter(student_event_coordinators::event_id.eq(data.event_id))
-                .filter(student_event_coordinators::student_id.eq(user.id))
-                .get_result(&mut state.connection.get().map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::INTERNAL_SERVER_ERROR
-                })?)
-                .map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::UNAUTHORIZED
-                });
-            match student_domain_id {
-                Ok(_) => {}
-                Err(_) => match student_event_id {
-                    Ok(_) => {}
-                    Err(e) => {
-                        return Err(e);
-                    }
-                },
-            }
-        }
-        _ => return Err(StatusCode::UNAUTHORIZED),
-    };
-    diesel::update(team_event_participations::table)
-        .set(team_event_participations::attended.eq(true))
-        .filter(team_event_participations::team_id.eq(data.user_id))
-        .filter(team_event_participations::event_id.eq(data.event_id))
-        .execute(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?;
+) -> Result<(), AppError> {
+    authorize(
+        &state,
+        &user,
+        Action::MarkEventAttendance {
+            event_id: data.event_id,
+        },
+    )?;
+    let changed = state.connection.get()?.transaction(|connection| {
+        set_attendance(
+            connection,
+            Attendance::TeamEvent {
+                event_id: data.event_id,
+                team_id: data.user_id,
+            },
+            true,
+        )
+    })?;
+    changed.ok_or_else(|| AppError::not_found("Not registered"))?;
     Ok(())
 }
 
@@ -867,251 +500,240 @@ pub async fn remove_event_team_attendance(
     State(state): State<SiteState>,
     user: User,
     Form(data): Form<EventIndividualAttendance>,
-) -> Result<(), StatusCode> {
-    if !user.verified || !user.is_payment_done(&state.connection) {
-        return Err(StatusCode::UNAUTHORIZED);
-    }
-    match user.role {
-        Role::SUPER_ADMIN => {}
-        Role::FACULTY_COORDINATOR => {
-            let _: i32 = events::table
-                .inner_join(domains::table.inner_join(faculty_coordinators::table))
-                .select(faculty_coordinators::domain_id)
-                .filter(faculty_coordinators::domain_id.eq(events::domain_id))
-                .filter(faculty_coordinators::faculty_id.eq(user.id))
-                .get_result(&mut state.connection.get().map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::INTERNAL_SERVER_ERROR
-                })?)
-                .map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::UNAUTHORIZED
-                })?;
-        }
-        Role::STUDENT_COORDINATOR => {
-            let student_domain_id: Result<i32, StatusCode> = events::table
-                .inner_join(domains::table.inner_join(student_domain_coordinators::table))
-                .select(student_domain_coordinators::domain_id)
-                .filter(student_domain_coordinators::domain_id.eq(events::domain_id))
-                .filter(student_domain_coordinators::student_id.eq(user.id))
-                .get_result(&mut state.connection.get().map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::INTERNAL_SERVER_ERROR
-                })?)
-                .map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::UNAUTHORIZED
-                });
-            let student_event_id: Result<i32, StatusCode> = events::table
-                .inner_join(student_event_coordinators::table)
-                .select(student_event_coordinators::event_id)
-                .filter(student_event_coordinators::event_id.eq(data.event_id))
-                .filter(student_event_coordinators::student_id.eq(user.id))
-                .get_result(&mut state.connection.get().map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::INTERNAL_SERVER_ERROR
-                })?)
-                .map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::UNAUTHORIZED
-                });
-            match student_domain_id {
-                Ok(_) => {}
-                Err(_) => match student_event_id {
-                    Ok(_) => {}
-                    Err(e) => {
-                        return Err(e);
-                    }
-                },
-            }
-        }
-        _ => return Err(StatusCode::UNAUTHORIZED),
-    };
-    diesel::update(team_event_participations::table)
-        .set(team_event_participations::attended.eq(false))
-        .filter(team_event_participations::team_id.eq(data.user_id))
-        .filter(team_event_participations::event_id.eq(data.event_id))
-        .returning(team_event_participations::team_id)
-        .execute(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map(|_| ())
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })
+) -> Result<(), AppError> {
+    authorize(
+        &state,
+        &user,
+        Action::MarkEventAttendance {
+            event_id: data.event_id,
+        },
+    )?;
+    let changed = state.connection.get()?.transaction(|connection| {
+        set_attendance(
+            connection,
+            Attendance::TeamEvent {
+                event_id: data.event_id,
+                team_id: data.user_id,
+            },
+            false,
+        )
+    })?;
+    changed.ok_or_else(|| AppError::not_found("Not registered"))?;
+    Ok(())
 }
 
 pub async fn join_event_individual(
     State(state): State<SiteState>,
     user: User,
     Form(data): Form<EventId>,
-) -> Result<(), StatusCode> {
+) -> Result<Json<JoinStatus>, AppError> {
     if !user.verified || !user.is_payment_done(&state.connection) {
-        return Err(StatusCode::UNAUTHORIZED);
+        return Err(AppError::forbidden(
+            "Verify your email and pay the fee to register",
+        ));
     }
-    EventIndividualAttendance {
-        user_id: user.id,
-        event_id: data.id,
+    let connection = &mut state.connection.get()?;
+    Schedule::of_event(connection, data.id)
+        .optional()?
+        .ok_or_else(|| AppError::not_found("No such event"))?
+        .check_join()?;
+    let (name, taken) = connection.transaction(|connection| {
+        let taken = take_seat(connection, &EventSeats(data.id), user.id)?;
+        if let Some(JoinStatus::JOINED) = taken.as_ref().map(|taken| taken.status) {
+            queue_confirmations(&state, connection, data.id, std::slice::from_ref(&user))?;
+        }
+        let name: String = events::table
+            .select(events::name)
+            .find(data.id)
+            .get_result(connection)?;
+        Ok::<_, Error>((name, taken))
+    })?;
+    let taken = taken.ok_or_else(|| AppError::conflict("Already registered or on the waitlist"))?;
+    if let JoinStatus::JOINED = taken.status {
+        state.wake_outbox();
     }
-    .insert_into(individual_event_participation::table)
-    .execute(&mut state.connection.get().map_err(|e| {
-        log::error!("{e:?}");
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?)
-    .map_err(|e| {
-        log::error!("{e:?}");
-        StatusCode::NOT_MODIFIED
-    })
-    .map(|_| ())
+    notify_promoted_users(&state, &taken.promoted, &name);
+    Ok(Json(taken.status))
 }
 
 pub async fn leave_event_individual(
     State(state): State<SiteState>,
     user: User,
     Form(data): Form<EventId>,
-) -> Result<(), StatusCode> {
+) -> Result<(), AppError> {
     if !user.verified || !user.is_payment_done(&state.connection) {
-        return Err(StatusCode::UNAUTHORIZED);
+        return Err(AppError::forbidden(
+            "Verify your email and pay the fee to register",
+        ));
     }
-    diesel::delete(individual_event_participation::table)
-        .filter(individual_event_participation::user_id.eq(user.id))
-        .filter(individual_event_participation::event_id.eq(data.id))
-        .execute(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::NOT_MODIFIED
-        })
-        .map(|_| ())
+    let connection = &mut state.connection.get()?;
+    Schedule::of_event(connection, data.id)
+        .optional()?
+        .ok_or_else(|| AppError::not_found("No such event"))?
+        .check_leave()?;
+    let (name, promoted) = connection.transaction(|connection| {
+        let promoted = release_seat(connection, &EventSeats(data.id), user.id)?;
+        let name: String = events::table
+            .select(events::name)
+            .filter(events::id.eq(data.id))
+            .get_result(connection)?;
+        Ok::<_, Error>((name, promoted))
+    })?;
+    notify_promoted_users(&state, &promoted, &name);
+    Ok(())
+}
+
+/// Registers a user regardless of the registration window and capacity, for late entries.
+pub async fn register_event_individual(
+    State(state): State<SiteState>,
+    user: User,
+    Form(data): Form<EventIndividualAttendance>,
+) -> Result<(), AppError> {
+    authorize(&state, &user, Action::OverrideRegistration)?;
+    state.connection.get()?.transaction(|connection| {
+        diesel::delete(individual_event_waitlist::table)
+            .filter(individual_event_waitlist::event_id.eq(data.event_id))
+            .filter(individual_event_waitlist::user_id.eq(data.user_id))
+            .execute(connection)?;
+        data.insert_into(individual_event_participation::table)
+            .on_conflict_do_nothing()
+            .execute(connection)
+    })?;
+    Ok(())
 }
 
 pub async fn join_event_team(
     State(state): State<SiteState>,
     user: User,
     Form(data): Form<EventTeamAttendance>,
-) -> Result<(), StatusCode> {
-    let is_leader: bool = team_members::table
-        .select(team_members::is_leader)
-        .filter(team_members::team_id.eq(data.team_id))
-        .filter(team_members::student_id.eq(user.id))
-        .get_result(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::UNAUTHORIZED
-        })?;
-    if !is_leader {
-        return Err(StatusCode::UNAUTHORIZED);
+) -> Result<Json<JoinStatus>, AppError> {
+    let connection = &mut state.connection.get()?;
+    require_leader(connection, data.team_id, user.id)?;
+    Schedule::of_event(connection, data.event_id)
+        .optional()?
+        .ok_or_else(|| AppError::not_found("No such event"))?
+        .check_join()?;
+    let (name, min_team_size, max_team_size, count_pending_requests): (String, i32, i32, bool) =
+        events::table
+            .select((
+                events::name,
+                events::min_team_size,
+                events::max_team_size,
+                events::count_pending_requests,
+            ))
+            .filter(events::id.eq(data.event_id))
+            .get_result(connection)?;
+    let taken = connection
+        .transaction::<_, AppError, _>(|connection| {
+            lock_team(connection, data.team_id)?;
+            let size = team_size(connection, data.team_id, count_pending_requests)?;
+            check_team_size(&name, size, min_team_size, max_team_size)?;
+            let taken = take_seat(connection, &TeamEventSeats(data.event_id), data.team_id)?;
+            if let Some(JoinStatus::JOINED) = taken.as_ref().map(|taken| taken.status) {
+                let members: Vec<User> = users::table
+                    .select(User::as_select())
+                    .filter(
+                        users::id.eq_any(
+                            team_members::table
+                                .select(team_members::student_id)
+                                .filter(team_members::team_id.eq(data.team_id)),
+                        ),
+                    )
+                    .load(connection)?;
+                queue_confirmations(&state, connection, data.event_id, &members)?;
+            }
+            Ok(taken)
+        })?
+        .ok_or_else(|| AppError::conflict("The team is already registered or on the waitlist"))?;
+    if let JoinStatus::JOINED = taken.status {
+        state.wake_outbox();
     }
-    data.insert_into(team_event_participations::table)
-        .execute(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::NOT_MODIFIED
-        })
-        .map(|_| ())
+    notify_promoted_teams(&state, &taken.promoted, &name);
+    Ok(Json(taken.status))
 }
 
 pub async fn leave_event_team(
     State(state): State<SiteState>,
     user: User,
     Form(data): Form<EventTeamAttendance>,
-) -> Result<(), StatusCode> {
-    let is_leader: bool = team_members::table
-        .select(team_members::is_leader)
-        .filter(team_members::team_id.eq(data.team_id))
-        .filter(team_members::student_id.eq(user.id))
-        .get_result(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::UNAUTHORIZED
-        })?;
-    if !is_leader {
-        return Err(StatusCode::UNAUTHORIZED);
-    }
-    diesel::delete(team_event_participations::table)
-        .filter(team_event_participations::team_id.eq(data.team_id))
-        .filter(team_event_participations::event_id.eq(data.event_id))
-        .execute(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::NOT_MODIFIED
-        })
-        .map(|_| ())
+) -> Result<(), AppError> {
+    let connection = &mut state.connection.get()?;
+    require_leader(connection, data.team_id, user.id)?;
+    Schedule::of_event(connection, data.event_id)
+        .optional()?
+        .ok_or_else(|| AppError::not_found("No such event"))?
+        .check_leave()?;
+    let (name, promoted) = connection.transaction(|connection| {
+        let promoted = release_seat(connection, &TeamEventSeats(data.event_id), data.team_id)?;
+        let name: String = events::table
+            .select(events::name)
+            .filter(events::id.eq(data.event_id))
+            .get_result(connection)?;
+        Ok::<_, Error>((name, promoted))
+    })?;
+    notify_promoted_teams(&state, &promoted, &name);
+    Ok(())
+}
+
+/// Registers a team regardless of the registration window and capacity, for late entries.
+pub async fn register_event_team(
+    State(state): State<SiteState>,
+    user: User,
+    Form(data): Form<EventTeamAttendance>,
+) -> Result<(), AppError> {
+    authorize(&state, &user, Action::OverrideRegistration)?;
+    state.connection.get()?.transaction(|connection| {
+        diesel::delete(team_event_waitlist::table)
+            .filter(team_event_waitlist::event_id.eq(data.event_id))
+            .filter(team_event_waitlist::team_id.eq(data.team_id))
+            .execute(connection)?;
+        data.insert_into(team_event_participations::table)
+            .on_conflict_do_nothing()
+            .execute(connection)
+    })?;
+    Ok(())
 }
 
 pub async fn joined_events_individual(
     State(state): State<SiteState>,
     user: User,
-) -> Result<Json<Vec<Event>>, StatusCode> {
-    individual_event_participation::table
-        .inner_join(events::table)
-        .select(Event::as_select())
-        .filter(individual_event_participation::user_id.eq(user.id))
-        .load(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::NOT_MODIFIED
-        })
-        .map(|v| Json(v))
+) -> Result<Json<Vec<Event>>, AppError> {
+    Ok(Json(
+        individual_event_participation::table
+            .inner_join(events::table)
+            .select(Event::as_select())
+            .filter(individual_event_participation::user_id.eq(user.id))
+            .load(&mut state.connection.get()?)?,
+    ))
 }
 
 pub async fn joined_events_team(
     State(state): State<SiteState>,
     user: User,
     Query(data): Query<TeamId>,
-) -> Result<Json<Vec<Event>>, StatusCode> {
-    team_event_participations::table
-        .inner_join(teams::table.inner_join(team_members::table))
-        .filter(teams::id.eq(data.id))
-        .inner_join(events::table)
-        .select(Event::as_select())
-        .filter(team_members::student_id.eq(user.id))
-        .distinct()
-        .load(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::NOT_MODIFIED
-        })
-        .map(|v| Json(v))
+) -> Result<Json<Vec<Event>>, AppError> {
+    Ok(Json(
+        team_event_participations::table
+            .inner_join(teams::table.inner_join(team_members::table))
+            .filter(teams::id.eq(data.id))
+            .inner_join(events::table)
+            .select(Event::as_select())
+            .filter(team_members::student_id.eq(user.id))
+            .distinct()
+            .load(&mut state.connection.get()?)?,
+    ))
 }
 
 pub async fn event_domain(
     State(state): State<SiteState>,
     Query(data): Query<EventId>,
-) -> Result<Json<Domain>, StatusCode> {
+) -> Result<Json<Domain>, AppError> {
     events::table
         .inner_join(domains::table)
         .select(Domain::as_select())
         .filter(events::id.eq(data.id))
-        .get_result(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::NOT_MODIFIED
-        })
-        .map(|v| Json(v))
+        .get_result(&mut state.connection.get()?)
+        .optional()?
+        .map(Json)
+        .ok_or_else(|| AppError::not_found("No such event"))
 }
@@ -0,0 +1,187 @@
+//! Review of faculty accounts. Faculty sign up, or add a faculty profile, as plain participants
+//! and land in a queue. A super admin approves them as faculty coordinators of a domain, or
+//! rejects them, and the applicant gets a mail either way.
+use axum::{
+    extract::{Query, State},
+    Form, Json,
+};
+use diesel::prelude::*;
+use diesel::result::Error;
+
+use crate::{
+    error::AppError,
+    forms::{
+        faculty::{ApplicationQuery, ApproveFaculty, RejectFaculty},
+        users::Profile,
+    },
+    models::{
+        faculty::{
+            ApplicationStatus, Faculty, FacultyApplication, FacultyApplicationResponse,
+            NewFacultyApplication,
+        },
+        users::{Role, User},
+    },
+    outbox::enqueue,
+    policy::require_super_admin,
+    roles::{appoint, Appointment},
+    schema::{domains, faculty, faculty_applications, faculty_coordinators, users},
+    state::SiteState,
+};
+
+/// Puts the faculty profile of `user_id` in the review queue, on the caller's connection so it
+/// shares their transaction.
+pub fn file_application(connection: &mut PgConnection, user_id: i32) -> QueryResult<usize> {
+    NewFacultyApplication { user_id }
+        .insert_into(faculty_applications::table)
+        .execute(connection)
+}
+
+/// Applications with the given status, `PENDING` by default, oldest first.
+pub async fn get_faculty_applications(
+    State(state): State<SiteState>,
+    user: User,
+    Query(data): Query<ApplicationQuery>,
+) -> Result<Json<Vec<FacultyApplicationResponse>>, AppError> {
+    require_super_admin(&state, &user)?;
+    let applications: Vec<(FacultyApplication, Faculty, Profile)> = faculty_applications::table
+        .inner_join(faculty::table.inner_join(users::table))
+        .filter(faculty_applications::status.eq(data.status.unwrap_or(ApplicationStatus::PENDING)))
+        .order(faculty_applications::created_at.asc())
+        .select((
+            FacultyApplication::as_select(),
+            Faculty::as_select(),
+            Profile::as_select(),
+        ))
+        .load(&mut state.connection.get()?)?;
+    Ok(Json(
+        applications
+            .into_iter()
+            .map(
+                |(application, faculty, profile)| FacultyApplicationResponse {
+                    application,
+                    faculty,
+                    profile,
+                },
+            )
+            .collect(),
+    ))
+}
+
+/// Marks a pending application as reviewed, `None` when there is no pending one for `user_id`.
+fn review(
+    connection: &mut PgConnection,
+    user_id: i32,
+    reviewer: &User,
+    status: ApplicationStatus,
+    reason: Option<&str>,
+) -> QueryResult<Option<User>> {
+    let reviewed = diesel::update(faculty_applications::table)
+        .filter(faculty_applications::user_id.eq(user_id))
+        .filter(faculty_applications::status.eq(ApplicationStatus::PENDING))
+        .set((
+            faculty_applications::status.eq(status),
+            faculty_applications::reason.eq(reason),
+            faculty_applications::reviewed_by.eq(reviewer.id),
+            faculty_applications::reviewed_at.eq(diesel::dsl::now.nullable()),
+        ))
+        .execute(connection)?;
+    if reviewed == 0 {
+        return Ok(None);
+    }
+    users::table
+        .select(User::as_select())
+        .filter(users::id.eq(user_id))
+        .get_result(connection)
+        .map(Some)
+}
+
+/// Makes the applicant a faculty coordinator of the domain.
+pub async fn approve_faculty(
+    State(state): State<SiteState>,
+    user: User,
+    Form(data): Form<ApproveFaculty>,
+) -> Result<(), AppError> {
+    require_super_admin(&state, &user)?;
+    let connection = &mut state.connection.get()?;
+    let domain_name: String = domains::table
+        .select(domains::name)
+        .filter(domains::id.eq(data.domain_id))
+        .get_result(connection)
+        .optional()?
+        .ok_or_else(|| AppError::not_found("No such domain"))?;
+    let approved = connection.transaction::<_, AppError, _>(|connection| {
+        let Some(applicant) = review(
+            connection,
+            data.user_id,
+            &user,
+            ApplicationStatus::APPROVED,
+            None,
+        )?
+        else {
+            return Ok(false);
+        };
+        appoint(
+            connection,
+            applicant.id,
+            Appointment::Faculty,
+            |connection| {
+                diesel::insert_into(faculty_coordinators::table)
+                    .values((
+                        faculty_coordinators::faculty_id.eq(applicant.id),
+                        faculty_coordinators::domain_id.eq(data.domain_id),
+                    ))
+                    .on_conflict_do_nothing()
+                    .execute(connection)
+            },
+        )?;
+        enqueue(
+            connection,
+            &[applicant.faculty_approved_email(
+                &state.templates,
+                &Role::FACULTY_COORDINATOR,
+                &domain_name,
+            )],
+        )?;
+        Ok(true)
+    })?;
+    if !approved {
+        return Err(AppError::not_found("No pending application for this user"));
+    }
+    state.wake_outbox();
+    Ok(())
+}
+
+/// Leaves the applicant a participant and tells them why.
+pub async fn reject_faculty(
+    State(state): State<SiteState>,
+    user: User,
+    Form(data): Form<RejectFaculty>,
+) -> Result<(), AppError> {
+    require_super_admin(&state, &user)?;
+    let reason = data.reason.as_deref().filter(|r| !r.trim().is_empty());
+    let rejected = state
+        .connection
+        .get()?
+        .transaction::<_, Error, _>(|connection| {
+            let Some(applicant) = review(
+                connection,
+                data.user_id,
+                &user,
+                ApplicationStatus::REJECTED,
+                reason,
+            )?
+            else {
+                return Ok(false);
+            };
+            enqueue(
+                connection,
+                &[applicant.faculty_rejected_email(&state.templates, reason)],
+            )?;
+            Ok(true)
+        })?;
+    if !rejected {
+        return Err(AppError::not_found("No pending application for this user"));
+    }
+    state.wake_outbox();
+    Ok(())
+}
@@ -1,7 +1,19 @@
+pub mod certificates;
+pub mod checkin;
 pub mod domains;
 pub mod events;
 pub mod faculty;
+pub mod identities;
+pub mod judging;
+pub mod outbox;
+pub mod payments;
+pub mod points;
+pub mod results;
+pub mod roles;
+pub mod sessions;
+pub mod sponsors;
 pub mod student;
 pub mod teams;
+pub mod two_factor;
 pub mod users;
 pub mod workshops;
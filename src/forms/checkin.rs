@@ -0,0 +1,40 @@
+use serde::Deserialize;
+
+use crate::models::checkin::CheckInTarget;
+
+#[derive(Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum QrFormat {
+    #[default]
+    Png,
+    Svg,
+}
+
+/// Which registration to issue a check-in code for: `event_id` alone for an individual event,
+/// `event_id` with `team_id` for a team event, or `workshop_id`.
+#[derive(Deserialize, Debug, Clone)]
+pub struct CheckInQuery {
+    pub event_id: Option<i32>,
+    pub team_id: Option<i32>,
+    pub workshop_id: Option<i32>,
+    #[serde(default)]
+    pub format: QrFormat,
+}
+
+impl CheckInQuery {
+    pub fn target(&self) -> Option<CheckInTarget> {
+        match (self.event_id, self.team_id, self.workshop_id) {
+            (Some(event_id), None, None) => Some(CheckInTarget::Event { event_id }),
+            (Some(event_id), Some(team_id), None) => {
+                Some(CheckInTarget::Team { event_id, team_id })
+            }
+            (None, None, Some(workshop_id)) => Some(CheckInTarget::Workshop { workshop_id }),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ScanCheckIn {
+    pub token: String,
+}
@@ -2,8 +2,13 @@ use serde::Deserialize;
 
 use diesel::prelude::*;
 
+use crate::error::AppError;
+use crate::models::events::Event;
 use crate::models::events::Mode;
 use crate::models::events::ParticipationType;
+use crate::registration::{
+    check_schedule_order, check_team_size_order, DEFAULT_MAX_TEAM_SIZE, DEFAULT_MIN_TEAM_SIZE,
+};
 #[derive(Deserialize, Insertable, Queryable, Debug, Clone)]
 #[diesel(table_name = crate::schema::events)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
@@ -22,6 +27,26 @@ pub struct CreateEvent {
     pub registeration_end: chrono::NaiveDateTime,
     pub participation_type: ParticipationType,
     pub whatsapp_link: String,
+    pub max_participants: Option<i32>,
+    pub max_teams: Option<i32>,
+    pub min_team_size: Option<i32>,
+    pub max_team_size: Option<i32>,
+    pub count_pending_requests: Option<bool>,
+}
+
+impl CreateEvent {
+    pub fn validate(&self) -> Result<(), AppError> {
+        check_schedule_order(
+            self.registeration_start,
+            self.registeration_end,
+            self.start_time,
+            self.end_time,
+        )?;
+        check_team_size_order(
+            self.min_team_size.unwrap_or(DEFAULT_MIN_TEAM_SIZE),
+            self.max_team_size.unwrap_or(DEFAULT_MAX_TEAM_SIZE),
+        )
+    }
 }
 
 #[derive(Queryable, Deserialize, Debug, Clone)]
@@ -49,6 +74,29 @@ pub struct ChangeEvent {
     pub registeration_end: Option<chrono::NaiveDateTime>,
     pub participation_type: Option<ParticipationType>,
     pub whatsapp_link: Option<String>,
+    pub max_participants: Option<i32>,
+    pub max_teams: Option<i32>,
+    pub min_team_size: Option<i32>,
+    pub max_team_size: Option<i32>,
+    pub count_pending_requests: Option<bool>,
+}
+
+impl ChangeEvent {
+    /// Checks the schedule and team sizes the event will have once this change is applied to
+    /// `current`.
+    pub fn validate(&self, current: &Event) -> Result<(), AppError> {
+        check_schedule_order(
+            self.registeration_start
+                .unwrap_or(current.registeration_start),
+            self.registeration_end.unwrap_or(current.registeration_end),
+            self.start_time.unwrap_or(current.start_time),
+            self.end_time.unwrap_or(current.end_time),
+        )?;
+        check_team_size_order(
+            self.min_team_size.unwrap_or(current.min_team_size),
+            self.max_team_size.unwrap_or(current.max_team_size),
+        )
+    }
 }
 
 #[derive(Queryable, Deserialize, Debug, Clone)]
@@ -1,14 +1,11 @@
-use argon2::{
-    password_hash::{self, rand_core::OsRng, PasswordHasher, SaltString},
-    Argon2,
-};
+use argon2::password_hash;
 use diesel::prelude::*;
 use serde::Deserialize;
 
 use crate::models::{
-    faculty::{Faculty, Title},
+    faculty::{ApplicationStatus, Faculty, Title},
     students::Department,
-    users::{Role, User},
+    users::{hash_password, Role, User},
 };
 
 #[derive(Deserialize, Clone)]
@@ -17,7 +14,6 @@ pub struct FacultySignUp {
     pub dob: chrono::NaiveDate,
     pub email: String,
     pub phone: String,
-    pub role: Role,
     pub password: String,
     pub title: Title,
     pub dept: Department,
@@ -27,20 +23,17 @@ impl TryInto<User> for FacultySignUp {
     type Error = password_hash::Error;
 
     fn try_into(self) -> Result<User, Self::Error> {
-        let salt = SaltString::generate(&mut OsRng);
-        let argon2 = Argon2::default();
-        let password_hash = argon2.hash_password(self.password.as_bytes(), &salt)?;
-
         Ok(User {
             id: 0,
             name: self.name,
             dob: self.dob,
             email: self.email,
             phone: self.phone,
-            role: self.role,
+            // Faculty only get their role once a super admin approves them
+            role: Role::PARTICIPANT,
             photo_hash: None,
             verified: false,
-            password_hash: password_hash.to_string(),
+            password_hash: hash_password(&self.password)?,
         })
     }
 }
@@ -64,3 +57,22 @@ pub struct NewFacultyProfile {
     pub dept: Department,
 }
 
+#[derive(Deserialize, Debug, Clone)]
+pub struct ApplicationQuery {
+    /// `PENDING` when left out
+    pub status: Option<ApplicationStatus>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ApproveFaculty {
+    pub user_id: i32,
+    /// The domain they coordinate from now on
+    pub domain_id: i32,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct RejectFaculty {
+    pub user_id: i32,
+    /// Passed on to the applicant
+    pub reason: Option<String>,
+}
@@ -0,0 +1,9 @@
+use serde::Deserialize;
+
+/// Query the provider redirects back with, either a code or an error.
+#[derive(Deserialize, Debug, Clone)]
+pub struct OidcCallback {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    pub error: Option<String>,
+}
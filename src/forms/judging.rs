@@ -0,0 +1,34 @@
+use diesel::prelude::*;
+use serde::Deserialize;
+
+#[derive(Deserialize, Insertable, Debug, Clone)]
+#[diesel(table_name = crate::schema::event_judges)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct EventJudge {
+    pub event_id: i32,
+    pub user_id: i32,
+}
+
+#[derive(Deserialize, Insertable, Debug, Clone)]
+#[diesel(table_name = crate::schema::judging_criteria)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct CreateCriterion {
+    pub event_id: i32,
+    pub name: String,
+    pub weight: f64,
+    pub max_marks: i32,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct CriterionId {
+    pub id: i32,
+}
+
+/// Marks for one criterion, for `user_id` in individual events or `team_id` in team events.
+#[derive(Deserialize, Debug, Clone)]
+pub struct SubmitScore {
+    pub criterion_id: i32,
+    pub user_id: Option<i32>,
+    pub team_id: Option<i32>,
+    pub marks: f64,
+}
@@ -0,0 +1,14 @@
+use serde::Deserialize;
+
+use crate::models::outbox::MailStatus;
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct OutboxQuery {
+    /// `FAILED` when left out
+    pub status: Option<MailStatus>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct MailId {
+    pub id: i32,
+}
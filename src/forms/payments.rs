@@ -0,0 +1,71 @@
+use diesel::prelude::*;
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct SubmitPayment {
+    pub payment_id: String,
+    pub payment_amount: i32,
+}
+
+#[derive(Insertable, Debug, Clone)]
+#[diesel(table_name = crate::schema::payments)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewPayment {
+    pub user_id: i32,
+    pub payment_id: String,
+    pub payment_amount: i32,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct PaymentId {
+    pub payment_id: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct RejectPayment {
+    pub payment_id: String,
+    pub reason: String,
+}
+
+/// Body of a payment gateway webhook, only the fields we act upon.
+#[derive(Deserialize, Debug, Clone)]
+pub struct WebhookEvent {
+    pub event: String,
+    pub payload: WebhookPayload,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct WebhookPayload {
+    pub payment: Option<WebhookEntity<GatewayPayment>>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct WebhookEntity<T> {
+    pub entity: T,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct GatewayPayment {
+    pub id: String,
+    pub order_id: Option<String>,
+    /// In the smallest currency unit, i.e. paise
+    pub amount: i64,
+    pub email: Option<String>,
+    #[serde(default)]
+    pub notes: serde_json::Value,
+}
+
+impl GatewayPayment {
+    /// The gateway sends notes as an object of strings, or as an empty array when there are none.
+    pub fn user_id(&self) -> Option<i32> {
+        match self.notes.get("user_id")? {
+            serde_json::Value::String(v) => v.trim().parse().ok(),
+            serde_json::Value::Number(v) => v.as_i64()?.try_into().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn amount_in_rupees(&self) -> i32 {
+        (self.amount / 100).try_into().unwrap_or(i32::MAX)
+    }
+}
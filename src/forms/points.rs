@@ -0,0 +1,23 @@
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct SpendPoints {
+    pub amount: i32,
+    /// What the points were spent on
+    pub note: String,
+}
+
+/// Hands `amount` of the points `team_id` got for `event_id` to one of its members.
+#[derive(Deserialize, Debug, Clone)]
+pub struct DistributeTeamPoints {
+    pub team_id: i32,
+    pub event_id: i32,
+    pub user_id: i32,
+    pub amount: i32,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct LeaderboardQuery {
+    pub domain_id: Option<i32>,
+    pub limit: Option<i64>,
+}
@@ -0,0 +1,18 @@
+use serde::Deserialize;
+
+use crate::models::results::Placement;
+
+/// Winner of an event, either `user_id` for individual events or `team_id` for team events.
+#[derive(Deserialize, Debug, Clone)]
+pub struct PublishEventResult {
+    pub event_id: i32,
+    pub placement: Placement,
+    pub user_id: Option<i32>,
+    pub team_id: Option<i32>,
+    pub note: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct EventResultId {
+    pub id: i32,
+}
@@ -0,0 +1,9 @@
+use serde::Deserialize;
+
+use crate::models::users::Role;
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct SetRole {
+    pub user_id: i32,
+    pub role: Role,
+}
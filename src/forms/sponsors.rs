@@ -0,0 +1,42 @@
+use serde::{Deserialize, Deserializer};
+
+use diesel::prelude::*;
+
+use crate::models::sponsors::SponsorTier;
+
+#[derive(Deserialize, Insertable, Debug, Clone)]
+#[diesel(table_name = crate::schema::sponsors)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct CreateSponsor {
+    pub name: String,
+    pub tier: SponsorTier,
+    #[serde(default)]
+    pub display_order: i32,
+    pub website: Option<String>,
+}
+
+#[derive(Deserialize, AsChangeset, Debug, Clone)]
+#[diesel(table_name = crate::schema::sponsors)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct ChangeSponsor {
+    pub id: i32,
+    pub name: Option<String>,
+    pub tier: Option<SponsorTier>,
+    pub display_order: Option<i32>,
+    /// Left alone when not given, and cleared when given empty
+    #[serde(default, deserialize_with = "clearable")]
+    pub website: Option<Option<String>>,
+}
+
+/// A field that is present is `Some`, holding `None` when it is empty.
+fn clearable<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Option<String>>, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    Ok(Some(Some(value).filter(|v| !v.trim().is_empty())))
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct SponsorId {
+    pub id: i32,
+}
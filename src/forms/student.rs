@@ -1,13 +1,10 @@
-use argon2::{
-    password_hash::{self, rand_core::OsRng, PasswordHasher, SaltString},
-    Argon2,
-};
+use argon2::password_hash;
 use diesel::prelude::*;
-use serde::{Deserialize, Serialize};
+use serde::Deserialize;
 
 use crate::models::{
     students::{Department, Student},
-    users::{Role, User},
+    users::{hash_password, Role, User},
 };
 
 #[derive(Deserialize, Clone)]
@@ -26,10 +23,6 @@ impl TryInto<User> for StudentSignUp {
     type Error = password_hash::Error;
 
     fn try_into(self) -> Result<User, Self::Error> {
-        let salt = SaltString::generate(&mut OsRng);
-        let argon2 = Argon2::default();
-        let password_hash = argon2.hash_password(self.password.as_bytes(), &salt)?;
-
         Ok(User {
             id: 0,
             name: self.name,
@@ -39,7 +32,7 @@ impl TryInto<User> for StudentSignUp {
             role: Role::PARTICIPANT,
             photo_hash: None,
             verified: false,
-            password_hash: password_hash.to_string(),
+            password_hash: hash_password(&self.password)?,
         })
     }
 }
@@ -0,0 +1,15 @@
+use serde::Deserialize;
+
+use crate::models::users::Role;
+
+/// Six digits from the authenticator app, or, where accepted, one of the recovery codes.
+#[derive(Deserialize, Debug, Clone)]
+pub struct TwoFactorCode {
+    pub code: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct RequiredRole {
+    pub role: Role,
+    pub required: bool,
+}
@@ -2,7 +2,10 @@ use serde::Deserialize;
 
 use diesel::prelude::*;
 
+use crate::error::AppError;
 use crate::models::events::Mode;
+use crate::models::workshops::Workshop;
+use crate::registration::check_schedule_order;
 #[derive(Deserialize, Insertable, Queryable, Debug, Clone)]
 #[diesel(table_name = crate::schema::workshops)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
@@ -21,6 +24,18 @@ pub struct CreateWorkshop {
     pub prof_name: String,
     pub prof_title: String,
     pub whatsapp_link: String,
+    pub max_participants: Option<i32>,
+}
+
+impl CreateWorkshop {
+    pub fn validate(&self) -> Result<(), AppError> {
+        check_schedule_order(
+            self.registeration_start,
+            self.registeration_end,
+            self.start_time,
+            self.end_time,
+        )
+    }
 }
 
 #[derive(Queryable, Deserialize, Debug, Clone)]
@@ -47,6 +62,20 @@ pub struct ChangeWorkshop {
     pub whatsapp_link: Option<String>,
     pub prof_name: Option<String>,
     pub prof_title: Option<String>,
+    pub max_participants: Option<i32>,
+}
+
+impl ChangeWorkshop {
+    /// Checks the schedule the workshop will have once this change is applied to `current`.
+    pub fn validate(&self, current: &Workshop) -> Result<(), AppError> {
+        check_schedule_order(
+            self.registeration_start
+                .unwrap_or(current.registeration_start),
+            self.registeration_end.unwrap_or(current.registeration_end),
+            self.start_time.unwrap_or(current.start_time),
+            self.end_time.unwrap_or(current.end_time),
+        )
+    }
 }
 
 #[derive(Queryable, Deserialize, Debug, Clone)]
@@ -0,0 +1,630 @@
+//! Judging of competitive events.
+//!
+//! Judges are appointed per event by the coordinators of its domain, who also set the rubric: a
+//! list of criteria, each with a weight and the most marks it can get. Judges then mark every
+//! registered individual or team against every criterion. A judge's score for an entrant is the
+//! weighted sum of their marks as a percentage of the best possible, and only counts once every
+//! criterion is marked. To keep a harsh judge from weighing less than a lenient one, each judge's
+//! scores are turned into z-scores over the entrants they judged before being averaged, and the
+//! ranking goes by that. Once the coordinators finalize the event nothing about its judging can
+//! change any more.
+use std::collections::BTreeMap;
+
+use axum::{
+    extract::{Query, State},
+    Form, Json,
+};
+use diesel::prelude::*;
+use diesel::result::Error;
+use http::StatusCode;
+
+use crate::{
+    error::AppError,
+    forms::{
+        events::EventId,
+        judging::{CreateCriterion, CriterionId, EventJudge, SubmitScore},
+        users::Profile,
+    },
+    models::{
+        judging::{Criterion, JudgedEntrant, JudgingResults, NewScore, Score},
+        users::User,
+    },
+    policy::{authorize, Action},
+    schema::{
+        event_judges, events, individual_event_participation, judge_scores, judging_criteria,
+        team_event_participations, teams, users,
+    },
+    state::SiteState,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Entrant {
+    User(i32),
+    Team(i32),
+}
+
+impl Entrant {
+    fn of(user_id: Option<i32>, team_id: Option<i32>) -> Option<Self> {
+        match (user_id, team_id) {
+            (Some(user_id), None) => Some(Entrant::User(user_id)),
+            (None, Some(team_id)) => Some(Entrant::Team(team_id)),
+            _ => None,
+        }
+    }
+}
+
+struct Standing {
+    entrant: Entrant,
+    judges: usize,
+    average: f64,
+    normalized: f64,
+}
+
+fn mean(values: &[f64]) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+/// Standings of everyone with at least one complete score sheet, best first.
+fn aggregate(criteria: &[Criterion], scores: &[Score]) -> Vec<Standing> {
+    let total_weight: f64 = criteria.iter().map(|c| c.weight).sum();
+    let criteria: BTreeMap<i32, &Criterion> = criteria.iter().map(|c| (c.id, c)).collect();
+
+    let mut sheets: BTreeMap<(i32, Entrant), (f64, usize)> = BTreeMap::new();
+    for score in scores {
+        let (Some(criterion), Some(entrant)) = (
+            criteria.get(&score.criterion_id),
+            Entrant::of(score.user_id, score.team_id),
+        ) else {
+            continue;
+        };
+        let sheet = sheets.entry((score.judge_id, entrant)).or_default();
+        sheet.0 += score.marks / f64::from(criterion.max_marks) * criterion.weight;
+        sheet.1 += 1;
+    }
+
+    let mut by_judge: BTreeMap<i32, Vec<(Entrant, f64)>> = BTreeMap::new();
+    for ((judge_id, entrant), (weighted, marked)) in sheets {
+        if marked == criteria.len() && total_weight > 0.0 {
+            by_judge
+                .entry(judge_id)
+                .or_default()
+                .push((entrant, weighted / total_weight * 100.0));
+        }
+    }
+
+    let mut by_entrant: BTreeMap<Entrant, (Vec<f64>, Vec<f64>)> = BTreeMap::new();
+    for sheets in by_judge.values() {
+        let percentages: Vec<f64> = sheets.iter().map(|&(_, p)| p).collect();
+        let judge_mean = mean(&percentages);
+        let deviation = mean(
+            &percentages
+                .iter()
+                .map(|p| (p - judge_mean).powi(2))
+                .collect::<Vec<f64>>(),
+        )
+        .sqrt();
+        for &(entrant, percentage) in sheets {
+            // A judge who gave everyone the same score says nothing about the order
+            let z = if deviation > f64::EPSILON {
+                (percentage - judge_mean) / deviation
+            } else {
+                0.0
+            };
+            let (raw, normalized) = by_entrant.entry(entrant).or_default();
+            raw.push(percentage);
+            normalized.push(z);
+        }
+    }
+
+    let mut standings: Vec<Standing> = by_entrant
+        .into_iter()
+        .map(|(entrant, (raw, normalized))| Standing {
+            entrant,
+            judges: raw.len(),
+            average: mean(&raw),
+            normalized: mean(&normalized),
+        })
+        .collect();
+    standings.sort_by(|a, b| {
+        b.normalized
+            .total_cmp(&a.normalized)
+            .then(b.average.total_cmp(&a.average))
+    });
+    standings
+}
+
+/// Locks the event row, so inside a transaction the judging can not be finalized before it ends.
+fn judging_finalized(connection: &mut PgConnection, event_id: i32) -> QueryResult<bool> {
+    events::table
+        .select(events::judging_finalized_at.is_not_null())
+        .filter(events::id.eq(event_id))
+        .for_update()
+        .get_result(connection)
+}
+
+fn is_judge(connection: &mut PgConnection, event_id: i32, user_id: i32) -> QueryResult<bool> {
+    diesel::select(diesel::dsl::exists(
+        event_judges::table
+            .filter(event_judges::event_id.eq(event_id))
+            .filter(event_judges::user_id.eq(user_id)),
+    ))
+    .get_result(connection)
+}
+
+/// Rejects changes to the judging of an event that is final, or that does not exist.
+fn check_not_finalized(connection: &mut PgConnection, event_id: i32) -> Result<(), AppError> {
+    let finalized = judging_finalized(connection, event_id)
+        .optional()?
+        .ok_or_else(|| AppError::not_found("No such event"))?;
+    if finalized {
+        return Err(AppError::forbidden("The judging of this event is final"));
+    }
+    Ok(())
+}
+
+/// The rubric is fixed once the first mark is in, so every sheet is scored against the same one.
+fn check_rubric_open(connection: &mut PgConnection, event_id: i32) -> Result<(), AppError> {
+    check_not_finalized(connection, event_id)?;
+    let scored: bool = diesel::select(diesel::dsl::exists(
+        judge_scores::table
+            .inner_join(judging_criteria::table)
+            .filter(judging_criteria::event_id.eq(event_id)),
+    ))
+    .get_result(connection)?;
+    if scored {
+        return Err(AppError::conflict(
+            "Scores were already submitted, the rubric can no longer change",
+        ));
+    }
+    Ok(())
+}
+
+pub async fn get_judges(
+    State(state): State<SiteState>,
+    user: User,
+    Query(data): Query<EventId>,
+) -> Result<Json<Vec<Profile>>, AppError> {
+    authorize(&state, &user, Action::ManageJudging { event_id: data.id })?;
+    Ok(Json(
+        event_judges::table
+            .inner_join(users::table)
+            .select(Profile::as_select())
+            .filter(event_judges::event_id.eq(data.id))
+            .load(&mut state.connection.get()?)?,
+    ))
+}
+
+pub async fn add_judge(
+    State(state): State<SiteState>,
+    user: User,
+    Form(data): Form<EventJudge>,
+) -> Result<(), AppError> {
+    authorize(
+        &state,
+        &user,
+        Action::ManageJudging {
+            event_id: data.event_id,
+        },
+    )?;
+    let connection = &mut state.connection.get()?;
+    check_not_finalized(connection, data.event_id)?;
+    data.insert_into(event_judges::table)
+        .execute(connection)
+        .map_err(|e| match AppError::from(e) {
+            e if e.status() == StatusCode::CONFLICT => {
+                AppError::conflict("Already a judge of this event")
+            }
+            e => e,
+        })?;
+    Ok(())
+}
+
+/// Removes a judge along with the marks they gave for the event.
+pub async fn remove_judge(
+    State(state): State<SiteState>,
+    user: User,
+    Form(data): Form<EventJudge>,
+) -> Result<(), AppError> {
+    authorize(
+        &state,
+        &user,
+        Action::ManageJudging {
+            event_id: data.event_id,
+        },
+    )?;
+    let connection = &mut state.connection.get()?;
+    check_not_finalized(connection, data.event_id)?;
+    connection.transaction::<_, Error, _>(|connection| {
+        diesel::delete(judge_scores::table)
+            .filter(judge_scores::judge_id.eq(data.user_id))
+            .filter(
+                judge_scores::criterion_id.eq_any(
+                    judging_criteria::table
+                        .select(judging_criteria::id)
+                        .filter(judging_criteria::event_id.eq(data.event_id)),
+                ),
+            )
+            .execute(connection)?;
+        diesel::delete(event_judges::table)
+            .filter(event_judges::event_id.eq(data.event_id))
+            .filter(event_judges::user_id.eq(data.user_id))
+            .execute(connection)
+    })?;
+    Ok(())
+}
+
+pub async fn get_rubric(
+    State(state): State<SiteState>,
+    Query(data): Query<EventId>,
+) -> Result<Json<Vec<Criterion>>, AppError> {
+    Ok(Json(
+        judging_criteria::table
+            .select(Criterion::as_select())
+            .filter(judging_criteria::event_id.eq(data.id))
+            .order(judging_criteria::id)
+            .load(&mut state.connection.get()?)?,
+    ))
+}
+
+pub async fn add_criterion(
+    State(state): State<SiteState>,
+    user: User,
+    Form(mut data): Form<CreateCriterion>,
+) -> Result<Json<Criterion>, AppError> {
+    authorize(
+        &state,
+        &user,
+        Action::ManageJudging {
+            event_id: data.event_id,
+        },
+    )?;
+    data.name = data.name.trim().to_owned();
+    if data.name.is_empty() {
+        return Err(AppError::invalid("name", "name is required"));
+    }
+    if !data.weight.is_finite() || data.weight <= 0.0 {
+        return Err(AppError::invalid("weight", "weight must be positive"));
+    }
+    if data.max_marks <= 0 {
+        return Err(AppError::invalid("max_marks", "max_marks must be positive"));
+    }
+    let connection = &mut state.connection.get()?;
+    check_rubric_open(connection, data.event_id)?;
+    data.insert_into(judging_criteria::table)
+        .returning(Criterion::as_returning())
+        .get_result(connection)
+        .map(Json)
+        .map_err(|e| match AppError::from(e) {
+            e if e.status() == StatusCode::CONFLICT => {
+                AppError::conflict("The rubric already has a criterion with this name")
+                    .field("name", "Already taken")
+            }
+            e => e,
+        })
+}
+
+pub async fn delete_criterion(
+    State(state): State<SiteState>,
+    user: User,
+    Form(data): Form<CriterionId>,
+) -> Result<(), AppError> {
+    let connection = &mut state.connection.get()?;
+    let event_id: i32 = judging_criteria::table
+        .select(judging_criteria::event_id)
+        .filter(judging_criteria::id.eq(data.id))
+        .get_result(connection)
+        .optional()?
+        .ok_or_else(|| AppError::not_found("No such criterion"))?;
+    authorize(&state, &user, Action::ManageJudging { event_id })?;
+    check_rubric_open(connection, event_id)?;
+    diesel::delete(judging_criteria::table)
+        .filter(judging_criteria::id.eq(data.id))
+        .execute(connection)?;
+    Ok(())
+}
+
+/// Marks the calling judge has given so far in the event.
+pub async fn get_my_scores(
+    State(state): State<SiteState>,
+    user: User,
+    Query(data): Query<EventId>,
+) -> Result<Json<Vec<Score>>, AppError> {
+    let connection = &mut state.connection.get()?;
+    if !user.verified || !is_judge(connection, data.id, user.id)? {
+        return Err(AppError::forbidden(
+            "Only the judges of this event can do this",
+        ));
+    }
+    Ok(Json(
+        judge_scores::table
+            .inner_join(judging_criteria::table)
+            .select(Score::as_select())
+            .filter(judging_criteria::event_id.eq(data.id))
+            .filter(judge_scores::judge_id.eq(user.id))
+            .order(judge_scores::id)
+            .load(connection)?,
+    ))
+}
+
+/// Sets the calling judge's marks for one criterion, replacing what they gave before.
+pub async fn submit_score(
+    State(state): State<SiteState>,
+    user: User,
+    Form(data): Form<SubmitScore>,
+) -> Result<Json<Score>, AppError> {
+    if !user.verified {
+        return Err(AppError::forbidden("Verify your email first"));
+    }
+    let connection = &mut state.connection.get()?;
+    let criterion: Criterion = judging_criteria::table
+        .select(Criterion::as_select())
+        .filter(judging_criteria::id.eq(data.criterion_id))
+        .get_result(connection)
+        .optional()?
+        .ok_or_else(|| AppError::not_found("No such criterion"))?;
+    if !is_judge(connection, criterion.event_id, user.id)? {
+        return Err(AppError::forbidden(
+            "Only the judges of this event can do this",
+        ));
+    }
+    if !data.marks.is_finite() || data.marks < 0.0 || data.marks > f64::from(criterion.max_marks) {
+        return Err(AppError::invalid(
+            "marks",
+            format!("marks must be between 0 and {}", criterion.max_marks),
+        ));
+    }
+    let registered: bool = match Entrant::of(data.user_id, data.team_id) {
+        Some(Entrant::User(user_id)) => diesel::select(diesel::dsl::exists(
+            individual_event_participation::table
+                .filter(individual_event_participation::event_id.eq(criterion.event_id))
+                .filter(individual_event_participation::user_id.eq(user_id)),
+        ))
+        .get_result(connection)?,
+        Some(Entrant::Team(team_id)) => diesel::select(diesel::dsl::exists(
+            team_event_participations::table
+                .filter(team_event_participations::event_id.eq(criterion.event_id))
+                .filter(team_event_participations::team_id.eq(team_id)),
+        ))
+        .get_result(connection)?,
+        None => {
+            return Err(AppError::new(
+                StatusCode::BAD_REQUEST,
+                "Give either user_id or team_id",
+            ))
+        }
+    };
+    if !registered {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            "Only registered participants can be scored",
+        ));
+    }
+    Ok(Json(connection.transaction::<_, AppError, _>(
+        |connection| {
+            check_not_finalized(connection, criterion.event_id)?;
+            diesel::delete(judge_scores::table)
+                .filter(judge_scores::criterion_id.eq(criterion.id))
+                .filter(judge_scores::judge_id.eq(user.id))
+                .filter(judge_scores::user_id.is_not_distinct_from(data.user_id))
+                .filter(judge_scores::team_id.is_not_distinct_from(data.team_id))
+                .execute(connection)?;
+            NewScore {
+                criterion_id: criterion.id,
+                judge_id: user.id,
+                user_id: data.user_id,
+                team_id: data.team_id,
+                marks: data.marks,
+            }
+            .insert_into(judge_scores::table)
+            .returning(Score::as_returning())
+            .get_result(connection)
+            .map_err(AppError::from)
+        },
+    )?))
+}
+
+/// Locks the scores of an event for good.
+pub async fn finalize_judging(
+    State(state): State<SiteState>,
+    user: User,
+    Form(data): Form<EventId>,
+) -> Result<(), AppError> {
+    authorize(&state, &user, Action::ManageJudging { event_id: data.id })?;
+    let finalized = diesel::update(events::table)
+        .set(events::judging_finalized_at.eq(diesel::dsl::now.nullable()))
+        .filter(events::id.eq(data.id))
+        .filter(events::judging_finalized_at.is_null())
+        .execute(&mut state.connection.get()?)?;
+    if finalized == 0 {
+        return Err(AppError::conflict(
+            "The judging of this event is already final",
+        ));
+    }
+    Ok(())
+}
+
+/// Ranked standings of an event. Visible to everyone once final, and to the coordinators who run
+/// the judging before that.
+pub async fn get_judging_results(
+    State(state): State<SiteState>,
+    user: User,
+    Query(data): Query<EventId>,
+) -> Result<Json<JudgingResults>, AppError> {
+    let connection = &mut state.connection.get()?;
+    let finalized_at: Option<chrono::NaiveDateTime> = events::table
+        .select(events::judging_finalized_at)
+        .filter(events::id.eq(data.id))
+        .get_result(connection)
+        .optional()?
+        .ok_or_else(|| AppError::not_found("No such event"))?;
+    if finalized_at.is_none() {
+        authorize(&state, &user, Action::ManageJudging { event_id: data.id })?;
+    }
+    let results = connection.transaction::<_, Error, _>(|connection| {
+        let criteria: Vec<Criterion> = judging_criteria::table
+            .select(Criterion::as_select())
+            .filter(judging_criteria::event_id.eq(data.id))
+            .load(connection)?;
+        let scores: Vec<Score> = judge_scores::table
+            .inner_join(judging_criteria::table)
+            .select(Score::as_select())
+            .filter(judging_criteria::event_id.eq(data.id))
+            .load(connection)?;
+        let standings = aggregate(&criteria, &scores);
+
+        let mut user_ids = Vec::new();
+        let mut team_ids = Vec::new();
+        for standing in &standings {
+            match standing.entrant {
+                Entrant::User(id) => user_ids.push(id),
+                Entrant::Team(id) => team_ids.push(id),
+            }
+        }
+        let user_names: BTreeMap<i32, String> = users::table
+            .select((users::id, users::name))
+            .filter(users::id.eq_any(user_ids))
+            .load::<(i32, String)>(connection)?
+            .into_iter()
+            .collect();
+        let team_names: BTreeMap<i32, String> = teams::table
+            .select((teams::id, teams::name))
+            .filter(teams::id.eq_any(team_ids))
+            .load::<(i32, String)>(connection)?
+            .into_iter()
+            .collect();
+
+        let mut entrants: Vec<JudgedEntrant> = Vec::with_capacity(standings.len());
+        for (i, standing) in standings.iter().enumerate() {
+            // Entrants the judges could not tell apart share a rank
+            let rank = match (entrants.last(), i.checked_sub(1).map(|j| &standings[j])) {
+                (Some(last), Some(previous))
+                    if previous.normalized == standing.normalized
+                        && previous.average == standing.average =>
+                {
+                    last.rank
+                }
+                _ => i + 1,
+            };
+            let (user_id, team_id, name) = match standing.entrant {
+                Entrant::User(id) => (Some(id), None, user_names.get(&id)),
+                Entrant::Team(id) => (None, Some(id), team_names.get(&id)),
+            };
+            entrants.push(JudgedEntrant {
+                rank,
+                user_id,
+                team_id,
+                name: name.cloned().unwrap_or_default(),
+                judges: standing.judges,
+                average: standing.average,
+                normalized: standing.normalized,
+            });
+        }
+        Ok(entrants)
+    });
+    Ok(Json(JudgingResults {
+        finalized_at,
+        entrants: results?,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn criterion(id: i32, weight: f64, max_marks: i32) -> Criterion {
+        Criterion {
+            id,
+            event_id: 1,
+            name: format!("Criterion {id}"),
+            weight,
+            max_marks,
+        }
+    }
+
+    fn score(criterion_id: i32, judge_id: i32, user_id: i32, marks: f64) -> Score {
+        Score {
+            id: 0,
+            criterion_id,
+            judge_id,
+            user_id: Some(user_id),
+            team_id: None,
+            marks,
+            submitted_at: Default::default(),
+        }
+    }
+
+    fn entrants(standings: &[Standing]) -> Vec<Entrant> {
+        standings.iter().map(|s| s.entrant).collect()
+    }
+
+    #[test]
+    fn ties_keep_both_entrants_level() {
+        let criteria = [criterion(1, 1.0, 10)];
+        let scores = [
+            score(1, 10, 1, 10.0),
+            score(1, 10, 2, 5.0),
+            score(1, 11, 1, 5.0),
+            score(1, 11, 2, 10.0),
+        ];
+        let standings = aggregate(&criteria, &scores);
+        assert_eq!(entrants(&standings), [Entrant::User(1), Entrant::User(2)]);
+        for standing in &standings {
+            assert_eq!(standing.judges, 2);
+            assert_eq!(standing.average, 75.0);
+            assert_eq!(standing.normalized, 0.0);
+        }
+    }
+
+    #[test]
+    fn partial_sheets_do_not_count() {
+        let criteria = [criterion(1, 2.0, 10), criterion(2, 1.0, 5)];
+        let scores = [
+            score(1, 10, 1, 10.0),
+            score(2, 10, 1, 2.5),
+            // Only one of the two criteria is marked for the second entrant
+            score(1, 10, 2, 0.0),
+        ];
+        let standings = aggregate(&criteria, &scores);
+        assert_eq!(entrants(&standings), [Entrant::User(1)]);
+        assert!((standings[0].average - 250.0 / 3.0).abs() < 1e-9);
+        // The judge's only complete sheet says nothing about the order
+        assert_eq!(standings[0].normalized, 0.0);
+    }
+
+    #[test]
+    fn a_judge_of_one_entrant_neither_helps_nor_hurts() {
+        let criteria = [criterion(1, 1.0, 10)];
+        let scores = [
+            score(1, 10, 1, 10.0),
+            score(1, 10, 2, 0.0),
+            score(1, 11, 2, 10.0),
+        ];
+        let standings = aggregate(&criteria, &scores);
+        assert_eq!(entrants(&standings), [Entrant::User(1), Entrant::User(2)]);
+        assert_eq!(standings[0].judges, 1);
+        assert_eq!(standings[0].normalized, 1.0);
+        assert_eq!(standings[1].judges, 2);
+        assert_eq!(standings[1].average, 50.0);
+        assert_eq!(standings[1].normalized, -0.5);
+    }
+
+    #[test]
+    fn harsh_and_lenient_judges_weigh_the_same() {
+        let criteria = [criterion(1, 1.0, 10)];
+        let scores = [
+            // A harsh judge prefers the second entrant
+            score(1, 10, 1, 1.0),
+            score(1, 10, 2, 3.0),
+            // A lenient judge prefers the first
+            score(1, 11, 1, 10.0),
+            score(1, 11, 2, 9.0),
+        ];
+        let standings = aggregate(&criteria, &scores);
+        assert_eq!(standings[0].normalized, standings[1].normalized);
+        // Level after normalizing, the raw average of 60 against 55 decides
+        assert_eq!(entrants(&standings), [Entrant::User(2), Entrant::User(1)]);
+    }
+}
@@ -1,11 +1,32 @@
 pub mod auth;
+pub mod certificate;
+pub mod checkin;
+pub mod config;
 pub mod domain;
+pub mod error;
 pub mod event;
+pub mod faculty;
 pub mod forms;
+pub mod judging;
+pub mod mail;
 pub mod models;
+pub mod oidc;
+pub mod outbox;
+pub mod payment;
+pub mod points;
+pub mod policy;
 pub mod profile;
+pub mod registration;
+pub mod results;
+pub mod roles;
 pub mod routes;
 pub mod schema;
+pub mod sponsor;
 pub mod state;
 pub mod team;
+pub mod templates;
+pub mod throttle;
+pub mod tokens;
+pub mod two_factor;
+pub mod waitlist;
 pub mod workshop;
@@ -0,0 +1,207 @@
+//! Where outgoing mail ends up, chosen with `mail.transport` in [`crate::config`]:
+//! - `smtp`, the default, sends through `mail.smtp.host`:`port` with `tls` one of `starttls`,
+//!   `implicit` or `none`. It only connects once there is something to send, so the server starts
+//!   without the mail server;
+//! - `file` writes every mail as an `.eml` file into `mail.dir`, for running without a mail server;
+//! - `memory` keeps mail in memory, for tests.
+//!
+//! Only [`crate::outbox`] sends mail, handlers queue it there.
+use std::{
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::Context;
+use axum::async_trait;
+use mail_send::{mail_builder::MessageBuilder, Credentials, SmtpClient, SmtpClientBuilder};
+use serde::Deserialize;
+use tokio::{net::TcpStream, sync::Mutex};
+use tokio_rustls::client::TlsStream;
+
+use crate::config::{MailConfig, MailTransport};
+
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, message: MessageBuilder<'_>) -> anyhow::Result<()>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+    /// Plain connection upgraded with `STARTTLS`, usually on port 587
+    StartTls,
+    /// TLS from the start, usually on port 465
+    Implicit,
+    /// No encryption at all, only for a relay on the same machine or a local test server
+    None,
+}
+
+enum SmtpConnection {
+    Tls(Box<SmtpClient<TlsStream<TcpStream>>>),
+    Plain(SmtpClient<TcpStream>),
+}
+
+pub struct SmtpMailer {
+    builder: SmtpClientBuilder<String>,
+    tls: SmtpTls,
+    connection: Mutex<Option<SmtpConnection>>,
+}
+
+impl SmtpMailer {
+    pub fn new(
+        host: String,
+        port: u16,
+        tls: SmtpTls,
+        credentials: Option<(String, String)>,
+    ) -> Self {
+        let mut builder = SmtpClientBuilder::new(host, port)
+            .implicit_tls(tls == SmtpTls::Implicit)
+            .timeout(Duration::from_secs(60));
+        if let Some((username, password)) = credentials {
+            builder = builder.credentials(Credentials::new(username, password));
+        }
+        Self {
+            builder,
+            tls,
+            connection: Mutex::new(None),
+        }
+    }
+
+    async fn connect(&self) -> mail_send::Result<SmtpConnection> {
+        Ok(match self.tls {
+            SmtpTls::None => SmtpConnection::Plain(self.builder.connect_plain().await?),
+            SmtpTls::StartTls | SmtpTls::Implicit => {
+                SmtpConnection::Tls(Box::new(self.builder.connect().await?))
+            }
+        })
+    }
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, message: MessageBuilder<'_>) -> anyhow::Result<()> {
+        let mut connection = self.connection.lock().await;
+        // The server drops idle connections, so the one kept from the last mail may be gone
+        let alive = match connection.as_mut() {
+            Some(SmtpConnection::Tls(client)) => client.noop().await.is_ok(),
+            Some(SmtpConnection::Plain(client)) => client.noop().await.is_ok(),
+            None => false,
+        };
+        if !alive {
+            *connection = Some(self.connect().await?);
+        }
+        match connection.as_mut() {
+            Some(SmtpConnection::Tls(client)) => client.send(message).await?,
+            Some(SmtpConnection::Plain(client)) => client.send(message).await?,
+            None => unreachable!("Connected just above"),
+        }
+        Ok(())
+    }
+}
+
+/// Writes each mail to its own `.eml` file, which any mail client can open.
+pub struct FileMailer {
+    dir: PathBuf,
+}
+
+impl FileMailer {
+    pub fn new(dir: PathBuf) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("Creating the mail directory {}", dir.display()))?;
+        Ok(Self { dir })
+    }
+}
+
+#[async_trait]
+impl Mailer for FileMailer {
+    async fn send(&self, message: MessageBuilder<'_>) -> anyhow::Result<()> {
+        let sent_at = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?;
+        let path = self.dir.join(format!(
+            "{}-{:08x}.eml",
+            sent_at.as_millis(),
+            rand::random::<u32>()
+        ));
+        tokio::fs::write(&path, message.write_to_vec()?).await?;
+        log::info!("Mail written to {}", path.display());
+        Ok(())
+    }
+}
+
+/// Keeps every mail, as sent over the wire, until [`MemoryMailer::take`] is called.
+#[derive(Default)]
+pub struct MemoryMailer {
+    sent: std::sync::Mutex<Vec<String>>,
+}
+
+impl MemoryMailer {
+    pub fn take(&self) -> Vec<String> {
+        std::mem::take(&mut *self.sent.lock().unwrap())
+    }
+}
+
+#[async_trait]
+impl Mailer for MemoryMailer {
+    async fn send(&self, message: MessageBuilder<'_>) -> anyhow::Result<()> {
+        let message = message.write_to_string()?;
+        self.sent.lock().unwrap().push(message);
+        Ok(())
+    }
+}
+
+/// The mailer `mail.transport` asks for.
+pub fn mailer(config: &MailConfig) -> anyhow::Result<Arc<dyn Mailer>> {
+    Ok(match config.transport {
+        MailTransport::Smtp => Arc::new(SmtpMailer::new(
+            config.smtp.host.clone(),
+            config.smtp.port(),
+            config.smtp.tls,
+            config
+                .smtp
+                .username
+                .clone()
+                .zip(config.smtp.password.clone()),
+        )),
+        MailTransport::File => Arc::new(FileMailer::new(config.dir.clone())?),
+        MailTransport::Memory => Arc::new(MemoryMailer::default()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(subject: &str) -> MessageBuilder<'_> {
+        MessageBuilder::new()
+            .from(("Techfest", "techfest@sliet.ac.in"))
+            .to(("Student", "student@sliet.ac.in"))
+            .subject(subject)
+            .text_body("See you there")
+    }
+
+    #[tokio::test]
+    async fn memory_keeps_mail_until_taken() {
+        let mailer = MemoryMailer::default();
+        mailer.send(message("First")).await.unwrap();
+        mailer.send(message("Second")).await.unwrap();
+        let sent = mailer.take();
+        assert_eq!(sent.len(), 2);
+        assert!(sent[0].contains("Subject: First"));
+        assert!(sent[1].contains("Subject: Second"));
+        assert!(sent[1].contains("student@sliet.ac.in"));
+        assert!(mailer.take().is_empty());
+    }
+
+    #[tokio::test]
+    async fn memory_transport_is_chosen_from_the_config() {
+        let config = MailConfig {
+            transport: MailTransport::Memory,
+            ..MailConfig::default()
+        };
+        mailer(&config)
+            .unwrap()
+            .send(message("Hello"))
+            .await
+            .unwrap();
+    }
+}
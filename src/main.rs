@@ -2,14 +2,16 @@
 static GLOBAL: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
 
 mod schema;
+use std::net::SocketAddr;
+
 use axum::extract::Request;
 use axum::middleware::{from_fn, Next};
 use axum::response::Response;
+use sliet_techfest_backend::config::Config;
+use sliet_techfest_backend::outbox::deliver_mail;
 use sliet_techfest_backend::routes::setup_routes;
 use sliet_techfest_backend::state::SiteState;
 
-use std::env;
-
 use dotenvy::dotenv;
 use tower_http::compression::CompressionLayer;
 use tower_http::cors::CorsLayer;
@@ -32,8 +34,11 @@ async fn main() {
     tokio_rustls::rustls::crypto::aws_lc_rs::default_provider()
         .install_default()
         .unwrap();
-    let state = &mut SiteState::init().await.unwrap();
-    let frontend_url = env::var("FRONTEND_URL").unwrap();
+    let config = Config::load().expect("A valid configuration");
+    let bind = config.server.bind;
+    let cors_origins = config.cors_origins();
+    let state = &mut SiteState::init(config).await.unwrap();
+    tokio::spawn(deliver_mail(state.clone()));
     let routes = setup_routes()
         .with_state(state.clone())
         .layer(from_fn(add_no_cache))
@@ -57,15 +62,19 @@ async fn main() {
                 ])
                 .expose_headers([http::header::CONTENT_TYPE])
                 .allow_credentials(true)
-                .allow_origin([frontend_url.parse().unwrap()]),
+                .allow_origin(cors_origins),
         )
         .layer(CompressionLayer::new().br(true).zstd(true).gzip(true));
-    log::info!("Binding to port 3000");
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:3000")
+    log::info!("Binding to {bind}");
+    let listener = tokio::net::TcpListener::bind(bind)
         .await
         .expect("Failed to bind to address");
-    log::info!("Listening on all ip addresses");
-    axum::serve(listener, routes)
-        .await
-        .expect("Failed while serving");
+    log::info!("Listening on {bind}");
+    // The peer address decides whether the forwarding headers are believed, see `auth::client_ip`
+    axum::serve(
+        listener,
+        routes.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    .expect("Failed while serving");
 }
@@ -1,8 +1,20 @@
+pub mod certificates;
+pub mod checkin;
 pub mod domains;
 pub mod events;
 pub mod faculty;
+pub mod identities;
+pub mod judging;
+pub mod outbox;
 pub mod payments;
+pub mod points;
+pub mod results;
+pub mod roles;
+pub mod sessions;
+pub mod sponsors;
 pub mod students;
 pub mod team;
+pub mod tokens;
+pub mod two_factor;
 pub mod users;
 pub mod workshops;
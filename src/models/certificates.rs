@@ -0,0 +1,78 @@
+use chrono::{NaiveDate, NaiveDateTime};
+use diesel::prelude::*;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+#[derive(Queryable, Selectable, Serialize, Debug, Clone)]
+#[diesel(table_name = crate::schema::certificates)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Certificate {
+    pub id: String,
+    pub user_id: i32,
+    pub event_id: Option<i32>,
+    pub workshop_id: Option<i32>,
+    pub recipient_name: String,
+    pub title: String,
+    pub domain_name: String,
+    pub held_on: NaiveDate,
+    pub issued_at: NaiveDateTime,
+}
+
+/// What anyone holding a certificate id may see, without revealing who the user is beyond the
+/// name printed on it.
+#[derive(Queryable, Selectable, Serialize, Debug, Clone)]
+#[diesel(table_name = crate::schema::certificates)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct CertificateVerification {
+    pub id: String,
+    pub recipient_name: String,
+    pub title: String,
+    pub domain_name: String,
+    pub held_on: NaiveDate,
+    pub issued_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug, Clone)]
+#[diesel(table_name = crate::schema::certificates)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewCertificate {
+    pub id: String,
+    pub user_id: i32,
+    pub event_id: Option<i32>,
+    pub workshop_id: Option<i32>,
+    pub recipient_name: String,
+    pub title: String,
+    pub domain_name: String,
+    pub held_on: NaiveDate,
+}
+
+/// Certificate ids are printed and typed back in by hand, so they are random rather than
+/// sequential and can not be guessed from one another.
+pub fn new_certificate_id() -> String {
+    hex::encode(rand::thread_rng().gen::<[u8; 12]>())
+}
+
+/// Layout of the certificate PDF, in points from the bottom left of the page. Every text may use
+/// the `{name}`, `{title}`, `{domain}`, `{date}`, `{certificate_id}` and `{verification_url}`
+/// placeholders.
+#[derive(Deserialize, Debug, Clone)]
+pub struct CertificateTemplate {
+    pub width: f32,
+    pub height: f32,
+    /// Inset of a thin frame around the page, none when missing
+    pub border: Option<f32>,
+    pub texts: Vec<TemplateText>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct TemplateText {
+    pub text: String,
+    pub size: f32,
+    pub y: f32,
+    /// Left edge of the text, centered on the page when missing
+    pub x: Option<f32>,
+    #[serde(default)]
+    pub bold: bool,
+    #[serde(default)]
+    pub color: [f32; 3],
+}
@@ -0,0 +1,50 @@
+use std::time::{Duration, SystemTime, SystemTimeError};
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::forms::users::Profile;
+
+/// How long a check-in QR code stays scannable. The participant's page simply asks for a new
+/// one, so this only has to cover the walk up to the gate.
+pub const CHECKIN_TOKEN_TTL: Duration = Duration::from_secs(60 * 10);
+
+/// Audience of check-in tokens, so a session JWT can never be scanned as one and vice versa.
+pub const CHECKIN_AUDIENCE: &str = "checkin";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum CheckInTarget {
+    Event { event_id: i32 },
+    Team { event_id: i32, team_id: i32 },
+    Workshop { workshop_id: i32 },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CheckInClaims {
+    /// Random id, recorded on the first scan so the same code can not be used twice
+    pub jti: String,
+    pub aud: String,
+    pub user_id: i32,
+    pub target: CheckInTarget,
+    pub exp: u64,
+}
+
+impl CheckInClaims {
+    pub fn new(user_id: i32, target: CheckInTarget) -> Result<Self, SystemTimeError> {
+        Ok(Self {
+            jti: hex::encode(rand::thread_rng().gen::<[u8; 16]>()),
+            aud: CHECKIN_AUDIENCE.to_owned(),
+            user_id,
+            target,
+            exp: (SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)? + CHECKIN_TOKEN_TTL)
+                .as_secs(),
+        })
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct CheckInResponse {
+    pub target: CheckInTarget,
+    pub profile: Profile,
+}
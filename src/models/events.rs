@@ -21,6 +21,20 @@ pub struct Event {
     pub whatsapp_link: String,
     pub participation_type: ParticipationType,
     pub photo_hash: Option<Vec<u8>>,
+    pub max_participants: Option<i32>,
+    pub max_teams: Option<i32>,
+    pub min_team_size: i32,
+    pub max_team_size: i32,
+    /// Count pending team requests towards `max_team_size`, so invitations can not overbook
+    pub count_pending_requests: bool,
+}
+
+/// Whether a join took one of the seats or was queued behind a full event.
+#[derive(Serialize, Debug, Clone, Copy)]
+#[allow(non_camel_case_types)]
+pub enum JoinStatus {
+    JOINED,
+    WAITLISTED,
 }
 
 #[derive(diesel_derive_enum::DbEnum, Debug, Clone, Serialize, Deserialize)]
@@ -32,7 +46,7 @@ pub enum ParticipationType {
     TEAM
 }
 
-#[derive(diesel_derive_enum::DbEnum, Debug, Clone, Serialize, Deserialize)]
+#[derive(diesel_derive_enum::DbEnum, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[ExistingTypePath = "crate::schema::sql_types::Mode"]
 #[allow(non_camel_case_types)]
 #[DbValueStyle = "SCREAMING_SNAKE_CASE"]
@@ -41,3 +55,13 @@ pub enum Mode {
     HYBRID,
     OFFLINE,
 }
+
+impl std::fmt::Display for Mode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::ONLINE => "online",
+            Self::HYBRID => "hybrid",
+            Self::OFFLINE => "offline",
+        })
+    }
+}
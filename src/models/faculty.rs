@@ -33,3 +33,43 @@ pub struct FacultyResponse {
     #[serde(flatten)]
     pub profile: Profile,
 }
+
+#[derive(diesel_derive_enum::DbEnum, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[ExistingTypePath = "crate::schema::sql_types::ApplicationStatus"]
+#[allow(non_camel_case_types)]
+#[DbValueStyle = "SCREAMING_SNAKE_CASE"]
+pub enum ApplicationStatus {
+    PENDING,
+    APPROVED,
+    REJECTED,
+}
+
+/// Files a faculty profile for review, see [`crate::faculty`].
+#[derive(Insertable, Debug, Clone)]
+#[diesel(table_name = crate::schema::faculty_applications)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewFacultyApplication {
+    pub user_id: i32,
+}
+
+#[derive(Queryable, Selectable, Serialize, Debug, Clone)]
+#[diesel(table_name = crate::schema::faculty_applications)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct FacultyApplication {
+    pub status: ApplicationStatus,
+    /// Why it was rejected
+    pub reason: Option<String>,
+    pub reviewed_by: Option<i32>,
+    pub reviewed_at: Option<chrono::NaiveDateTime>,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct FacultyApplicationResponse {
+    #[serde(flatten)]
+    pub application: FacultyApplication,
+    #[serde(flatten)]
+    pub faculty: Faculty,
+    #[serde(flatten)]
+    pub profile: Profile,
+}
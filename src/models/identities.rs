@@ -0,0 +1,55 @@
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Provider name stored with identities from Google.
+pub const GOOGLE: &str = "google";
+
+/// Audience of the short-lived cookie that carries a sign in across the trip to the provider.
+pub const OIDC_LOGIN_AUDIENCE: &str = "oidc-login";
+
+#[derive(Insertable, Debug, Clone)]
+#[diesel(table_name = crate::schema::user_identities)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewIdentity {
+    pub provider: String,
+    pub subject: String,
+    pub user_id: i32,
+    pub email: String,
+}
+
+/// The parts of the provider's discovery document we use.
+#[derive(Deserialize, Debug, Clone)]
+pub struct ProviderMetadata {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub jwks_uri: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct TokenResponse {
+    pub id_token: String,
+}
+
+/// Claims of an ID token, once its signature, issuer, audience and expiry are checked.
+#[derive(Deserialize, Debug, Clone)]
+pub struct IdTokenClaims {
+    pub sub: String,
+    pub email: Option<String>,
+    #[serde(default)]
+    pub email_verified: bool,
+    pub name: Option<String>,
+    pub nonce: Option<String>,
+}
+
+/// What has to survive the redirect to the provider and back: `state` guards the callback
+/// against forged requests, `nonce` ties the ID token to this sign in, and `verifier` is the PKCE
+/// secret the code is exchanged with.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OidcLoginClaims {
+    pub aud: String,
+    pub state: String,
+    pub nonce: String,
+    pub verifier: String,
+    pub exp: u64,
+}
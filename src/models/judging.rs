@@ -0,0 +1,60 @@
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use serde::Serialize;
+
+#[derive(Queryable, Selectable, Serialize, Debug, Clone)]
+#[diesel(table_name = crate::schema::judging_criteria)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Criterion {
+    pub id: i32,
+    pub event_id: i32,
+    pub name: String,
+    pub weight: f64,
+    pub max_marks: i32,
+}
+
+#[derive(Queryable, Selectable, Serialize, Debug, Clone)]
+#[diesel(table_name = crate::schema::judge_scores)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Score {
+    pub id: i32,
+    pub criterion_id: i32,
+    pub judge_id: i32,
+    pub user_id: Option<i32>,
+    pub team_id: Option<i32>,
+    pub marks: f64,
+    pub submitted_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug, Clone)]
+#[diesel(table_name = crate::schema::judge_scores)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewScore {
+    pub criterion_id: i32,
+    pub judge_id: i32,
+    pub user_id: Option<i32>,
+    pub team_id: Option<i32>,
+    pub marks: f64,
+}
+
+/// Aggregated standing of one individual or team.
+#[derive(Serialize, Debug, Clone)]
+pub struct JudgedEntrant {
+    pub rank: usize,
+    pub user_id: Option<i32>,
+    pub team_id: Option<i32>,
+    pub name: String,
+    /// Judges that scored every criterion for this entrant
+    pub judges: usize,
+    /// Mean of the judges' weighted scores, as a percentage of the best possible score
+    pub average: f64,
+    /// Mean of the judges' scores after putting every judge on the same scale, which is what the
+    /// ranking uses so a harsh judge and a lenient one count the same
+    pub normalized: f64,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct JudgingResults {
+    pub finalized_at: Option<NaiveDateTime>,
+    pub entrants: Vec<JudgedEntrant>,
+}
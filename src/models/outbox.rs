@@ -0,0 +1,58 @@
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Attempts after which a mail is given up on and marked `FAILED`.
+pub const MAX_ATTEMPTS: i32 = 8;
+
+#[derive(diesel_derive_enum::DbEnum, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[ExistingTypePath = "crate::schema::sql_types::MailStatus"]
+#[allow(non_camel_case_types)]
+#[DbValueStyle = "SCREAMING_SNAKE_CASE"]
+pub enum MailStatus {
+    PENDING,
+    SENT,
+    FAILED,
+}
+
+/// A mail as handlers hand it to the outbox, see [`crate::outbox::enqueue`].
+#[derive(Insertable, Debug, Clone)]
+#[diesel(table_name = crate::schema::outbox)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewMail {
+    pub recipient_name: String,
+    pub recipient_email: String,
+    pub subject: String,
+    pub html_body: String,
+    /// The plain text alternative, for mail clients that do not show HTML
+    pub text_body: Option<String>,
+}
+
+#[derive(Queryable, Selectable, Debug, Clone)]
+#[diesel(table_name = crate::schema::outbox)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct QueuedMail {
+    pub id: i32,
+    pub recipient_name: String,
+    pub recipient_email: String,
+    pub subject: String,
+    pub html_body: String,
+    pub text_body: Option<String>,
+    pub attempts: i32,
+}
+
+/// A mail as listed to super admins, without its body.
+#[derive(Queryable, Selectable, Serialize, Debug, Clone)]
+#[diesel(table_name = crate::schema::outbox)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct OutboxEntry {
+    pub id: i32,
+    pub recipient_email: String,
+    pub subject: String,
+    pub status: MailStatus,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub next_attempt_at: NaiveDateTime,
+    pub created_at: NaiveDateTime,
+    pub sent_at: Option<NaiveDateTime>,
+}
@@ -1,13 +1,27 @@
 use diesel::prelude::*;
-use serde::Deserialize;
+use serde::Serialize;
 
-#[derive(Insertable, Queryable, Selectable, Deserialize, Debug, Clone)]
+use crate::forms::users::Profile;
+
+#[derive(Queryable, Selectable, Serialize, Debug, Clone)]
 #[diesel(table_name = crate::schema::payments)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct Payment {
-    user_id: i32,
-    payment_id: String,
-    payment_amount: i32,
-    #[serde(skip)]
-    verified: bool,
+    pub user_id: i32,
+    pub payment_id: String,
+    pub payment_amount: i32,
+    pub verified: bool,
+    pub rejection_reason: Option<String>,
+    pub submitted_at: chrono::NaiveDateTime,
+    pub reviewed_by: Option<i32>,
+    pub order_id: Option<String>,
+    /// Who the gateway says paid, when that is not the user who submitted the payment
+    pub claimed_by: Option<i32>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct PaymentResponse {
+    #[serde(flatten)]
+    pub payment: Payment,
+    pub profile: Profile,
 }
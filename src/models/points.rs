@@ -0,0 +1,75 @@
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(diesel_derive_enum::DbEnum, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[ExistingTypePath = "crate::schema::sql_types::PointReason"]
+#[allow(non_camel_case_types)]
+#[DbValueStyle = "SCREAMING_SNAKE_CASE"]
+pub enum PointReason {
+    EVENT_ATTENDANCE,
+    WORKSHOP_ATTENDANCE,
+    ATTENDANCE_REVOKED,
+    TEAM_SHARE,
+    SPENT,
+}
+
+#[derive(Queryable, Selectable, Serialize, Debug, Clone)]
+#[diesel(table_name = crate::schema::points_ledger)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct LedgerEntry {
+    pub id: i32,
+    pub amount: i32,
+    pub reason: PointReason,
+    pub event_id: Option<i32>,
+    pub workshop_id: Option<i32>,
+    pub domain_id: Option<i32>,
+    pub note: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug, Clone)]
+#[diesel(table_name = crate::schema::points_ledger)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewLedgerEntry {
+    pub user_id: Option<i32>,
+    pub team_id: Option<i32>,
+    pub amount: i32,
+    pub reason: PointReason,
+    pub event_id: Option<i32>,
+    pub workshop_id: Option<i32>,
+    pub domain_id: Option<i32>,
+    pub note: Option<String>,
+}
+
+/// A registration whose attendance earns points.
+#[derive(Debug, Clone, Copy)]
+pub enum Attendance {
+    Event { event_id: i32, user_id: i32 },
+    TeamEvent { event_id: i32, team_id: i32 },
+    Workshop { workshop_id: i32, user_id: i32 },
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct PointsSummary {
+    /// What can still be spent
+    pub balance: i64,
+    /// Everything ever earned, which is what the leaderboards rank by
+    pub earned: i64,
+    pub entries: Vec<LedgerEntry>,
+}
+
+/// Points a team got for an event that its leader has not handed out yet.
+#[derive(Queryable, Serialize, Debug, Clone)]
+pub struct TeamPoints {
+    pub event_id: i32,
+    pub event_name: String,
+    pub remaining: i64,
+}
+
+#[derive(Queryable, Serialize, Debug, Clone)]
+pub struct LeaderboardEntry {
+    pub user_id: i32,
+    pub name: String,
+    pub points: i64,
+}
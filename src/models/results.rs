@@ -0,0 +1,43 @@
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(diesel_derive_enum::DbEnum, Debug, Clone, Copy, Serialize, Deserialize)]
+#[ExistingTypePath = "crate::schema::sql_types::Placement"]
+#[allow(non_camel_case_types)]
+#[DbValueStyle = "SCREAMING_SNAKE_CASE"]
+pub enum Placement {
+    FIRST,
+    SECOND,
+    THIRD,
+    SPECIAL_MENTION,
+}
+
+#[derive(Insertable, Debug, Clone)]
+#[diesel(table_name = crate::schema::event_results)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewEventResult {
+    pub event_id: i32,
+    pub placement: Placement,
+    pub user_id: Option<i32>,
+    pub team_id: Option<i32>,
+    pub note: Option<String>,
+    pub published_by: Option<i32>,
+}
+
+/// A winner of an event, with the names needed to show it without further lookups. Exactly one
+/// of `user_id` and `team_id` is set, along with the matching name.
+#[derive(Queryable, Serialize, Debug, Clone)]
+pub struct EventResult {
+    pub id: i32,
+    pub event_id: i32,
+    pub event_name: String,
+    pub domain_id: i32,
+    pub placement: Placement,
+    pub user_id: Option<i32>,
+    pub user_name: Option<String>,
+    pub team_id: Option<i32>,
+    pub team_name: Option<String>,
+    pub note: Option<String>,
+    pub published_at: NaiveDateTime,
+}
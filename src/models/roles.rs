@@ -0,0 +1,16 @@
+use serde::Serialize;
+
+use crate::forms::users::Profile;
+
+/// An account with a role above `PARTICIPANT`, with everything it coordinates.
+#[derive(Serialize, Debug, Clone)]
+pub struct PrivilegedAccount {
+    #[serde(flatten)]
+    pub profile: Profile,
+    /// Domains coordinated as faculty
+    pub faculty_domains: Vec<i32>,
+    /// Domains coordinated as a student
+    pub student_domains: Vec<i32>,
+    pub events: Vec<i32>,
+    pub workshops: Vec<i32>,
+}
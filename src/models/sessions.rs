@@ -0,0 +1,61 @@
+use std::time::Duration;
+
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use serde::Serialize;
+
+/// Lifetime of the JWT sent with every request. Kept short since it is only checked against the
+/// session, not renewed, and the refresh token hands out a new one anyway.
+pub const ACCESS_TOKEN_TTL: Duration = Duration::from_secs(60 * 15);
+
+/// How long a device stays signed in without refreshing.
+pub const REFRESH_TOKEN_TTL: Duration = Duration::from_secs(60 * 60 * 24 * 30);
+
+#[derive(Insertable, Debug, Clone)]
+#[diesel(table_name = crate::schema::sessions)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewSession {
+    pub user_id: i32,
+    pub token_hash: String,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+}
+
+#[derive(Queryable, Selectable, Debug, Clone)]
+#[diesel(table_name = crate::schema::sessions)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Session {
+    pub id: i32,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub last_used_at: NaiveDateTime,
+    pub expires_at: NaiveDateTime,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SessionInfo {
+    pub id: i32,
+    /// User agent of the browser that signed in
+    pub device: Option<String>,
+    pub ip: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub last_used_at: NaiveDateTime,
+    pub expires_at: NaiveDateTime,
+    /// Whether this is the session the request was made with
+    pub current: bool,
+}
+
+impl SessionInfo {
+    pub fn new(session: Session, current: Option<i32>) -> Self {
+        Self {
+            current: current == Some(session.id),
+            id: session.id,
+            device: session.user_agent,
+            ip: session.ip,
+            created_at: session.created_at,
+            last_used_at: session.last_used_at,
+            expires_at: session.expires_at,
+        }
+    }
+}
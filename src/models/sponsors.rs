@@ -0,0 +1,27 @@
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(diesel_derive_enum::DbEnum, Debug, Clone, Serialize, Deserialize)]
+#[ExistingTypePath = "crate::schema::sql_types::SponsorTier"]
+#[allow(non_camel_case_types)]
+#[DbValueStyle = "SCREAMING_SNAKE_CASE"]
+pub enum SponsorTier {
+    TITLE,
+    PLATINUM,
+    GOLD,
+    SILVER,
+    BRONZE,
+    PARTNER,
+}
+
+#[derive(Queryable, Selectable, Serialize, Debug, Clone)]
+#[diesel(table_name = crate::schema::sponsors)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Sponsor {
+    pub id: i32,
+    pub name: String,
+    pub tier: SponsorTier,
+    pub display_order: i32,
+    pub website: Option<String>,
+    pub photo_hash: Option<Vec<u8>>,
+}
@@ -0,0 +1,46 @@
+use std::time::Duration;
+
+use diesel::prelude::*;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// What a token may be redeemed for. A token issued for one purpose is never accepted for
+/// another, so new kinds of links get a variant of their own.
+#[derive(diesel_derive_enum::DbEnum, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[ExistingTypePath = "crate::schema::sql_types::TokenPurpose"]
+#[allow(non_camel_case_types)]
+#[DbValueStyle = "SCREAMING_SNAKE_CASE"]
+pub enum TokenPurpose {
+    EMAIL_VERIFICATION,
+    PASSWORD_RESET,
+}
+
+impl TokenPurpose {
+    /// How long a link stays valid after it was sent.
+    pub fn ttl(self) -> Duration {
+        match self {
+            Self::EMAIL_VERIFICATION => Duration::from_secs(60 * 60 * 24 * 7),
+            Self::PASSWORD_RESET => Duration::from_secs(60 * 60),
+        }
+    }
+}
+
+#[derive(Insertable, Debug, Clone)]
+#[diesel(table_name = crate::schema::tokens)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewToken {
+    pub user_id: i32,
+    pub purpose: TokenPurpose,
+    pub token_hash: String,
+}
+
+/// 256 random bits, hex encoded so they fit in links and cookies as is.
+pub fn new_token() -> String {
+    hex::encode(rand::thread_rng().gen::<[u8; 32]>())
+}
+
+/// Tokens are stored hashed, so a leaked database can not be used to redeem them.
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
@@ -0,0 +1,63 @@
+use std::time::Duration;
+
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Audience of the cookie that holds a sign in until its second step.
+pub const TWO_FACTOR_AUDIENCE: &str = "two-factor";
+
+/// How long the second step of a sign in may take.
+pub const CHALLENGE_TTL: Duration = Duration::from_secs(60 * 5);
+
+/// Recovery codes handed out at a time.
+pub const RECOVERY_CODE_COUNT: usize = 10;
+
+#[derive(Queryable, Selectable, Debug, Clone)]
+#[diesel(table_name = crate::schema::two_factor)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct TwoFactor {
+    pub secret: Vec<u8>,
+    pub enabled_at: Option<NaiveDateTime>,
+    pub last_used_step: Option<i64>,
+}
+
+#[derive(Insertable, Debug, Clone)]
+#[diesel(table_name = crate::schema::two_factor)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewTwoFactor {
+    pub user_id: i32,
+    pub secret: Vec<u8>,
+}
+
+#[derive(Insertable, Debug, Clone)]
+#[diesel(table_name = crate::schema::recovery_codes)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewRecoveryCode {
+    pub user_id: i32,
+    pub code_hash: String,
+}
+
+/// What an authenticator app needs, either typed in as `secret` or scanned from `qr`, an SVG of
+/// `uri`.
+#[derive(Serialize, Debug, Clone)]
+pub struct TwoFactorSetup {
+    pub secret: String,
+    pub uri: String,
+    pub qr: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct TwoFactorStatus {
+    pub enabled: bool,
+    /// Whether the user's role has to use two-factor
+    pub required: bool,
+    pub recovery_codes_left: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TwoFactorClaims {
+    pub aud: String,
+    pub id: i32,
+    pub exp: u64,
+}
@@ -1,23 +1,27 @@
-use std::{fmt::Display, sync::Arc};
+use std::fmt::Display;
 
+use argon2::{
+    password_hash::{self, rand_core::OsRng, PasswordHasher, SaltString},
+    Argon2,
+};
 use axum::{async_trait, extract::FromRequestParts};
 use axum_extra::extract::cookie::Cookie;
+use chrono::NaiveDateTime;
 use diesel::{
     prelude::*,
     r2d2::{ConnectionManager, Pool},
 };
 use http::{request::Parts, StatusCode};
-use jsonwebtoken::Validation;
-use mail_send::{mail_builder::MessageBuilder, SmtpClient, SmtpClientBuilder};
+use jsonwebtoken::{errors::ErrorKind, Validation};
 use serde::{Deserialize, Serialize};
-use tokio::{net::TcpStream, sync::Mutex};
-use tokio_rustls::client::TlsStream;
 
 use crate::{
-    auth::{UserClaims, KEYS},
-    forms::users::{ResetClaims, VerificationClaims},
-    schema::{payments, users},
+    auth::{keys, UserClaims, ACCESS_COOKIE},
+    error::AppError,
+    models::{events::Mode, outbox::NewMail},
+    schema::{payments, sessions, users},
     state::SiteState,
+    templates::{format_time, MailKind, MailTemplates},
 };
 #[derive(diesel_derive_enum::DbEnum, Debug, Clone, Serialize, Deserialize)]
 #[ExistingTypePath = "crate::schema::sql_types::Role"]
@@ -50,6 +54,13 @@ impl Role {
     ];
 }
 
+/// The argon2 hash stored in `users.password_hash`, with a fresh salt.
+pub fn hash_password(password: &str) -> Result<String, password_hash::Error> {
+    Ok(Argon2::default()
+        .hash_password(password.as_bytes(), &SaltString::generate(&mut OsRng))?
+        .to_string())
+}
+
 #[derive(Queryable, Selectable, Insertable, Deserialize, Debug, Clone)]
 #[diesel(table_name = crate::schema::users)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
@@ -66,167 +77,250 @@ pub struct User {
     pub password_hash: String,
 }
 
-static VERIFICATION_EMAIL_TEMPLATE: &'static str = include_str!("verification_email.html");
-static PASSWORD_RESET_EMAIL_TEMPLATE: &'static str = include_str!("password_reset_email.html");
-
 impl User {
     pub fn is_payment_done(&self, db: &Pool<ConnectionManager<PgConnection>>) -> bool {
+        // Only once the address is confirmed, and a changed email has to be confirmed again
         if let Some((_, "sliet.ac.in")) = self.email.trim_ascii().rsplit_once('@') {
-            true
-        } else {
-            true
-            /* match self.role {
-                Role::SUPER_ADMIN => true,
-                Role::STUDENT_COORDINATOR => true,
-                Role::FACULTY_COORDINATOR => true,
-                _ => {
-                    match payments::table
-                        .select(payments::verified)
+            return self.verified;
+        }
+        match self.role {
+            Role::SUPER_ADMIN => true,
+            Role::STUDENT_COORDINATOR => true,
+            Role::FACULTY_COORDINATOR => true,
+            Role::PARTICIPANT => {
+                match diesel::select(diesel::dsl::exists(
+                    payments::table
                         .filter(payments::user_id.eq(self.id))
-                        .filter(payments::verified.eq(true))
-                        .get_result(&mut match db.get() {
-                            Ok(v) => v,
-                            Err(e) => {
-                                log::error!("{e:?}");
-                                return false;
-                            }
-                        }) {
-                        Ok(v) => v,
-                        Err(e) => {
-                            log::error!("{e:?}");
-                            return false;
-                        }
+                        .filter(payments::verified.eq(true)),
+                ))
+                .get_result(&mut match db.get() {
+                    Ok(v) => v,
+                    Err(e) => {
+                        log::error!("{e:?}");
+                        return false;
                     }
-                } 
-            }*/
+                }) {
+                    Ok(v) => v,
+                    Err(e) => {
+                        log::error!("{e:?}");
+                        false
+                    }
+                }
+            }
         }
     }
-    pub async fn send_verification_email(
-        &self,
-        mailer: Arc<Mutex<SmtpClient<TlsStream<TcpStream>>>>,
-        mailer_config: &SmtpClientBuilder<String>,
-    ) -> mail_send::Result<()> {
-        let verification_claims: u64 = VerificationClaims {
-            id: self.id,
-            pass_hash: self.password_hash.clone(),
-        }
-        .into();
-        let replace = VERIFICATION_EMAIL_TEMPLATE.replace(
-            "{verification_query}",
-            &format!("?id={}&token={}", self.id, verification_claims),
+    /// `token` is an `EMAIL_VERIFICATION` token from [`crate::tokens::issue_token`].
+    pub fn verification_email(&self, templates: &MailTemplates, token: &str) -> NewMail {
+        let verify_url = format!("{}/auth/verify?token={token}", templates.branding.api_url);
+        templates.mail(
+            MailKind::Verification,
+            &self.name,
+            &self.email,
+            &[("verify_url", &verify_url)],
+        )
+    }
+
+    /// `token` is a `PASSWORD_RESET` token from [`crate::tokens::issue_token`].
+    pub fn password_reset_email(&self, templates: &MailTemplates, token: &str) -> NewMail {
+        let reset_url = format!(
+            "{}/password_reset/verify?token={token}",
+            templates.branding.site_url
         );
-        let message = MessageBuilder::new()
-            .from(("Techfest", "techfest@sliet.ac.in"))
-            .to((self.name.clone(), self.email.clone()))
-            .subject("Email verification for techfest 24")
-            .html_body(&replace);
-        let display_message = message.clone().write_to_string().unwrap();
-        log::info!("Mail being sent is: {}", display_message);
-
-        let mut mailer = mailer.lock().await;
-        match mailer.noop().await {
-            Ok(()) => mailer.send(message).await,
-            Err(e) => {
-                log::error!("{e:?}");
-                *mailer = mailer_config.connect().await?;
-                mailer.send(message).await
-            }
-        }
+        templates.mail(
+            MailKind::PasswordReset,
+            &self.name,
+            &self.email,
+            &[("reset_url", &reset_url)],
+        )
+    }
+
+    /// Tells a waitlisted user that a seat freed up for `title` and they are now registered.
+    pub fn waitlist_promotion_email(&self, templates: &MailTemplates, title: &str) -> NewMail {
+        templates.mail(
+            MailKind::WaitlistPromotion,
+            &self.name,
+            &self.email,
+            &[("title", title)],
+        )
     }
 
-    pub async fn send_password_reset_email(
+    /// Tells the user that signing in is locked for `minutes` after repeated wrong passwords.
+    pub fn account_locked_email(
         &self,
-        mailer: Arc<Mutex<SmtpClient<TlsStream<TcpStream>>>>,
-        mailer_config: &SmtpClientBuilder<String>,
-    ) -> mail_send::Result<()> {
-        let reset_claims: u64 = ResetClaims::from(self).into();
-        let replace = PASSWORD_RESET_EMAIL_TEMPLATE.replace(
-            "{password_reset}",
-            &format!("?id={}&token={}", self.id, reset_claims),
-        );
-        let message = MessageBuilder::new()
-            .from(("Techfest", "techfest@sliet.ac.in"))
-            .to((self.name.clone(), self.email.clone()))
-            .subject("Password reset for techfest'24")
-            .html_body(&replace);
-        let display_message = message.clone().write_to_string().unwrap();
-        log::info!("Mail being sent is: {}", display_message);
-
-        let mut mailer = mailer.lock().await;
-        match mailer.noop().await {
-            Ok(()) => mailer.send(message).await,
-            Err(e) => {
-                log::error!("{e:?}");
-                *mailer = mailer_config.connect().await?;
-                mailer.send(message).await
-            }
-        }
+        templates: &MailTemplates,
+        minutes: u64,
+        ip: &str,
+    ) -> NewMail {
+        templates.mail(
+            MailKind::AccountLocked,
+            &self.name,
+            &self.email,
+            &[("minutes", &minutes.to_string()), ("ip", ip)],
+        )
+    }
+
+    /// Confirms that the user, or their team, got a seat for an event or workshop.
+    pub fn registration_confirmation_email(
+        &self,
+        templates: &MailTemplates,
+        title: &str,
+        start_time: NaiveDateTime,
+        venue: &str,
+    ) -> NewMail {
+        templates.mail(
+            MailKind::RegistrationConfirmation,
+            &self.name,
+            &self.email,
+            &[
+                ("title", title),
+                ("start_time", &format_time(start_time)),
+                ("venue", venue),
+            ],
+        )
+    }
+
+    /// Tells the user that `inviter_name` asked them to join `team_name`.
+    pub fn team_invitation_email(
+        &self,
+        templates: &MailTemplates,
+        team_name: &str,
+        inviter_name: &str,
+    ) -> NewMail {
+        templates.mail(
+            MailKind::TeamInvitation,
+            &self.name,
+            &self.email,
+            &[("team_name", team_name), ("inviter_name", inviter_name)],
+        )
+    }
+
+    /// Tells a member that `team_name` dropped below the `min_team_size` of `title`.
+    pub fn team_below_minimum_email(
+        &self,
+        templates: &MailTemplates,
+        team_name: &str,
+        title: &str,
+        min_team_size: i32,
+    ) -> NewMail {
+        templates.mail(
+            MailKind::TeamBelowMinimum,
+            &self.name,
+            &self.email,
+            &[
+                ("team_name", team_name),
+                ("title", title),
+                ("min_team_size", &min_team_size.to_string()),
+            ],
+        )
+    }
+
+    /// Tells a participant when and where `title` now takes place.
+    pub fn event_changed_email(
+        &self,
+        templates: &MailTemplates,
+        title: &str,
+        mode: &Mode,
+        start_time: NaiveDateTime,
+        end_time: NaiveDateTime,
+        venue: &str,
+    ) -> NewMail {
+        templates.mail(
+            MailKind::EventChanged,
+            &self.name,
+            &self.email,
+            &[
+                ("title", title),
+                ("mode", &mode.to_string()),
+                ("start_time", &format_time(start_time)),
+                ("end_time", &format_time(end_time)),
+                ("venue", venue),
+            ],
+        )
+    }
+
+    /// Tells a faculty applicant they were approved as `role` for `domain_name`.
+    pub fn faculty_approved_email(
+        &self,
+        templates: &MailTemplates,
+        role: &Role,
+        domain_name: &str,
+    ) -> NewMail {
+        templates.mail(
+            MailKind::FacultyApproved,
+            &self.name,
+            &self.email,
+            &[("role", &role.to_string()), ("domain_name", domain_name)],
+        )
+    }
+
+    /// Tells a faculty applicant they were not approved, and why if a reason was given.
+    pub fn faculty_rejected_email(
+        &self,
+        templates: &MailTemplates,
+        reason: Option<&str>,
+    ) -> NewMail {
+        templates.mail(
+            MailKind::FacultyRejected,
+            &self.name,
+            &self.email,
+            &[("reason", reason.unwrap_or("No reason was given."))],
+        )
     }
 }
 
 #[async_trait]
-impl<'a> FromRequestParts<SiteState> for User {
-    type Rejection = (StatusCode, String);
+impl FromRequestParts<SiteState> for User {
+    type Rejection = AppError;
 
     async fn from_request_parts(
         parts: &mut Parts,
         state: &SiteState,
     ) -> Result<Self, Self::Rejection> {
-        match parts.headers.get("Cookie") {
-            Some(cookie_string) => match Cookie::split_parse(match cookie_string.to_str() {
-                Ok(s) => s,
-                Err(_) => return Err((StatusCode::BAD_REQUEST, "Invalid Cookies".to_string())),
-            })
+        let not_signed_in = || AppError::unauthorized("Not signed in").with_code("not_signed_in");
+        let cookies = parts
+            .headers
+            .get("Cookie")
+            .ok_or_else(not_signed_in)?
+            .to_str()
+            .map_err(|_| AppError::new(StatusCode::BAD_REQUEST, "Invalid cookies"))?;
+        let cookie = Cookie::split_parse(cookies)
             .flatten()
-            .filter(|c| c.name() == "jwt-token")
-            .next()
-            {
-                Some(c) => match jsonwebtoken::decode::<UserClaims>(
-                    c.value(),
-                    &KEYS.decoding,
-                    &Validation::new(jsonwebtoken::Algorithm::HS256),
-                ) {
-                    Ok(token) => {
-                        let user =
-                            match users::table
-                                .filter(users::id.eq(token.claims.id))
-                                .select(User::as_select())
-                                .get_result(&mut state.connection.get().map_err(|e| {
-                                    (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
-                                })?) {
-                                Ok(user) => user,
-                                Err(e) => {
-                                    log::error!("{}", e);
-                                    return Err((
-                                        StatusCode::UNAUTHORIZED,
-                                        "Incorrent Username or Password".to_string(),
-                                    ));
-                                }
-                            };
-
-                        if user.password_hash == token.claims.hash {
-                            return Ok(user);
-                        }
-                        return Err((
-                            StatusCode::UNAUTHORIZED,
-                            "Incorrect username or password".to_string(),
-                        ));
-                    }
-                    Err(e) => {
-                        log::error!("At line {}, {}", line!(), e);
-                        return Err((
-                            StatusCode::BAD_REQUEST,
-                            "Could not parse the JWT".to_string(),
-                        ));
-                    }
-                },
-                None => {
-                    return Err((StatusCode::UNAUTHORIZED, "JWT Cookie not found".to_string()));
-                }
-            },
-            None => {
-                return Err((StatusCode::UNAUTHORIZED, "JWT Cookie not found".to_string())).into()
+            .find(|c| c.name() == ACCESS_COOKIE)
+            .ok_or_else(not_signed_in)?;
+        let token = match jsonwebtoken::decode::<UserClaims>(
+            cookie.value(),
+            &keys().decoding,
+            &Validation::new(jsonwebtoken::Algorithm::HS256),
+        ) {
+            Ok(token) => token,
+            Err(e) if *e.kind() == ErrorKind::ExpiredSignature => {
+                return Err(
+                    AppError::unauthorized("JWT expired, refresh it at /auth/refresh")
+                        .with_code("token_expired"),
+                );
+            }
+            Err(e) => {
+                log::error!("At line {}, {}", line!(), e);
+                return Err(AppError::new(
+                    StatusCode::BAD_REQUEST,
+                    "Could not parse the JWT",
+                ));
             }
         };
+        // Joined with a live session, so a revoked one is refused straight away, as is one
+        // opened before the password last changed
+        users::table
+            .inner_join(sessions::table)
+            .filter(users::id.eq(token.claims.id))
+            .filter(sessions::id.eq(token.claims.sid))
+            .filter(sessions::revoked_at.is_null())
+            .filter(sessions::epoch.eq(users::session_epoch))
+            .filter(sessions::expires_at.gt(diesel::dsl::now))
+            .select(User::as_select())
+            .get_result(&mut state.connection.get()?)
+            .optional()?
+            .ok_or_else(|| {
+                AppError::unauthorized("Session expired or signed out").with_code("signed_out")
+            })
     }
 }
@@ -22,4 +22,5 @@ pub struct Workshop {
     pub registeration_end: chrono::NaiveDateTime,
     pub whatsapp_link: String,
     pub photo_hash: Option<Vec<u8>>,
+    pub max_participants: Option<i32>,
 }
@@ -0,0 +1,424 @@
+//! Sign in with Google, through the OpenID Connect authorization code flow with PKCE.
+//!
+//! `/auth/google` sends the browser to the provider, which sends it back to `/auth/google/callback`
+//! with a code. The code is exchanged for an ID token, whose signature is checked against the
+//! provider's published keys. The Google account is then linked to a user, by its stable subject
+//! id once linked and by email the first time, and a user is created if there is none. A user
+//! whose email was never verified loses their password and sessions when linked. Emails on
+//! a trusted domain count as verified. From there on it is an ordinary session, with the same
+//! cookies as a password sign in.
+//!
+//! The client is set up from the `oidc` section of [`crate::config`], and everything about the
+//! provider is read from its discovery document, so pointing `oidc.issuer` (`OIDC_ISSUER`) at
+//! `examples/oidc_mock_provider.rs` exercises the whole flow locally.
+use std::time::{Duration, SystemTime};
+
+use anyhow::anyhow;
+use axum::{
+    extract::{Query, State},
+    response::Redirect,
+};
+use axum_extra::extract::{
+    cookie::{Cookie, SameSite},
+    CookieJar,
+};
+use base64::prelude::*;
+use diesel::prelude::*;
+use diesel::result::Error;
+use http::StatusCode;
+use jsonwebtoken::{
+    jwk::{Jwk, JwkSet},
+    Algorithm, DecodingKey, Header, Validation,
+};
+use rand::Rng;
+use reqwest::Url;
+use sha2::{Digest, Sha256};
+use tokio::sync::{OnceCell, RwLock};
+
+use crate::{
+    auth::{keys, start_session, Client},
+    config::OidcConfig,
+    error::AppError,
+    forms::identities::OidcCallback,
+    models::{
+        identities::{
+            IdTokenClaims, NewIdentity, OidcLoginClaims, ProviderMetadata, TokenResponse, GOOGLE,
+            OIDC_LOGIN_AUDIENCE,
+        },
+        tokens::new_token,
+        users::{hash_password, Role, User},
+    },
+    schema::{user_identities, users},
+    state::SiteState,
+    two_factor::{challenge, enabled_factor},
+};
+
+const LOGIN_COOKIE: &str = "oidc-login";
+
+/// How long the user has to get through the provider's pages.
+const LOGIN_TTL: Duration = Duration::from_secs(60 * 10);
+
+diesel::define_sql_function! {
+    fn lower(x: diesel::sql_types::Text) -> diesel::sql_types::Text;
+}
+
+struct Provider {
+    issuer: String,
+    client_id: String,
+    client_secret: String,
+    redirect_url: String,
+    /// Email domains whose accounts are verified as soon as they sign in
+    trusted_domains: Vec<String>,
+    http: reqwest::Client,
+    metadata: OnceCell<ProviderMetadata>,
+    jwks: RwLock<JwkSet>,
+}
+
+static PROVIDER: once_cell::sync::OnceCell<Provider> = once_cell::sync::OnceCell::new();
+
+/// Sets up the provider from `oidc`, once at startup. Google sign in stays off when
+/// [`OidcConfig::enabled`] is not.
+pub fn init_provider(oidc: &OidcConfig) {
+    let (Some(client_id), Some(client_secret), Some(redirect_url)) = (
+        oidc.client_id.clone(),
+        oidc.client_secret.clone(),
+        oidc.redirect_url.clone(),
+    ) else {
+        return;
+    };
+    let provider = Provider {
+        issuer: oidc.issuer.clone(),
+        client_id,
+        client_secret,
+        redirect_url,
+        trusted_domains: oidc.trusted_domains.clone(),
+        http: reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .expect("To build an HTTP client"),
+        metadata: OnceCell::new(),
+        jwks: RwLock::new(JwkSet { keys: Vec::new() }),
+    };
+    if PROVIDER.set(provider).is_err() {
+        log::warn!("The OIDC provider was already set, keeping the first one");
+    }
+}
+
+impl Provider {
+    async fn metadata(&self) -> anyhow::Result<&ProviderMetadata> {
+        self.metadata
+            .get_or_try_init(|| async {
+                let url = format!(
+                    "{}/.well-known/openid-configuration",
+                    self.issuer.trim_end_matches('/')
+                );
+                Ok(self
+                    .http
+                    .get(url)
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json()
+                    .await?)
+            })
+            .await
+    }
+
+    /// The signing key with id `kid`. The key set is fetched again when the key is not known, since
+    /// providers rotate their keys.
+    async fn key(&self, kid: &str) -> anyhow::Result<Jwk> {
+        if let Some(jwk) = self.jwks.read().await.find(kid) {
+            return Ok(jwk.clone());
+        }
+        let jwks_uri = &self.metadata().await?.jwks_uri;
+        let jwks: JwkSet = self
+            .http
+            .get(jwks_uri)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let jwk = jwks.find(kid).cloned();
+        *self.jwks.write().await = jwks;
+        jwk.ok_or_else(|| anyhow!("The provider has no key {kid}"))
+    }
+
+    /// Swaps the code for an ID token and checks it.
+    async fn exchange(&self, code: &str, verifier: &str) -> anyhow::Result<IdTokenClaims> {
+        let metadata = self.metadata().await?;
+        let response: TokenResponse = self
+            .http
+            .post(&metadata.token_endpoint)
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", &self.redirect_url),
+                ("client_id", &self.client_id),
+                ("client_secret", &self.client_secret),
+                ("code_verifier", verifier),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let header = jsonwebtoken::decode_header(&response.id_token)?;
+        if header.alg != Algorithm::RS256 {
+            return Err(anyhow!("ID token signed with {:?}", header.alg));
+        }
+        let kid = header
+            .kid
+            .ok_or_else(|| anyhow!("ID token has no key id"))?;
+        let key = DecodingKey::from_jwk(&self.key(&kid).await?)?;
+        let mut validation = Validation::new(Algorithm::RS256);
+        validation.set_audience(&[&self.client_id]);
+        // Google leaves the scheme out of `iss` on some tokens
+        validation.set_issuer(&[
+            metadata.issuer.as_str(),
+            metadata.issuer.trim_start_matches("https://"),
+        ]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+        Ok(jsonwebtoken::decode::<IdTokenClaims>(&response.id_token, &key, &validation)?.claims)
+    }
+
+    fn is_trusted(&self, email: &str) -> bool {
+        email
+            .rsplit_once('@')
+            .is_some_and(|(_, domain)| self.trusted_domains.contains(&domain.to_lowercase()))
+    }
+}
+
+fn provider() -> Result<&'static Provider, AppError> {
+    PROVIDER
+        .get()
+        .ok_or_else(|| AppError::not_found("Sign in with Google is not set up"))
+}
+
+/// Logs a failure talking to the provider and answers 502.
+fn bad_gateway(error: impl std::fmt::Debug) -> AppError {
+    log::error!("{error:?}");
+    AppError::from(StatusCode::BAD_GATEWAY)
+}
+
+fn login_cookie(value: String, max_age: Duration) -> Cookie<'static> {
+    // Lax, so it comes along when the provider sends the browser back
+    Cookie::build((LOGIN_COOKIE, value))
+        .http_only(true)
+        .secure(true)
+        .same_site(SameSite::Lax)
+        .max_age(max_age.try_into().unwrap())
+        .path("/auth/google")
+        .build()
+}
+
+/// Sends the browser to the provider to sign in.
+pub async fn google_sign_in(cookie_jar: CookieJar) -> Result<(CookieJar, Redirect), AppError> {
+    let provider = provider()?;
+    let metadata = provider.metadata().await.map_err(bad_gateway)?;
+    let login = OidcLoginClaims {
+        aud: OIDC_LOGIN_AUDIENCE.to_owned(),
+        state: new_token(),
+        nonce: new_token(),
+        verifier: BASE64_URL_SAFE_NO_PAD.encode(rand::thread_rng().gen::<[u8; 32]>()),
+        exp: (SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_err(AppError::internal)?
+            + LOGIN_TTL)
+            .as_secs(),
+    };
+    let challenge = BASE64_URL_SAFE_NO_PAD.encode(Sha256::digest(login.verifier.as_bytes()));
+    let mut params = vec![
+        ("response_type", "code"),
+        ("client_id", provider.client_id.as_str()),
+        ("redirect_uri", provider.redirect_url.as_str()),
+        ("scope", "openid email profile"),
+        ("state", login.state.as_str()),
+        ("nonce", login.nonce.as_str()),
+        ("code_challenge", challenge.as_str()),
+        ("code_challenge_method", "S256"),
+        ("prompt", "select_account"),
+    ];
+    // Only a hint to the account chooser, the domain is checked again on the way back
+    if let [domain] = provider.trusted_domains.as_slice() {
+        params.push(("hd", domain.as_str()));
+    }
+    let url =
+        Url::parse_with_params(&metadata.authorization_endpoint, &params).map_err(bad_gateway)?;
+    let cookie = jsonwebtoken::encode(&Header::default(), &login, &keys().encoding)
+        .map_err(AppError::internal)?;
+    Ok((
+        cookie_jar.add(login_cookie(cookie, LOGIN_TTL)),
+        Redirect::to(url.as_str()),
+    ))
+}
+
+/// A hash of a password nobody knows.
+fn unusable_password() -> QueryResult<String> {
+    hash_password(&new_token()).map_err(|e| Error::QueryBuilderError(e.to_string().into()))
+}
+
+/// Finds the user the identity belongs to, linking or creating one the first time. An existing
+/// user is only taken over as is when their email is verified.
+fn link_user(
+    connection: &mut PgConnection,
+    claims: &IdTokenClaims,
+    email: &str,
+    trusted: bool,
+) -> QueryResult<User> {
+    connection.transaction(|connection| {
+        let linked: Option<User> = user_identities::table
+            .inner_join(users::table)
+            .select(User::as_select())
+            .filter(user_identities::provider.eq(GOOGLE))
+            .filter(user_identities::subject.eq(&claims.sub))
+            .get_result(connection)
+            .optional()?;
+        let mut user = match linked {
+            Some(user) => {
+                diesel::update(user_identities::table)
+                    .filter(user_identities::provider.eq(GOOGLE))
+                    .filter(user_identities::subject.eq(&claims.sub))
+                    .set((
+                        user_identities::email.eq(email),
+                        user_identities::last_sign_in_at.eq(diesel::dsl::now),
+                    ))
+                    .execute(connection)?;
+                user
+            }
+            None => {
+                let existing: Option<User> = users::table
+                    .select(User::as_select())
+                    .filter(lower(users::email).eq(email.to_lowercase()))
+                    .get_result(connection)
+                    .optional()?;
+                let user = match existing {
+                    Some(user) if user.verified => user,
+                    // Whoever signed up with the address never proved they own it, so their
+                    // password and sessions go before the account is handed to the Google account
+                    Some(user) => diesel::update(users::table)
+                        .filter(users::id.eq(user.id))
+                        .set((
+                            users::password_hash.eq(unusable_password()?),
+                            users::session_epoch.eq(users::session_epoch + 1),
+                        ))
+                        .returning(User::as_returning())
+                        .get_result(connection)?,
+                    // Nobody knows this password, a password can be set through a reset. The
+                    // date of birth and phone are placeholders until the user gives theirs.
+                    None => diesel::insert_into(users::table)
+                        .values((
+                            User {
+                                id: 0,
+                                name: claims
+                                    .name
+                                    .clone()
+                                    .filter(|name| !name.trim().is_empty())
+                                    .unwrap_or_else(|| {
+                                        email.split('@').next().unwrap_or(email).to_owned()
+                                    }),
+                                dob: chrono::NaiveDate::default(),
+                                email: email.to_owned(),
+                                phone: String::new(),
+                                role: Role::PARTICIPANT,
+                                photo_hash: None,
+                                verified: false,
+                                password_hash: unusable_password()?,
+                            },
+                            users::profile_complete.eq(false),
+                        ))
+                        .returning(User::as_returning())
+                        .get_result(connection)?,
+                };
+                NewIdentity {
+                    provider: GOOGLE.to_owned(),
+                    subject: claims.sub.clone(),
+                    user_id: user.id,
+                    email: email.to_owned(),
+                }
+                .insert_into(user_identities::table)
+                .execute(connection)?;
+                user
+            }
+        };
+        if trusted && !user.verified {
+            diesel::update(users::table)
+                .filter(users::id.eq(user.id))
+                .set(users::verified.eq(true))
+                .execute(connection)?;
+            user.verified = true;
+        }
+        Ok(user)
+    })
+}
+
+/// Where the provider sends the browser back to. Signs the user in and sends them on to the
+/// frontend.
+pub async fn google_callback(
+    State(state): State<SiteState>,
+    client: Client,
+    cookie_jar: CookieJar,
+    Query(data): Query<OidcCallback>,
+) -> Result<(CookieJar, Redirect), AppError> {
+    let provider = provider()?;
+    if let Some(error) = data.error {
+        return Err(AppError::unauthorized(format!(
+            "Sign in with Google failed: {error}"
+        )));
+    }
+    let mut validation = Validation::new(Algorithm::HS256);
+    validation.set_audience(&[OIDC_LOGIN_AUDIENCE]);
+    let login = cookie_jar
+        .get(LOGIN_COOKIE)
+        .and_then(|cookie| {
+            jsonwebtoken::decode::<OidcLoginClaims>(cookie.value(), &keys().decoding, &validation)
+                .map_err(|e| log::error!("{e:?}"))
+                .ok()
+        })
+        .ok_or_else(|| {
+            AppError::new(
+                StatusCode::BAD_REQUEST,
+                "Sign in with Google took too long, try again",
+            )
+        })?
+        .claims;
+    let cookie_jar = cookie_jar.add(login_cookie(String::new(), Duration::ZERO));
+    let (Some(code), Some(returned_state)) = (data.code, data.state) else {
+        return Err(StatusCode::BAD_REQUEST.into());
+    };
+    if returned_state != login.state {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            "Sign in with Google was not started here",
+        ));
+    }
+    let claims = provider
+        .exchange(&code, &login.verifier)
+        .await
+        .map_err(bad_gateway)?;
+    if claims.nonce.as_deref() != Some(login.nonce.as_str()) {
+        log::error!("ID token nonce does not match the sign in");
+        return Err(StatusCode::UNAUTHORIZED.into());
+    }
+    let Some(email) = claims.email.clone().filter(|_| claims.email_verified) else {
+        return Err(AppError::forbidden(
+            "The Google account has no verified email",
+        ));
+    };
+    let trusted = provider.is_trusted(&email);
+    let connection = &mut state.connection.get()?;
+    let user = link_user(connection, &claims, &email, trusted)?;
+    let factor = enabled_factor(connection, user.id)?;
+    // Where the browser ends up once signed in
+    let frontend_url = format!("{}/", state.config.site.url());
+    if factor.is_some() {
+        // The frontend asks for a code and finishes at `/auth/2fa`
+        let cookie_jar = challenge(cookie_jar, &user)?;
+        let url = Url::parse_with_params(&frontend_url, &[("two_factor", "required")])
+            .map_err(AppError::internal)?;
+        return Ok((cookie_jar, Redirect::to(url.as_str())));
+    }
+    let cookie_jar = start_session(&state, cookie_jar, &client, &user)?;
+    Ok((cookie_jar, Redirect::to(&frontend_url)))
+}
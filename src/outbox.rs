@@ -0,0 +1,275 @@
+//! Outgoing mail. Handlers only add mail to the `outbox` table, which is quick and can share
+//! their transaction, and a single background task sends it. A mail that fails to send is tried
+//! again later, waiting twice as long each time, until it is marked `FAILED` after
+//! [`MAX_ATTEMPTS`]. Super admins can list failed mail and queue it again.
+use std::time::Duration;
+
+use axum::{
+    extract::{Query, State},
+    Form, Json,
+};
+use diesel::dsl::IntervalDsl;
+use diesel::prelude::*;
+use mail_send::mail_builder::MessageBuilder;
+
+use crate::{
+    error::AppError,
+    forms::outbox::{MailId, OutboxQuery},
+    models::{
+        outbox::{MailStatus, NewMail, OutboxEntry, QueuedMail, MAX_ATTEMPTS},
+        users::User,
+    },
+    policy::require_super_admin,
+    schema::{outbox, team_members, users},
+    state::SiteState,
+};
+
+/// Mail sent per round, before looking for newly due mail again.
+const BATCH_SIZE: i64 = 20;
+/// How often due retries are looked for when nothing new was queued.
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+/// A send that takes longer counts as failed, so one stuck connection can not hold up the queue.
+const SEND_TIMEOUT: Duration = Duration::from_secs(60);
+const FIRST_RETRY: Duration = Duration::from_secs(30);
+const MAX_RETRY: Duration = Duration::from_secs(60 * 60);
+
+/// Adds mail to the outbox on the caller's connection, so it is only sent if their transaction
+/// commits. Call [`SiteState::wake_outbox`] afterwards to have it sent right away.
+pub fn enqueue(connection: &mut PgConnection, mails: &[NewMail]) -> QueryResult<usize> {
+    diesel::insert_into(outbox::table)
+        .values(mails)
+        .execute(connection)
+}
+
+/// [`enqueue`] for a single mail outside of any transaction.
+pub fn queue_mail(state: &SiteState, mail: NewMail) -> Result<(), AppError> {
+    let connection = &mut state.connection.get()?;
+    enqueue(connection, &[mail])?;
+    state.wake_outbox();
+    Ok(())
+}
+
+/// Queues the mail `build` makes for everyone in `user_ids`. Failures are only logged, as this is
+/// called once the change the mail is about has been committed.
+pub fn mail_users(state: &SiteState, user_ids: &[i32], build: impl Fn(&User) -> NewMail) {
+    if user_ids.is_empty() {
+        return;
+    }
+    let mut connection = match state.connection.get() {
+        Ok(v) => v,
+        Err(e) => {
+            log::error!("{e:?}");
+            return;
+        }
+    };
+    let recipients: Vec<User> = match users::table
+        .select(User::as_select())
+        .filter(users::id.eq_any(user_ids))
+        .load(&mut connection)
+    {
+        Ok(v) => v,
+        Err(e) => {
+            log::error!("{e:?}");
+            return;
+        }
+    };
+    let mails: Vec<NewMail> = recipients.iter().map(build).collect();
+    match enqueue(&mut connection, &mails) {
+        Ok(_) => state.wake_outbox(),
+        Err(e) => log::error!("{e:?}"),
+    }
+}
+
+/// Same as [`mail_users`] for every member of the teams in `team_ids`.
+pub fn mail_teams(state: &SiteState, team_ids: &[i32], build: impl Fn(&User) -> NewMail) {
+    if team_ids.is_empty() {
+        return;
+    }
+    let mut connection = match state.connection.get() {
+        Ok(v) => v,
+        Err(e) => {
+            log::error!("{e:?}");
+            return;
+        }
+    };
+    let members: Vec<i32> = match team_members::table
+        .select(team_members::student_id)
+        .filter(team_members::team_id.eq_any(team_ids))
+        .load(&mut connection)
+    {
+        Ok(v) => v,
+        Err(e) => {
+            log::error!("{e:?}");
+            return;
+        }
+    };
+    mail_users(state, &members, build);
+}
+
+fn retry_delay(attempts: i32) -> Duration {
+    FIRST_RETRY
+        .saturating_mul(2u32.saturating_pow(attempts.max(1) as u32 - 1))
+        .min(MAX_RETRY)
+}
+
+async fn send(state: &SiteState, mail: &QueuedMail) -> anyhow::Result<()> {
+    let branding = &state.templates.branding;
+    let mut message = MessageBuilder::new()
+        .from((branding.from_name.as_str(), branding.from_email.as_str()))
+        .to((mail.recipient_name.as_str(), mail.recipient_email.as_str()))
+        .subject(mail.subject.as_str())
+        .html_body(mail.html_body.as_str());
+    if let Some(text_body) = &mail.text_body {
+        message = message.text_body(text_body.as_str());
+    }
+    state.mailer.send(message).await
+}
+
+fn record_attempt(
+    connection: &mut PgConnection,
+    mail: &QueuedMail,
+    result: anyhow::Result<()>,
+) -> QueryResult<usize> {
+    let attempts = mail.attempts + 1;
+    let update = diesel::update(outbox::table.filter(outbox::id.eq(mail.id)));
+    match result {
+        Ok(()) => update
+            .set((
+                outbox::status.eq(MailStatus::SENT),
+                outbox::attempts.eq(attempts),
+                outbox::last_error.eq(None::<String>),
+                outbox::sent_at.eq(diesel::dsl::now.nullable()),
+            ))
+            .execute(connection),
+        Err(e) if attempts >= MAX_ATTEMPTS => {
+            log::error!(
+                "Giving up on mail {} to {}: {e:?}",
+                mail.id,
+                mail.recipient_email
+            );
+            update
+                .set((
+                    outbox::status.eq(MailStatus::FAILED),
+                    outbox::attempts.eq(attempts),
+                    outbox::last_error.eq(e.to_string()),
+                ))
+                .execute(connection)
+        }
+        Err(e) => {
+            log::warn!("Mail {} to {} failed: {e:?}", mail.id, mail.recipient_email);
+            update
+                .set((
+                    outbox::attempts.eq(attempts),
+                    outbox::last_error.eq(e.to_string()),
+                    outbox::next_attempt_at
+                        .eq(diesel::dsl::now + (retry_delay(attempts).as_secs() as i64).seconds()),
+                ))
+                .execute(connection)
+        }
+    }
+}
+
+fn due_mail(state: &SiteState) -> anyhow::Result<Vec<QueuedMail>> {
+    Ok(outbox::table
+        .select(QueuedMail::as_select())
+        .filter(outbox::status.eq(MailStatus::PENDING))
+        .filter(outbox::next_attempt_at.le(diesel::dsl::now))
+        .order(outbox::next_attempt_at.asc())
+        .limit(BATCH_SIZE)
+        .load(&mut state.connection.get()?)?)
+}
+
+/// Sends queued mail for as long as the server runs. The pool connection is never held while
+/// talking to the mail server.
+pub async fn deliver_mail(state: SiteState) {
+    loop {
+        let due = due_mail(&state).unwrap_or_else(|e| {
+            log::error!("{e:?}");
+            Vec::new()
+        });
+        if due.is_empty() {
+            let _ = tokio::time::timeout(POLL_INTERVAL, state.outbox.notified()).await;
+            continue;
+        }
+        for mail in due {
+            let result = match tokio::time::timeout(SEND_TIMEOUT, send(&state, &mail)).await {
+                Ok(result) => result,
+                Err(_) => Err(anyhow::anyhow!("Timed out after {SEND_TIMEOUT:?}")),
+            };
+            let recorded = state
+                .connection
+                .get()
+                .map_err(anyhow::Error::from)
+                .and_then(|mut connection| Ok(record_attempt(&mut connection, &mail, result)?));
+            if let Err(e) = recorded {
+                log::error!("{e:?}");
+            }
+        }
+    }
+}
+
+/// Mail with the given status, `FAILED` by default, newest first.
+pub async fn get_outbox(
+    State(state): State<SiteState>,
+    user: User,
+    Query(data): Query<OutboxQuery>,
+) -> Result<Json<Vec<OutboxEntry>>, AppError> {
+    require_super_admin(&state, &user)?;
+    Ok(Json(
+        outbox::table
+            .select(OutboxEntry::as_select())
+            .filter(outbox::status.eq(data.status.unwrap_or(MailStatus::FAILED)))
+            .order(outbox::created_at.desc())
+            .limit(200)
+            .load(&mut state.connection.get()?)?,
+    ))
+}
+
+/// Queues a failed mail again, with a fresh set of attempts.
+pub async fn retry_mail(
+    State(state): State<SiteState>,
+    user: User,
+    Form(data): Form<MailId>,
+) -> Result<(), AppError> {
+    require_super_admin(&state, &user)?;
+    let requeued = diesel::update(outbox::table)
+        .filter(outbox::id.eq(data.id))
+        .filter(outbox::status.eq(MailStatus::FAILED))
+        .set((
+            outbox::status.eq(MailStatus::PENDING),
+            outbox::attempts.eq(0),
+            outbox::next_attempt_at.eq(diesel::dsl::now),
+        ))
+        .execute(&mut state.connection.get()?)?;
+    if requeued == 0 {
+        return Err(AppError::not_found("No such failed mail"));
+    }
+    state.wake_outbox();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retries_wait_twice_as_long_each_time() {
+        assert_eq!(retry_delay(1), FIRST_RETRY);
+        assert_eq!(retry_delay(2), FIRST_RETRY * 2);
+        assert_eq!(retry_delay(3), FIRST_RETRY * 4);
+        assert_eq!(retry_delay(7), FIRST_RETRY * 64);
+    }
+
+    #[test]
+    fn retries_wait_at_most_an_hour() {
+        assert_eq!(retry_delay(8), MAX_RETRY);
+        assert_eq!(retry_delay(40), MAX_RETRY);
+        assert_eq!(retry_delay(i32::MAX), MAX_RETRY);
+    }
+
+    #[test]
+    fn a_mail_never_tried_waits_like_the_first_retry() {
+        assert_eq!(retry_delay(0), FIRST_RETRY);
+        assert_eq!(retry_delay(-1), FIRST_RETRY);
+    }
+}
@@ -0,0 +1,327 @@
+use axum::{body::Bytes, extract::State, Form, Json};
+use diesel::prelude::*;
+use diesel::sql_types::{Int4, Nullable};
+use hmac::{Hmac, Mac};
+use http::{HeaderMap, StatusCode};
+use sha2::Sha256;
+
+use crate::{
+    error::AppError,
+    forms::{
+        payments::{NewPayment, PaymentId, RejectPayment, SubmitPayment, WebhookEvent},
+        users::Profile,
+    },
+    models::{
+        payments::{Payment, PaymentResponse},
+        users::User,
+    },
+    policy::require_super_admin,
+    schema::{payment_webhook_events, payments, users},
+    state::SiteState,
+};
+
+type HmacSha256 = Hmac<Sha256>;
+
+diesel::define_sql_function! {
+    fn coalesce(x: Nullable<Int4>, y: Int4) -> Int4;
+}
+
+pub async fn submit_payment(
+    State(state): State<SiteState>,
+    user: User,
+    Form(data): Form<SubmitPayment>,
+) -> Result<Json<Payment>, AppError> {
+    if !user.verified {
+        return Err(AppError::forbidden("Verify your email first"));
+    }
+    let payment_id = data.payment_id.trim();
+    if payment_id.is_empty() {
+        return Err(AppError::invalid("payment_id", "Required"));
+    }
+    if data.payment_amount <= 0 {
+        return Err(AppError::invalid("payment_amount", "Must be more than 0"));
+    }
+    NewPayment {
+        user_id: user.id,
+        payment_id: payment_id.to_owned(),
+        payment_amount: data.payment_amount,
+    }
+    .insert_into(payments::table)
+    .returning(Payment::as_returning())
+    .get_result(&mut state.connection.get()?)
+    .map(Json)
+    .map_err(|e| match AppError::from(e) {
+        e if e.status() == StatusCode::CONFLICT => {
+            AppError::conflict("This payment was already submitted")
+                .field("payment_id", "Already submitted")
+        }
+        e => e,
+    })
+}
+
+pub async fn get_payments(
+    State(state): State<SiteState>,
+    user: User,
+) -> Result<Json<Vec<Payment>>, AppError> {
+    Ok(Json(
+        payments::table
+            .select(Payment::as_select())
+            .filter(payments::user_id.eq(user.id))
+            .order(payments::submitted_at.desc())
+            .load(&mut state.connection.get()?)?,
+    ))
+}
+
+/// Payments waiting for a super admin, oldest first.
+pub async fn get_payment_queue(
+    State(state): State<SiteState>,
+    user: User,
+) -> Result<Json<Vec<PaymentResponse>>, AppError> {
+    require_super_admin(&state, &user)?;
+    let queue: Vec<(Payment, Profile)> = payments::table
+        .inner_join(users::table.on(users::id.eq(payments::user_id)))
+        .select((Payment::as_select(), Profile::as_select()))
+        .filter(payments::verified.eq(false))
+        .filter(payments::rejection_reason.is_null())
+        .order(payments::submitted_at.asc())
+        .load(&mut state.connection.get()?)?;
+    Ok(Json(
+        queue
+            .into_iter()
+            .map(|(payment, profile)| PaymentResponse { payment, profile })
+            .collect(),
+    ))
+}
+
+/// Why a review changed nothing: the payment does not exist, or it was already settled the other
+/// way, which takes a new submission to undo.
+fn not_reviewable(connection: &mut PgConnection, payment_id: &str, settled: &str) -> AppError {
+    let exists = diesel::select(diesel::dsl::exists(
+        payments::table.filter(payments::payment_id.eq(payment_id)),
+    ))
+    .get_result(connection);
+    match exists {
+        Ok(true) => AppError::conflict(format!("The payment was already {settled}")),
+        Ok(false) => AppError::not_found("No such payment"),
+        Err(e) => e.into(),
+    }
+}
+
+/// A payment the gateway says someone else made goes to them, see [`payment_webhook`].
+pub async fn verify_payment(
+    State(state): State<SiteState>,
+    user: User,
+    Form(data): Form<PaymentId>,
+) -> Result<Json<Payment>, AppError> {
+    require_super_admin(&state, &user)?;
+    let connection = &mut state.connection.get()?;
+    diesel::update(payments::table)
+        .filter(payments::payment_id.eq(&data.payment_id))
+        .filter(payments::rejection_reason.is_null())
+        .set((
+            payments::user_id.eq(coalesce(payments::claimed_by, payments::user_id)),
+            payments::claimed_by.eq(None::<i32>),
+            payments::verified.eq(true),
+            payments::reviewed_by.eq(user.id),
+        ))
+        .returning(Payment::as_returning())
+        .get_result(connection)
+        .optional()?
+        .map(Json)
+        .ok_or_else(|| not_reviewable(connection, &data.payment_id, "rejected"))
+}
+
+pub async fn reject_payment(
+    State(state): State<SiteState>,
+    user: User,
+    Form(data): Form<RejectPayment>,
+) -> Result<Json<Payment>, AppError> {
+    require_super_admin(&state, &user)?;
+    if data.reason.trim().is_empty() {
+        return Err(AppError::invalid("reason", "Required"));
+    }
+    let connection = &mut state.connection.get()?;
+    diesel::update(payments::table)
+        .filter(payments::payment_id.eq(&data.payment_id))
+        .filter(payments::verified.eq(false))
+        .set((
+            payments::rejection_reason.eq(data.reason.trim()),
+            payments::reviewed_by.eq(user.id),
+        ))
+        .returning(Payment::as_returning())
+        .get_result(connection)
+        .optional()?
+        .map(Json)
+        .ok_or_else(|| not_reviewable(connection, &data.payment_id, "verified"))
+}
+
+/// Hex encoded HMAC-SHA256 of a webhook body, as the gateway sends it in `X-Razorpay-Signature`.
+pub fn webhook_signature(secret: &[u8], body: &[u8]) -> String {
+    let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+fn verify_webhook_signature(secret: &[u8], body: &[u8], signature: &str) -> bool {
+    let signature = match hex::decode(signature.trim()) {
+        Ok(v) => v,
+        Err(_) => return false,
+    };
+    let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(body);
+    mac.verify_slice(&signature).is_ok()
+}
+
+/// Receives `payment.captured` / `order.paid` events and marks the payment verified.
+/// Replays are recognised by the event id and acknowledged without touching the payment again.
+/// Payments that match no user are refused without recording the event, so the gateway keeps
+/// retrying and the failure shows in its dashboard. A payment another user already submitted is
+/// not theirs to keep, nor for them to hold up: it goes back to the review queue with the payer
+/// the gateway names, and the event is recorded.
+pub async fn payment_webhook(
+    State(state): State<SiteState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<(), AppError> {
+    let Some(secret) = state.config.payment.webhook_secret.as_deref() else {
+        log::error!("payment.webhook_secret is not set, refusing payment webhook");
+        return Err(StatusCode::SERVICE_UNAVAILABLE.into());
+    };
+    let signature = headers
+        .get("X-Razorpay-Signature")
+        .and_then(|v| v.to_str().ok())
+        .ok_or_else(|| AppError::unauthorized("Missing signature"))?;
+    if !verify_webhook_signature(secret.as_bytes(), &body, signature) {
+        log::warn!("Payment webhook with an invalid signature");
+        return Err(AppError::unauthorized("Invalid signature"));
+    }
+    let event: WebhookEvent = serde_json::from_slice(&body).map_err(|e| {
+        log::error!("{e:?}");
+        AppError::new(StatusCode::BAD_REQUEST, "Malformed event")
+    })?;
+    if !matches!(event.event.as_str(), "payment.captured" | "order.paid") {
+        log::info!("Ignoring {} payment webhook", event.event);
+        return Ok(());
+    }
+    let payment = event
+        .payload
+        .payment
+        .ok_or_else(|| AppError::new(StatusCode::BAD_REQUEST, "The event has no payment"))?
+        .entity;
+    let event_id = headers
+        .get("X-Razorpay-Event-Id")
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_owned())
+        .unwrap_or_else(|| format!("{}:{}", event.event, payment.id));
+
+    state
+        .connection
+        .get()?
+        .transaction::<_, AppError, _>(|connection| {
+            let inserted = diesel::insert_into(payment_webhook_events::table)
+                .values((
+                    payment_webhook_events::event_id.eq(&event_id),
+                    payment_webhook_events::payment_id.eq(&payment.id),
+                ))
+                .on_conflict_do_nothing()
+                .execute(connection)?;
+            if inserted == 0 {
+                log::info!("Payment webhook {event_id} was already processed");
+                return Ok(());
+            }
+            let user_id = match (payment.user_id(), &payment.email) {
+                (Some(id), _) => Some(id),
+                (None, Some(email)) => users::table
+                    .select(users::id)
+                    .filter(users::email.eq(email.trim()))
+                    .get_result(connection)
+                    .optional()?,
+                (None, None) => None,
+            };
+            // Answering with an error rolls the event back, so the gateway delivers it again
+            // once the user exists
+            let user_id = user_id.ok_or_else(|| {
+                log::error!("Could not match gateway payment {} to a user", payment.id);
+                AppError::not_found("No user matches the payment")
+            })?;
+            let upsert = diesel::insert_into(payments::table)
+                .values((
+                    payments::user_id.eq(user_id),
+                    payments::payment_id.eq(&payment.id),
+                    payments::payment_amount.eq(payment.amount_in_rupees()),
+                    payments::order_id.eq(&payment.order_id),
+                    payments::verified.eq(true),
+                ))
+                .on_conflict(payments::payment_id)
+                .do_update()
+                .set((
+                    payments::verified.eq(true),
+                    payments::order_id.eq(&payment.order_id),
+                    payments::rejection_reason.eq(None::<String>),
+                ));
+            // Someone else submitted this payment id. The `WHERE` of an upsert is not on
+            // `QueryDsl`, hence the long way round.
+            let written = diesel::query_dsl::methods::FilterDsl::filter(
+                upsert,
+                payments::user_id.eq(user_id),
+            )
+            .execute(connection)?;
+            if written == 0 {
+                log::error!(
+                    "Gateway payment {} was submitted by someone other than user {user_id}, \
+                     flagged for review",
+                    payment.id
+                );
+                diesel::update(payments::table)
+                    .filter(payments::payment_id.eq(&payment.id))
+                    .set((
+                        payments::claimed_by.eq(user_id),
+                        payments::verified.eq(false),
+                        payments::rejection_reason.eq(None::<String>),
+                        payments::order_id.eq(&payment.order_id),
+                    ))
+                    .execute(connection)?;
+            }
+            Ok(())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // RFC 4231, test case 2
+    const KEY: &[u8] = b"Jefe";
+    const BODY: &[u8] = b"what do ya want for nothing?";
+    const MAC: &str = "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843";
+
+    #[test]
+    fn signature_matches_rfc_4231() {
+        assert_eq!(webhook_signature(KEY, BODY), MAC);
+    }
+
+    #[test]
+    fn accepts_the_right_signature() {
+        assert!(verify_webhook_signature(KEY, BODY, MAC));
+        assert!(verify_webhook_signature(KEY, BODY, &MAC.to_uppercase()));
+        assert!(verify_webhook_signature(KEY, BODY, &format!(" {MAC}\n")));
+    }
+
+    #[test]
+    fn refuses_a_changed_body_or_secret() {
+        assert!(!verify_webhook_signature(
+            KEY,
+            b"what do ya want for nothing!",
+            MAC
+        ));
+        assert!(!verify_webhook_signature(b"jefe", BODY, MAC));
+    }
+
+    #[test]
+    fn refuses_malformed_signatures() {
+        assert!(!verify_webhook_signature(KEY, BODY, ""));
+        assert!(!verify_webhook_signature(KEY, BODY, "not hex"));
+        assert!(!verify_webhook_signature(KEY, BODY, &MAC[..32]));
+        assert!(!verify_webhook_signature(KEY, BODY, &MAC[1..]));
+    }
+}
@@ -0,0 +1,406 @@
+//! Points earned by attending events and workshops, shared out inside teams and spent.
+//!
+//! Everything goes through the append-only `points_ledger`. Marking attendance credits the
+//! event's or workshop's `points`, and removing the mark takes back exactly what was credited, so
+//! editing `points` later never leaves a balance off. Team events credit the team, and its leader
+//! hands the points on to members. Leaderboards rank by points earned, so spending never costs
+//! anyone their place.
+use axum::{
+    extract::{Query, State},
+    Form, Json,
+};
+use diesel::prelude::*;
+use diesel::result::Error;
+
+use crate::{
+    certificate::revoke_certificates,
+    error::AppError,
+    forms::{
+        points::{DistributeTeamPoints, LeaderboardQuery, SpendPoints},
+        teams::TeamId,
+    },
+    models::{
+        points::{
+            Attendance, LeaderboardEntry, LedgerEntry, NewLedgerEntry, PointReason, PointsSummary,
+            TeamPoints,
+        },
+        users::User,
+    },
+    schema::{
+        events, individual_event_participation, points_ledger, team_event_participations,
+        team_members, teams, users, workshop_participation, workshops,
+    },
+    state::SiteState,
+    team::require_leader,
+};
+
+const DEFAULT_LEADERBOARD_SIZE: i64 = 50;
+const MAX_LEADERBOARD_SIZE: i64 = 200;
+
+/// Sets the `attended` flag of a registration and credits, or takes back, the points for it.
+/// Taking it back also revokes the certificates it earned.
+/// `Ok(None)` when there is no such registration, otherwise whether the flag changed.
+pub fn set_attendance(
+    connection: &mut PgConnection,
+    attendance: Attendance,
+    attended: bool,
+) -> QueryResult<Option<bool>> {
+    let current: Option<bool> = match attendance {
+        Attendance::Event { event_id, user_id } => individual_event_participation::table
+            .select(individual_event_participation::attended)
+            .filter(individual_event_participation::event_id.eq(event_id))
+            .filter(individual_event_participation::user_id.eq(user_id))
+            .for_update()
+            .get_result(connection)
+            .optional()?,
+        Attendance::TeamEvent { event_id, team_id } => team_event_participations::table
+            .select(team_event_participations::attended)
+            .filter(team_event_participations::event_id.eq(event_id))
+            .filter(team_event_participations::team_id.eq(team_id))
+            .for_update()
+            .get_result(connection)
+            .optional()?,
+        Attendance::Workshop {
+            workshop_id,
+            user_id,
+        } => workshop_participation::table
+            .select(workshop_participation::attended)
+            .filter(workshop_participation::workshop_id.eq(workshop_id))
+            .filter(workshop_participation::user_id.eq(user_id))
+            .for_update()
+            .get_result(connection)
+            .optional()?,
+    };
+    match current {
+        None => return Ok(None),
+        Some(current) if current == attended => return Ok(Some(false)),
+        Some(_) => {}
+    }
+    match attendance {
+        Attendance::Event { event_id, user_id } => {
+            diesel::update(individual_event_participation::table)
+                .set(individual_event_participation::attended.eq(attended))
+                .filter(individual_event_participation::event_id.eq(event_id))
+                .filter(individual_event_participation::user_id.eq(user_id))
+                .execute(connection)?
+        }
+        Attendance::TeamEvent { event_id, team_id } => {
+            diesel::update(team_event_participations::table)
+                .set(team_event_participations::attended.eq(attended))
+                .filter(team_event_participations::event_id.eq(event_id))
+                .filter(team_event_participations::team_id.eq(team_id))
+                .execute(connection)?
+        }
+        Attendance::Workshop {
+            workshop_id,
+            user_id,
+        } => diesel::update(workshop_participation::table)
+            .set(workshop_participation::attended.eq(attended))
+            .filter(workshop_participation::workshop_id.eq(workshop_id))
+            .filter(workshop_participation::user_id.eq(user_id))
+            .execute(connection)?,
+    };
+    record_attendance_points(connection, attendance, attended)?;
+    if !attended {
+        revoke_certificates(connection, attendance)?;
+    }
+    Ok(Some(true))
+}
+
+fn record_attendance_points(
+    connection: &mut PgConnection,
+    attendance: Attendance,
+    attended: bool,
+) -> QueryResult<()> {
+    let (user_id, team_id, event_id, workshop_id) = match attendance {
+        Attendance::Event { event_id, user_id } => (Some(user_id), None, Some(event_id), None),
+        Attendance::TeamEvent { event_id, team_id } => (None, Some(team_id), Some(event_id), None),
+        Attendance::Workshop {
+            workshop_id,
+            user_id,
+        } => (Some(user_id), None, None, Some(workshop_id)),
+    };
+    let (points, domain_id): (i32, i32) = match attendance {
+        Attendance::Event { event_id, .. } | Attendance::TeamEvent { event_id, .. } => {
+            events::table
+                .select((events::points, events::domain_id))
+                .filter(events::id.eq(event_id))
+                .get_result(connection)?
+        }
+        Attendance::Workshop { workshop_id, .. } => workshops::table
+            .select((workshops::points, workshops::domain_id))
+            .filter(workshops::id.eq(workshop_id))
+            .get_result(connection)?,
+    };
+    let (amount, reason) = if attended {
+        let reason = if let Attendance::Workshop { .. } = attendance {
+            PointReason::WORKSHOP_ATTENDANCE
+        } else {
+            PointReason::EVENT_ATTENDANCE
+        };
+        (points, reason)
+    } else {
+        let credited: Option<i64> = points_ledger::table
+            .select(diesel::dsl::sum(points_ledger::amount))
+            .filter(points_ledger::user_id.is_not_distinct_from(user_id))
+            .filter(points_ledger::team_id.is_not_distinct_from(team_id))
+            .filter(points_ledger::event_id.is_not_distinct_from(event_id))
+            .filter(points_ledger::workshop_id.is_not_distinct_from(workshop_id))
+            .filter(points_ledger::reason.eq_any([
+                PointReason::EVENT_ATTENDANCE,
+                PointReason::WORKSHOP_ATTENDANCE,
+                PointReason::ATTENDANCE_REVOKED,
+            ]))
+            .get_result(connection)?;
+        let credited = i32::try_from(credited.unwrap_or_default()).unwrap_or(i32::MAX);
+        (-credited, PointReason::ATTENDANCE_REVOKED)
+    };
+    if amount == 0 {
+        return Ok(());
+    }
+    NewLedgerEntry {
+        user_id,
+        team_id,
+        amount,
+        reason,
+        event_id,
+        workshop_id,
+        domain_id: Some(domain_id),
+        note: None,
+    }
+    .insert_into(points_ledger::table)
+    .execute(connection)
+    .map(|_| ())
+}
+
+fn user_balance(connection: &mut PgConnection, user_id: i32) -> QueryResult<i64> {
+    points_ledger::table
+        .select(diesel::dsl::sum(points_ledger::amount))
+        .filter(points_ledger::user_id.eq(user_id))
+        .get_result::<Option<i64>>(connection)
+        .map(Option::unwrap_or_default)
+}
+
+fn team_balance(connection: &mut PgConnection, team_id: i32, event_id: i32) -> QueryResult<i64> {
+    points_ledger::table
+        .select(diesel::dsl::sum(points_ledger::amount))
+        .filter(points_ledger::team_id.eq(team_id))
+        .filter(points_ledger::event_id.eq(event_id))
+        .get_result::<Option<i64>>(connection)
+        .map(Option::unwrap_or_default)
+}
+
+fn require_verified(user: &User) -> Result<(), AppError> {
+    if !user.verified {
+        return Err(AppError::forbidden("Verify your email first"));
+    }
+    Ok(())
+}
+
+/// The caller's balance, lifetime earnings and ledger, newest first.
+pub async fn get_points(
+    State(state): State<SiteState>,
+    user: User,
+) -> Result<Json<PointsSummary>, AppError> {
+    require_verified(&user)?;
+    let summary = state
+        .connection
+        .get()?
+        .transaction::<_, Error, _>(|connection| {
+            let entries = points_ledger::table
+                .select(LedgerEntry::as_select())
+                .filter(points_ledger::user_id.eq(user.id))
+                .order(points_ledger::id.desc())
+                .load(connection)?;
+            let earned: Option<i64> = points_ledger::table
+                .select(diesel::dsl::sum(points_ledger::amount))
+                .filter(points_ledger::user_id.eq(user.id))
+                .filter(points_ledger::reason.ne(PointReason::SPENT))
+                .get_result(connection)?;
+            Ok(PointsSummary {
+                balance: user_balance(connection, user.id)?,
+                earned: earned.unwrap_or_default(),
+                entries,
+            })
+        })?;
+    Ok(Json(summary))
+}
+
+/// Points the team still holds per event, for any of its members to see.
+pub async fn get_team_points(
+    State(state): State<SiteState>,
+    user: User,
+    Query(data): Query<TeamId>,
+) -> Result<Json<Vec<TeamPoints>>, AppError> {
+    require_verified(&user)?;
+    let connection = &mut state.connection.get()?;
+    let is_member: bool = diesel::select(diesel::dsl::exists(
+        team_members::table
+            .filter(team_members::team_id.eq(data.id))
+            .filter(team_members::student_id.eq(user.id)),
+    ))
+    .get_result(connection)?;
+    if !is_member {
+        return Err(AppError::forbidden("Only members of the team can see this"));
+    }
+    Ok(Json(
+        points_ledger::table
+            .inner_join(events::table)
+            .filter(points_ledger::team_id.eq(data.id))
+            .group_by((events::id, events::name))
+            .select((
+                events::id,
+                events::name,
+                diesel::dsl::sum(points_ledger::amount).assume_not_null(),
+            ))
+            .order(events::id)
+            .load(connection)?,
+    ))
+}
+
+/// Moves points a team got for an event to one of its members. Only the team leader may do this.
+pub async fn distribute_team_points(
+    State(state): State<SiteState>,
+    user: User,
+    Form(data): Form<DistributeTeamPoints>,
+) -> Result<(), AppError> {
+    require_verified(&user)?;
+    if data.amount <= 0 {
+        return Err(AppError::invalid("amount", "amount must be positive"));
+    }
+    let connection = &mut state.connection.get()?;
+    require_leader(connection, data.team_id, user.id)?;
+    let is_member: bool = diesel::select(diesel::dsl::exists(
+        team_members::table
+            .filter(team_members::team_id.eq(data.team_id))
+            .filter(team_members::student_id.eq(data.user_id)),
+    ))
+    .get_result(connection)?;
+    if !is_member {
+        return Err(AppError::invalid(
+            "user_id",
+            "Points can only go to members of the team",
+        ));
+    }
+    let short = connection.transaction::<_, Error, _>(|connection| {
+        // Serializes distributions of the same team so the balance can not be overdrawn
+        teams::table
+            .select(teams::id)
+            .filter(teams::id.eq(data.team_id))
+            .for_update()
+            .execute(connection)?;
+        let balance = team_balance(connection, data.team_id, data.event_id)?;
+        if balance < i64::from(data.amount) {
+            return Ok(Some(balance));
+        }
+        let domain_id: i32 = events::table
+            .select(events::domain_id)
+            .filter(events::id.eq(data.event_id))
+            .get_result(connection)?;
+        let share = |user_id, team_id, amount| NewLedgerEntry {
+            user_id,
+            team_id,
+            amount,
+            reason: PointReason::TEAM_SHARE,
+            event_id: Some(data.event_id),
+            workshop_id: None,
+            domain_id: Some(domain_id),
+            note: None,
+        };
+        diesel::insert_into(points_ledger::table)
+            .values([
+                share(None, Some(data.team_id), -data.amount),
+                share(Some(data.user_id), None, data.amount),
+            ])
+            .execute(connection)?;
+        Ok(None)
+    })?;
+    match short {
+        Some(balance) => Err(AppError::conflict(format!(
+            "The team only has {balance} points left for this event"
+        ))
+        .field("amount", "More than the team has")),
+        None => Ok(()),
+    }
+}
+
+/// Records the caller spending points on `note`.
+pub async fn spend_points(
+    State(state): State<SiteState>,
+    user: User,
+    Form(data): Form<SpendPoints>,
+) -> Result<(), AppError> {
+    require_verified(&user)?;
+    if data.amount <= 0 {
+        return Err(AppError::invalid("amount", "amount must be positive"));
+    }
+    let note = data.note.trim();
+    if note.is_empty() {
+        return Err(AppError::invalid(
+            "note",
+            "Say what the points are spent on",
+        ));
+    }
+    let short = state
+        .connection
+        .get()?
+        .transaction::<_, Error, _>(|connection| {
+            // Serializes spending by the same user so the balance can not be overdrawn
+            users::table
+                .select(users::id)
+                .filter(users::id.eq(user.id))
+                .for_update()
+                .execute(connection)?;
+            let balance = user_balance(connection, user.id)?;
+            if balance < i64::from(data.amount) {
+                return Ok(Some(balance));
+            }
+            NewLedgerEntry {
+                user_id: Some(user.id),
+                team_id: None,
+                amount: -data.amount,
+                reason: PointReason::SPENT,
+                event_id: None,
+                workshop_id: None,
+                domain_id: None,
+                note: Some(note.to_owned()),
+            }
+            .insert_into(points_ledger::table)
+            .execute(connection)?;
+            Ok(None)
+        })?;
+    match short {
+        Some(balance) => Err(
+            AppError::conflict(format!("You only have {balance} points left"))
+                .field("amount", "More than you have"),
+        ),
+        None => Ok(()),
+    }
+}
+
+/// Users ranked by points earned, overall or within one domain.
+pub async fn get_leaderboard(
+    State(state): State<SiteState>,
+    Query(data): Query<LeaderboardQuery>,
+) -> Result<Json<Vec<LeaderboardEntry>>, AppError> {
+    let limit = data
+        .limit
+        .unwrap_or(DEFAULT_LEADERBOARD_SIZE)
+        .clamp(1, MAX_LEADERBOARD_SIZE);
+    let mut query = points_ledger::table
+        .inner_join(users::table)
+        .filter(points_ledger::reason.ne(PointReason::SPENT))
+        .group_by((users::id, users::name))
+        .select((
+            users::id,
+            users::name,
+            diesel::dsl::sum(points_ledger::amount).assume_not_null(),
+        ))
+        .order((diesel::dsl::sum(points_ledger::amount).desc(), users::id))
+        .limit(limit)
+        .into_boxed();
+    if let Some(domain_id) = data.domain_id {
+        query = query.filter(points_ledger::domain_id.eq(domain_id));
+    }
+    Ok(Json(query.load(&mut state.connection.get()?)?))
+}
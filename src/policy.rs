@@ -0,0 +1,262 @@
+//! Answers "may this user do that to this resource" for every privileged handler.
+//!
+//! The rules follow `permissionDia.d2`:
+//! - a super admin may do anything, and is the only one who manages domains and registers people
+//!   outside the registration window;
+//! - a faculty coordinator manages the events and workshops of the domains they coordinate, and
+//!   appoints the student coordinators for them;
+//! - a student coordinator of a domain creates, updates, deletes and marks attendance for the
+//!   events and workshops of that domain, and runs the judging and publishes the results of its
+//!   events;
+//! - a student coordinator appointed to a single event or workshop may update it and mark its
+//!   attendance, but not delete it.
+//!
+//! Events and workshops are always resolved to their own domain before any coordinator table is
+//! consulted, so being a coordinator somewhere never grants access to another domain.
+use diesel::prelude::*;
+
+use crate::{
+    error::AppError,
+    models::users::{Role, User},
+    schema::{
+        events, faculty_coordinators, student_domain_coordinators, student_event_coordinators,
+        student_workshop_coordinators, workshops,
+    },
+    state::SiteState,
+    two_factor::require_enrolled,
+};
+
+#[derive(Debug, Clone, Copy)]
+pub enum Action {
+    /// Create, update or delete domains and appoint their faculty coordinators
+    ManageDomain,
+    /// Register someone for an event or workshop outside its registration window
+    OverrideRegistration,
+    AppointDomainStudentCoordinator {
+        domain_id: i32,
+    },
+    CreateEvent {
+        domain_id: i32,
+    },
+    UpdateEvent {
+        event_id: i32,
+    },
+    DeleteEvent {
+        event_id: i32,
+    },
+    MarkEventAttendance {
+        event_id: i32,
+    },
+    AppointEventCoordinator {
+        event_id: i32,
+    },
+    /// Record or withdraw winners of an event
+    PublishEventResults {
+        event_id: i32,
+    },
+    /// Appoint judges, set the rubric and finalize the scores of an event
+    ManageJudging {
+        event_id: i32,
+    },
+    CreateWorkshop {
+        domain_id: i32,
+    },
+    UpdateWorkshop {
+        workshop_id: i32,
+    },
+    DeleteWorkshop {
+        workshop_id: i32,
+    },
+    MarkWorkshopAttendance {
+        workshop_id: i32,
+    },
+    AppointWorkshopCoordinator {
+        workshop_id: i32,
+    },
+}
+
+/// What an action touches, once resolved against the database.
+enum Resource {
+    Global,
+    Domain(i32),
+    Event { id: i32, domain_id: i32 },
+    Workshop { id: i32, domain_id: i32 },
+}
+
+impl Resource {
+    fn domain_id(&self) -> Option<i32> {
+        match self {
+            Resource::Global => None,
+            Resource::Domain(domain_id) => Some(*domain_id),
+            Resource::Event { domain_id, .. } => Some(*domain_id),
+            Resource::Workshop { domain_id, .. } => Some(*domain_id),
+        }
+    }
+}
+
+impl Action {
+    fn resource(&self, connection: &mut PgConnection) -> Result<Resource, AppError> {
+        let event = |connection: &mut PgConnection, id: i32| {
+            events::table
+                .select(events::domain_id)
+                .filter(events::id.eq(id))
+                .get_result(connection)
+                .optional()?
+                .map(|domain_id| Resource::Event { id, domain_id })
+                .ok_or_else(|| AppError::not_found("No such event"))
+        };
+        let workshop = |connection: &mut PgConnection, id: i32| {
+            workshops::table
+                .select(workshops::domain_id)
+                .filter(workshops::id.eq(id))
+                .get_result(connection)
+                .optional()?
+                .map(|domain_id| Resource::Workshop { id, domain_id })
+                .ok_or_else(|| AppError::not_found("No such workshop"))
+        };
+        match *self {
+            Action::ManageDomain | Action::OverrideRegistration => Ok(Resource::Global),
+            Action::AppointDomainStudentCoordinator { domain_id }
+            | Action::CreateEvent { domain_id }
+            | Action::CreateWorkshop { domain_id } => Ok(Resource::Domain(domain_id)),
+            Action::UpdateEvent { event_id }
+            | Action::DeleteEvent { event_id }
+            | Action::MarkEventAttendance { event_id }
+            | Action::AppointEventCoordinator { event_id }
+            | Action::PublishEventResults { event_id }
+            | Action::ManageJudging { event_id } => event(connection, event_id),
+            Action::UpdateWorkshop { workshop_id }
+            | Action::DeleteWorkshop { workshop_id }
+            | Action::MarkWorkshopAttendance { workshop_id }
+            | Action::AppointWorkshopCoordinator { workshop_id } => {
+                workshop(connection, workshop_id)
+            }
+        }
+    }
+
+    fn allowed_for_faculty_coordinator(&self) -> bool {
+        !matches!(self, Action::ManageDomain | Action::OverrideRegistration)
+    }
+
+    fn allowed_for_domain_student_coordinator(&self) -> bool {
+        matches!(
+            self,
+            Action::CreateEvent { .. }
+                | Action::UpdateEvent { .. }
+                | Action::DeleteEvent { .. }
+                | Action::MarkEventAttendance { .. }
+                | Action::PublishEventResults { .. }
+                | Action::ManageJudging { .. }
+                | Action::CreateWorkshop { .. }
+                | Action::UpdateWorkshop { .. }
+                | Action::DeleteWorkshop { .. }
+                | Action::MarkWorkshopAttendance { .. }
+        )
+    }
+
+    fn allowed_for_resource_student_coordinator(&self) -> bool {
+        matches!(
+            self,
+            Action::UpdateEvent { .. }
+                | Action::MarkEventAttendance { .. }
+                | Action::UpdateWorkshop { .. }
+                | Action::MarkWorkshopAttendance { .. }
+        )
+    }
+}
+
+fn is_faculty_coordinator(
+    connection: &mut PgConnection,
+    user_id: i32,
+    domain_id: i32,
+) -> QueryResult<bool> {
+    diesel::select(diesel::dsl::exists(
+        faculty_coordinators::table
+            .filter(faculty_coordinators::faculty_id.eq(user_id))
+            .filter(faculty_coordinators::domain_id.eq(domain_id)),
+    ))
+    .get_result(connection)
+}
+
+fn is_domain_student_coordinator(
+    connection: &mut PgConnection,
+    user_id: i32,
+    domain_id: i32,
+) -> QueryResult<bool> {
+    diesel::select(diesel::dsl::exists(
+        student_domain_coordinators::table
+            .filter(student_domain_coordinators::student_id.eq(user_id))
+            .filter(student_domain_coordinators::domain_id.eq(domain_id)),
+    ))
+    .get_result(connection)
+}
+
+fn is_resource_student_coordinator(
+    connection: &mut PgConnection,
+    user_id: i32,
+    resource: &Resource,
+) -> QueryResult<bool> {
+    match *resource {
+        Resource::Event { id, .. } => diesel::select(diesel::dsl::exists(
+            student_event_coordinators::table
+                .filter(student_event_coordinators::student_id.eq(user_id))
+                .filter(student_event_coordinators::event_id.eq(id)),
+        ))
+        .get_result(connection),
+        Resource::Workshop { id, .. } => diesel::select(diesel::dsl::exists(
+            student_workshop_coordinators::table
+                .filter(student_workshop_coordinators::student_id.eq(user_id))
+                .filter(student_workshop_coordinators::workshop_id.eq(id)),
+        ))
+        .get_result(connection),
+        Resource::Global | Resource::Domain(_) => Ok(false),
+    }
+}
+
+fn not_allowed() -> AppError {
+    AppError::forbidden("You are not allowed to do this")
+}
+
+/// `Ok(())` when `user` may perform `action`, `FORBIDDEN` when not or when their role has to use
+/// two-factor and they have not turned it on, and `NOT_FOUND` when the event or workshop it
+/// refers to does not exist.
+pub fn authorize(state: &SiteState, user: &User, action: Action) -> Result<(), AppError> {
+    if !user.verified {
+        return Err(AppError::forbidden("Verify your email first"));
+    }
+    require_enrolled(state, user)?;
+    if let Role::SUPER_ADMIN = user.role {
+        return Ok(());
+    }
+    let connection = &mut state.connection.get()?;
+    let resource = action.resource(connection)?;
+    let domain_id = resource.domain_id().ok_or_else(not_allowed)?;
+    let allowed = match user.role {
+        Role::FACULTY_COORDINATOR if action.allowed_for_faculty_coordinator() => {
+            is_faculty_coordinator(connection, user.id, domain_id)?
+        }
+        Role::STUDENT_COORDINATOR => {
+            let domain_wide = action.allowed_for_domain_student_coordinator()
+                && is_domain_student_coordinator(connection, user.id, domain_id)?;
+            domain_wide
+                || (action.allowed_for_resource_student_coordinator()
+                    && is_resource_student_coordinator(connection, user.id, &resource)?)
+        }
+        _ => false,
+    };
+    if allowed {
+        Ok(())
+    } else {
+        log::warn!("{} ({}) is not allowed to {:?}", user.id, user.role, action);
+        Err(not_allowed())
+    }
+}
+
+/// `Ok(())` for super admins who turned on two-factor where their role has to use it, for the
+/// handlers only they may use.
+pub fn require_super_admin(state: &SiteState, user: &User) -> Result<(), AppError> {
+    let Role::SUPER_ADMIN = user.role else {
+        return Err(AppError::forbidden("Only a super admin can do this"));
+    };
+    require_enrolled(state, user)
+}
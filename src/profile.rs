@@ -1,8 +1,4 @@
-use argon2::password_hash::SaltString;
-use argon2::Argon2;
-use argon2::PasswordHasher;
 use base64::prelude::*;
-use rand::rngs::OsRng;
 use std::collections::HashMap;
 use std::io::Cursor;
 
@@ -13,58 +9,89 @@ use axum::{Form, Json};
 use axum_macros::debug_handler;
 use diesel::prelude::*;
 use highway::HighwayHash;
-use http::{header, HeaderMap, StatusCode};
+use http::{header, HeaderMap};
 use tokio_util::io::ReaderStream;
 
+use crate::auth::Client;
+use crate::error::AppError;
+use crate::faculty::file_application;
 use crate::forms::faculty::NewFacultyProfile;
 use crate::forms::student::NewStudentProfile;
 use crate::forms::users::{
-    ChangeProfile, GetProfilePhoto, PasswordResetQuery, Profile, ResetClaims, ResetSendQuery,
-    VerificationClaims, VerificationQuery,
+    ChangeProfile, GetProfilePhoto, PasswordResetQuery, Profile, ResetSendQuery, VerificationQuery,
 };
 use crate::models::faculty::Faculty;
 use crate::models::students::{Department, Student};
 use crate::models::team::TeamRequest;
-use crate::models::users::User;
+use crate::models::tokens::TokenPurpose;
+use crate::models::users::{hash_password, User};
+use crate::outbox::enqueue;
 use crate::schema::{faculty, students, team_requests, users};
 use crate::state::SiteState;
+use crate::throttle::{too_many_attempts, Key, Scope};
+use crate::tokens::{issue_token, redeem_token};
 
 pub async fn get_profile(
     State(state): State<SiteState>,
     user: User,
-) -> Result<Json<Profile>, StatusCode> {
-    users::table
-        .select(Profile::as_select())
-        .filter(users::id.eq(user.id))
-        .get_result(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })
-        .map(|v| Json(v))
+) -> Result<Json<Profile>, AppError> {
+    Ok(Json(
+        users::table
+            .select(Profile::as_select())
+            .filter(users::id.eq(user.id))
+            .get_result(&mut state.connection.get()?)?,
+    ))
 }
 
+/// A new email is unverified until the link mailed to it is followed, as the fee waiver and
+/// everything gated on verification go by the address.
 pub async fn change_profile(
     State(state): State<SiteState>,
     user: User,
     Form(data): Form<ChangeProfile>,
-) -> Result<Json<Profile>, StatusCode> {
-    diesel::update(users::table)
-        .set(data)
-        .filter(users::id.eq(user.id))
-        .returning(Profile::as_returning())
-        .get_result(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })
-        .map(|v| Json(v))
+) -> Result<Json<Profile>, AppError> {
+    let completes_profile = data.completes_profile();
+    let email_changed = data
+        .email
+        .as_deref()
+        .is_some_and(|email| !email.trim().eq_ignore_ascii_case(user.email.trim()));
+    let profile = state
+        .connection
+        .get()?
+        .transaction::<_, diesel::result::Error, _>(|connection| {
+            if email_changed {
+                diesel::update(users::table)
+                    .filter(users::id.eq(user.id))
+                    .set(users::verified.eq(false))
+                    .execute(connection)?;
+            }
+            let user: User = diesel::update(users::table)
+                .set(data)
+                .filter(users::id.eq(user.id))
+                .returning(User::as_returning())
+                .get_result(connection)?;
+            if completes_profile {
+                diesel::update(users::table)
+                    .filter(users::id.eq(user.id))
+                    .set(users::profile_complete.eq(true))
+                    .execute(connection)?;
+            }
+            if email_changed {
+                let token = issue_token(connection, user.id, TokenPurpose::EMAIL_VERIFICATION)?;
+                enqueue(
+                    connection,
+                    &[user.verification_email(&state.templates, &token)],
+                )?;
+            }
+            users::table
+                .select(Profile::as_select())
+                .filter(users::id.eq(user.id))
+                .get_result(connection)
+        })?;
+    if email_changed {
+        state.wake_outbox();
+    }
+    Ok(Json(profile))
 }
 
 // Not Deleting the image in case some other user also happens to have the same exact image
@@ -72,19 +99,13 @@ pub async fn set_profile_photo(
     State(state): State<SiteState>,
     user: User,
     photo: Bytes,
-) -> Result<(), StatusCode> {
+) -> Result<(), AppError> {
     let hash = state.bulk_hasher.hash256(photo.to_vec().as_slice());
     let photo = image::ImageReader::new(Cursor::new(photo))
         .with_guessed_format()
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::BAD_REQUEST
-        })?
+        .map_err(AppError::internal)?
         .decode()
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::BAD_REQUEST
-        })?;
+        .map_err(|e| AppError::invalid("photo", format!("Not a readable image: {e}")))?;
     let photo = photo.thumbnail(512, 512);
     photo
         .save_with_format(
@@ -100,10 +121,7 @@ pub async fn set_profile_photo(
             ),
             image::ImageFormat::Avif,
         )
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::BAD_REQUEST
-        })?;
+        .map_err(AppError::internal)?;
     diesel::update(users::table)
         .filter(users::id.eq(user.id))
         .set(
@@ -113,14 +131,7 @@ pub async fn set_profile_photo(
                 .flatten()
                 .collect::<Vec<u8>>()),
         )
-        .execute(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::BAD_REQUEST
-        })?;
+        .execute(&mut state.connection.get()?)?;
     Ok(())
 }
 
@@ -129,40 +140,29 @@ pub async fn get_profile_photo(
     State(state): State<SiteState>,
     user: User,
     query: Option<Query<GetProfilePhoto>>,
-) -> impl IntoResponse {
+) -> Result<impl IntoResponse, AppError> {
     // `File` implements `AsyncRead`
     let photo_hash = if let Some(other_profile) = query {
         users::table
             .select(users::photo_hash)
             .filter(users::id.eq(other_profile.id))
-            .get_result(&mut state.connection.get().map_err(|e| {
-                log::error!("{e:?}");
-                StatusCode::INTERNAL_SERVER_ERROR
-            })?)
-            .map_err(|e| {
-                log::error!("{e:?}");
-                StatusCode::NOT_FOUND
-            })?
+            .get_result::<Option<Vec<u8>>>(&mut state.connection.get()?)
+            .optional()?
+            .ok_or_else(|| AppError::not_found("No such user"))?
     } else {
         user.photo_hash
     };
-    let photo_hash = match photo_hash {
-        None => return Err(StatusCode::NOT_FOUND),
-        Some(v) => v,
-    };
-    let file = match tokio::fs::File::open(format!(
+    let photo_hash = photo_hash.ok_or_else(|| AppError::not_found("The user has no photo"))?;
+    let file = tokio::fs::File::open(format!(
         "{}/{}",
         state.image_dir.to_string_lossy(),
         BASE64_URL_SAFE_NO_PAD.encode(photo_hash)
     ))
     .await
-    {
-        Ok(file) => file,
-        Err(e) => {
-            log::error!("{e:?}");
-            return Err(StatusCode::NOT_FOUND);
-        }
-    };
+    .map_err(|e| {
+        log::error!("{e:?}");
+        AppError::not_found("The photo is missing")
+    })?;
     let stream = ReaderStream::new(file);
     let body = Body::from_stream(stream);
 
@@ -180,124 +180,102 @@ pub async fn get_profile_photo(
 pub async fn get_individual_team_requests(
     State(state): State<SiteState>,
     user: User,
-) -> Result<Json<Vec<TeamRequest>>, StatusCode> {
-    team_requests::table
-        .select(TeamRequest::as_select())
-        .filter(team_requests::student_id.eq(user.id))
-        .load(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map(|v| Json(v))
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::UNAUTHORIZED
-        })
+) -> Result<Json<Vec<TeamRequest>>, AppError> {
+    Ok(Json(
+        team_requests::table
+            .select(TeamRequest::as_select())
+            .filter(team_requests::student_id.eq(user.id))
+            .load(&mut state.connection.get()?)?,
+    ))
 }
 
+/// Throttled per address and per email, unknown emails included, so it can not be used to flood
+/// an inbox.
 pub async fn send_reset_mail(
     State(state): State<SiteState>,
+    client: Client,
     Form(data): Form<ResetSendQuery>,
-) -> Result<(), StatusCode> {
+) -> Result<(), AppError> {
+    let mut keys = vec![Key::email(&data.email)];
+    keys.extend(client.ip.map(Key::Ip));
+    state
+        .throttle
+        .check(Scope::Mail, &keys)
+        .map_err(too_many_attempts)?;
+    state.throttle.hit(Scope::Mail, &keys);
+    let connection = &mut state.connection.get()?;
     let user = users::table
         .select(User::as_select())
         .filter(users::email.eq(data.email.trim()))
-        .get_result(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::UNAUTHORIZED
-        })?;
-    user.send_password_reset_email(state.mailer, &state.mail_builder)
-        .await
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })
+        .get_result(connection)
+        .optional()?
+        .ok_or_else(|| AppError::not_found("No account uses this email"))?;
+    connection.transaction::<_, diesel::result::Error, _>(|connection| {
+        let token = issue_token(connection, user.id, TokenPurpose::PASSWORD_RESET)?;
+        enqueue(
+            connection,
+            &[user.password_reset_email(&state.templates, &token)],
+        )
+    })?;
+    state.wake_outbox();
+    Ok(())
 }
 
+/// Sets a new password and moves the session epoch on, which signs the user out everywhere.
 pub async fn reset_password(
     State(state): State<SiteState>,
     Form(data): Form<PasswordResetQuery>,
-) -> Result<(), StatusCode> {
-    let user: User = users::table
-        .select(User::as_select())
-        .filter(users::id.eq(data.id))
-        .get_result(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::UNAUTHORIZED
+) -> Result<(), AppError> {
+    let password_hash = hash_password(&data.password).map_err(AppError::internal)?;
+    let reset = state
+        .connection
+        .get()?
+        .transaction::<_, diesel::result::Error, _>(|connection| {
+            let Some(user_id) =
+                redeem_token(connection, &data.token, TokenPurpose::PASSWORD_RESET)?
+            else {
+                return Ok(false);
+            };
+            diesel::update(users::table)
+                .set((
+                    users::password_hash.eq(password_hash),
+                    users::session_epoch.eq(users::session_epoch + 1),
+                ))
+                .filter(users::id.eq(user_id))
+                .execute(connection)?;
+            Ok(true)
         })?;
-    let valid_verification_claims: u64 = ResetClaims::from(&user).into();
-    if data.token != valid_verification_claims {
-        log::error!("Password reset tokens do not match");
-        return Err(StatusCode::UNAUTHORIZED);
+    if !reset {
+        return Err(AppError::unauthorized(
+            "The reset link is invalid, expired or already used",
+        ));
     }
-
-    let salt = SaltString::generate(&mut OsRng);
-    let argon2 = Argon2::default();
-    let password_hash = argon2
-        .hash_password(data.password.as_bytes(), &salt)
-        .map(|v| v.to_string())
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?;
-    diesel::update(users::table)
-        .set(users::password_hash.eq(password_hash))
-        .filter(users::id.eq(user.id))
-        .execute(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map(|_| ())
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })
+    Ok(())
 }
 
 pub async fn verify_user(
     State(state): State<SiteState>,
     Query(data): Query<VerificationQuery>,
-) -> Result<Redirect, StatusCode> {
-    let pass_hash: String = users::table
-        .select(users::password_hash)
-        .filter(users::id.eq(data.id))
-        .get_result(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::UNAUTHORIZED
-        })?;
-    let verification_claims: u64 = VerificationClaims {
-        id: data.id,
-        pass_hash,
-    }
-    .into();
-    if verification_claims == data.token {
-        diesel::update(users::table)
-            .filter(users::id.eq(data.id))
-            .set(users::verified.eq(true))
-            .execute(&mut state.connection.get().map_err(|e| {
-                log::error!("{e:?}");
-                StatusCode::INTERNAL_SERVER_ERROR
-            })?)
-            .map(|_| Redirect::to("https://techfestsliet.org/"))
-            .map_err(|e| {
-                log::error!("{e:?}");
-                StatusCode::UNAUTHORIZED
-            })
-    } else {
-        Err(StatusCode::UNAUTHORIZED)
-    }
+) -> Result<Redirect, AppError> {
+    state
+        .connection
+        .get()?
+        .transaction::<_, diesel::result::Error, _>(|connection| {
+            let Some(user_id) =
+                redeem_token(connection, &data.token, TokenPurpose::EMAIL_VERIFICATION)?
+            else {
+                return Ok(false);
+            };
+            diesel::update(users::table)
+                .filter(users::id.eq(user_id))
+                .set(users::verified.eq(true))
+                .execute(connection)?;
+            Ok(true)
+        })?
+        .then(|| Redirect::to(&state.config.site.verified_redirect()))
+        .ok_or_else(|| {
+            AppError::unauthorized("The verification link is invalid, expired or already used")
+        })
 }
 
 pub async fn get_departments() -> Json<HashMap<String, String>> {
@@ -311,61 +289,45 @@ pub async fn get_departments() -> Json<HashMap<String, String>> {
 pub async fn get_student_profile(
     State(state): State<SiteState>,
     user: User,
-) -> Result<Json<Student>, StatusCode> {
+) -> Result<Json<Student>, AppError> {
     students::table
         .select(Student::as_select())
         .filter(students::user_id.eq(user.id))
-        .get_result(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })
-        .map(|v| Json(v))
+        .get_result(&mut state.connection.get()?)
+        .optional()?
+        .map(Json)
+        .ok_or_else(|| AppError::not_found("No student profile yet"))
 }
 
 pub async fn get_faculty_profile(
     State(state): State<SiteState>,
     user: User,
-) -> Result<Json<Faculty>, StatusCode> {
+) -> Result<Json<Faculty>, AppError> {
     faculty::table
         .select(Faculty::as_select())
         .filter(faculty::user_id.eq(user.id))
-        .get_result(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })
-        .map(|v| Json(v))
+        .get_result(&mut state.connection.get()?)
+        .optional()?
+        .map(Json)
+        .ok_or_else(|| AppError::not_found("No faculty profile yet"))
 }
 
 pub async fn create_student_profile(
     State(state): State<SiteState>,
     user: User,
     Form(data): Form<NewStudentProfile>,
-) -> Result<Json<Student>, StatusCode> {
-    Student {
-        user_id: user.id,
-        reg_no: data.reg_no,
-        college: data.college,
-        dept: data.dept,
-    }
-    .insert_into(students::table)
-    .returning(Student::as_returning())
-    .get_result(&mut state.connection.get().map_err(|e| {
-        log::error!("{e:?}");
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?)
-    .map_err(|e| {
-        log::error!("{e:?}");
-        StatusCode::INTERNAL_SERVER_ERROR
-    })
-    .map(|v| Json(v))
+) -> Result<Json<Student>, AppError> {
+    Ok(Json(
+        Student {
+            user_id: user.id,
+            reg_no: data.reg_no,
+            college: data.college,
+            dept: data.dept,
+        }
+        .insert_into(students::table)
+        .returning(Student::as_returning())
+        .get_result(&mut state.connection.get()?)?,
+    ))
 }
 
 #[debug_handler]
@@ -373,21 +335,23 @@ pub async fn create_faculty_profile(
     State(state): State<SiteState>,
     user: User,
     Form(data): Form<NewFacultyProfile>,
-) -> Result<Json<Faculty>, StatusCode> {
-    Faculty {
-        user_id: user.id,
-        title: data.title,
-        dept: data.dept,
-    }
-    .insert_into(faculty::table)
-    .returning(Faculty::as_returning())
-    .get_result(&mut state.connection.get().map_err(|e| {
-        log::error!("{e:?}");
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?)
-    .map_err(|e| {
-        log::error!("{e:?}");
-        StatusCode::INTERNAL_SERVER_ERROR
-    })
-    .map(|v| Json(v))
+) -> Result<Json<Faculty>, AppError> {
+    Ok(Json(
+        state
+            .connection
+            .get()?
+            .transaction::<_, diesel::result::Error, _>(|connection| {
+                let faculty = Faculty {
+                    user_id: user.id,
+                    title: data.title,
+                    dept: data.dept,
+                }
+                .insert_into(faculty::table)
+                .returning(Faculty::as_returning())
+                .get_result(connection)?;
+                // Only a super admin makes them a faculty coordinator, see `crate::faculty`
+                file_application(connection, user.id)?;
+                Ok(faculty)
+            })?,
+    ))
 }
@@ -0,0 +1,264 @@
+//! When joining and leaving events and workshops is allowed.
+//!
+//! Joins are only accepted between `registeration_start` and `registeration_end`, and nobody can
+//! leave once `start_time` has passed. The clock is the database's `now()`, the same one the
+//! timestamp defaults use, so the server's own time zone never comes into play. Super admins can
+//! still register people late through the override routes.
+//!
+//! Team events also bound how many members a team may have, see [`check_team_size`].
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+
+use crate::{
+    error::AppError,
+    schema::{events, workshops},
+};
+
+#[derive(Queryable, Debug, Clone, Copy)]
+pub struct Schedule {
+    pub registeration_start: NaiveDateTime,
+    pub registeration_end: NaiveDateTime,
+    pub start_time: NaiveDateTime,
+    pub now: NaiveDateTime,
+}
+
+impl Schedule {
+    pub fn of_event(connection: &mut PgConnection, event_id: i32) -> QueryResult<Self> {
+        events::table
+            .select((
+                events::registeration_start,
+                events::registeration_end,
+                events::start_time,
+                diesel::dsl::now,
+            ))
+            .filter(events::id.eq(event_id))
+            .get_result(connection)
+    }
+
+    pub fn of_workshop(connection: &mut PgConnection, workshop_id: i32) -> QueryResult<Self> {
+        workshops::table
+            .select((
+                workshops::registeration_start,
+                workshops::registeration_end,
+                workshops::start_time,
+                diesel::dsl::now,
+            ))
+            .filter(workshops::id.eq(workshop_id))
+            .get_result(connection)
+    }
+
+    pub fn check_join(&self) -> Result<(), AppError> {
+        if self.now < self.registeration_start {
+            return Err(AppError::forbidden(format!(
+                "Registration opens at {}",
+                self.registeration_start
+            )));
+        }
+        if self.now > self.registeration_end {
+            return Err(AppError::forbidden(format!(
+                "Registration closed at {}",
+                self.registeration_end
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn check_leave(&self) -> Result<(), AppError> {
+        if self.now >= self.start_time {
+            return Err(AppError::forbidden(format!(
+                "Can not leave after the start at {}",
+                self.start_time
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Registration has to open before it closes and close by the time the event ends, and the event
+/// itself has to start before it ends.
+pub fn check_schedule_order(
+    registeration_start: NaiveDateTime,
+    registeration_end: NaiveDateTime,
+    start_time: NaiveDateTime,
+    end_time: NaiveDateTime,
+) -> Result<(), AppError> {
+    if registeration_start >= registeration_end {
+        return Err(AppError::invalid(
+            "registeration_end",
+            "registeration_start must be before registeration_end",
+        ));
+    }
+    if start_time >= end_time {
+        return Err(AppError::invalid(
+            "end_time",
+            "start_time must be before end_time",
+        ));
+    }
+    if registeration_start > start_time {
+        return Err(AppError::invalid(
+            "registeration_start",
+            "registeration_start must not be after start_time",
+        ));
+    }
+    if registeration_end > end_time {
+        return Err(AppError::invalid(
+            "registeration_end",
+            "registeration_end must not be after end_time",
+        ));
+    }
+    Ok(())
+}
+
+/// Column defaults for `events.min_team_size` / `events.max_team_size`.
+pub const DEFAULT_MIN_TEAM_SIZE: i32 = 1;
+pub const DEFAULT_MAX_TEAM_SIZE: i32 = 4;
+
+pub fn check_team_size_order(min_team_size: i32, max_team_size: i32) -> Result<(), AppError> {
+    if min_team_size < 1 {
+        return Err(AppError::invalid(
+            "min_team_size",
+            "min_team_size must be at least 1",
+        ));
+    }
+    if min_team_size > max_team_size {
+        return Err(AppError::invalid(
+            "max_team_size",
+            "min_team_size must not be more than max_team_size",
+        ));
+    }
+    Ok(())
+}
+
+/// Whether a team of `size` may take part in an event allowing `min_team_size..=max_team_size`.
+pub fn check_team_size(
+    name: &str,
+    size: i64,
+    min_team_size: i32,
+    max_team_size: i32,
+) -> Result<(), AppError> {
+    if size < i64::from(min_team_size) || size > i64::from(max_team_size) {
+        return Err(AppError::forbidden(format!(
+            "{name} needs teams of {min_team_size} to {max_team_size} members, yours has {size}"
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(day: u32, hour: u32) -> NaiveDateTime {
+        chrono::NaiveDate::from_ymd_opt(2024, 11, day)
+            .unwrap()
+            .and_hms_opt(hour, 0, 0)
+            .unwrap()
+    }
+
+    fn message_of(result: Result<(), AppError>) -> String {
+        let error = result.unwrap_err();
+        assert_eq!(error.status(), http::StatusCode::BAD_REQUEST);
+        error.message().to_owned()
+    }
+
+    #[test]
+    fn accepts_an_ordered_schedule() {
+        assert!(check_schedule_order(at(1, 9), at(5, 18), at(6, 10), at(6, 17)).is_ok());
+        // Registration may stay open until the very end
+        assert!(check_schedule_order(at(1, 9), at(6, 17), at(6, 10), at(6, 17)).is_ok());
+        assert!(check_schedule_order(at(6, 10), at(6, 12), at(6, 10), at(6, 17)).is_ok());
+    }
+
+    #[test]
+    fn refuses_registration_closing_before_it_opens() {
+        let message = message_of(check_schedule_order(
+            at(5, 9),
+            at(5, 9),
+            at(6, 10),
+            at(6, 17),
+        ));
+        assert!(message.contains("registeration_start must be before"));
+    }
+
+    #[test]
+    fn refuses_an_event_ending_before_it_starts() {
+        let message = message_of(check_schedule_order(
+            at(1, 9),
+            at(5, 18),
+            at(6, 17),
+            at(6, 10),
+        ));
+        assert!(message.contains("start_time must be before end_time"));
+    }
+
+    #[test]
+    fn refuses_registration_opening_after_the_start() {
+        let message = message_of(check_schedule_order(
+            at(6, 11),
+            at(6, 12),
+            at(6, 10),
+            at(6, 17),
+        ));
+        assert!(message.contains("registeration_start must not be after start_time"));
+    }
+
+    #[test]
+    fn refuses_registration_closing_after_the_end() {
+        let message = message_of(check_schedule_order(
+            at(1, 9),
+            at(7, 9),
+            at(6, 10),
+            at(6, 17),
+        ));
+        assert!(message.contains("registeration_end must not be after end_time"));
+    }
+
+    fn schedule(now: NaiveDateTime) -> Schedule {
+        Schedule {
+            registeration_start: at(1, 9),
+            registeration_end: at(5, 18),
+            start_time: at(6, 10),
+            now,
+        }
+    }
+
+    #[test]
+    fn joins_only_inside_the_window() {
+        assert!(schedule(at(1, 8)).check_join().is_err());
+        assert!(schedule(at(1, 9)).check_join().is_ok());
+        assert!(schedule(at(5, 18)).check_join().is_ok());
+        assert!(schedule(at(5, 19)).check_join().is_err());
+    }
+
+    #[test]
+    fn leaves_only_before_the_start() {
+        assert!(schedule(at(6, 9)).check_leave().is_ok());
+        assert!(schedule(at(6, 10)).check_leave().is_err());
+    }
+
+    #[test]
+    fn fits_teams_within_the_bounds() {
+        assert!(check_team_size("Robowars", 2, 2, 4).is_ok());
+        assert!(check_team_size("Robowars", 4, 2, 4).is_ok());
+        assert!(check_team_size("Solo", 1, 1, 1).is_ok());
+    }
+
+    #[test]
+    fn refuses_teams_outside_the_bounds() {
+        for size in [0, 1, 5] {
+            let error = check_team_size("Robowars", size, 2, 4).unwrap_err();
+            assert_eq!(error.status(), http::StatusCode::FORBIDDEN);
+            assert_eq!(
+                error.message(),
+                format!("Robowars needs teams of 2 to 4 members, yours has {size}")
+            );
+        }
+    }
+
+    #[test]
+    fn checks_the_size_order() {
+        assert!(check_team_size_order(1, 1).is_ok());
+        assert!(message_of(check_team_size_order(0, 3)).contains("at least 1"));
+        assert!(message_of(check_team_size_order(4, 3)).contains("not be more than"));
+    }
+}
@@ -0,0 +1,191 @@
+//! Winners of events, published by the coordinators of the event's domain and shown publicly per
+//! event and per domain, and on every winner's profile.
+use axum::{
+    extract::{Query, State},
+    Form, Json,
+};
+use diesel::prelude::*;
+use http::StatusCode;
+
+use crate::{
+    error::AppError,
+    forms::{
+        domains::DomainId,
+        events::EventId,
+        results::{EventResultId, PublishEventResult},
+    },
+    models::{
+        results::{EventResult, NewEventResult},
+        users::User,
+    },
+    policy::{authorize, Action},
+    schema::{
+        event_results, events, individual_event_participation, team_event_participations,
+        team_members, teams, users,
+    },
+    state::SiteState,
+};
+
+enum ResultsOf {
+    Result(i32),
+    Event(i32),
+    Domain(i32),
+    /// Results won by the user alone or by any team they are in
+    Winner(i32),
+}
+
+fn load_results(connection: &mut PgConnection, of: ResultsOf) -> QueryResult<Vec<EventResult>> {
+    let query = event_results::table
+        .inner_join(events::table)
+        .left_join(users::table.on(event_results::user_id.eq(users::id.nullable())))
+        .left_join(teams::table)
+        .select((
+            event_results::id,
+            event_results::event_id,
+            events::name,
+            events::domain_id,
+            event_results::placement,
+            event_results::user_id,
+            users::name.nullable(),
+            event_results::team_id,
+            teams::name.nullable(),
+            event_results::note,
+            event_results::published_at,
+        ))
+        .order((
+            events::start_time,
+            event_results::event_id,
+            event_results::placement,
+            event_results::id,
+        ))
+        .into_boxed();
+    let query = match of {
+        ResultsOf::Result(id) => query.filter(event_results::id.eq(id)),
+        ResultsOf::Event(event_id) => query.filter(event_results::event_id.eq(event_id)),
+        ResultsOf::Domain(domain_id) => query.filter(events::domain_id.eq(domain_id)),
+        ResultsOf::Winner(user_id) => query.filter(
+            event_results::user_id
+                .eq(user_id)
+                .or(event_results::team_id.eq_any(
+                    team_members::table
+                        .select(team_members::team_id.nullable())
+                        .filter(team_members::student_id.eq(user_id)),
+                )),
+        ),
+    };
+    query.load(connection)
+}
+
+pub async fn get_event_results(
+    State(state): State<SiteState>,
+    Query(data): Query<EventId>,
+) -> Result<Json<Vec<EventResult>>, AppError> {
+    let connection = &mut state.connection.get()?;
+    Ok(Json(load_results(connection, ResultsOf::Event(data.id))?))
+}
+
+/// Results of every event in the domain, oldest event first.
+pub async fn get_domain_results(
+    State(state): State<SiteState>,
+    Query(data): Query<DomainId>,
+) -> Result<Json<Vec<EventResult>>, AppError> {
+    let connection = &mut state.connection.get()?;
+    Ok(Json(load_results(connection, ResultsOf::Domain(data.id))?))
+}
+
+/// What the caller has won, alone or with a team.
+pub async fn get_profile_results(
+    State(state): State<SiteState>,
+    user: User,
+) -> Result<Json<Vec<EventResult>>, AppError> {
+    if !user.verified {
+        return Err(AppError::forbidden("Verify your email first"));
+    }
+    let connection = &mut state.connection.get()?;
+    Ok(Json(load_results(connection, ResultsOf::Winner(user.id))?))
+}
+
+/// Records a winner. The winner has to be registered for the event, as an individual or as a
+/// team, and can only hold one result per event.
+pub async fn publish_event_result(
+    State(state): State<SiteState>,
+    user: User,
+    Form(data): Form<PublishEventResult>,
+) -> Result<Json<EventResult>, AppError> {
+    authorize(
+        &state,
+        &user,
+        Action::PublishEventResults {
+            event_id: data.event_id,
+        },
+    )?;
+    let connection = &mut state.connection.get()?;
+    let registered: bool = match (data.user_id, data.team_id) {
+        (Some(user_id), None) => diesel::select(diesel::dsl::exists(
+            individual_event_participation::table
+                .filter(individual_event_participation::event_id.eq(data.event_id))
+                .filter(individual_event_participation::user_id.eq(user_id)),
+        ))
+        .get_result(connection)?,
+        (None, Some(team_id)) => diesel::select(diesel::dsl::exists(
+            team_event_participations::table
+                .filter(team_event_participations::event_id.eq(data.event_id))
+                .filter(team_event_participations::team_id.eq(team_id)),
+        ))
+        .get_result(connection)?,
+        _ => {
+            return Err(AppError::new(
+                StatusCode::BAD_REQUEST,
+                "Give either user_id or team_id",
+            ))
+        }
+    };
+    if !registered {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            "The winner did not take part in this event",
+        ));
+    }
+    let id: i32 = NewEventResult {
+        event_id: data.event_id,
+        placement: data.placement,
+        user_id: data.user_id,
+        team_id: data.team_id,
+        note: data
+            .note
+            .map(|note| note.trim().to_owned())
+            .filter(|note| !note.is_empty()),
+        published_by: Some(user.id),
+    }
+    .insert_into(event_results::table)
+    .returning(event_results::id)
+    .get_result(connection)
+    .map_err(|e| match AppError::from(e) {
+        e if e.status() == StatusCode::CONFLICT => {
+            AppError::conflict("The winner already has a result for this event")
+        }
+        e => e,
+    })?;
+    load_results(connection, ResultsOf::Result(id))?
+        .pop()
+        .map(Json)
+        .ok_or_else(|| AppError::internal("A result vanished right after it was published"))
+}
+
+pub async fn withdraw_event_result(
+    State(state): State<SiteState>,
+    user: User,
+    Form(data): Form<EventResultId>,
+) -> Result<(), AppError> {
+    let event_id: i32 = event_results::table
+        .select(event_results::event_id)
+        .filter(event_results::id.eq(data.id))
+        .get_result(&mut state.connection.get()?)
+        .optional()?
+        .ok_or_else(|| AppError::not_found("No such result"))?;
+    authorize(&state, &user, Action::PublishEventResults { event_id })?;
+    diesel::delete(event_results::table)
+        .filter(event_results::id.eq(data.id))
+        .execute(&mut state.connection.get()?)?;
+    Ok(())
+}
@@ -0,0 +1,289 @@
+//! Keeps `users.role` in line with the coordinator tables, which is what [`crate::policy`] reads.
+//!
+//! Appointing someone a coordinator raises their role as far as the appointment needs, and
+//! dismissing them from their last appointment lowers it back to `PARTICIPANT`. Super admins can
+//! also set a role outright, which drops the appointments the new role can not use, and list every
+//! privileged account. A super admin keeps their role through any appointment or dismissal.
+use std::collections::HashMap;
+
+use axum::{extract::State, Form, Json};
+use diesel::prelude::*;
+use diesel::result::Error;
+use http::StatusCode;
+
+use crate::{
+    error::AppError,
+    forms::{roles::SetRole, users::Profile},
+    models::{
+        roles::PrivilegedAccount,
+        users::{Role, User},
+    },
+    policy::require_super_admin,
+    schema::{
+        faculty, faculty_coordinators, student_domain_coordinators, student_event_coordinators,
+        student_workshop_coordinators, students, users,
+    },
+    state::SiteState,
+};
+
+#[derive(Debug, Clone, Copy)]
+pub enum Appointment {
+    /// Faculty coordinator of a domain
+    Faculty,
+    /// Student coordinator of a domain, event or workshop
+    Student,
+}
+
+/// Sets the role and drops the appointments it does not use.
+fn set_role(connection: &mut PgConnection, user_id: i32, role: &Role) -> QueryResult<()> {
+    diesel::update(users::table)
+        .filter(users::id.eq(user_id))
+        .set(users::role.eq(role))
+        .execute(connection)?;
+    let (drop_faculty, drop_student) = match role {
+        Role::SUPER_ADMIN => (false, false),
+        Role::FACULTY_COORDINATOR => (false, true),
+        Role::STUDENT_COORDINATOR => (true, false),
+        Role::PARTICIPANT => (true, true),
+    };
+    if drop_faculty {
+        diesel::delete(faculty_coordinators::table)
+            .filter(faculty_coordinators::faculty_id.eq(user_id))
+            .execute(connection)?;
+    }
+    if drop_student {
+        diesel::delete(student_domain_coordinators::table)
+            .filter(student_domain_coordinators::student_id.eq(user_id))
+            .execute(connection)?;
+        diesel::delete(student_event_coordinators::table)
+            .filter(student_event_coordinators::student_id.eq(user_id))
+            .execute(connection)?;
+        diesel::delete(student_workshop_coordinators::table)
+            .filter(student_workshop_coordinators::student_id.eq(user_id))
+            .execute(connection)?;
+    }
+    Ok(())
+}
+
+/// Lowers a coordinator without any appointment left to `PARTICIPANT`.
+fn settle_role(connection: &mut PgConnection, user_id: i32) -> QueryResult<()> {
+    let role: Role = users::table
+        .select(users::role)
+        .filter(users::id.eq(user_id))
+        .get_result(connection)?;
+    let appointed = match role {
+        Role::FACULTY_COORDINATOR => diesel::select(diesel::dsl::exists(
+            faculty_coordinators::table.filter(faculty_coordinators::faculty_id.eq(user_id)),
+        ))
+        .get_result(connection)?,
+        Role::STUDENT_COORDINATOR => diesel::select(
+            diesel::dsl::exists(
+                student_domain_coordinators::table
+                    .filter(student_domain_coordinators::student_id.eq(user_id)),
+            )
+            .or(diesel::dsl::exists(
+                student_event_coordinators::table
+                    .filter(student_event_coordinators::student_id.eq(user_id)),
+            ))
+            .or(diesel::dsl::exists(
+                student_workshop_coordinators::table
+                    .filter(student_workshop_coordinators::student_id.eq(user_id)),
+            )),
+        )
+        .get_result(connection)?,
+        Role::SUPER_ADMIN | Role::PARTICIPANT => true,
+    };
+    if !appointed {
+        set_role(connection, user_id, &Role::PARTICIPANT)?;
+    }
+    Ok(())
+}
+
+/// Runs `insert`, which adds `user_id` to a coordinator table, and raises their role to match in
+/// the same transaction. A faculty coordinator can not also be appointed as a student, as their
+/// role would never let them use it.
+pub fn appoint(
+    connection: &mut PgConnection,
+    user_id: i32,
+    appointment: Appointment,
+    insert: impl FnOnce(&mut PgConnection) -> QueryResult<usize>,
+) -> Result<(), AppError> {
+    let role: Role = users::table
+        .select(users::role)
+        .filter(users::id.eq(user_id))
+        .get_result(connection)
+        .optional()?
+        .ok_or_else(|| AppError::not_found("No such user"))?;
+    let raised = match (appointment, role) {
+        (_, Role::SUPER_ADMIN) => None,
+        (Appointment::Faculty, Role::FACULTY_COORDINATOR) => None,
+        (Appointment::Faculty, _) => Some(Role::FACULTY_COORDINATOR),
+        (Appointment::Student, Role::FACULTY_COORDINATOR) => {
+            return Err(AppError::conflict(
+                "A faculty coordinator can not be a student coordinator",
+            ))
+        }
+        (Appointment::Student, Role::STUDENT_COORDINATOR) => None,
+        (Appointment::Student, Role::PARTICIPANT) => Some(Role::STUDENT_COORDINATOR),
+    };
+    connection
+        .transaction::<_, Error, _>(|connection| {
+            insert(connection)?;
+            if let Some(role) = raised {
+                set_role(connection, user_id, &role)?;
+            }
+            Ok(())
+        })
+        .map_err(|e| match AppError::from(e) {
+            e if e.status() == StatusCode::CONFLICT => {
+                AppError::conflict("Already a coordinator there")
+            }
+            e => e,
+        })
+}
+
+/// Runs `delete`, which removes `user_id` from a coordinator table, and lowers their role in the
+/// same transaction if that was their last appointment.
+pub fn dismiss(
+    connection: &mut PgConnection,
+    user_id: i32,
+    delete: impl FnOnce(&mut PgConnection) -> QueryResult<usize>,
+) -> Result<(), AppError> {
+    let dismissed = connection.transaction::<_, Error, _>(|connection| {
+        if delete(connection)? == 0 {
+            return Ok(false);
+        }
+        settle_role(connection, user_id)?;
+        Ok(true)
+    })?;
+    if !dismissed {
+        return Err(AppError::not_found("Not a coordinator there"));
+    }
+    Ok(())
+}
+
+/// Sets the role of `user_id` as [`set_user_role`] does, once it is checked to fit them: only
+/// faculty can be faculty coordinators and only students can be student coordinators.
+pub fn change_role(
+    connection: &mut PgConnection,
+    user_id: i32,
+    role: &Role,
+) -> Result<(), AppError> {
+    let (is_faculty, is_student): (bool, bool) = users::table
+        .select((
+            diesel::dsl::exists(faculty::table.filter(faculty::user_id.eq(users::id))),
+            diesel::dsl::exists(students::table.filter(students::user_id.eq(users::id))),
+        ))
+        .filter(users::id.eq(user_id))
+        .get_result(connection)
+        .optional()?
+        .ok_or_else(|| AppError::not_found("No such user"))?;
+    match role {
+        Role::FACULTY_COORDINATOR if !is_faculty => {
+            return Err(AppError::conflict(
+                "Only faculty can be faculty coordinators",
+            ))
+        }
+        Role::STUDENT_COORDINATOR if !is_student => {
+            return Err(AppError::conflict(
+                "Only students can be student coordinators",
+            ))
+        }
+        _ => {}
+    }
+    Ok(connection.transaction(|connection| set_role(connection, user_id, role))?)
+}
+
+/// Every account above `PARTICIPANT`, with what it coordinates.
+pub async fn get_privileged_accounts(
+    State(state): State<SiteState>,
+    user: User,
+) -> Result<Json<Vec<PrivilegedAccount>>, AppError> {
+    require_super_admin(&state, &user)?;
+    let connection = &mut state.connection.get()?;
+    let load = |connection: &mut PgConnection| {
+        let accounts: Vec<(i32, Profile)> = users::table
+            .filter(users::role.ne(Role::PARTICIPANT))
+            .order((users::role.asc(), users::name.asc()))
+            .select((users::id, Profile::as_select()))
+            .load(connection)?;
+        let ids: Vec<i32> = accounts.iter().map(|(id, _)| *id).collect();
+        let faculty_domains: Vec<(i32, i32)> = faculty_coordinators::table
+            .select((
+                faculty_coordinators::faculty_id,
+                faculty_coordinators::domain_id,
+            ))
+            .filter(faculty_coordinators::faculty_id.eq_any(&ids))
+            .load(connection)?;
+        let student_domains: Vec<(i32, i32)> = student_domain_coordinators::table
+            .select((
+                student_domain_coordinators::student_id,
+                student_domain_coordinators::domain_id,
+            ))
+            .filter(student_domain_coordinators::student_id.eq_any(&ids))
+            .load(connection)?;
+        let events: Vec<(i32, i32)> = student_event_coordinators::table
+            .select((
+                student_event_coordinators::student_id,
+                student_event_coordinators::event_id,
+            ))
+            .filter(student_event_coordinators::student_id.eq_any(&ids))
+            .load(connection)?;
+        let workshops: Vec<(i32, i32)> = student_workshop_coordinators::table
+            .select((
+                student_workshop_coordinators::student_id,
+                student_workshop_coordinators::workshop_id,
+            ))
+            .filter(student_workshop_coordinators::student_id.eq_any(&ids))
+            .load(connection)?;
+        Ok::<_, Error>((
+            accounts,
+            faculty_domains,
+            student_domains,
+            events,
+            workshops,
+        ))
+    };
+    let (accounts, faculty_domains, student_domains, events, workshops) = load(connection)?;
+    let group = |pairs: Vec<(i32, i32)>| {
+        let mut grouped: HashMap<i32, Vec<i32>> = HashMap::new();
+        for (user_id, id) in pairs {
+            grouped.entry(user_id).or_default().push(id);
+        }
+        grouped
+    };
+    let (mut faculty_domains, mut student_domains, mut events, mut workshops) = (
+        group(faculty_domains),
+        group(student_domains),
+        group(events),
+        group(workshops),
+    );
+    Ok(Json(
+        accounts
+            .into_iter()
+            .map(|(id, profile)| PrivilegedAccount {
+                profile,
+                faculty_domains: faculty_domains.remove(&id).unwrap_or_default(),
+                student_domains: student_domains.remove(&id).unwrap_or_default(),
+                events: events.remove(&id).unwrap_or_default(),
+                workshops: workshops.remove(&id).unwrap_or_default(),
+            })
+            .collect(),
+    ))
+}
+
+/// Promotes or demotes a user. Appointments the new role can not use are dropped, so demoting to
+/// `PARTICIPANT` takes every coordinator post away.
+pub async fn set_user_role(
+    State(state): State<SiteState>,
+    user: User,
+    Form(data): Form<SetRole>,
+) -> Result<(), AppError> {
+    require_super_admin(&state, &user)?;
+    // Also keeps the last super admin from demoting themselves
+    if data.user_id == user.id {
+        return Err(AppError::conflict("You can not change your own role"));
+    }
+    let connection = &mut state.connection.get()?;
+    change_role(connection, data.user_id, &data.role)
+}
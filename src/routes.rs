@@ -1,8 +1,17 @@
 use crate::auth::faculty_sign_up;
+use crate::auth::get_sessions;
 use crate::auth::logout;
+use crate::auth::logout_all;
+use crate::auth::refresh;
 use crate::auth::resend_email;
+use crate::auth::revoke_session;
 use crate::auth::sign_in;
 use crate::auth::student_sign_up;
+use crate::certificate::download_certificate;
+use crate::certificate::get_certificates;
+use crate::certificate::verify_certificate;
+use crate::checkin::get_checkin_qr;
+use crate::checkin::scan_checkin;
 use crate::domain::add_domain_faculty_coordinator;
 use crate::domain::add_domain_student_coordinator;
 use crate::domain::change_domain;
@@ -12,6 +21,8 @@ use crate::domain::get_domain;
 use crate::domain::get_domain_faculty_coordinator;
 use crate::domain::get_domain_photo;
 use crate::domain::get_domain_student_coordinator;
+use crate::domain::remove_domain_faculty_coordinator;
+use crate::domain::remove_domain_student_coordinator;
 use crate::domain::set_domain_photo;
 use crate::event::add_event_coordinator;
 use crate::event::change_event;
@@ -23,6 +34,7 @@ use crate::event::get_event_coordinator;
 use crate::event::get_event_individual_attendance;
 use crate::event::get_event_photo;
 use crate::event::get_event_team_attendance;
+use crate::event::get_event_undersized_teams;
 use crate::event::get_events_by_domain;
 use crate::event::join_event_individual;
 use crate::event::join_event_team;
@@ -32,9 +44,40 @@ use crate::event::leave_event_individual;
 use crate::event::leave_event_team;
 use crate::event::mark_event_individual_attendance;
 use crate::event::mark_event_team_attendance;
+use crate::event::register_event_individual;
+use crate::event::register_event_team;
+use crate::event::remove_event_coordinator;
 use crate::event::remove_event_individual_attendance;
 use crate::event::remove_event_team_attendance;
 use crate::event::set_event_photo;
+use crate::faculty::approve_faculty;
+use crate::faculty::get_faculty_applications;
+use crate::faculty::reject_faculty;
+use crate::judging::add_criterion;
+use crate::judging::add_judge;
+use crate::judging::delete_criterion;
+use crate::judging::finalize_judging;
+use crate::judging::get_judges;
+use crate::judging::get_judging_results;
+use crate::judging::get_my_scores;
+use crate::judging::get_rubric;
+use crate::judging::remove_judge;
+use crate::judging::submit_score;
+use crate::oidc::google_callback;
+use crate::oidc::google_sign_in;
+use crate::outbox::get_outbox;
+use crate::outbox::retry_mail;
+use crate::payment::get_payment_queue;
+use crate::payment::get_payments;
+use crate::payment::payment_webhook;
+use crate::payment::reject_payment;
+use crate::payment::submit_payment;
+use crate::payment::verify_payment;
+use crate::points::distribute_team_points;
+use crate::points::get_leaderboard;
+use crate::points::get_points;
+use crate::points::get_team_points;
+use crate::points::spend_points;
 use crate::profile::change_profile;
 use crate::profile::create_faculty_profile;
 use crate::profile::create_student_profile;
@@ -48,6 +91,19 @@ use crate::profile::reset_password;
 use crate::profile::send_reset_mail;
 use crate::profile::set_profile_photo;
 use crate::profile::verify_user;
+use crate::results::get_domain_results;
+use crate::results::get_event_results;
+use crate::results::get_profile_results;
+use crate::results::publish_event_result;
+use crate::results::withdraw_event_result;
+use crate::roles::get_privileged_accounts;
+use crate::roles::set_user_role;
+use crate::sponsor::change_sponsor;
+use crate::sponsor::create_sponsor;
+use crate::sponsor::delete_sponsor;
+use crate::sponsor::get_sponsor_photo;
+use crate::sponsor::get_sponsors;
+use crate::sponsor::set_sponsor_photo;
 use crate::state::SiteState;
 use crate::team::accept_team_request;
 use crate::team::change_team;
@@ -59,6 +115,14 @@ use crate::team::get_teams;
 use crate::team::reject_team_request;
 use crate::team::remove_member;
 use crate::team::send_team_request;
+use crate::two_factor::disable_two_factor;
+use crate::two_factor::enable_two_factor;
+use crate::two_factor::get_required_roles;
+use crate::two_factor::get_two_factor;
+use crate::two_factor::regenerate_recovery_codes;
+use crate::two_factor::set_required_role;
+use crate::two_factor::setup_two_factor;
+use crate::two_factor::verify_two_factor;
 use crate::workshop::add_workshop_coordinator;
 use crate::workshop::change_workshop;
 use crate::workshop::create_workshop;
@@ -71,6 +135,8 @@ use crate::workshop::join_workshop;
 use crate::workshop::joined_workshops_individual;
 use crate::workshop::leave_workshop_individual;
 use crate::workshop::mark_workshop_attendance;
+use crate::workshop::register_workshop;
+use crate::workshop::remove_workshop_coordinator;
 use crate::workshop::set_workshop_photo;
 use axum::{
     routing::{get, post},
@@ -80,11 +146,19 @@ use axum::{
 pub fn setup_routes() -> Router<SiteState> {
     Router::new()
         .route("/auth/sign_in", post(sign_in))
+        .route("/auth/2fa", post(verify_two_factor))
+        .route("/auth/google", get(google_sign_in))
+        .route("/auth/google/callback", get(google_callback))
+        .route("/auth/refresh", post(refresh))
         .route("/auth/logout", get(logout))
+        .route("/auth/logout/all", post(logout_all))
         .route("/auth/student/sign_up", post(student_sign_up))
         .route("/auth/faculty/sign_up", post(faculty_sign_up))
         .route("/auth/verify", get(verify_user).post(resend_email))
-        .route("/profile/password_reset", post(reset_password).put(send_reset_mail))
+        .route(
+            "/profile/password_reset",
+            post(reset_password).put(send_reset_mail),
+        )
         .route("/profile", get(get_profile).patch(change_profile))
         .route(
             "/profile/student",
@@ -98,7 +172,20 @@ pub fn setup_routes() -> Router<SiteState> {
             "/profile/photo",
             get(get_profile_photo).post(set_profile_photo),
         )
+        .route(
+            "/profile/sessions",
+            get(get_sessions).delete(revoke_session),
+        )
+        .route(
+            "/profile/2fa",
+            get(get_two_factor)
+                .post(setup_two_factor)
+                .put(enable_two_factor)
+                .delete(disable_two_factor),
+        )
+        .route("/profile/2fa/recovery", post(regenerate_recovery_codes))
         .route("/profile/requests", get(get_individual_team_requests))
+        .route("/profile/results", get(get_profile_results))
         .route(
             "/domain",
             get(get_domain)
@@ -107,13 +194,18 @@ pub fn setup_routes() -> Router<SiteState> {
                 .patch(change_domain),
         )
         .route("/domain/event", get(get_events_by_domain))
+        .route("/domain/results", get(get_domain_results))
         .route(
             "/domain/coordinator/faculty",
-            get(get_domain_faculty_coordinator).post(add_domain_faculty_coordinator),
+            get(get_domain_faculty_coordinator)
+                .post(add_domain_faculty_coordinator)
+                .delete(remove_domain_faculty_coordinator),
         )
         .route(
             "/domain/coordinator/student",
-            get(get_domain_student_coordinator).post(add_domain_student_coordinator),
+            get(get_domain_student_coordinator)
+                .post(add_domain_student_coordinator)
+                .delete(remove_domain_student_coordinator),
         )
         .route(
             "/domain/photo",
@@ -128,7 +220,9 @@ pub fn setup_routes() -> Router<SiteState> {
         )
         .route(
             "/event/coordinator",
-            get(get_event_coordinator).post(add_event_coordinator),
+            get(get_event_coordinator)
+                .post(add_event_coordinator)
+                .delete(remove_event_coordinator),
         )
         .route(
             "/event/attendance/individual",
@@ -142,8 +236,29 @@ pub fn setup_routes() -> Router<SiteState> {
                 .post(mark_event_team_attendance)
                 .delete(remove_event_team_attendance),
         )
-            .route("/event/domain", get(event_domain))
+        .route(
+            "/event/attendance/team/undersized",
+            get(get_event_undersized_teams),
+        )
+        .route("/event/domain", get(event_domain))
+        .route(
+            "/event/results",
+            get(get_event_results)
+                .post(publish_event_result)
+                .delete(withdraw_event_result),
+        )
         .route("/event/photo", get(get_event_photo).post(set_event_photo))
+        .route(
+            "/judging/judges",
+            get(get_judges).post(add_judge).delete(remove_judge),
+        )
+        .route(
+            "/judging/rubric",
+            get(get_rubric).post(add_criterion).delete(delete_criterion),
+        )
+        .route("/judging/score", get(get_my_scores).post(submit_score))
+        .route("/judging/finalize", post(finalize_judging))
+        .route("/judging/results", get(get_judging_results))
         .route(
             "/event/join/individual",
             post(join_event_individual).delete(leave_event_individual),
@@ -152,6 +267,11 @@ pub fn setup_routes() -> Router<SiteState> {
             "/event/join/team",
             post(join_event_team).delete(leave_event_team),
         )
+        .route(
+            "/event/register/individual",
+            post(register_event_individual),
+        )
+        .route("/event/register/team", post(register_event_team))
         .route("/event/joined/individual", get(joined_events_individual))
         .route("/event/joined/team", get(joined_events_team))
         .route(
@@ -163,13 +283,19 @@ pub fn setup_routes() -> Router<SiteState> {
         )
         .route(
             "/workshop/coordinator",
-            get(get_workshop_coordinator).post(add_workshop_coordinator),
+            get(get_workshop_coordinator)
+                .post(add_workshop_coordinator)
+                .delete(remove_workshop_coordinator),
         )
         .route(
             "/workshop/photo",
             get(get_workshop_photo).post(set_workshop_photo),
         )
-        .route("/workshop/join", post(join_workshop).delete(leave_workshop_individual))
+        .route(
+            "/workshop/join",
+            post(join_workshop).delete(leave_workshop_individual),
+        )
+        .route("/workshop/register", post(register_workshop))
         .route(
             "/workshop/attendance",
             get(get_workshop_attendance).post(mark_workshop_attendance),
@@ -193,5 +319,47 @@ pub fn setup_routes() -> Router<SiteState> {
                 .delete(reject_team_request)
                 .put(accept_team_request),
         )
+        .route("/payment", get(get_payments).post(submit_payment))
+        .route(
+            "/payment/review",
+            get(get_payment_queue)
+                .post(verify_payment)
+                .delete(reject_payment),
+        )
+        .route("/payment/webhook", post(payment_webhook))
+        .route("/points", get(get_points))
+        .route("/points/spend", post(spend_points))
+        .route("/points/team", get(get_team_points))
+        .route("/points/team/distribute", post(distribute_team_points))
+        .route("/leaderboard", get(get_leaderboard))
+        .route(
+            "/sponsor",
+            get(get_sponsors)
+                .post(create_sponsor)
+                .delete(delete_sponsor)
+                .patch(change_sponsor),
+        )
+        .route(
+            "/sponsor/photo",
+            get(get_sponsor_photo).post(set_sponsor_photo),
+        )
+        .route("/certificate", get(get_certificates))
+        .route("/certificate/download", get(download_certificate))
+        .route("/certificate/verify", get(verify_certificate))
+        .route("/checkin/qr", get(get_checkin_qr))
+        .route("/checkin/scan", post(scan_checkin))
         .route("/departments", get(get_departments))
+        .route(
+            "/admin/2fa",
+            get(get_required_roles).post(set_required_role),
+        )
+        .route("/admin/faculty", get(get_faculty_applications))
+        .route("/admin/faculty/approve", post(approve_faculty))
+        .route("/admin/faculty/reject", post(reject_faculty))
+        .route("/admin/outbox", get(get_outbox))
+        .route("/admin/outbox/retry", post(retry_mail))
+        .route(
+            "/admin/roles",
+            get(get_privileged_accounts).post(set_user_role),
+        )
 }
@@ -1,10 +1,18 @@
 // @generated automatically by Diesel CLI.
 
 pub mod sql_types {
+    #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "application_status"))]
+    pub struct ApplicationStatus;
+
     #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
     #[diesel(postgres_type(name = "department"))]
     pub struct Department;
 
+    #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "mail_status"))]
+    pub struct MailStatus;
+
     #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
     #[diesel(postgres_type(name = "mode"))]
     pub struct Mode;
@@ -13,13 +21,43 @@ pub mod sql_types {
     #[diesel(postgres_type(name = "participation_type"))]
     pub struct ParticipationType;
 
+    #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "placement"))]
+    pub struct Placement;
+
+    #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "point_reason"))]
+    pub struct PointReason;
+
     #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
     #[diesel(postgres_type(name = "role"))]
     pub struct Role;
 
+    #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "sponsor_tier"))]
+    pub struct SponsorTier;
+
     #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
     #[diesel(postgres_type(name = "title"))]
     pub struct Title;
+
+    #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "token_purpose"))]
+    pub struct TokenPurpose;
+}
+
+diesel::table! {
+    certificates (id) {
+        id -> Text,
+        user_id -> Int4,
+        event_id -> Nullable<Int4>,
+        workshop_id -> Nullable<Int4>,
+        recipient_name -> Text,
+        title -> Text,
+        domain_name -> Text,
+        held_on -> Date,
+        issued_at -> Timestamp,
+    }
 }
 
 diesel::table! {
@@ -31,6 +69,29 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    event_judges (event_id, user_id) {
+        event_id -> Int4,
+        user_id -> Int4,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::Placement;
+
+    event_results (id) {
+        id -> Int4,
+        event_id -> Int4,
+        placement -> Placement,
+        user_id -> Nullable<Int4>,
+        team_id -> Nullable<Int4>,
+        note -> Nullable<Text>,
+        published_by -> Nullable<Int4>,
+        published_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     use diesel::sql_types::*;
     use super::sql_types::Mode;
@@ -53,6 +114,12 @@ diesel::table! {
         whatsapp_link -> Text,
         photo_hash -> Nullable<Bytea>,
         participation_type -> ParticipationType,
+        max_participants -> Nullable<Int4>,
+        max_teams -> Nullable<Int4>,
+        min_team_size -> Int4,
+        max_team_size -> Int4,
+        count_pending_requests -> Bool,
+        judging_finalized_at -> Nullable<Timestamp>,
     }
 }
 
@@ -68,6 +135,20 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::ApplicationStatus;
+
+    faculty_applications (user_id) {
+        user_id -> Int4,
+        status -> ApplicationStatus,
+        reason -> Nullable<Text>,
+        reviewed_by -> Nullable<Int4>,
+        reviewed_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     faculty_coordinators (faculty_id, domain_id) {
         faculty_id -> Int4,
@@ -83,20 +164,132 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    individual_event_waitlist (event_id, user_id) {
+        event_id -> Int4,
+        user_id -> Int4,
+        joined_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    judge_scores (id) {
+        id -> Int4,
+        criterion_id -> Int4,
+        judge_id -> Int4,
+        user_id -> Nullable<Int4>,
+        team_id -> Nullable<Int4>,
+        marks -> Float8,
+        submitted_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    judging_criteria (id) {
+        id -> Int4,
+        event_id -> Int4,
+        name -> Text,
+        weight -> Float8,
+        max_marks -> Int4,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::MailStatus;
+
+    outbox (id) {
+        id -> Int4,
+        recipient_name -> Text,
+        recipient_email -> Text,
+        subject -> Text,
+        html_body -> Text,
+        status -> MailStatus,
+        attempts -> Int4,
+        last_error -> Nullable<Text>,
+        next_attempt_at -> Timestamp,
+        created_at -> Timestamp,
+        sent_at -> Nullable<Timestamp>,
+        text_body -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    payment_webhook_events (event_id) {
+        event_id -> Text,
+        payment_id -> Nullable<Text>,
+        received_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     payments (payment_id) {
         user_id -> Int4,
         payment_id -> Text,
         payment_amount -> Int4,
         verified -> Bool,
+        rejection_reason -> Nullable<Text>,
+        submitted_at -> Timestamp,
+        reviewed_by -> Nullable<Int4>,
+        order_id -> Nullable<Text>,
+        claimed_by -> Nullable<Int4>,
     }
 }
 
 diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::PointReason;
+
+    points_ledger (id) {
+        id -> Int4,
+        user_id -> Nullable<Int4>,
+        team_id -> Nullable<Int4>,
+        amount -> Int4,
+        reason -> PointReason,
+        event_id -> Nullable<Int4>,
+        workshop_id -> Nullable<Int4>,
+        domain_id -> Nullable<Int4>,
+        note -> Nullable<Text>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    recovery_codes (id) {
+        id -> Int4,
+        user_id -> Int4,
+        code_hash -> Text,
+        used_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    sessions (id) {
+        id -> Int4,
+        user_id -> Int4,
+        token_hash -> Text,
+        previous_token_hash -> Nullable<Text>,
+        user_agent -> Nullable<Text>,
+        ip -> Nullable<Text>,
+        created_at -> Timestamp,
+        last_used_at -> Timestamp,
+        expires_at -> Timestamp,
+        revoked_at -> Nullable<Timestamp>,
+        epoch -> Int4,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::SponsorTier;
+
     sponsors (id) {
         id -> Int4,
         name -> Text,
-        photo_hash -> Bytea,
+        photo_hash -> Nullable<Bytea>,
+        tier -> SponsorTier,
+        display_order -> Int4,
+        website -> Nullable<Text>,
     }
 }
 
@@ -138,6 +331,15 @@ diesel::table! {
         team_id -> Int4,
         event_id -> Int4,
         attended -> Bool,
+        below_min_size -> Bool,
+    }
+}
+
+diesel::table! {
+    team_event_waitlist (event_id, team_id) {
+        event_id -> Int4,
+        team_id -> Int4,
+        joined_at -> Timestamp,
     }
 }
 
@@ -163,6 +365,60 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::TokenPurpose;
+
+    tokens (id) {
+        id -> Int4,
+        user_id -> Int4,
+        purpose -> TokenPurpose,
+        token_hash -> Text,
+        created_at -> Timestamp,
+        expires_at -> Timestamp,
+        used_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    two_factor (user_id) {
+        user_id -> Int4,
+        secret -> Bytea,
+        enabled_at -> Nullable<Timestamp>,
+        last_used_step -> Nullable<Int8>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::Role;
+
+    two_factor_required_roles (role) {
+        role -> Role,
+        required_by -> Nullable<Int4>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    used_checkin_tokens (jti) {
+        jti -> Text,
+        used_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    user_identities (provider, subject) {
+        provider -> Text,
+        subject -> Text,
+        user_id -> Int4,
+        email -> Text,
+        created_at -> Timestamp,
+        last_sign_in_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     use diesel::sql_types::*;
     use super::sql_types::Role;
@@ -177,6 +433,8 @@ diesel::table! {
         photo_hash -> Nullable<Bytea>,
         verified -> Bool,
         password_hash -> Text,
+        session_epoch -> Int4,
+        profile_complete -> Bool,
     }
 }
 
@@ -188,6 +446,14 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    workshop_waitlist (workshop_id, user_id) {
+        workshop_id -> Int4,
+        user_id -> Int4,
+        joined_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     use diesel::sql_types::*;
     use super::sql_types::Mode;
@@ -209,16 +475,37 @@ diesel::table! {
         registeration_end -> Timestamp,
         whatsapp_link -> Text,
         photo_hash -> Nullable<Bytea>,
+        max_participants -> Nullable<Int4>,
     }
 }
 
+diesel::joinable!(certificates -> events (event_id));
+diesel::joinable!(certificates -> users (user_id));
+diesel::joinable!(certificates -> workshops (workshop_id));
+diesel::joinable!(event_judges -> events (event_id));
+diesel::joinable!(event_judges -> users (user_id));
+diesel::joinable!(event_results -> events (event_id));
+diesel::joinable!(event_results -> teams (team_id));
 diesel::joinable!(events -> domains (domain_id));
 diesel::joinable!(faculty -> users (user_id));
+diesel::joinable!(faculty_applications -> faculty (user_id));
+diesel::joinable!(faculty_applications -> users (reviewed_by));
 diesel::joinable!(faculty_coordinators -> domains (domain_id));
 diesel::joinable!(faculty_coordinators -> faculty (faculty_id));
 diesel::joinable!(individual_event_participation -> events (event_id));
 diesel::joinable!(individual_event_participation -> users (user_id));
-diesel::joinable!(payments -> users (user_id));
+diesel::joinable!(individual_event_waitlist -> events (event_id));
+diesel::joinable!(individual_event_waitlist -> users (user_id));
+diesel::joinable!(judge_scores -> judging_criteria (criterion_id));
+diesel::joinable!(judge_scores -> teams (team_id));
+diesel::joinable!(judging_criteria -> events (event_id));
+diesel::joinable!(points_ledger -> domains (domain_id));
+diesel::joinable!(points_ledger -> events (event_id));
+diesel::joinable!(points_ledger -> teams (team_id));
+diesel::joinable!(points_ledger -> users (user_id));
+diesel::joinable!(points_ledger -> workshops (workshop_id));
+diesel::joinable!(recovery_codes -> users (user_id));
+diesel::joinable!(sessions -> users (user_id));
 diesel::joinable!(student_domain_coordinators -> domains (domain_id));
 diesel::joinable!(student_domain_coordinators -> students (student_id));
 diesel::joinable!(student_event_coordinators -> events (event_id));
@@ -228,31 +515,58 @@ diesel::joinable!(student_workshop_coordinators -> workshops (workshop_id));
 diesel::joinable!(students -> users (user_id));
 diesel::joinable!(team_event_participations -> events (event_id));
 diesel::joinable!(team_event_participations -> teams (team_id));
+diesel::joinable!(team_event_waitlist -> events (event_id));
+diesel::joinable!(team_event_waitlist -> teams (team_id));
 diesel::joinable!(team_members -> students (student_id));
 diesel::joinable!(team_members -> teams (team_id));
 diesel::joinable!(team_requests -> students (student_id));
 diesel::joinable!(team_requests -> teams (team_id));
+diesel::joinable!(tokens -> users (user_id));
+diesel::joinable!(two_factor -> users (user_id));
+diesel::joinable!(two_factor_required_roles -> users (required_by));
+diesel::joinable!(user_identities -> users (user_id));
 diesel::joinable!(workshop_participation -> users (user_id));
 diesel::joinable!(workshop_participation -> workshops (workshop_id));
+diesel::joinable!(workshop_waitlist -> users (user_id));
+diesel::joinable!(workshop_waitlist -> workshops (workshop_id));
 diesel::joinable!(workshops -> domains (domain_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    certificates,
     domains,
+    event_judges,
+    event_results,
     events,
     faculty,
+    faculty_applications,
     faculty_coordinators,
     individual_event_participation,
+    individual_event_waitlist,
+    judge_scores,
+    judging_criteria,
+    outbox,
+    payment_webhook_events,
     payments,
+    points_ledger,
+    recovery_codes,
+    sessions,
     sponsors,
     student_domain_coordinators,
     student_event_coordinators,
     student_workshop_coordinators,
     students,
     team_event_participations,
+    team_event_waitlist,
     team_members,
     team_requests,
     teams,
+    tokens,
+    two_factor,
+    two_factor_required_roles,
+    used_checkin_tokens,
+    user_identities,
     users,
     workshop_participation,
+    workshop_waitlist,
     workshops,
 );
@@ -0,0 +1,217 @@
+use std::io::Cursor;
+
+use axum::{
+    body::{Body, Bytes},
+    extract::{Query, State},
+    response::IntoResponse,
+    Form, Json,
+};
+use base64::{prelude::BASE64_URL_SAFE_NO_PAD, Engine};
+use diesel::prelude::*;
+use highway::HighwayHash;
+use http::{header, HeaderMap};
+use reqwest::Url;
+use tokio_util::io::ReaderStream;
+
+use crate::{
+    error::AppError,
+    forms::sponsors::{ChangeSponsor, CreateSponsor, SponsorId},
+    models::{sponsors::Sponsor, users::User},
+    policy::require_super_admin,
+    schema::sponsors,
+    state::SiteState,
+};
+
+/// Public listing, highest tier first and then by the configured display order.
+pub async fn get_sponsors(State(state): State<SiteState>) -> Result<Json<Vec<Sponsor>>, AppError> {
+    Ok(Json(
+        sponsors::table
+            .select(Sponsor::as_select())
+            .order((
+                sponsors::tier.asc(),
+                sponsors::display_order.asc(),
+                sponsors::name.asc(),
+            ))
+            .load(&mut state.connection.get()?)?,
+    ))
+}
+
+/// The website as it is linked to publicly, which has to be a plain web address.
+fn check_website(website: &str) -> Result<String, AppError> {
+    let invalid = || AppError::invalid("website", "Must be an http or https address");
+    let url = Url::parse(website.trim()).map_err(|_| invalid())?;
+    if !matches!(url.scheme(), "http" | "https") || url.host_str().is_none() {
+        return Err(invalid());
+    }
+    Ok(url.into())
+}
+
+pub async fn create_sponsor(
+    State(state): State<SiteState>,
+    user: User,
+    Form(mut data): Form<CreateSponsor>,
+) -> Result<Json<Sponsor>, AppError> {
+    require_super_admin(&state, &user)?;
+    data.website = data
+        .website
+        .as_deref()
+        .filter(|website| !website.trim().is_empty())
+        .map(check_website)
+        .transpose()?;
+    Ok(Json(
+        data.insert_into(sponsors::table)
+            .returning(Sponsor::as_returning())
+            .get_result(&mut state.connection.get()?)?,
+    ))
+}
+
+pub async fn change_sponsor(
+    State(state): State<SiteState>,
+    user: User,
+    Form(mut data): Form<ChangeSponsor>,
+) -> Result<Json<Sponsor>, AppError> {
+    require_super_admin(&state, &user)?;
+    if let Some(Some(website)) = &mut data.website {
+        *website = check_website(website)?;
+    }
+    diesel::update(sponsors::table)
+        .filter(sponsors::id.eq(data.id))
+        .set(data)
+        .returning(Sponsor::as_returning())
+        .get_result(&mut state.connection.get()?)
+        .optional()?
+        .map(Json)
+        .ok_or_else(|| AppError::not_found("No such sponsor"))
+}
+
+pub async fn delete_sponsor(
+    State(state): State<SiteState>,
+    user: User,
+    Form(data): Form<SponsorId>,
+) -> Result<Json<Sponsor>, AppError> {
+    require_super_admin(&state, &user)?;
+    diesel::delete(sponsors::table)
+        .filter(sponsors::id.eq(data.id))
+        .returning(Sponsor::as_returning())
+        .get_result(&mut state.connection.get()?)
+        .optional()?
+        .map(Json)
+        .ok_or_else(|| AppError::not_found("No such sponsor"))
+}
+
+// Not Deleting the image in case some other sponsor also happens to have the same exact image
+pub async fn set_sponsor_photo(
+    State(state): State<SiteState>,
+    Query(data): Query<SponsorId>,
+    user: User,
+    photo: Bytes,
+) -> Result<(), AppError> {
+    require_super_admin(&state, &user)?;
+    let hash = state.bulk_hasher.hash256(photo.to_vec().as_slice());
+    let photo = image::ImageReader::new(Cursor::new(photo))
+        .with_guessed_format()
+        .map_err(AppError::internal)?
+        .decode()
+        .map_err(|e| AppError::invalid("photo", format!("Not a readable image: {e}")))?;
+    let photo = photo.thumbnail(512, 512);
+    let dir = format!(
+        "{}/{}",
+        state.image_dir.to_string_lossy(),
+        BASE64_URL_SAFE_NO_PAD.encode(
+            hash.map(|v| v.to_le_bytes())
+                .into_iter()
+                .flatten()
+                .collect::<Vec<u8>>()
+        )
+    );
+    log::info!("Saving image to {}", dir);
+    photo
+        .save_with_format(dir, image::ImageFormat::Avif)
+        .map_err(AppError::internal)?;
+    let updated = diesel::update(sponsors::table)
+        .filter(sponsors::id.eq(data.id))
+        .set(
+            sponsors::photo_hash.eq(hash
+                .map(|v| v.to_le_bytes())
+                .into_iter()
+                .flatten()
+                .collect::<Vec<u8>>()),
+        )
+        .execute(&mut state.connection.get()?)?;
+    if updated == 0 {
+        return Err(AppError::not_found("No such sponsor"));
+    }
+    Ok(())
+}
+
+pub async fn get_sponsor_photo(
+    State(state): State<SiteState>,
+    Query(data): Query<SponsorId>,
+) -> Result<impl IntoResponse, AppError> {
+    let photo_hash: Vec<u8> = sponsors::table
+        .select(sponsors::photo_hash)
+        .filter(sponsors::id.eq(data.id))
+        .get_result::<Option<Vec<u8>>>(&mut state.connection.get()?)
+        .optional()?
+        .ok_or_else(|| AppError::not_found("No such sponsor"))?
+        .ok_or_else(|| AppError::not_found("The sponsor has no photo"))?;
+    let file = tokio::fs::File::open(format!(
+        "{}/{}",
+        state.image_dir.to_string_lossy(),
+        BASE64_URL_SAFE_NO_PAD.encode(photo_hash)
+    ))
+    .await
+    .map_err(|e| {
+        log::error!("{e:?}");
+        AppError::not_found("The photo is missing")
+    })?;
+    let stream = ReaderStream::new(file);
+    let body = Body::from_stream(stream);
+
+    let mut header_map = HeaderMap::new();
+    header_map.insert(
+        header::CONTENT_TYPE,
+        "image/avif"
+            .parse()
+            .expect("Parsing \"image/avif\" should have been fine."),
+    );
+
+    Ok((header_map, body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn takes_only_web_addresses() {
+        assert_eq!(
+            check_website(" https://sponsor.example.com ").unwrap(),
+            "https://sponsor.example.com/"
+        );
+        assert!(check_website("http://sponsor.example.com/about").is_ok());
+        for website in [
+            "javascript:alert(1)",
+            "ftp://sponsor.example.com",
+            "sponsor.example.com",
+            "data:text/html,hi",
+        ] {
+            assert_eq!(
+                check_website(website).unwrap_err().status(),
+                http::StatusCode::BAD_REQUEST,
+                "{website}"
+            );
+        }
+    }
+
+    #[test]
+    fn an_empty_website_clears_it() {
+        let change = |json: &str| serde_json::from_str::<ChangeSponsor>(json).unwrap().website;
+        assert_eq!(change(r#"{"id": 1}"#), None);
+        assert_eq!(change(r#"{"id": 1, "website": ""}"#), Some(None));
+        assert_eq!(
+            change(r#"{"id": 1, "website": "https://a.org"}"#),
+            Some(Some("https://a.org".to_owned()))
+        );
+    }
+}
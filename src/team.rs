@@ -4,54 +4,179 @@ use axum::{
 };
 use axum_extra::extract::Form;
 use diesel::prelude::*;
-use diesel::result::Error;
 use http::StatusCode;
 
 use crate::{
+    error::AppError,
     forms::teams::{ChangeTeam, MemberId, NewTeamReq, TeamId, TeamName},
     models::{
         team::{NewTeamRequest, Team, TeamMember, TeamMemberResp, TeamRequest, TeamResponse},
         users::User,
     },
-    schema::{students, team_members, team_requests, teams, users},
+    outbox::{enqueue, mail_teams},
+    schema::{
+        events, students, team_event_participations, team_members, team_requests, teams, users,
+    },
     state::SiteState,
 };
 
+/// Members of the team, plus the requests it has sent out when `count_pending` is set.
+pub fn team_size(
+    connection: &mut PgConnection,
+    team_id: i32,
+    count_pending: bool,
+) -> QueryResult<i64> {
+    let members: i64 = team_members::table
+        .filter(team_members::team_id.eq(team_id))
+        .count()
+        .get_result(connection)?;
+    if !count_pending {
+        return Ok(members);
+    }
+    let pending: i64 = team_requests::table
+        .filter(team_requests::team_id.eq(team_id))
+        .count()
+        .get_result(connection)?;
+    Ok(members + pending)
+}
+
+/// Locks the team row, so its members and requests hold still until the transaction ends.
+pub fn lock_team(connection: &mut PgConnection, team_id: i32) -> QueryResult<()> {
+    teams::table
+        .select(teams::id)
+        .find(team_id)
+        .for_update()
+        .get_result::<i32>(connection)?;
+    Ok(())
+}
+
+/// Flags the events the team has joined where it no longer has `min_team_size` members, and
+/// clears the flag where it has enough again. Returns the name and `min_team_size` of the events
+/// that were newly flagged.
+pub fn refresh_below_min_size(
+    connection: &mut PgConnection,
+    team_id: i32,
+) -> QueryResult<Vec<(String, i32)>> {
+    let members = team_size(connection, team_id, false)?;
+    let joined: Vec<(i32, String, i32, bool)> = team_event_participations::table
+        .inner_join(events::table)
+        .select((
+            events::id,
+            events::name,
+            events::min_team_size,
+            team_event_participations::below_min_size,
+        ))
+        .filter(team_event_participations::team_id.eq(team_id))
+        .load(connection)?;
+    let mut flagged = Vec::new();
+    for (event_id, name, min_team_size, was_below) in joined {
+        let below = members < i64::from(min_team_size);
+        if below == was_below {
+            continue;
+        }
+        diesel::update(team_event_participations::table)
+            .filter(team_event_participations::team_id.eq(team_id))
+            .filter(team_event_participations::event_id.eq(event_id))
+            .set(team_event_participations::below_min_size.eq(below))
+            .execute(connection)?;
+        if below {
+            flagged.push((name, min_team_size));
+        }
+    }
+    Ok(flagged)
+}
+
+/// Whether `user_id` leads the team, refused with 403 when they do not.
+pub fn require_leader(
+    connection: &mut PgConnection,
+    team_id: i32,
+    user_id: i32,
+) -> Result<(), AppError> {
+    let is_leader: bool = team_members::table
+        .select(team_members::is_leader)
+        .filter(team_members::team_id.eq(team_id))
+        .filter(team_members::student_id.eq(user_id))
+        .get_result(connection)
+        .optional()?
+        .unwrap_or(false);
+    if !is_leader {
+        return Err(AppError::forbidden("Only the team leader can do this"));
+    }
+    Ok(())
+}
+
+/// Only verified participants who paid the fee can form and join teams.
+fn require_registered(state: &SiteState, user: &User) -> Result<(), AppError> {
+    if !user.verified || !user.is_payment_done(&state.connection) {
+        return Err(AppError::forbidden(
+            "Verify your email and pay the fee to form teams",
+        ));
+    }
+    Ok(())
+}
+
+/// Makes sure the team still fits every event it has already joined once `new_members` people
+/// join it and `new_requests` more requests are pending.
+fn check_room_in_joined_events(
+    connection: &mut PgConnection,
+    team_id: i32,
+    new_members: i64,
+    new_requests: i64,
+) -> Result<(), AppError> {
+    let joined: Vec<(String, i32, bool)> = team_event_participations::table
+        .inner_join(events::table)
+        .select((
+            events::name,
+            events::max_team_size,
+            events::count_pending_requests,
+        ))
+        .filter(team_event_participations::team_id.eq(team_id))
+        .load(connection)?;
+    for (name, max_team_size, count_pending_requests) in joined {
+        let extra = if count_pending_requests {
+            new_members + new_requests
+        } else {
+            new_members
+        };
+        if extra <= 0 {
+            continue;
+        }
+        let size = team_size(connection, team_id, count_pending_requests)?;
+        if size + extra > i64::from(max_team_size) {
+            return Err(AppError::forbidden(format!(
+                "{name} allows at most {max_team_size} members per team"
+            )));
+        }
+    }
+    Ok(())
+}
+
 pub async fn get_teams(
     State(state): State<SiteState>,
     user: Option<User>,
     data: Option<Query<TeamId>>,
-) -> Result<Json<Vec<Team>>, StatusCode> {
+) -> Result<Json<Vec<Team>>, AppError> {
+    let connection = &mut state.connection.get()?;
     if let Some(data) = data {
-        teams::table
-            .select(Team::as_select())
-            .filter(teams::id.eq(data.id))
-            .load(&mut state.connection.get().map_err(|e| {
-                log::error!("{e:?}");
-                StatusCode::INTERNAL_SERVER_ERROR
-            })?)
-            .map(|v| Json(v))
-            .map_err(|e| {
-                log::error!("{e:?}");
-                StatusCode::NOT_FOUND
-            })
+        Ok(Json(
+            teams::table
+                .select(Team::as_select())
+                .filter(teams::id.eq(data.id))
+                .load(connection)?,
+        ))
     } else if let Some(user) = user {
-        log::info!("{user:#?}");
-        team_members::table
-            .inner_join(teams::table)
-            .select(Team::as_select())
-            .filter(team_members::student_id.eq(user.id))
-            .load(&mut state.connection.get().map_err(|e| {
-                log::error!("{e:?}");
-                StatusCode::INTERNAL_SERVER_ERROR
-            })?)
-            .map(|v| Json(v))
-            .map_err(|e| {
-                log::error!("{e:?}");
-                StatusCode::NOT_FOUND
-            })
+        Ok(Json(
+            team_members::table
+                .inner_join(teams::table)
+                .select(Team::as_select())
+                .filter(team_members::student_id.eq(user.id))
+                .load(connection)?,
+        ))
     } else {
-        Err(StatusCode::BAD_REQUEST)
+        Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            "Sign in or ask for a team by id",
+        ))
     }
 }
 
@@ -59,68 +184,54 @@ pub async fn create_team(
     State(state): State<SiteState>,
     user: User,
     axum_extra::extract::Form(data): axum_extra::extract::Form<NewTeamReq>,
-) -> Result<(), StatusCode> {
-    if !user.verified || !user.is_payment_done(&state.connection) {
-        return Err(StatusCode::UNAUTHORIZED);
-    }
-    state
-        .connection
-        .get()
-        .map_err(|e| {
-            log::error!("at line {} {e:?}", line!());
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?
-        .transaction::<_, Error, _>(|connection| {
-            let team_id: i32 = TeamName { name: data.name }
-                .insert_into(teams::table)
-                .returning(teams::id)
+) -> Result<(), AppError> {
+    require_registered(&state, &user)?;
+    let connection = &mut state.connection.get()?;
+    let invited = connection.transaction::<_, AppError, _>(|connection| {
+        let team_name = data.name.clone();
+        let team_id: i32 = TeamName { name: data.name }
+            .insert_into(teams::table)
+            .returning(teams::id)
+            .get_result(connection)?;
+        TeamMember {
+            team_id,
+            student_id: user.id,
+            is_leader: true,
+        }
+        .insert_into(team_members::table)
+        .execute(connection)?;
+
+        let mut invitations = Vec::new();
+        for member in data.members.iter().map(|v| v.trim_ascii()) {
+            if member.is_empty() {
+                continue;
+            }
+            let invitee: User = users::table
+                .select(User::as_select())
+                .filter(users::email.eq(member))
                 .get_result(connection)
-                .map_err(|e| {
-                    log::error!("{e:?}");
-                    e
+                .optional()?
+                .ok_or_else(|| {
+                    AppError::invalid("members", format!("No user with the email {member}"))
                 })?;
-            TeamMember {
+            TeamRequest {
                 team_id,
-                student_id: user.id,
-                is_leader: true,
-            }
-            .insert_into(team_members::table)
-            .execute(connection)
-            .map(|_| ())
-            .map_err(|e| {
-                log::error!("{e:?}");
-                e
-            })?;
-
-            log::info!("{:?}", data.members.clone());
-            if let Some(v) = data.members.first() {
-                if !v.is_empty() && data.members.len() < 4 {
-                    for member in data.members.into_iter() {
-                        let student_id = users::table
-                            .select(users::id)
-                            .filter(users::email.eq(member.trim_ascii()))
-                            .get_result(connection)
-                            .map_err(|e| {
-                                log::error!("at line {} {e:?}", line!());
-                                e
-                            })?;
-                        TeamRequest {
-                            team_id,
-                            student_id,
-                        }
-                        .insert_into(team_requests::table)
-                        .execute(connection)
-                        .map(|_| ())
-                        .map_err(|e| {
-                            log::error!("at line {} {e:?}", line!());
-                            e
-                        })?;
-                    }
-                }
+                student_id: invitee.id,
             }
-            Ok(())
-        })
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+            .insert_into(team_requests::table)
+            .execute(connection)?;
+            invitations.push(invitee.team_invitation_email(
+                &state.templates,
+                &team_name,
+                &user.name,
+            ));
+        }
+        enqueue(connection, &invitations)?;
+        Ok(!invitations.is_empty())
+    })?;
+    if invited {
+        state.wake_outbox();
+    }
     Ok(())
 }
 
@@ -128,80 +239,36 @@ pub async fn delete_team(
     State(state): State<SiteState>,
     user: User,
     Query(data): Query<TeamId>,
-) -> Result<(), StatusCode> {
-    if !user.verified || !user.is_payment_done(&state.connection) {
-        return Err(StatusCode::UNAUTHORIZED);
-    }
-    let is_leader: bool = team_members::table
-        .select(team_members::is_leader)
-        .filter(team_members::team_id.eq(data.id))
-        .filter(team_members::student_id.eq(user.id))
-        .get_result(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::UNAUTHORIZED
-        })?;
-    if !is_leader {
-        return Err(StatusCode::UNAUTHORIZED);
-    }
+) -> Result<(), AppError> {
+    require_registered(&state, &user)?;
+    let connection = &mut state.connection.get()?;
+    require_leader(connection, data.id, user.id)?;
     diesel::delete(teams::table)
         .filter(teams::id.eq(data.id))
-        .execute(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map(|_| ())
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::UNAUTHORIZED
-        })
+        .execute(connection)?;
+    Ok(())
 }
 
 pub async fn change_team(
     State(state): State<SiteState>,
     user: User,
     Query(data): Query<ChangeTeam>,
-) -> Result<(), StatusCode> {
-    if !user.verified || !user.is_payment_done(&state.connection) {
-        return Err(StatusCode::UNAUTHORIZED);
-    }
-    let is_leader: bool = team_members::table
-        .select(team_members::is_leader)
-        .filter(team_members::team_id.eq(data.id))
-        .filter(team_members::student_id.eq(user.id))
-        .get_result(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::UNAUTHORIZED
-        })?;
-    if !is_leader {
-        return Err(StatusCode::UNAUTHORIZED);
-    }
+) -> Result<(), AppError> {
+    require_registered(&state, &user)?;
+    let connection = &mut state.connection.get()?;
+    require_leader(connection, data.id, user.id)?;
     diesel::update(teams::table)
         .filter(teams::id.eq(data.id))
         .set(data)
-        .execute(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map(|_| ())
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::UNAUTHORIZED
-        })
+        .execute(connection)?;
+    Ok(())
 }
 
 pub async fn get_team_members(
     State(state): State<SiteState>,
     Query(data): Query<TeamId>,
-) -> Result<Json<Vec<TeamMemberResp>>, StatusCode> {
-    team_members::table
+) -> Result<Json<Vec<TeamMemberResp>>, AppError> {
+    let members: Vec<(Team, User, TeamMember)> = team_members::table
         .inner_join(teams::table)
         .inner_join(students::table.inner_join(users::table))
         .select((
@@ -210,73 +277,59 @@ pub async fn get_team_members(
             TeamMember::as_select(),
         ))
         .filter(team_members::team_id.eq(data.id))
-        .load(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map(|v| {
-            Json(
-                v.into_iter()
-                    .map(|(team, user, member)| TeamMemberResp {
-                        team_id: team.id,
-                        student_id: user.id,
-                        is_leader: member.is_leader,
-                        team_name: team.name,
-                        name: user.name,
-                        verified: user.verified,
-                        email: user.email,
-                    })
-                    .collect::<Vec<TeamMemberResp>>(),
-            )
-        })
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::UNAUTHORIZED
-        })
+        .load(&mut state.connection.get()?)?;
+    Ok(Json(
+        members
+            .into_iter()
+            .map(|(team, user, member)| TeamMemberResp {
+                team_id: team.id,
+                student_id: user.id,
+                is_leader: member.is_leader,
+                team_name: team.name,
+                name: user.name,
+                verified: user.verified,
+                email: user.email,
+            })
+            .collect(),
+    ))
 }
 
 pub async fn remove_member(
     State(state): State<SiteState>,
     user: User,
     Query(data): Query<MemberId>,
-) -> Result<(), StatusCode> {
-    if !user.verified || !user.is_payment_done(&state.connection) {
-        return Err(StatusCode::UNAUTHORIZED);
-    }
-    let is_leader: bool = team_members::table
-        .select(team_members::is_leader)
-        .filter(team_members::team_id.eq(data.team_id))
-        .filter(team_members::student_id.eq(user.id))
-        .get_result(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::UNAUTHORIZED
-        })?;
-    if !is_leader {
-        return Err(StatusCode::UNAUTHORIZED);
+) -> Result<(), AppError> {
+    require_registered(&state, &user)?;
+    let connection = &mut state.connection.get()?;
+    require_leader(connection, data.team_id, user.id)?;
+    let (team_name, flagged) = connection.transaction::<_, AppError, _>(|connection| {
+        lock_team(connection, data.team_id)?;
+        let removed = diesel::delete(team_members::table)
+            .filter(team_members::team_id.eq(data.team_id))
+            .filter(team_members::student_id.eq(data.student_id))
+            .execute(connection)?;
+        if removed == 0 {
+            return Err(AppError::not_found("They are not in the team"));
+        }
+        let team_name: String = teams::table
+            .select(teams::name)
+            .find(data.team_id)
+            .get_result(connection)?;
+        Ok((team_name, refresh_below_min_size(connection, data.team_id)?))
+    })?;
+    for (title, min_team_size) in flagged {
+        mail_teams(&state, &[data.team_id], |member| {
+            member.team_below_minimum_email(&state.templates, &team_name, &title, min_team_size)
+        });
     }
-    diesel::delete(team_members::table)
-        .filter(team_members::team_id.eq(data.team_id))
-        .filter(team_members::student_id.eq(data.student_id))
-        .execute(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map(|_| ())
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::UNAUTHORIZED
-        })
+    Ok(())
 }
 
 pub async fn get_team_request(
     State(state): State<SiteState>,
     user: User,
     data: Option<Query<TeamId>>,
-) -> Result<Json<Vec<TeamResponse>>, StatusCode> {
+) -> Result<Json<Vec<TeamResponse>>, AppError> {
     let query =
         team_requests::table
             .inner_join(teams::table.inner_join(
@@ -289,146 +342,110 @@ pub async fn get_team_request(
     } else {
         query
     };
-    query
+    let requests: Vec<(User, Team)> = query
         .filter(team_requests::student_id.eq(user.id))
-        .load(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map(|v| {
-            Json(
-                v.into_iter()
-                    .map(|(user, team)| TeamResponse {
-                        team_name: team.name,
-                        team_id: team.id,
-                        leader_name: user.name,
-                    })
-                    .collect::<Vec<TeamResponse>>(),
-            )
-        })
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::UNAUTHORIZED
-        })
+        .load(&mut state.connection.get()?)?;
+    Ok(Json(
+        requests
+            .into_iter()
+            .map(|(user, team)| TeamResponse {
+                team_name: team.name,
+                team_id: team.id,
+                leader_name: user.name,
+            })
+            .collect(),
+    ))
 }
 
 pub async fn accept_team_request(
     State(state): State<SiteState>,
     user: User,
     Query(data): Query<TeamId>,
-) -> Result<(), StatusCode> {
-    if !user.verified || !user.is_payment_done(&state.connection) {
-        return Err(StatusCode::UNAUTHORIZED);
-    }
+) -> Result<(), AppError> {
+    require_registered(&state, &user)?;
+    let connection = &mut state.connection.get()?;
     let request: TeamRequest = team_requests::table
         .select(TeamRequest::as_select())
         .filter(team_requests::team_id.eq(data.id))
         .filter(team_requests::student_id.eq(user.id))
-        .get_result(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::UNAUTHORIZED
-        })?;
-    TeamMember {
-        team_id: request.team_id,
-        student_id: request.student_id,
-        is_leader: false,
-    }
-    .insert_into(team_members::table)
-    .execute(&mut state.connection.get().map_err(|e| {
-        log::error!("{e:?}");
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?)
-    .map(|_| ())
-    .map_err(|e| {
-        log::error!("{e:?}");
-        StatusCode::UNAUTHORIZED
-    })?;
-    diesel::delete(team_requests::table)
-        .filter(team_requests::team_id.eq(data.id))
-        .filter(team_requests::student_id.eq(user.id))
-        .execute(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map(|_| ())
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })
+        .get_result(connection)
+        .optional()?
+        .ok_or_else(|| AppError::not_found("No request from this team"))?;
+    connection.transaction::<_, AppError, _>(|connection| {
+        lock_team(connection, request.team_id)?;
+        check_room_in_joined_events(connection, request.team_id, 1, -1)?;
+        TeamMember {
+            team_id: request.team_id,
+            student_id: request.student_id,
+            is_leader: false,
+        }
+        .insert_into(team_members::table)
+        .execute(connection)?;
+        diesel::delete(team_requests::table)
+            .filter(team_requests::team_id.eq(data.id))
+            .filter(team_requests::student_id.eq(user.id))
+            .execute(connection)?;
+        refresh_below_min_size(connection, request.team_id)?;
+        Ok(())
+    })
 }
 
 pub async fn send_team_request(
     State(state): State<SiteState>,
     user: User,
     Form(data): Form<NewTeamRequest>,
-) -> Result<(), StatusCode> {
-    if !user.verified || !user.is_payment_done(&state.connection) {
-        return Err(StatusCode::UNAUTHORIZED);
-    }
-    let is_leader: bool = team_members::table
-        .select(team_members::is_leader)
-        .filter(team_members::team_id.eq(data.team_id))
-        .filter(team_members::student_id.eq(user.id))
-        .get_result(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::UNAUTHORIZED
-        })?;
-    if !is_leader {
-        return Err(StatusCode::UNAUTHORIZED);
-    }
-    let student_id = users::table
-        .select(users::id)
-        .filter(users::email.eq(data.email))
-        .get_result(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::UNAUTHORIZED
+) -> Result<(), AppError> {
+    require_registered(&state, &user)?;
+    let connection = &mut state.connection.get()?;
+    require_leader(connection, data.team_id, user.id)?;
+    let invitee: User = users::table
+        .select(User::as_select())
+        .filter(users::email.eq(&data.email))
+        .get_result(connection)
+        .optional()?
+        .ok_or_else(|| AppError::invalid("email", "No user with this email"))?;
+    let team_name: String = teams::table
+        .select(teams::name)
+        .filter(teams::id.eq(data.team_id))
+        .get_result(connection)
+        .optional()?
+        .ok_or_else(|| AppError::not_found("No such team"))?;
+    connection.transaction::<_, AppError, _>(|connection| {
+        lock_team(connection, data.team_id)?;
+        check_room_in_joined_events(connection, data.team_id, 0, 1)?;
+        TeamRequest {
+            team_id: data.team_id,
+            student_id: invitee.id,
+        }
+        .insert_into(team_requests::table)
+        .execute(connection)
+        .map_err(|e| match AppError::from(e) {
+            e if e.status() == StatusCode::CONFLICT => {
+                AppError::conflict("They are already invited").field("email", "Already invited")
+            }
+            e => e,
         })?;
-    TeamRequest {
-        team_id: data.team_id,
-        student_id,
-    }
-    .insert_into(team_requests::table)
-    .execute(&mut state.connection.get().map_err(|e| {
-        log::error!("{e:?}");
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?)
-    .map(|_| ())
-    .map_err(|e| {
-        log::error!("{e:?}");
-        StatusCode::CONFLICT
-    })
+        Ok(enqueue(
+            connection,
+            &[invitee.team_invitation_email(&state.templates, &team_name, &user.name)],
+        )?)
+    })?;
+    state.wake_outbox();
+    Ok(())
 }
 
 pub async fn reject_team_request(
     State(state): State<SiteState>,
     user: User,
     Query(data): Query<TeamId>,
-) -> Result<Json<TeamRequest>, StatusCode> {
-    log::info!("{:#?}", user);
-    log::info!("{:#?}", data);
-    diesel::delete(team_requests::table)
-        .filter(team_requests::team_id.eq(data.id))
-        .filter(team_requests::student_id.eq(user.id))
-        .returning(TeamRequest::as_select())
-        .get_result(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map(|v| Json(v))
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })
+) -> Result<Json<TeamRequest>, AppError> {
+    Ok(Json(
+        diesel::delete(team_requests::table)
+            .filter(team_requests::team_id.eq(data.id))
+            .filter(team_requests::student_id.eq(user.id))
+            .returning(TeamRequest::as_select())
+            .get_result(&mut state.connection.get()?)
+            .optional()?
+            .ok_or_else(|| AppError::not_found("No request from this team"))?,
+    ))
 }
@@ -0,0 +1,372 @@
+//! Mail templates. Every kind of mail has a subject, an HTML body and a plain text body, built
+//! into the binary from `templates/mail/<name>.subject`, `<name>.html` and `<name>.txt`. A file
+//! with the same name in `mail.template_dir` is used instead, so the wording can change without a
+//! rebuild.
+//!
+//! Templates fill in `{{ variable }}`, HTML escaped in the HTML body. Unknown variables are refused
+//! when the templates are loaded, so a typo stops the server from starting instead of showing up
+//! in someone's inbox. Every template can use the branding, see [`Branding`], and the
+//! `recipient_name`, besides the variables of its [`MailKind`].
+use std::{collections::HashMap, path::Path};
+
+use anyhow::{anyhow, Context};
+use chrono::NaiveDateTime;
+
+use crate::{config::Config, models::outbox::NewMail};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MailKind {
+    Verification,
+    PasswordReset,
+    WaitlistPromotion,
+    AccountLocked,
+    RegistrationConfirmation,
+    TeamInvitation,
+    EventChanged,
+    FacultyApproved,
+    FacultyRejected,
+    TeamBelowMinimum,
+}
+
+impl MailKind {
+    pub const VARIANTS: [MailKind; 10] = [
+        MailKind::Verification,
+        MailKind::PasswordReset,
+        MailKind::WaitlistPromotion,
+        MailKind::AccountLocked,
+        MailKind::RegistrationConfirmation,
+        MailKind::TeamInvitation,
+        MailKind::EventChanged,
+        MailKind::FacultyApproved,
+        MailKind::FacultyRejected,
+        MailKind::TeamBelowMinimum,
+    ];
+
+    /// The file name of its templates, without the extension.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Verification => "verification",
+            Self::PasswordReset => "password_reset",
+            Self::WaitlistPromotion => "waitlist_promotion",
+            Self::AccountLocked => "account_locked",
+            Self::RegistrationConfirmation => "registration_confirmation",
+            Self::TeamInvitation => "team_invitation",
+            Self::EventChanged => "event_changed",
+            Self::FacultyApproved => "faculty_approved",
+            Self::FacultyRejected => "faculty_rejected",
+            Self::TeamBelowMinimum => "team_below_minimum",
+        }
+    }
+
+    /// Variables its templates can use on top of [`COMMON_VARIABLES`].
+    pub fn variables(self) -> &'static [&'static str] {
+        match self {
+            Self::Verification => &["verify_url"],
+            Self::PasswordReset => &["reset_url"],
+            Self::WaitlistPromotion => &["title"],
+            Self::AccountLocked => &["minutes", "ip"],
+            Self::RegistrationConfirmation => &["title", "start_time", "venue"],
+            Self::TeamInvitation => &["team_name", "inviter_name"],
+            Self::EventChanged => &["title", "mode", "start_time", "end_time", "venue"],
+            Self::FacultyApproved => &["role", "domain_name"],
+            Self::FacultyRejected => &["reason"],
+            Self::TeamBelowMinimum => &["team_name", "title", "min_team_size"],
+        }
+    }
+
+    /// The subject, HTML and plain text templates built into the binary.
+    fn embedded(self) -> [&'static str; 3] {
+        macro_rules! embedded {
+            ($name:literal) => {
+                [
+                    include_str!(concat!("../templates/mail/", $name, ".subject")),
+                    include_str!(concat!("../templates/mail/", $name, ".html")),
+                    include_str!(concat!("../templates/mail/", $name, ".txt")),
+                ]
+            };
+        }
+        match self {
+            Self::Verification => embedded!("verification"),
+            Self::PasswordReset => embedded!("password_reset"),
+            Self::WaitlistPromotion => embedded!("waitlist_promotion"),
+            Self::AccountLocked => embedded!("account_locked"),
+            Self::RegistrationConfirmation => embedded!("registration_confirmation"),
+            Self::TeamInvitation => embedded!("team_invitation"),
+            Self::EventChanged => embedded!("event_changed"),
+            Self::FacultyApproved => embedded!("faculty_approved"),
+            Self::FacultyRejected => embedded!("faculty_rejected"),
+            Self::TeamBelowMinimum => embedded!("team_below_minimum"),
+        }
+    }
+}
+
+/// Variables every template can use.
+pub const COMMON_VARIABLES: [&str; 7] = [
+    "recipient_name",
+    "fest_name",
+    "fest_year",
+    "fest",
+    "tagline",
+    "site_url",
+    "api_url",
+];
+
+/// How the fest presents itself in mail, from the `site`, `fest` and `mail` sections of the
+/// [`Config`].
+#[derive(Debug, Clone)]
+pub struct Branding {
+    pub fest_name: String,
+    pub fest_year: u16,
+    pub tagline: String,
+    /// The frontend, without a trailing `/`
+    pub site_url: String,
+    /// Where this server is reachable, without a trailing `/`
+    pub api_url: String,
+    pub from_name: String,
+    pub from_email: String,
+}
+
+impl Branding {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            fest_name: config.fest.name.clone(),
+            fest_year: config.fest.year,
+            tagline: config.fest.tagline.clone(),
+            site_url: config.site.url().to_owned(),
+            api_url: config.site.api_url(),
+            from_name: config.mail.from_name.clone(),
+            from_email: config.mail.from_email.clone(),
+        }
+    }
+
+    /// The name with the short year, like `techFEST'24`.
+    pub fn fest(&self) -> String {
+        format!("{}'{:02}", self.fest_name, self.fest_year % 100)
+    }
+}
+
+/// How times are written in mail, like `14 March, 10:30 AM`.
+pub fn format_time(time: NaiveDateTime) -> String {
+    time.format("%-d %B, %-I:%M %p").to_string()
+}
+
+fn escape_html(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[derive(Debug)]
+enum Segment {
+    Text(String),
+    Variable(String),
+}
+
+#[derive(Debug)]
+struct Template {
+    segments: Vec<Segment>,
+}
+
+impl Template {
+    fn parse(source: &str, known: &[&str]) -> anyhow::Result<Self> {
+        let mut segments = Vec::new();
+        let mut rest = source;
+        while let Some(start) = rest.find("{{") {
+            let length = rest[start..]
+                .find("}}")
+                .ok_or_else(|| anyhow!("Unclosed {{{{ in {:?}", &rest[start..]))?;
+            let name = rest[start + 2..start + length].trim();
+            if !known.contains(&name) {
+                return Err(anyhow!("Unknown variable {name:?}, known are {known:?}"));
+            }
+            segments.push(Segment::Text(rest[..start].to_owned()));
+            segments.push(Segment::Variable(name.to_owned()));
+            rest = &rest[start + length + 2..];
+        }
+        segments.push(Segment::Text(rest.to_owned()));
+        Ok(Self { segments })
+    }
+
+    fn render(&self, variables: &[(&str, &str)], escape: bool) -> String {
+        let mut rendered = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Text(text) => rendered.push_str(text),
+                Segment::Variable(name) => {
+                    let value = match variables.iter().find(|(n, _)| n == name) {
+                        Some((_, value)) => value,
+                        None => {
+                            log::error!("No value for {name} in a mail template");
+                            ""
+                        }
+                    };
+                    if escape {
+                        rendered.push_str(&escape_html(value));
+                    } else {
+                        rendered.push_str(value);
+                    }
+                }
+            }
+        }
+        rendered
+    }
+}
+
+#[derive(Debug)]
+struct MailTemplate {
+    subject: Template,
+    html: Template,
+    text: Template,
+}
+
+#[derive(Debug)]
+pub struct MailTemplates {
+    pub branding: Branding,
+    templates: HashMap<MailKind, MailTemplate>,
+}
+
+impl MailTemplates {
+    /// The built in templates, with those found in `dir` used instead.
+    pub fn load(branding: Branding, dir: Option<&Path>) -> anyhow::Result<Self> {
+        let mut templates = HashMap::new();
+        for kind in MailKind::VARIANTS {
+            let known: Vec<&str> = COMMON_VARIABLES
+                .iter()
+                .chain(kind.variables())
+                .copied()
+                .collect();
+            let [subject, html, text] = ["subject", "html", "txt"]
+                .into_iter()
+                .zip(kind.embedded())
+                .map(|(extension, embedded)| {
+                    let file = format!("{}.{extension}", kind.name());
+                    let source = match dir.map(|dir| dir.join(&file)) {
+                        Some(path) if path.is_file() => {
+                            log::info!("Using the mail template {}", path.display());
+                            std::fs::read_to_string(&path)
+                                .with_context(|| format!("Reading {}", path.display()))?
+                        }
+                        _ => embedded.to_owned(),
+                    };
+                    Template::parse(source.trim_end_matches('\n'), &known)
+                        .with_context(|| format!("In the mail template {file}"))
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?
+                .try_into()
+                .map_err(|_| anyhow!("Every mail has three templates"))?;
+            templates.insert(
+                kind,
+                MailTemplate {
+                    subject,
+                    html,
+                    text,
+                },
+            );
+        }
+        Ok(Self {
+            branding,
+            templates,
+        })
+    }
+
+    /// The [`Branding`] of `config`, with the templates in `mail.template_dir`, if set.
+    pub fn from_config(config: &Config) -> anyhow::Result<Self> {
+        Self::load(Branding::new(config), config.mail.template_dir.as_deref())
+    }
+
+    /// Fills in the templates of `kind` for one recipient. `variables` has a value for each of
+    /// [`MailKind::variables`].
+    pub fn mail(
+        &self,
+        kind: MailKind,
+        recipient_name: &str,
+        recipient_email: &str,
+        variables: &[(&str, &str)],
+    ) -> NewMail {
+        let template = &self.templates[&kind];
+        let fest = self.branding.fest();
+        let fest_year = self.branding.fest_year.to_string();
+        let mut all = vec![
+            ("recipient_name", recipient_name),
+            ("fest_name", self.branding.fest_name.as_str()),
+            ("fest_year", fest_year.as_str()),
+            ("fest", fest.as_str()),
+            ("tagline", self.branding.tagline.as_str()),
+            ("site_url", self.branding.site_url.as_str()),
+            ("api_url", self.branding.api_url.as_str()),
+        ];
+        all.extend_from_slice(variables);
+        NewMail {
+            recipient_name: recipient_name.to_owned(),
+            recipient_email: recipient_email.to_owned(),
+            // A title with a line break must not end the header early
+            subject: template
+                .subject
+                .render(&all, false)
+                .replace(['\r', '\n'], " "),
+            html_body: template.html.render(&all, true),
+            text_body: Some(template.text.render(&all, false)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn templates() -> MailTemplates {
+        MailTemplates::load(Branding::new(&Config::default()), None).unwrap()
+    }
+
+    #[test]
+    fn escapes_html() {
+        assert_eq!(
+            escape_html(r#"<a href="x">Tom & Jerry's</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&#39;s&lt;/a&gt;"
+        );
+        assert_eq!(escape_html("Robowars 2024"), "Robowars 2024");
+    }
+
+    #[test]
+    fn refuses_unknown_variables() {
+        let error = Template::parse("Hello {{ recipient_nmae }}", &COMMON_VARIABLES).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("Unknown variable \"recipient_nmae\""));
+        assert!(Template::parse("Hello {{ recipient_name", &COMMON_VARIABLES).is_err());
+        assert!(Template::parse("Hello {{recipient_name}}", &COMMON_VARIABLES).is_ok());
+    }
+
+    #[test]
+    fn every_built_in_template_loads() {
+        let templates = templates();
+        for kind in MailKind::VARIANTS {
+            assert!(templates.templates.contains_key(&kind), "{}", kind.name());
+        }
+    }
+
+    #[test]
+    fn escapes_values_only_in_the_html_body() {
+        let mail = templates().mail(
+            MailKind::WaitlistPromotion,
+            "Tom & Jerry",
+            "tom@sliet.ac.in",
+            &[("title", "<Robowars>\r\nBcc: someone")],
+        );
+        assert!(mail.html_body.contains("&lt;Robowars&gt;"));
+        assert!(!mail.html_body.contains("<Robowars>"));
+        let text_body = mail.text_body.unwrap();
+        assert!(text_body.contains("<Robowars>"));
+        assert!(text_body.contains("Tom & Jerry"));
+        assert!(!mail.subject.contains(['\r', '\n']));
+    }
+}
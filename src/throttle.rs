@@ -0,0 +1,258 @@
+//! Throttling of sign in attempts and of the endpoints that send email.
+//!
+//! Every failed sign in, and every email sent, is counted per client address and per email
+//! address. Each one makes the next attempt wait twice as long as the last, and enough failed
+//! sign ins lock the key out for a while. Counts are forgotten once a key has been quiet for long
+//! enough. We run a single instance, so all of this lives in memory and starts over on restart.
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use http::StatusCode;
+
+use crate::error::AppError;
+
+/// Past this many tracked keys, quiet ones are dropped before tracking another.
+const PRUNE_ABOVE: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Scope {
+    SignIn,
+    /// Password reset and verification emails
+    Mail,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Key {
+    Ip(String),
+    Email(String),
+}
+
+impl Key {
+    pub fn email(email: &str) -> Self {
+        Key::Email(email.trim().to_lowercase())
+    }
+}
+
+struct Policy {
+    /// Wait after the first hit, doubled with every further one
+    base: Duration,
+    max_delay: Duration,
+    /// Hits after which the key is locked out instead
+    lock_after: Option<u32>,
+    lock_for: Duration,
+    /// Quiet time after which the count starts over
+    forget_after: Duration,
+}
+
+const MINUTE: Duration = Duration::from_secs(60);
+
+/// How long an email address is locked out of signing in after too many wrong passwords.
+pub const SIGN_IN_LOCKOUT: Duration = Duration::from_secs(60 * 15);
+
+fn policy(scope: Scope, key: &Key) -> Policy {
+    match (scope, key) {
+        (Scope::SignIn, Key::Email(_)) => Policy {
+            base: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+            lock_after: Some(5),
+            lock_for: SIGN_IN_LOCKOUT,
+            forget_after: 60 * MINUTE,
+        },
+        // Looser than per email, a whole hostel can share one address
+        (Scope::SignIn, Key::Ip(_)) => Policy {
+            base: Duration::from_secs(1),
+            max_delay: MINUTE,
+            lock_after: Some(50),
+            lock_for: 15 * MINUTE,
+            forget_after: 60 * MINUTE,
+        },
+        (Scope::Mail, Key::Email(_)) => Policy {
+            base: MINUTE,
+            max_delay: 60 * MINUTE,
+            lock_after: None,
+            lock_for: Duration::ZERO,
+            forget_after: 24 * 60 * MINUTE,
+        },
+        (Scope::Mail, Key::Ip(_)) => Policy {
+            base: Duration::from_secs(10),
+            max_delay: 10 * MINUTE,
+            lock_after: None,
+            lock_for: Duration::ZERO,
+            forget_after: 24 * 60 * MINUTE,
+        },
+    }
+}
+
+struct Entry {
+    hits: u32,
+    last_hit: Instant,
+    blocked_until: Instant,
+}
+
+#[derive(Default)]
+pub struct Throttle {
+    entries: Mutex<HashMap<(Scope, Key), Entry>>,
+}
+
+impl Throttle {
+    /// How long until every one of `keys` may try again, if any of them has to wait.
+    pub fn check(&self, scope: Scope, keys: &[Key]) -> Result<(), Duration> {
+        let now = Instant::now();
+        let entries = self.entries.lock().unwrap();
+        let wait = keys
+            .iter()
+            .filter_map(|key| entries.get(&(scope, key.clone())))
+            .map(|entry| entry.blocked_until.saturating_duration_since(now))
+            .max()
+            .unwrap_or_default();
+        if wait.is_zero() {
+            Ok(())
+        } else {
+            Err(wait)
+        }
+    }
+
+    /// Counts a failed sign in, or a sent email, against each key. Returns the keys this hit just
+    /// locked out, so the owner can be told.
+    pub fn hit(&self, scope: Scope, keys: &[Key]) -> Vec<Key> {
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() > PRUNE_ABOVE {
+            entries.retain(|(scope, key), entry| {
+                let policy = policy(*scope, key);
+                now.duration_since(entry.last_hit) < policy.forget_after
+                    || entry.blocked_until > now
+            });
+        }
+        let mut locked = Vec::new();
+        for key in keys {
+            let policy = policy(scope, key);
+            let entry = entries.entry((scope, key.clone())).or_insert(Entry {
+                hits: 0,
+                last_hit: now,
+                blocked_until: now,
+            });
+            if now.duration_since(entry.last_hit) >= policy.forget_after
+                && entry.blocked_until <= now
+            {
+                entry.hits = 0;
+            }
+            entry.hits += 1;
+            entry.last_hit = now;
+            entry.blocked_until = match policy.lock_after {
+                Some(lock_after) if entry.hits >= lock_after => {
+                    if entry.hits == lock_after {
+                        locked.push(key.clone());
+                    }
+                    now + policy.lock_for
+                }
+                _ => {
+                    now + policy
+                        .base
+                        .saturating_mul(2u32.saturating_pow(entry.hits - 1))
+                        .min(policy.max_delay)
+                }
+            };
+        }
+        locked
+    }
+
+    /// Forgets the count of a key, after a successful sign in.
+    pub fn clear(&self, scope: Scope, key: &Key) {
+        self.entries.lock().unwrap().remove(&(scope, key.clone()));
+    }
+}
+
+pub fn too_many_attempts(wait: Duration) -> AppError {
+    AppError::new(
+        StatusCode::TOO_MANY_REQUESTS,
+        format!(
+            "Too many attempts, try again in {} seconds",
+            wait.as_secs().max(1)
+        ),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::slice::from_ref;
+
+    use super::*;
+
+    fn wait(throttle: &Throttle, scope: Scope, key: &Key) -> Duration {
+        throttle
+            .check(scope, from_ref(key))
+            .err()
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn backs_off_twice_as_long_each_time() {
+        let throttle = Throttle::default();
+        let key = Key::Ip("10.0.0.1".to_owned());
+        assert!(throttle.check(Scope::SignIn, from_ref(&key)).is_ok());
+        for (hit, most) in [(1, 1), (2, 2), (3, 4), (4, 8)] {
+            assert!(throttle.hit(Scope::SignIn, from_ref(&key)).is_empty());
+            let wait = wait(&throttle, Scope::SignIn, &key);
+            let most = Duration::from_secs(most);
+            assert!(wait <= most && wait > most / 2, "hit {hit} waits {wait:?}");
+        }
+    }
+
+    #[test]
+    fn caps_the_delay() {
+        let throttle = Throttle::default();
+        let key = Key::email("someone@example.com");
+        for _ in 0..10 {
+            throttle.hit(Scope::Mail, from_ref(&key));
+        }
+        let wait = wait(&throttle, Scope::Mail, &key);
+        assert!(wait <= 60 * MINUTE && wait > 59 * MINUTE);
+    }
+
+    #[test]
+    fn locks_out_once_and_reports_it_once() {
+        let throttle = Throttle::default();
+        let key = Key::email(" Someone@Example.com ");
+        let ip = Key::Ip("10.0.0.1".to_owned());
+        let keys = [key.clone(), ip.clone()];
+        for _ in 1..5 {
+            assert!(throttle.hit(Scope::SignIn, &keys).is_empty());
+        }
+        assert_eq!(throttle.hit(Scope::SignIn, &keys), from_ref(&key));
+        assert!(wait(&throttle, Scope::SignIn, &key) > SIGN_IN_LOCKOUT - MINUTE);
+        // The address allows far more attempts before it is locked out
+        assert!(wait(&throttle, Scope::SignIn, &ip) <= Duration::from_secs(16));
+        assert!(throttle.hit(Scope::SignIn, &keys).is_empty());
+        // Either key waiting holds back the attempt
+        assert!(throttle.check(Scope::SignIn, &keys).unwrap_err() > SIGN_IN_LOCKOUT - MINUTE);
+    }
+
+    #[test]
+    fn keeps_scopes_and_keys_apart() {
+        let throttle = Throttle::default();
+        let key = Key::email("someone@example.com");
+        throttle.hit(Scope::Mail, from_ref(&key));
+        assert!(throttle.check(Scope::SignIn, from_ref(&key)).is_ok());
+        assert!(throttle
+            .check(Scope::Mail, &[Key::email("other@example.com")])
+            .is_ok());
+        assert_eq!(Key::email(" Someone@Example.COM"), key);
+    }
+
+    #[test]
+    fn clear_forgets_the_count() {
+        let throttle = Throttle::default();
+        let key = Key::email("someone@example.com");
+        for _ in 0..3 {
+            throttle.hit(Scope::SignIn, from_ref(&key));
+        }
+        throttle.clear(Scope::SignIn, &key);
+        assert!(throttle.check(Scope::SignIn, from_ref(&key)).is_ok());
+        throttle.hit(Scope::SignIn, from_ref(&key));
+        assert!(wait(&throttle, Scope::SignIn, &key) <= Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,59 @@
+//! Single-use tokens for links sent by email, such as email verification and password reset.
+//!
+//! A token is 256 random bits handed out once. Only its hash is stored, along with what it is
+//! for and when it expires, and redeeming it marks it used in the same statement that checks it.
+use diesel::dsl::IntervalDsl;
+use diesel::prelude::*;
+
+use crate::{
+    models::tokens::{hash_token, new_token, NewToken, TokenPurpose},
+    schema::tokens,
+};
+
+/// Creates a token for the user and returns it in the clear, to be put in a link. Tokens for the
+/// same purpose issued earlier stop working, so only the latest email counts.
+pub fn issue_token(
+    connection: &mut PgConnection,
+    user_id: i32,
+    purpose: TokenPurpose,
+) -> QueryResult<String> {
+    let token = new_token();
+    connection.transaction(|connection| {
+        diesel::update(tokens::table)
+            .filter(tokens::user_id.eq(user_id))
+            .filter(tokens::purpose.eq(purpose))
+            .filter(tokens::used_at.is_null())
+            .set(tokens::used_at.eq(diesel::dsl::now.nullable()))
+            .execute(connection)?;
+        diesel::insert_into(tokens::table)
+            .values((
+                NewToken {
+                    user_id,
+                    purpose,
+                    token_hash: hash_token(&token),
+                },
+                tokens::expires_at
+                    .eq(diesel::dsl::now + (purpose.ttl().as_secs() as i64).seconds()),
+            ))
+            .execute(connection)
+    })?;
+    Ok(token)
+}
+
+/// Uses up a token, returning the user it was issued to. `Ok(None)` when the token is unknown,
+/// meant for something else, expired or already used.
+pub fn redeem_token(
+    connection: &mut PgConnection,
+    token: &str,
+    purpose: TokenPurpose,
+) -> QueryResult<Option<i32>> {
+    diesel::update(tokens::table)
+        .filter(tokens::token_hash.eq(hash_token(token)))
+        .filter(tokens::purpose.eq(purpose))
+        .filter(tokens::used_at.is_null())
+        .filter(tokens::expires_at.gt(diesel::dsl::now))
+        .set(tokens::used_at.eq(diesel::dsl::now.nullable()))
+        .returning(tokens::user_id)
+        .get_result(connection)
+        .optional()
+}
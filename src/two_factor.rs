@@ -0,0 +1,636 @@
+//! Two-factor sign in with time-based one-time passwords (RFC 6238), as shown by any
+//! authenticator app, with one-time recovery codes for when the app is lost.
+//!
+//! A user with two-factor on who signs in with the right password gets `202 Accepted` and a
+//! short-lived cookie instead of a session, and finishes signing in at `/auth/2fa` with a code.
+//! Super admins can require two-factor for privileged roles. Members of those roles are refused
+//! every privileged action until they turn it on.
+use std::time::{Duration, SystemTime};
+
+use axum::{extract::State, Form, Json};
+use axum_extra::extract::{
+    cookie::{Cookie, SameSite},
+    CookieJar,
+};
+use diesel::prelude::*;
+use diesel::result::Error;
+use hmac::{Hmac, Mac};
+use jsonwebtoken::{Algorithm, Header, Validation};
+use qrcode::{render::svg, QrCode};
+use rand::Rng;
+use reqwest::Url;
+use sha1::Sha1;
+
+use crate::{
+    auth::{keys, start_session, Client},
+    error::AppError,
+    forms::two_factor::{RequiredRole, TwoFactorCode},
+    models::{
+        tokens::hash_token,
+        two_factor::{
+            NewRecoveryCode, NewTwoFactor, TwoFactor, TwoFactorClaims, TwoFactorSetup,
+            TwoFactorStatus, CHALLENGE_TTL, RECOVERY_CODE_COUNT, TWO_FACTOR_AUDIENCE,
+        },
+        users::{Role, User},
+    },
+    policy::require_super_admin,
+    schema::{recovery_codes, two_factor, two_factor_required_roles, users},
+    state::SiteState,
+    throttle::{too_many_attempts, Key, Scope},
+};
+
+/// Seconds a code stays the same
+const STEP: u64 = 30;
+const DIGITS: u32 = 6;
+/// Steps either side of the current one that are still accepted, for phones whose clock is off
+const SKEW: u64 = 1;
+const CHALLENGE_COOKIE: &str = "two-factor";
+
+/// RFC 4648 base32 without padding, the form authenticator apps take secrets in.
+fn base32(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+    let mut encoded = String::with_capacity((bytes.len() * 8).div_ceil(5));
+    let (mut buffer, mut bits) = (0u32, 0);
+    for &byte in bytes {
+        buffer = (buffer << 8) | byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            encoded.push(ALPHABET[((buffer >> bits) & 31) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        encoded.push(ALPHABET[((buffer << (5 - bits)) & 31) as usize] as char);
+    }
+    encoded
+}
+
+fn code_at(secret: &[u8], step: u64) -> u32 {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(&step.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+    let offset = (hash[hash.len() - 1] & 0xf) as usize;
+    let value = u32::from_be_bytes(hash[offset..offset + 4].try_into().unwrap()) & 0x7fff_ffff;
+    value % 10u32.pow(DIGITS)
+}
+
+fn is_totp(code: &str) -> bool {
+    code.len() == DIGITS as usize && code.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// The step `code` belongs to, if it is current and newer than the last one used.
+fn matching_step(secret: &[u8], code: &str, last_used_step: Option<i64>) -> Option<i64> {
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .ok()?
+        .as_secs()
+        / STEP;
+    matching_step_at(secret, code, last_used_step, now)
+}
+
+/// [`matching_step`] with the current step given.
+fn matching_step_at(
+    secret: &[u8],
+    code: &str,
+    last_used_step: Option<i64>,
+    now: u64,
+) -> Option<i64> {
+    if !is_totp(code) {
+        return None;
+    }
+    let code: u32 = code.parse().ok()?;
+    (now.saturating_sub(SKEW)..=now + SKEW)
+        .map(|step| step as i64)
+        .filter(|&step| last_used_step.is_none_or(|last| step > last))
+        .find(|&step| code_at(secret, step as u64) == code)
+}
+
+/// Recovery codes are typed off paper, so dashes, spaces and case do not matter.
+fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+fn new_recovery_code() -> String {
+    let code = base32(&rand::thread_rng().gen::<[u8; 10]>()).to_lowercase();
+    format!(
+        "{}-{}-{}-{}",
+        &code[..4],
+        &code[4..8],
+        &code[8..12],
+        &code[12..]
+    )
+}
+
+/// The user's secret, if two-factor is on.
+pub fn enabled_factor(
+    connection: &mut PgConnection,
+    user_id: i32,
+) -> QueryResult<Option<TwoFactor>> {
+    two_factor::table
+        .select(TwoFactor::as_select())
+        .filter(two_factor::user_id.eq(user_id))
+        .filter(two_factor::enabled_at.is_not_null())
+        .get_result(connection)
+        .optional()
+}
+
+fn is_required(connection: &mut PgConnection, role: &Role) -> QueryResult<bool> {
+    diesel::select(diesel::dsl::exists(
+        two_factor_required_roles::table.filter(two_factor_required_roles::role.eq(role.clone())),
+    ))
+    .get_result(connection)
+}
+
+/// Checks a code from the authenticator, or a recovery code when `allow_recovery`, and uses it
+/// up so it can not be replayed.
+fn redeem_code(
+    connection: &mut PgConnection,
+    user_id: i32,
+    factor: &TwoFactor,
+    code: &str,
+    allow_recovery: bool,
+) -> QueryResult<bool> {
+    let code = code.trim();
+    if is_totp(code) {
+        let Some(step) = matching_step(&factor.secret, code, factor.last_used_step) else {
+            return Ok(false);
+        };
+        // Conditional, so two requests racing with the same code can not both get through
+        diesel::update(two_factor::table)
+            .filter(two_factor::user_id.eq(user_id))
+            .filter(
+                two_factor::last_used_step
+                    .is_null()
+                    .or(two_factor::last_used_step.lt(step)),
+            )
+            .set(two_factor::last_used_step.eq(step))
+            .execute(connection)
+            .map(|updated| updated == 1)
+    } else if allow_recovery {
+        diesel::update(recovery_codes::table)
+            .filter(recovery_codes::user_id.eq(user_id))
+            .filter(recovery_codes::code_hash.eq(hash_token(&normalize_recovery_code(code))))
+            .filter(recovery_codes::used_at.is_null())
+            .set(recovery_codes::used_at.eq(diesel::dsl::now.nullable()))
+            .execute(connection)
+            .map(|updated| updated == 1)
+    } else {
+        Ok(false)
+    }
+}
+
+/// Replaces the user's recovery codes with a fresh set. They are only ever shown this once.
+fn issue_recovery_codes(connection: &mut PgConnection, user_id: i32) -> QueryResult<Vec<String>> {
+    diesel::delete(recovery_codes::table.filter(recovery_codes::user_id.eq(user_id)))
+        .execute(connection)?;
+    let codes: Vec<String> = (0..RECOVERY_CODE_COUNT)
+        .map(|_| new_recovery_code())
+        .collect();
+    diesel::insert_into(recovery_codes::table)
+        .values(
+            codes
+                .iter()
+                .map(|code| NewRecoveryCode {
+                    user_id,
+                    code_hash: hash_token(&normalize_recovery_code(code)),
+                })
+                .collect::<Vec<_>>(),
+        )
+        .execute(connection)?;
+    Ok(codes)
+}
+
+/// `FORBIDDEN` when the user's role has to use two-factor and they have not turned it on yet.
+/// Checked before every privileged action.
+pub fn require_enrolled(state: &SiteState, user: &User) -> Result<(), AppError> {
+    if let Role::PARTICIPANT = user.role {
+        return Ok(());
+    }
+    let missing: bool = diesel::select(
+        diesel::dsl::exists(
+            two_factor_required_roles::table
+                .filter(two_factor_required_roles::role.eq(user.role.clone())),
+        )
+        .and(diesel::dsl::not(diesel::dsl::exists(
+            two_factor::table
+                .filter(two_factor::user_id.eq(user.id))
+                .filter(two_factor::enabled_at.is_not_null()),
+        ))),
+    )
+    .get_result(&mut state.connection.get()?)?;
+    if missing {
+        log::warn!(
+            "{} ({}) has to turn on two-factor first",
+            user.id,
+            user.role
+        );
+        return Err(AppError::forbidden(format!(
+            "Every {} has to turn on two-factor first",
+            user.role
+        ))
+        .with_code("two_factor_required"));
+    }
+    Ok(())
+}
+
+fn challenge_cookie(value: String, max_age: Duration) -> Cookie<'static> {
+    Cookie::build((CHALLENGE_COOKIE, value))
+        .http_only(true)
+        .secure(true)
+        .same_site(SameSite::None)
+        .max_age(max_age.try_into().unwrap())
+        .partitioned(true)
+        .path("/auth/2fa")
+        .build()
+}
+
+/// Holds a sign in that passed its first step until `/auth/2fa` gets a code for it.
+pub fn challenge(cookie_jar: CookieJar, user: &User) -> Result<CookieJar, AppError> {
+    let claims = TwoFactorClaims {
+        aud: TWO_FACTOR_AUDIENCE.to_owned(),
+        id: user.id,
+        exp: (SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_err(AppError::internal)?
+            + CHALLENGE_TTL)
+            .as_secs(),
+    };
+    let token = jsonwebtoken::encode(&Header::default(), &claims, &keys().encoding)
+        .map_err(AppError::internal)?;
+    Ok(cookie_jar.add(challenge_cookie(token, CHALLENGE_TTL)))
+}
+
+/// Wrong codes count against the same limits as wrong passwords.
+fn throttle_keys(client: &Client, user: &User) -> Vec<Key> {
+    let mut keys = vec![Key::email(&user.email)];
+    keys.extend(client.ip.clone().map(Key::Ip));
+    keys
+}
+
+fn wrong_code() -> AppError {
+    AppError::unauthorized("Wrong code").field("code", "Wrong code")
+}
+
+/// Second step of signing in, for users with two-factor on.
+pub async fn verify_two_factor(
+    State(state): State<SiteState>,
+    client: Client,
+    cookie_jar: CookieJar,
+    Form(data): Form<TwoFactorCode>,
+) -> Result<CookieJar, AppError> {
+    let mut validation = Validation::new(Algorithm::HS256);
+    validation.set_audience(&[TWO_FACTOR_AUDIENCE]);
+    let user_id = cookie_jar
+        .get(CHALLENGE_COOKIE)
+        .and_then(|cookie| {
+            jsonwebtoken::decode::<TwoFactorClaims>(cookie.value(), &keys().decoding, &validation)
+                .map_err(|e| log::error!("{e:?}"))
+                .ok()
+        })
+        .ok_or_else(|| AppError::unauthorized("Signing in took too long, sign in again"))?
+        .claims
+        .id;
+    let connection = &mut state.connection.get()?;
+    let user: User = users::table
+        .select(User::as_select())
+        .filter(users::id.eq(user_id))
+        .get_result(connection)
+        .optional()?
+        .ok_or_else(|| AppError::unauthorized("Signing in took too long, sign in again"))?;
+    let keys = throttle_keys(&client, &user);
+    state
+        .throttle
+        .check(Scope::SignIn, &keys)
+        .map_err(too_many_attempts)?;
+    // Two-factor turned off since the password was checked leaves nothing more to ask for
+    let redeemed = match enabled_factor(connection, user.id)? {
+        Some(factor) => redeem_code(connection, user.id, &factor, &data.code, true)?,
+        None => true,
+    };
+    if !redeemed {
+        state.throttle.hit(Scope::SignIn, &keys);
+        return Err(wrong_code());
+    }
+    state.throttle.clear(Scope::SignIn, &keys[0]);
+    let cookie_jar = cookie_jar.add(challenge_cookie(String::new(), Duration::ZERO));
+    start_session(&state, cookie_jar, &client, &user)
+}
+
+pub async fn get_two_factor(
+    State(state): State<SiteState>,
+    user: User,
+) -> Result<Json<TwoFactorStatus>, AppError> {
+    let connection = &mut state.connection.get()?;
+    Ok(Json(TwoFactorStatus {
+        enabled: enabled_factor(connection, user.id)?.is_some(),
+        required: is_required(connection, &user.role)?,
+        recovery_codes_left: recovery_codes::table
+            .filter(recovery_codes::user_id.eq(user.id))
+            .filter(recovery_codes::used_at.is_null())
+            .count()
+            .get_result(connection)?,
+    }))
+}
+
+/// Starts setting up two-factor with a new secret. It is only turned on once a code from it is
+/// confirmed with `PUT /profile/2fa`.
+pub async fn setup_two_factor(
+    State(state): State<SiteState>,
+    user: User,
+) -> Result<Json<TwoFactorSetup>, AppError> {
+    let secret = rand::thread_rng().gen::<[u8; 20]>().to_vec();
+    let created = state
+        .connection
+        .get()?
+        .transaction::<_, Error, _>(|connection| {
+            if enabled_factor(connection, user.id)?.is_some() {
+                return Ok(false);
+            }
+            diesel::insert_into(two_factor::table)
+                .values(NewTwoFactor {
+                    user_id: user.id,
+                    secret: secret.clone(),
+                })
+                .on_conflict(two_factor::user_id)
+                .do_update()
+                .set((
+                    two_factor::secret.eq(&secret),
+                    two_factor::created_at.eq(diesel::dsl::now),
+                ))
+                .execute(connection)?;
+            Ok(true)
+        })?;
+    if !created {
+        return Err(AppError::conflict(
+            "Two-factor is already on, turn it off first",
+        ));
+    }
+
+    let secret = base32(&secret);
+    // The fest name without the year, so the entry in the authenticator app outlives the edition
+    let issuer = &state.templates.branding.fest_name;
+    let mut uri = Url::parse("otpauth://totp/").expect("The otpauth url should parse");
+    uri.path_segments_mut()
+        .expect("The otpauth url has a path")
+        .pop_if_empty()
+        .push(&format!("{issuer}:{}", user.email));
+    uri.query_pairs_mut()
+        .append_pair("secret", &secret)
+        .append_pair("issuer", issuer)
+        .append_pair("algorithm", "SHA1")
+        .append_pair("digits", &DIGITS.to_string())
+        .append_pair("period", &STEP.to_string());
+    let qr = QrCode::new(uri.as_str())
+        .map_err(AppError::internal)?
+        .render::<svg::Color>()
+        .min_dimensions(256, 256)
+        .build();
+    Ok(Json(TwoFactorSetup {
+        secret,
+        uri: uri.into(),
+        qr,
+    }))
+}
+
+/// Turns two-factor on once a code from the new secret checks out, and returns the recovery codes.
+pub async fn enable_two_factor(
+    State(state): State<SiteState>,
+    client: Client,
+    user: User,
+    Form(data): Form<TwoFactorCode>,
+) -> Result<Json<Vec<String>>, AppError> {
+    let keys = throttle_keys(&client, &user);
+    state
+        .throttle
+        .check(Scope::SignIn, &keys)
+        .map_err(too_many_attempts)?;
+    let connection = &mut state.connection.get()?;
+    let pending: TwoFactor = two_factor::table
+        .select(TwoFactor::as_select())
+        .filter(two_factor::user_id.eq(user.id))
+        .filter(two_factor::enabled_at.is_null())
+        .get_result(connection)
+        .optional()?
+        .ok_or_else(|| AppError::not_found("Start setting up two-factor first"))?;
+    let Some(step) = matching_step(&pending.secret, data.code.trim(), None) else {
+        state.throttle.hit(Scope::SignIn, &keys);
+        return Err(wrong_code());
+    };
+    let codes = connection.transaction::<_, Error, _>(|connection| {
+        let enabled = diesel::update(two_factor::table)
+            .filter(two_factor::user_id.eq(user.id))
+            .filter(two_factor::enabled_at.is_null())
+            .filter(two_factor::secret.eq(&pending.secret))
+            .set((
+                two_factor::enabled_at.eq(diesel::dsl::now.nullable()),
+                two_factor::last_used_step.eq(step),
+            ))
+            .execute(connection)?;
+        if enabled == 0 {
+            return Ok(None);
+        }
+        issue_recovery_codes(connection, user.id).map(Some)
+    })?;
+    codes.map(Json).ok_or_else(|| {
+        AppError::conflict("Two-factor was set up again in the meantime, use the newest secret")
+    })
+}
+
+/// Takes a code from a signed in user, for turning two-factor off or replacing recovery codes.
+fn confirm_code(
+    state: &SiteState,
+    client: &Client,
+    connection: &mut PgConnection,
+    user: &User,
+    code: &str,
+) -> Result<(), AppError> {
+    let keys = throttle_keys(client, user);
+    state
+        .throttle
+        .check(Scope::SignIn, &keys)
+        .map_err(too_many_attempts)?;
+    let factor = enabled_factor(connection, user.id)?
+        .ok_or_else(|| AppError::not_found("Two-factor is not on"))?;
+    if !redeem_code(connection, user.id, &factor, code, true)? {
+        state.throttle.hit(Scope::SignIn, &keys);
+        return Err(wrong_code());
+    }
+    Ok(())
+}
+
+pub async fn disable_two_factor(
+    State(state): State<SiteState>,
+    client: Client,
+    user: User,
+    Form(data): Form<TwoFactorCode>,
+) -> Result<(), AppError> {
+    let connection = &mut state.connection.get()?;
+    if is_required(connection, &user.role)? {
+        return Err(AppError::forbidden(format!(
+            "Every {} has to use two-factor",
+            user.role
+        )));
+    }
+    confirm_code(&state, &client, connection, &user, &data.code)?;
+    connection.transaction::<_, Error, _>(|connection| {
+        diesel::delete(recovery_codes::table.filter(recovery_codes::user_id.eq(user.id)))
+            .execute(connection)?;
+        diesel::delete(two_factor::table.filter(two_factor::user_id.eq(user.id)))
+            .execute(connection)?;
+        Ok(())
+    })?;
+    Ok(())
+}
+
+/// Replaces the recovery codes, for when they ran low or were lost.
+pub async fn regenerate_recovery_codes(
+    State(state): State<SiteState>,
+    client: Client,
+    user: User,
+    Form(data): Form<TwoFactorCode>,
+) -> Result<Json<Vec<String>>, AppError> {
+    let connection = &mut state.connection.get()?;
+    confirm_code(&state, &client, connection, &user, &data.code)?;
+    Ok(Json(connection.transaction(|connection| {
+        issue_recovery_codes(connection, user.id)
+    })?))
+}
+
+/// Roles that have to use two-factor.
+pub async fn get_required_roles(
+    State(state): State<SiteState>,
+    user: User,
+) -> Result<Json<Vec<Role>>, AppError> {
+    require_super_admin(&state, &user)?;
+    Ok(Json(
+        two_factor_required_roles::table
+            .select(two_factor_required_roles::role)
+            .load(&mut state.connection.get()?)?,
+    ))
+}
+
+/// Requires two-factor for a privileged role, or stops requiring it.
+pub async fn set_required_role(
+    State(state): State<SiteState>,
+    user: User,
+    Form(data): Form<RequiredRole>,
+) -> Result<(), AppError> {
+    require_super_admin(&state, &user)?;
+    if let Role::PARTICIPANT = data.role {
+        return Err(AppError::invalid(
+            "role",
+            "Only privileged roles can be required to use two-factor",
+        ));
+    }
+    let connection = &mut state.connection.get()?;
+    if data.required {
+        diesel::insert_into(two_factor_required_roles::table)
+            .values((
+                two_factor_required_roles::role.eq(data.role),
+                two_factor_required_roles::required_by.eq(user.id),
+            ))
+            .on_conflict_do_nothing()
+            .execute(connection)?;
+    } else {
+        diesel::delete(
+            two_factor_required_roles::table.filter(two_factor_required_roles::role.eq(data.role)),
+        )
+        .execute(connection)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The shared secret of the RFC 6238 test vectors
+    const RFC_SECRET: &[u8] = b"12345678901234567890";
+
+    fn code_at_time(secret: &[u8], unix_time: u64) -> String {
+        format!("{:06}", code_at(secret, unix_time / STEP))
+    }
+
+    #[test]
+    fn base32_matches_rfc_4648() {
+        for (plain, encoded) in [
+            ("", ""),
+            ("f", "MY"),
+            ("fo", "MZXQ"),
+            ("foo", "MZXW6"),
+            ("foob", "MZXW6YQ"),
+            ("fooba", "MZXW6YTB"),
+            ("foobar", "MZXW6YTBOI"),
+        ] {
+            assert_eq!(base32(plain.as_bytes()), encoded);
+        }
+    }
+
+    /// The SHA1 vectors of RFC 6238 appendix B, cut to our six digits.
+    #[test]
+    fn code_matches_rfc_6238() {
+        for (unix_time, code) in [
+            (59, "287082"),
+            (1111111109, "081804"),
+            (1111111111, "050471"),
+            (1234567890, "005924"),
+            (2000000000, "279037"),
+            (20000000000, "353130"),
+        ] {
+            assert_eq!(code_at_time(RFC_SECRET, unix_time), code, "at {unix_time}");
+        }
+    }
+
+    #[test]
+    fn accepts_codes_within_the_skew() {
+        let now = 1234567890 / STEP;
+        for step in [now - SKEW, now, now + SKEW] {
+            let code = format!("{:06}", code_at(RFC_SECRET, step));
+            assert_eq!(
+                matching_step_at(RFC_SECRET, &code, None, now),
+                Some(step as i64)
+            );
+        }
+        for step in [now - SKEW - 1, now + SKEW + 1] {
+            let code = format!("{:06}", code_at(RFC_SECRET, step));
+            assert_eq!(matching_step_at(RFC_SECRET, &code, None, now), None);
+        }
+    }
+
+    #[test]
+    fn refuses_replayed_and_older_codes() {
+        let now = 1234567890 / STEP;
+        let code = format!("{:06}", code_at(RFC_SECRET, now));
+        let used = Some(now as i64);
+        assert_eq!(matching_step_at(RFC_SECRET, &code, used, now), None);
+        assert_eq!(
+            matching_step_at(RFC_SECRET, &code, Some(now as i64 + 1), now),
+            None
+        );
+        assert_eq!(
+            matching_step_at(RFC_SECRET, &code, Some(now as i64 - 1), now),
+            Some(now as i64)
+        );
+    }
+
+    #[test]
+    fn refuses_malformed_codes() {
+        let now = 1234567890 / STEP;
+        for code in ["", "12345", "1234567", "12345a", " 05924"] {
+            assert_eq!(matching_step_at(RFC_SECRET, code, None, now), None);
+        }
+    }
+
+    #[test]
+    fn recovery_codes_ignore_dashes_spaces_and_case() {
+        let code = new_recovery_code();
+        assert_eq!(code.len(), 19);
+        assert_eq!(
+            normalize_recovery_code(&code.to_uppercase().replace('-', " ")),
+            normalize_recovery_code(&code)
+        );
+    }
+}
@@ -0,0 +1,275 @@
+//! Seat accounting for events and workshops that have a capacity.
+//!
+//! A join puts the user or team at the back of the waitlist and then fills the free seats from
+//! its front, so the waitlist is always served in the order people joined it. Whenever seats free
+//! up, because someone left or the capacity was raised, the oldest waitlisted entries are moved
+//! into the participation table the same way. Nobody is moved in once registration has closed, and
+//! a team is passed over while its size does not fit the event, keeping its place until it does.
+//!
+//! The three kinds of seats, [`EventSeats`], [`TeamEventSeats`] and [`WorkshopSeats`], only differ
+//! in their tables, see [`SeatSource`]. Every function here expects to run inside a transaction,
+//! the capacity row is locked with `FOR UPDATE` so two concurrent joins can not both take the last
+//! seat.
+use diesel::prelude::*;
+
+use crate::{
+    models::events::JoinStatus,
+    outbox::{mail_teams, mail_users},
+    registration::{check_team_size, Schedule},
+    schema::{
+        events, individual_event_participation, individual_event_waitlist,
+        team_event_participations, team_event_waitlist, workshop_participation, workshop_waitlist,
+        workshops,
+    },
+    state::SiteState,
+    team::{lock_team, team_size},
+};
+
+/// The capacity, participation table and waitlist seats are taken from. Holders are user ids, or
+/// team ids for team events.
+pub trait SeatSource {
+    /// Locks the row the capacity is on. `None` is unlimited.
+    fn capacity(&self, connection: &mut PgConnection) -> QueryResult<Option<i32>>;
+    fn schedule(&self, connection: &mut PgConnection) -> QueryResult<Schedule>;
+    fn holds_seat(&self, connection: &mut PgConnection, holder: i32) -> QueryResult<bool>;
+    fn taken(&self, connection: &mut PgConnection) -> QueryResult<i64>;
+    /// Everyone waiting, oldest first.
+    fn queue(&self, connection: &mut PgConnection) -> QueryResult<Vec<i32>>;
+    fn seat(&self, connection: &mut PgConnection, holders: &[i32]) -> QueryResult<usize>;
+    fn unseat(&self, connection: &mut PgConnection, holder: i32) -> QueryResult<usize>;
+    /// `0` when the holder is already waiting.
+    fn enqueue(&self, connection: &mut PgConnection, holder: i32) -> QueryResult<usize>;
+    fn dequeue(&self, connection: &mut PgConnection, holders: &[i32]) -> QueryResult<usize>;
+    /// Whether the holder may have a seat at all, the registration window aside.
+    fn admits(&self, connection: &mut PgConnection, holder: i32) -> QueryResult<bool>;
+}
+
+/// Seats of a team-less event.
+pub struct EventSeats(pub i32);
+/// Slots of a team event, held by teams.
+pub struct TeamEventSeats(pub i32);
+pub struct WorkshopSeats(pub i32);
+
+/// Implements the table bound part of [`SeatSource`], the same for every kind of seat.
+macro_rules! seat_tables {
+    ($parent:ident::$capacity:ident, $schedule:path, $seats:ident, $waitlist:ident, $of:ident, $holder:ident) => {
+        fn capacity(&self, connection: &mut PgConnection) -> QueryResult<Option<i32>> {
+            $parent::table
+                .select($parent::$capacity)
+                .find(self.0)
+                .for_update()
+                .get_result(connection)
+        }
+
+        fn schedule(&self, connection: &mut PgConnection) -> QueryResult<Schedule> {
+            $schedule(connection, self.0)
+        }
+
+        fn holds_seat(&self, connection: &mut PgConnection, holder: i32) -> QueryResult<bool> {
+            diesel::select(diesel::dsl::exists(
+                $seats::table
+                    .filter($seats::$of.eq(self.0))
+                    .filter($seats::$holder.eq(holder)),
+            ))
+            .get_result(connection)
+        }
+
+        fn taken(&self, connection: &mut PgConnection) -> QueryResult<i64> {
+            $seats::table
+                .filter($seats::$of.eq(self.0))
+                .count()
+                .get_result(connection)
+        }
+
+        fn queue(&self, connection: &mut PgConnection) -> QueryResult<Vec<i32>> {
+            $waitlist::table
+                .select($waitlist::$holder)
+                .filter($waitlist::$of.eq(self.0))
+                .order($waitlist::joined_at.asc())
+                .load(connection)
+        }
+
+        fn seat(&self, connection: &mut PgConnection, holders: &[i32]) -> QueryResult<usize> {
+            diesel::insert_into($seats::table)
+                .values(
+                    holders
+                        .iter()
+                        .map(|&holder| ($seats::$of.eq(self.0), $seats::$holder.eq(holder)))
+                        .collect::<Vec<_>>(),
+                )
+                .execute(connection)
+        }
+
+        fn unseat(&self, connection: &mut PgConnection, holder: i32) -> QueryResult<usize> {
+            diesel::delete($seats::table)
+                .filter($seats::$of.eq(self.0))
+                .filter($seats::$holder.eq(holder))
+                .execute(connection)
+        }
+
+        fn enqueue(&self, connection: &mut PgConnection, holder: i32) -> QueryResult<usize> {
+            diesel::insert_into($waitlist::table)
+                .values(($waitlist::$of.eq(self.0), $waitlist::$holder.eq(holder)))
+                .on_conflict_do_nothing()
+                .execute(connection)
+        }
+
+        fn dequeue(&self, connection: &mut PgConnection, holders: &[i32]) -> QueryResult<usize> {
+            diesel::delete($waitlist::table)
+                .filter($waitlist::$of.eq(self.0))
+                .filter($waitlist::$holder.eq_any(holders))
+                .execute(connection)
+        }
+    };
+}
+
+impl SeatSource for EventSeats {
+    seat_tables!(
+        events::max_participants,
+        Schedule::of_event,
+        individual_event_participation,
+        individual_event_waitlist,
+        event_id,
+        user_id
+    );
+
+    fn admits(&self, _: &mut PgConnection, _: i32) -> QueryResult<bool> {
+        Ok(true)
+    }
+}
+
+impl SeatSource for TeamEventSeats {
+    seat_tables!(
+        events::max_teams,
+        Schedule::of_event,
+        team_event_participations,
+        team_event_waitlist,
+        event_id,
+        team_id
+    );
+
+    /// The team's size has to fit the event, with its pending requests where the event counts them.
+    /// The team row is locked so members can not come or go until the seat is settled.
+    fn admits(&self, connection: &mut PgConnection, team_id: i32) -> QueryResult<bool> {
+        lock_team(connection, team_id)?;
+        let (name, min_team_size, max_team_size, count_pending_requests): (String, i32, i32, bool) =
+            events::table
+                .select((
+                    events::name,
+                    events::min_team_size,
+                    events::max_team_size,
+                    events::count_pending_requests,
+                ))
+                .find(self.0)
+                .get_result(connection)?;
+        let size = team_size(connection, team_id, count_pending_requests)?;
+        Ok(check_team_size(&name, size, min_team_size, max_team_size).is_ok())
+    }
+}
+
+impl SeatSource for WorkshopSeats {
+    seat_tables!(
+        workshops::max_participants,
+        Schedule::of_workshop,
+        workshop_participation,
+        workshop_waitlist,
+        workshop_id,
+        user_id
+    );
+
+    fn admits(&self, _: &mut PgConnection, _: i32) -> QueryResult<bool> {
+        Ok(true)
+    }
+}
+
+fn free_seats(max: Option<i32>, taken: i64) -> i64 {
+    match max {
+        Some(max) => (i64::from(max) - taken).max(0),
+        None => i64::MAX,
+    }
+}
+
+/// What a join came to.
+pub struct Taken {
+    pub status: JoinStatus,
+    /// Others who were waiting ahead and got a seat along the way, as they only fit now.
+    pub promoted: Vec<i32>,
+}
+
+/// Queues the holder and fills the free seats. `None` when they are already registered or
+/// already waiting.
+pub fn take_seat(
+    connection: &mut PgConnection,
+    source: &impl SeatSource,
+    holder: i32,
+) -> QueryResult<Option<Taken>> {
+    source.capacity(connection)?;
+    if source.holds_seat(connection, holder)? || source.enqueue(connection, holder)? == 0 {
+        return Ok(None);
+    }
+    let mut promoted = fill_seats(connection, source)?;
+    let status = match promoted.iter().position(|&id| id == holder) {
+        Some(index) => {
+            promoted.remove(index);
+            JoinStatus::JOINED
+        }
+        None => JoinStatus::WAITLISTED,
+    };
+    Ok(Some(Taken { status, promoted }))
+}
+
+/// Moves waitlisted holders into the free seats, oldest first, and returns who got in. Nobody gets
+/// in outside the registration window, and holders [`SeatSource::admits`] turns away keep waiting.
+pub fn fill_seats(
+    connection: &mut PgConnection,
+    source: &impl SeatSource,
+) -> QueryResult<Vec<i32>> {
+    let capacity = source.capacity(connection)?;
+    if source.schedule(connection)?.check_join().is_err() {
+        return Ok(Vec::new());
+    }
+    let mut free = free_seats(capacity, source.taken(connection)?);
+    let mut promoted = Vec::new();
+    for holder in source.queue(connection)? {
+        if free == 0 {
+            break;
+        }
+        if source.admits(connection, holder)? {
+            promoted.push(holder);
+            free -= 1;
+        }
+    }
+    if !promoted.is_empty() {
+        source.dequeue(connection, &promoted)?;
+        source.seat(connection, &promoted)?;
+    }
+    Ok(promoted)
+}
+
+/// Gives up a seat, or the place on the waitlist, and returns who was promoted into it.
+pub fn release_seat(
+    connection: &mut PgConnection,
+    source: &impl SeatSource,
+    holder: i32,
+) -> QueryResult<Vec<i32>> {
+    source.capacity(connection)?;
+    if source.unseat(connection, holder)? == 0 {
+        source.dequeue(connection, &[holder])?;
+        return Ok(Vec::new());
+    }
+    fill_seats(connection, source)
+}
+
+/// Queues a mail for everyone in `user_ids` that they got a seat for `title`.
+pub fn notify_promoted_users(state: &SiteState, user_ids: &[i32], title: &str) {
+    mail_users(state, user_ids, |user| {
+        user.waitlist_promotion_email(&state.templates, title)
+    });
+}
+
+/// Same as [`notify_promoted_users`] for every member of the promoted teams.
+pub fn notify_promoted_teams(state: &SiteState, team_ids: &[i32], title: &str) {
+    mail_teams(state, team_ids, |user| {
+        user.waitlist_promotion_email(&state.templates, title)
+    });
+}
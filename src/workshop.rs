@@ -9,11 +9,13 @@ use axum::{
 use axum_macros::debug_handler;
 use base64::{prelude::BASE64_URL_SAFE_NO_PAD, Engine};
 use diesel::prelude::*;
+use diesel::result::Error;
 use highway::HighwayHash;
-use http::{header, HeaderMap, StatusCode};
+use http::{header, HeaderMap};
 use tokio_util::io::ReaderStream;
 
 use crate::{
+    error::AppError,
     forms::{
         users::Profile,
         workshops::{
@@ -22,86 +24,51 @@ use crate::{
         },
     },
     models::{
+        events::JoinStatus,
+        points::Attendance,
         students::{Student, StudentResponse},
-        users::{Role, User},
+        users::User,
         workshops::Workshop,
     },
+    outbox::{enqueue, mail_users},
+    points::set_attendance,
+    policy::{authorize, Action},
+    registration::Schedule,
+    roles::{appoint, dismiss, Appointment},
     schema::{
-        domains, faculty_coordinators, student_domain_coordinators, student_workshop_coordinators,
-        students, users, workshop_participation, workshops,
+        student_workshop_coordinators, students, users, workshop_participation, workshop_waitlist,
+        workshops,
     },
     state::SiteState,
+    waitlist::{fill_seats, notify_promoted_users, release_seat, take_seat, WorkshopSeats},
 };
 
-pub async fn get_workshop(
-    State(state): State<SiteState>,
-) -> Result<Json<Vec<Workshop>>, StatusCode> {
-    workshops::table
-        .select(Workshop::as_select())
-        .get_results(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map(|v| Json(v))
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })
+pub async fn get_workshop(State(state): State<SiteState>) -> Result<Json<Vec<Workshop>>, AppError> {
+    Ok(Json(
+        workshops::table
+            .select(Workshop::as_select())
+            .get_results(&mut state.connection.get()?)?,
+    ))
 }
 
 pub async fn create_workshop(
     State(state): State<SiteState>,
     user: User,
     Form(data): Form<CreateWorkshop>,
-) -> Result<Json<Workshop>, StatusCode> {
-    if !user.verified || !user.is_payment_done(&state.connection) {
-        return Err(StatusCode::UNAUTHORIZED);
-    }
-    log::info!("{:?}", user);
-    match user.role {
-        Role::SUPER_ADMIN => {}
-        Role::FACULTY_COORDINATOR => {
-            let _: i32 = faculty_coordinators::table
-                .select(faculty_coordinators::domain_id)
-                .filter(faculty_coordinators::domain_id.eq(data.domain_id))
-                .filter(faculty_coordinators::faculty_id.eq(user.id))
-                .get_result(&mut state.connection.get().map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::INTERNAL_SERVER_ERROR
-                })?)
-                .map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::UNAUTHORIZED
-                })?;
-        }
-        Role::STUDENT_COORDINATOR => {
-            let _: i32 = workshops::table
-                .inner_join(domains::table.inner_join(student_domain_coordinators::table))
-                .select(student_domain_coordinators::domain_id)
-                .filter(student_domain_coordinators::domain_id.eq(workshops::domain_id))
-                .filter(student_domain_coordinators::student_id.eq(user.id))
-                .get_result(&mut state.connection.get().map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::INTERNAL_SERVER_ERROR
-                })?)
-                .map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::UNAUTHORIZED
-                })?;
-        }
-        _ => return Err(StatusCode::UNAUTHORIZED),
-    }
-    data.insert_into(workshops::table)
-        .returning(Workshop::as_returning())
-        .get_result(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map(|v| Json(v))
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })
+) -> Result<Json<Workshop>, AppError> {
+    authorize(
+        &state,
+        &user,
+        Action::CreateWorkshop {
+            domain_id: data.domain_id,
+        },
+    )?;
+    data.validate()?;
+    Ok(Json(
+        data.insert_into(workshops::table)
+            .returning(Workshop::as_returning())
+            .get_result(&mut state.connection.get()?)?,
+    ))
 }
 
 #[debug_handler]
@@ -109,135 +76,82 @@ pub async fn delete_workshop(
     State(state): State<SiteState>,
     user: User,
     Form(data): Form<DeleteWorkshop>,
-) -> Result<Json<Workshop>, StatusCode> {
-    if !user.verified || !user.is_payment_done(&state.connection) {
-        return Err(StatusCode::UNAUTHORIZED);
-    }
-    match user.role {
-        Role::SUPER_ADMIN => {}
-        Role::FACULTY_COORDINATOR => {
-            let _: i32 = workshops::table
-                .inner_join(domains::table.inner_join(faculty_coordinators::table))
-                .select(faculty_coordinators::domain_id)
-                .filter(faculty_coordinators::domain_id.eq(workshops::domain_id))
-                .filter(faculty_coordinators::faculty_id.eq(user.id))
-                .get_result(&mut state.connection.get().map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::INTERNAL_SERVER_ERROR
-                })?)
-                .map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::UNAUTHORIZED
-                })?;
-        }
-        Role::STUDENT_COORDINATOR => {
-            let _: i32 = workshops::table
-                .inner_join(domains::table.inner_join(student_domain_coordinators::table))
-                .select(student_domain_coordinators::domain_id)
-                .filter(student_domain_coordinators::domain_id.eq(workshops::domain_id))
-                .filter(student_domain_coordinators::student_id.eq(user.id))
-                .get_result(&mut state.connection.get().map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::INTERNAL_SERVER_ERROR
-                })?)
-                .map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::UNAUTHORIZED
-                })?;
-        }
-        _ => return Err(StatusCode::UNAUTHORIZED),
-    }
+) -> Result<Json<Workshop>, AppError> {
+    authorize(
+        &state,
+        &user,
+        Action::DeleteWorkshop {
+            workshop_id: data.id,
+        },
+    )?;
     diesel::delete(workshops::table)
         .filter(workshops::id.eq(data.id))
         .returning(Workshop::as_returning())
-        .get_result(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map(|v| Json(v))
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })
+        .get_result(&mut state.connection.get()?)
+        .optional()?
+        .map(Json)
+        .ok_or_else(|| AppError::not_found("No such workshop"))
 }
 
 pub async fn change_workshop(
     State(state): State<SiteState>,
     user: User,
     Form(data): Form<ChangeWorkshop>,
-) -> Result<Json<Workshop>, StatusCode> {
-    if !user.verified || !user.is_payment_done(&state.connection) {
-        return Err(StatusCode::UNAUTHORIZED);
-    }
-    match user.role {
-        Role::SUPER_ADMIN => {}
-        Role::FACULTY_COORDINATOR => {
-            let _: i32 = workshops::table
-                .inner_join(domains::table.inner_join(faculty_coordinators::table))
-                .select(faculty_coordinators::domain_id)
-                .filter(faculty_coordinators::domain_id.eq(workshops::domain_id))
-                .filter(faculty_coordinators::faculty_id.eq(user.id))
-                .get_result(&mut state.connection.get().map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::INTERNAL_SERVER_ERROR
-                })?)
-                .map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::UNAUTHORIZED
-                })?;
-        }
-        Role::STUDENT_COORDINATOR => {
-            let student_domain_id: Result<i32, StatusCode> = workshops::table
-                .inner_join(domains::table.inner_join(student_domain_coordinators::table))
-                .select(student_domain_coordinators::domain_id)
-                .filter(student_domain_coordinators::domain_id.eq(workshops::domain_id))
-                .filter(student_domain_coordinators::student_id.eq(user.id))
-                .get_result(&mut state.connection.get().map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::INTERNAL_SERVER_ERROR
-                })?)
-                .map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::UNAUTHORIZED
-                });
-            let student_workshop_id: Result<i32, StatusCode> = workshops::table
-                .inner_join(student_workshop_coordinators::table)
-                .select(student_workshop_coordinators::workshop_id)
-                .filter(student_workshop_coordinators::workshop_id.eq(data.id))
-                .filter(student_workshop_coordinators::student_id.eq(user.id))
-                .get_result(&mut state.connection.get().map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::INTERNAL_SERVER_ERROR
-                })?)
-                .map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::UNAUTHORIZED
-                });
-            match student_domain_id {
-                Ok(_) => {}
-                Err(_) => match student_workshop_id {
-                    Ok(_) => {}
-                    Err(e) => {
-                        return Err(e);
-                    }
-                },
-            }
+) -> Result<Json<Workshop>, AppError> {
+    authorize(
+        &state,
+        &user,
+        Action::UpdateWorkshop {
+            workshop_id: data.id,
+        },
+    )?;
+    let capacity_changed = data.max_participants.is_some();
+    let connection = &mut state.connection.get()?;
+    let current = workshops::table
+        .select(Workshop::as_select())
+        .filter(workshops::id.eq(data.id))
+        .get_result(connection)
+        .optional()?
+        .ok_or_else(|| AppError::not_found("No such workshop"))?;
+    data.validate(&current)?;
+    // Filled with the update, so nobody is promoted into seats that were not given after all
+    let (workshop, promoted) = connection.transaction(|connection| {
+        let workshop = diesel::update(workshops::table)
+            .filter(workshops::id.eq(data.id))
+            .set(data)
+            .returning(Workshop::as_returning())
+            .get_result(connection)?;
+        let promoted = match capacity_changed {
+            true => fill_seats(connection, &WorkshopSeats(workshop.id))?,
+            false => Vec::new(),
+        };
+        Ok::<_, Error>((workshop, promoted))
+    })?;
+    notify_promoted_users(&state, &promoted, &workshop.name);
+    if current.mode != workshop.mode
+        || current.venue != workshop.venue
+        || current.start_time != workshop.start_time
+        || current.end_time != workshop.end_time
+    {
+        match workshop_participation::table
+            .select(workshop_participation::user_id)
+            .filter(workshop_participation::workshop_id.eq(workshop.id))
+            .load::<i32>(connection)
+        {
+            Ok(users) => mail_users(&state, &users, |user| {
+                user.event_changed_email(
+                    &state.templates,
+                    &workshop.name,
+                    &workshop.mode,
+                    workshop.start_time,
+                    workshop.end_time,
+                    &workshop.venue,
+                )
+            }),
+            Err(e) => log::error!("{e:?}"),
         }
-        _ => return Err(StatusCode::UNAUTHORIZED),
     }
-    diesel::update(workshops::table)
-        .filter(workshops::id.eq(data.id))
-        .set(data)
-        .returning(Workshop::as_returning())
-        .get_result(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map(|v| Json(v))
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })
+    Ok(Json(workshop))
 }
 
 // Not Deleting the image in case some other user also happens to have the same exact image
@@ -246,75 +160,20 @@ pub async fn set_workshop_photo(
     user: User,
     Query(data): Query<WorkshopId>,
     photo: Bytes,
-) -> Result<(), StatusCode> {
-    match user.role {
-        Role::SUPER_ADMIN => {}
-        Role::FACULTY_COORDINATOR => {
-            let _: i32 = workshops::table
-                .inner_join(domains::table.inner_join(faculty_coordinators::table))
-                .select(faculty_coordinators::domain_id)
-                .filter(faculty_coordinators::domain_id.eq(workshops::domain_id))
-                .filter(faculty_coordinators::faculty_id.eq(user.id))
-                .get_result(&mut state.connection.get().map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::INTERNAL_SERVER_ERROR
-                })?)
-                .map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::UNAUTHORIZED
-                })?;
-        }
-        Role::STUDENT_COORDINATOR => {
-            let student_domain_id: Result<i32, StatusCode> = workshops::table
-                .inner_join(domains::table.inner_join(student_domain_coordinators::table))
-                .select(student_domain_coordinators::domain_id)
-                .filter(student_domain_coordinators::domain_id.eq(workshops::domain_id))
-                .filter(student_domain_coordinators::student_id.eq(user.id))
-                .get_result(&mut state.connection.get().map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::INTERNAL_SERVER_ERROR
-                })?)
-                .map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::UNAUTHORIZED
-                });
-            let student_workshop_id: Result<i32, StatusCode> = workshops::table
-                .inner_join(student_workshop_coordinators::table)
-                .select(student_workshop_coordinators::workshop_id)
-                .filter(student_workshop_coordinators::workshop_id.eq(data.id))
-                .filter(student_workshop_coordinators::student_id.eq(user.id))
-                .get_result(&mut state.connection.get().map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::INTERNAL_SERVER_ERROR
-                })?)
-                .map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::UNAUTHORIZED
-                });
-            match student_domain_id {
-                Ok(_) => {}
-                Err(_) => match student_workshop_id {
-                    Ok(_) => {}
-                    Err(e) => {
-                        return Err(e);
-                    }
-                },
-            }
-        }
-        _ => return Err(StatusCode::UNAUTHORIZED),
-    }
+) -> Result<(), AppError> {
+    authorize(
+        &state,
+        &user,
+        Action::UpdateWorkshop {
+            workshop_id: data.id,
+        },
+    )?;
     let hash = state.bulk_hasher.hash256(photo.to_vec().as_slice());
     let photo = image::ImageReader::new(Cursor::new(photo))
         .with_guessed_format()
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::BAD_REQUEST
-        })?
+        .map_err(AppError::internal)?
         .decode()
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::BAD_REQUEST
-        })?;
+        .map_err(|e| AppError::invalid("photo", format!("Not a readable image: {e}")))?;
     let photo = photo.thumbnail(512, 512);
     photo
         .save_with_format(
@@ -330,12 +189,9 @@ pub async fn set_workshop_photo(
             ),
             image::ImageFormat::Avif,
         )
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::BAD_REQUEST
-        })?;
-    diesel::update(workshops::table)
-        .filter(workshops::id.eq(user.id))
+        .map_err(AppError::internal)?;
+    let updated = diesel::update(workshops::table)
+        .filter(workshops::id.eq(data.id))
         .set(
             workshops::photo_hash.eq(hash
                 .map(|v| v.to_le_bytes())
@@ -343,14 +199,10 @@ pub async fn set_workshop_photo(
                 .flatten()
                 .collect::<Vec<u8>>()),
         )
-        .execute(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::BAD_REQUEST
-        })?;
+        .execute(&mut state.connection.get()?)?;
+    if updated == 0 {
+        return Err(AppError::not_found("No such workshop"));
+    }
     Ok(())
 }
 
@@ -358,36 +210,23 @@ pub async fn set_workshop_photo(
 pub async fn get_workshop_photo(
     State(state): State<SiteState>,
     Query(data): Query<WorkshopId>,
-) -> impl IntoResponse {
+) -> Result<impl IntoResponse, AppError> {
     // `File` implements `AsyncRead`
-    let photo_hash = workshops::table
+    let photo_hash: Vec<u8> = workshops::table
         .select(workshops::photo_hash)
         .filter(workshops::id.eq(data.id))
-        .get_result(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::NOT_FOUND
-        })?;
-    let photo_hash: Vec<u8> = match photo_hash {
-        None => return Err(StatusCode::NOT_FOUND),
-        Some(v) => v,
-    };
-    let file = match tokio::fs::File::open(format!(
+        .get_result::<Option<Vec<u8>>>(&mut state.connection.get()?)?
+        .ok_or_else(|| AppError::not_found("The workshop has no photo"))?;
+    let file = tokio::fs::File::open(format!(
         "{}/{}",
         state.image_dir.to_string_lossy(),
         BASE64_URL_SAFE_NO_PAD.encode(photo_hash)
     ))
     .await
-    {
-        Ok(file) => file,
-        Err(e) => {
-            log::error!("{e:?}");
-            return Err(StatusCode::NOT_FOUND);
-        }
-    };
+    .map_err(|e| {
+        log::error!("{e:?}");
+        AppError::not_found("The photo is missing")
+    })?;
     let stream = ReaderStream::new(file);
     let body = Body::from_stream(stream);
 
@@ -405,219 +244,112 @@ pub async fn get_workshop_photo(
 pub async fn get_workshop_coordinator(
     State(state): State<SiteState>,
     Query(data): Query<GetWorkshopStudentCoordinator>,
-) -> Result<Json<Vec<StudentResponse>>, StatusCode> {
-    student_workshop_coordinators::table
+) -> Result<Json<Vec<StudentResponse>>, AppError> {
+    let coordinators: Vec<(Student, Profile)> = student_workshop_coordinators::table
         .inner_join(workshops::table)
         .inner_join(students::table.inner_join(users::table))
         .filter(workshops::id.eq(data.id))
         .select((Student::as_select(), Profile::as_select()))
-        .get_results(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::NOT_FOUND
-        })?)
-        .map(|v: Vec<(Student, Profile)>| {
-            Json(
-                v.into_iter()
-                    .map(|(student, profile)| StudentResponse { student, profile })
-                    .collect::<Vec<StudentResponse>>(),
-            )
-        })
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::NOT_FOUND
-        })
+        .get_results(&mut state.connection.get()?)?;
+    Ok(Json(
+        coordinators
+            .into_iter()
+            .map(|(student, profile)| StudentResponse { student, profile })
+            .collect(),
+    ))
 }
 
+/// Also makes them a student coordinator, see [`crate::roles`].
 pub async fn add_workshop_coordinator(
     State(state): State<SiteState>,
     user: User,
     Form(data): Form<AddWorkshopStudentCoordinator>,
-) -> Result<(), StatusCode> {
-    if !user.verified || !user.is_payment_done(&state.connection) {
-        return Err(StatusCode::UNAUTHORIZED);
-    }
-    match user.role {
-        Role::SUPER_ADMIN => {}
-        Role::FACULTY_COORDINATOR => {
-            let _: i32 = workshops::table
-                .inner_join(domains::table.inner_join(faculty_coordinators::table))
-                .select(faculty_coordinators::domain_id)
-                .filter(faculty_coordinators::domain_id.eq(workshops::domain_id))
-                .filter(faculty_coordinators::faculty_id.eq(user.id))
-                .get_result(&mut state.connection.get().map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::INTERNAL_SERVER_ERROR
-                })?)
-                .map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::UNAUTHORIZED
-                })?;
-        }
-        _ => return Err(StatusCode::UNAUTHORIZED),
-    }
-    data.insert_into(student_workshop_coordinators::table)
-        .execute(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::BAD_REQUEST
-        })?;
-    Ok(())
+) -> Result<(), AppError> {
+    authorize(
+        &state,
+        &user,
+        Action::AppointWorkshopCoordinator {
+            workshop_id: data.workshop_id,
+        },
+    )?;
+    let connection = &mut state.connection.get()?;
+    appoint(
+        connection,
+        data.student_id,
+        Appointment::Student,
+        |connection| {
+            data.clone()
+                .insert_into(student_workshop_coordinators::table)
+                .execute(connection)
+        },
+    )
+}
+
+/// Lowers them to a participant when it was the last thing they coordinated.
+pub async fn remove_workshop_coordinator(
+    State(state): State<SiteState>,
+    user: User,
+    Form(data): Form<AddWorkshopStudentCoordinator>,
+) -> Result<(), AppError> {
+    authorize(
+        &state,
+        &user,
+        Action::AppointWorkshopCoordinator {
+            workshop_id: data.workshop_id,
+        },
+    )?;
+    let connection = &mut state.connection.get()?;
+    dismiss(connection, data.student_id, |connection| {
+        diesel::delete(student_workshop_coordinators::table)
+            .filter(student_workshop_coordinators::student_id.eq(data.student_id))
+            .filter(student_workshop_coordinators::workshop_id.eq(data.workshop_id))
+            .execute(connection)
+    })
 }
 
 pub async fn get_workshop_attendance(
     State(state): State<SiteState>,
     user: User,
     Query(data): Query<WorkshopId>,
-) -> Result<Json<Vec<i32>>, StatusCode> {
-    match user.role {
-        Role::SUPER_ADMIN => {}
-        Role::FACULTY_COORDINATOR => {
-            let _: i32 = workshops::table
-                .inner_join(domains::table.inner_join(faculty_coordinators::table))
-                .select(faculty_coordinators::domain_id)
-                .filter(faculty_coordinators::domain_id.eq(workshops::domain_id))
-                .filter(faculty_coordinators::faculty_id.eq(user.id))
-                .get_result(&mut state.connection.get().map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::INTERNAL_SERVER_ERROR
-                })?)
-                .map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::UNAUTHORIZED
-                })?;
-        }
-        Role::STUDENT_COORDINATOR => {
-            let student_domain_id: Result<i32, StatusCode> = workshops::table
-                .inner_join(domains::table.inner_join(student_domain_coordinators::table))
-                .select(student_domain_coordinators::domain_id)
-                .filter(student_domain_coordinators::domain_id.eq(workshops::domain_id))
-                .filter(student_domain_coordinators::student_id.eq(user.id))
-                .get_result(&mut state.connection.get().map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::INTERNAL_SERVER_ERROR
-                })?)
-                .map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::UNAUTHORIZED
-                });
-            let student_workshop_id: Result<i32, StatusCode> = workshops::table
-                .inner_join(student_workshop_coordinators::table)
-                .select(student_workshop_coordinators::workshop_id)
-                .filter(student_workshop_coordinators::workshop_id.eq(data.id))
-                .filter(student_workshop_coordinators::student_id.eq(user.id))
-                .get_result(&mut state.connection.get().map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::INTERNAL_SERVER_ERROR
-                })?)
-                .map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::UNAUTHORIZED
-                });
-            match student_domain_id {
-                Ok(_) => {}
-                Err(_) => match student_workshop_id {
-                    Ok(_) => {}
-                    Err(e) => {
-                        return Err(e);
-                    }
-                },
-            }
-        }
-        _ => return Err(StatusCode::UNAUTHORIZED),
-    };
-    workshop_participation::table
-        .select(workshop_participation::user_id)
-        .filter(workshop_participation::workshop_id.eq(data.id))
-        .load::<i32>(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map(|v| Json(v))
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })
+) -> Result<Json<Vec<i32>>, AppError> {
+    authorize(
+        &state,
+        &user,
+        Action::MarkWorkshopAttendance {
+            workshop_id: data.id,
+        },
+    )?;
+    Ok(Json(
+        workshop_participation::table
+            .select(workshop_participation::user_id)
+            .filter(workshop_participation::workshop_id.eq(data.id))
+            .load::<i32>(&mut state.connection.get()?)?,
+    ))
 }
 
 pub async fn mark_workshop_attendance(
     State(state): State<SiteState>,
     user: User,
     Form(data): Form<WorkshopIndividualAttendance>,
-) -> Result<(), StatusCode> {
-    if !user.verified || !user.is_payment_done(&state.connection) {
-        return Err(StatusCode::UNAUTHORIZED);
-    }
-    match user.role {
-        Role::SUPER_ADMIN => {}
-        Role::FACULTY_COORDINATOR => {
-            let _: i32 = workshops::table
-                .inner_join(domains::table.inner_join(faculty_coordinators::table))
-                .select(faculty_coordinators::domain_id)
-                .filter(faculty_coordinators::domain_id.eq(workshops::domain_id))
-                .filter(faculty_coordinators::faculty_id.eq(user.id))
-                .get_result(&mut state.connection.get().map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::INTERNAL_SERVER_ERROR
-                })?)
-                .map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::UNAUTHORIZED
-                })?;
-        }
-        Role::STUDENT_COORDINATOR => {
-            let student_domain_id: Result<i32, StatusCode> = workshops::table
-                .inner_join(domains::table.inner_join(student_domain_coordinators::table))
-                .select(student_domain_coordinators::domain_id)
-                .filter(student_domain_coordinators::domain_id.eq(workshops::domain_id))
-                .filter(student_domain_coordinators::student_id.eq(user.id))
-                .get_result(&mut state.connection.get().map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::INTERNAL_SERVER_ERROR
-                })?)
-                .map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::UNAUTHORIZED
-                });
-            let student_workshop_id: Result<i32, StatusCode> = workshops::table
-                .inner_join(student_workshop_coordinators::table)
-                .select(student_workshop_coordinators::workshop_id)
-                .filter(student_workshop_coordinators::workshop_id.eq(data.workshop_id))
-                .filter(student_workshop_coordinators::student_id.eq(user.id))
-                .get_result(&mut state.connection.get().map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::INTERNAL_SERVER_ERROR
-                })?)
-                .map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::UNAUTHORIZED
-                });
-            match student_domain_id {
-                Ok(_) => {}
-                Err(_) => match student_workshop_id {
-                    Ok(_) => {}
-                    Err(e) => {
-                        return Err(e);
-                    }
-                },
-            }
-        }
-        _ => return Err(StatusCode::UNAUTHORIZED),
-    };
-    diesel::update(workshop_participation::table)
-        .set(workshop_participation::attended.eq(true))
-        .filter(workshop_participation::user_id.eq(data.user_id))
-        .filter(workshop_participation::workshop_id.eq(data.workshop_id))
-        .execute(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?;
+) -> Result<(), AppError> {
+    authorize(
+        &state,
+        &user,
+        Action::MarkWorkshopAttendance {
+            workshop_id: data.workshop_id,
+        },
+    )?;
+    let changed = state.connection.get()?.transaction(|connection| {
+        set_attendance(
+            connection,
+            Attendance::Workshop {
+                workshop_id: data.workshop_id,
+                user_id: data.user_id,
+            },
+            true,
+        )
+    })?;
+    changed.ok_or_else(|| AppError::not_found("Not registered"))?;
     Ok(())
 }
 
@@ -625,141 +357,126 @@ pub async fn remove_workshop_individual_attendance(
     State(state): State<SiteState>,
     user: User,
     Form(data): Form<WorkshopIndividualAttendance>,
-) -> Result<(), StatusCode> {
-    match user.role {
-        Role::SUPER_ADMIN => {}
-        Role::FACULTY_COORDINATOR => {
-            let _: i32 = workshops::table
-                .inner_join(domains::table.inner_join(faculty_coordinators::table))
-                .select(faculty_coordinators::domain_id)
-                .filter(faculty_coordinators::domain_id.eq(workshops::domain_id))
-                .filter(faculty_coordinators::faculty_id.eq(user.id))
-                .get_result(&mut state.connection.get().map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::INTERNAL_SERVER_ERROR
-                })?)
-                .map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::UNAUTHORIZED
-                })?;
-        }
-        Role::STUDENT_COORDINATOR => {
-            let student_domain_id: Result<i32, StatusCode> = workshops::table
-                .inner_join(domains::table.inner_join(student_domain_coordinators::table))
-                .select(student_domain_coordinators::domain_id)
-                .filter(student_domain_coordinators::domain_id.eq(workshops::domain_id))
-                .filter(student_domain_coordinators::student_id.eq(user.id))
-                .get_result(&mut state.connection.get().map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::INTERNAL_SERVER_ERROR
-                })?)
-                .map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::UNAUTHORIZED
-                });
-            let student_workshop_id: Result<i32, StatusCode> = workshops::table
-                .inner_join(student_workshop_coordinators::table)
-                .select(student_workshop_coordinators::workshop_id)
-                .filter(student_workshop_coordinators::workshop_id.eq(data.workshop_id))
-                .filter(student_workshop_coordinators::student_id.eq(user.id))
-                .get_result(&mut state.connection.get().map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::INTERNAL_SERVER_ERROR
-                })?)
-                .map_err(|e| {
-                    log::error!("{e:?}");
-                    StatusCode::UNAUTHORIZED
-                });
-            match student_domain_id {
-                Ok(_) => {}
-                Err(_) => match student_workshop_id {
-                    Ok(_) => {}
-                    Err(e) => {
-                        return Err(e);
-                    }
-                },
-            }
-        }
-        _ => return Err(StatusCode::UNAUTHORIZED),
-    };
-    diesel::update(workshop_participation::table)
-        .set(workshop_participation::attended.eq(false))
-        .filter(workshop_participation::user_id.eq(data.user_id))
-        .filter(workshop_participation::workshop_id.eq(data.workshop_id))
-        .returning(workshop_participation::user_id)
-        .execute(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map(|_| ())
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })
+) -> Result<(), AppError> {
+    authorize(
+        &state,
+        &user,
+        Action::MarkWorkshopAttendance {
+            workshop_id: data.workshop_id,
+        },
+    )?;
+    let changed = state.connection.get()?.transaction(|connection| {
+        set_attendance(
+            connection,
+            Attendance::Workshop {
+                workshop_id: data.workshop_id,
+                user_id: data.user_id,
+            },
+            false,
+        )
+    })?;
+    changed.ok_or_else(|| AppError::not_found("Not registered"))?;
+    Ok(())
 }
 
 pub async fn leave_workshop_individual(
     State(state): State<SiteState>,
     user: User,
     Form(data): Form<WorkshopId>,
-) -> Result<(), StatusCode> {
+) -> Result<(), AppError> {
     if !user.verified || !user.is_payment_done(&state.connection) {
-        return Err(StatusCode::UNAUTHORIZED);
+        return Err(AppError::forbidden(
+            "Verify your email and pay the fee to register",
+        ));
     }
-    diesel::delete(workshop_participation::table)
-        .filter(workshop_participation::user_id.eq(user.id))
-        .filter(workshop_participation::workshop_id.eq(data.id))
-        .execute(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::NOT_MODIFIED
-        })
-        .map(|_| ())
+    let connection = &mut state.connection.get()?;
+    Schedule::of_workshop(connection, data.id)
+        .optional()?
+        .ok_or_else(|| AppError::not_found("No such workshop"))?
+        .check_leave()?;
+    let (name, promoted) = connection.transaction(|connection| {
+        let promoted = release_seat(connection, &WorkshopSeats(data.id), user.id)?;
+        let name: String = workshops::table
+            .select(workshops::name)
+            .filter(workshops::id.eq(data.id))
+            .get_result(connection)?;
+        Ok::<_, Error>((name, promoted))
+    })?;
+    notify_promoted_users(&state, &promoted, &name);
+    Ok(())
 }
 
 pub async fn join_workshop(
     State(state): State<SiteState>,
     user: User,
     Form(data): Form<WorkshopId>,
-) -> Result<(), StatusCode> {
+) -> Result<Json<JoinStatus>, AppError> {
     if !user.verified || !user.is_payment_done(&state.connection) {
-        return Err(StatusCode::UNAUTHORIZED);
+        return Err(AppError::forbidden(
+            "Verify your email and pay the fee to register",
+        ));
     }
-    WorkshopIndividualAttendance {
-        user_id: user.id,
-        workshop_id: data.id,
+    let connection = &mut state.connection.get()?;
+    Schedule::of_workshop(connection, data.id)
+        .optional()?
+        .ok_or_else(|| AppError::not_found("No such workshop"))?
+        .check_join()?;
+    let (workshop, taken) = connection.transaction(|connection| {
+        let taken = take_seat(connection, &WorkshopSeats(data.id), user.id)?;
+        let workshop = workshops::table
+            .select(Workshop::as_select())
+            .filter(workshops::id.eq(data.id))
+            .get_result(connection)?;
+        if let Some(JoinStatus::JOINED) = taken.as_ref().map(|taken| taken.status) {
+            // Queued with the seat, so it is only sent if the seat was really taken
+            enqueue(
+                connection,
+                &[user.registration_confirmation_email(
+                    &state.templates,
+                    &workshop.name,
+                    workshop.start_time,
+                    &workshop.venue,
+                )],
+            )?;
+        }
+        Ok::<_, Error>((workshop, taken))
+    })?;
+    let taken = taken.ok_or_else(|| AppError::conflict("Already registered or on the waitlist"))?;
+    if let JoinStatus::JOINED = taken.status {
+        state.wake_outbox();
     }
-    .insert_into(workshop_participation::table)
-    .execute(&mut state.connection.get().map_err(|e| {
-        log::error!("{e:?}");
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?)
-    .map_err(|e| {
-        log::error!("{e:?}");
-        StatusCode::NOT_MODIFIED
-    })
-    .map(|_| ())
+    notify_promoted_users(&state, &taken.promoted, &workshop.name);
+    Ok(Json(taken.status))
 }
 
+/// Registers a user regardless of the registration window and capacity, for late entries.
+pub async fn register_workshop(
+    State(state): State<SiteState>,
+    user: User,
+    Form(data): Form<WorkshopIndividualAttendance>,
+) -> Result<(), AppError> {
+    authorize(&state, &user, Action::OverrideRegistration)?;
+    state.connection.get()?.transaction(|connection| {
+        diesel::delete(workshop_waitlist::table)
+            .filter(workshop_waitlist::workshop_id.eq(data.workshop_id))
+            .filter(workshop_waitlist::user_id.eq(data.user_id))
+            .execute(connection)?;
+        data.insert_into(workshop_participation::table)
+            .on_conflict_do_nothing()
+            .execute(connection)
+    })?;
+    Ok(())
+}
 
 pub async fn joined_workshops_individual(
     State(state): State<SiteState>,
     user: User,
-) -> Result<Json<Vec<Workshop>>, StatusCode> {
-    workshop_participation::table
-        .inner_join(workshops::table)
-        .select(Workshop::as_select())
-        .filter(workshop_participation::user_id.eq(user.id))
-        .load(&mut state.connection.get().map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?)
-        .map_err(|e| {
-            log::error!("{e:?}");
-            StatusCode::NOT_MODIFIED
-        })
-        .map(|v| Json(v))
+) -> Result<Json<Vec<Workshop>>, AppError> {
+    Ok(Json(
+        workshop_participation::table
+            .inner_join(workshops::table)
+            .select(Workshop::as_select())
+            .filter(workshop_participation::user_id.eq(user.id))
+            .load(&mut state.connection.get()?)?,
+    ))
 }